@@ -1,19 +1,36 @@
 #[cfg(feature = "server")]
-use std::{path::Path, str::FromStr};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 #[cfg(feature = "server")]
-use tokio::fs;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+};
 
 #[cfg(feature = "server")]
 use fs::OpenOptions;
 use tokio::fs::read_dir;
 
+use std::collections::HashSet;
+
 use crate::ID;
 #[cfg(feature = "server")]
 use crate::{
     error::{ApiError, Error},
     hub::HUB_DATA_FOLDER,
-    new_id, Result,
+    new_id,
+    server::WriteBatcher,
+    Result,
 };
 
 #[cfg(feature = "server")]
@@ -22,12 +39,19 @@ use async_graphql::SimpleObject;
 use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "server")]
+lazy_static::lazy_static! {
+    /// Locks that keep the message files of each channel from being written to by more than one task at a time, keyed by channel ID.
+    static ref WRITE_LOCKS: std::sync::Mutex<HashMap<ID, Arc<Mutex<()>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
 /// Text channel, used to group a manage sets of messages.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Channel {
     /// ID of the channel.
     pub id: ID,
-    /// ID of the Hub that the channel belongs to.
+    /// ID of the Hub that the channel belongs to, nil for direct message channels.
     pub hub_id: ID,
     /// Description of the channel.
     pub description: String,
@@ -35,23 +59,60 @@ pub struct Channel {
     pub name: String,
     /// Date the channel was created in milliseconds since Unix Epoch.
     pub created: DateTime<Utc>,
+    /// ID of the channel that this channel is a thread in, `None` for normal channels.
+    #[serde(default)]
+    pub parent: Option<ID>,
+    /// Time at which the last message was sent in the channel, or the time it was created if no messages were sent since this was added.
+    #[serde(default = "unix_epoch")]
+    pub last_activity: DateTime<Utc>,
+    /// ID of the user that created the channel, nil if it was created before this was recorded.
+    #[serde(default)]
+    pub created_by: ID,
+    /// ID of the user that last changed the channel's name or description, `None` if it was never changed.
+    #[serde(default)]
+    pub updated_by: Option<ID>,
+    /// Time at which the channel's name or description was last changed, `None` if it was never changed.
+    #[serde(default)]
+    pub updated: Option<DateTime<Utc>>,
+}
+
+fn unix_epoch() -> DateTime<Utc> {
+    Utc.timestamp(0, 0)
 }
 
 #[cfg(feature = "server")]
 impl Channel {
     /// Creates a new channel object based on parameters.
     pub fn new(name: String, id: ID, hub_id: ID) -> Self {
+        let created = Utc::now();
         Self {
             name,
             id,
             hub_id,
             description: String::new(),
-            created: Utc::now(),
+            created,
+            parent: None,
+            last_activity: created,
+            created_by: ID::nil(),
+            updated_by: None,
+            updated: None,
         }
     }
 
+    /// Records that the given user changed the channel's info now.
+    pub fn record_update(&mut self, user_id: ID) {
+        self.updated_by = Some(user_id);
+        self.updated = Some(Utc::now());
+    }
+
     /// Get the path of the channel's data folder, used for storing message files.
     pub fn get_folder(&self) -> String {
+        if self.hub_id.is_nil() {
+            return crate::dm::dm_folder(&self.id);
+        }
+        if let Some(parent) = &self.parent {
+            return thread_folder(&self.hub_id, parent, &self.id);
+        }
         format!(
             "{}{}/{}",
             HUB_DATA_FOLDER,
@@ -66,7 +127,19 @@ impl Channel {
         Ok(())
     }
 
+    /// Gets the lock that keeps the channel's message files from being written to by more than one task at a time.
+    fn write_lock(id: ID) -> Arc<Mutex<()>> {
+        Arc::clone(
+            WRITE_LOCKS
+                .lock()
+                .expect("write lock map poisoned")
+                .entry(id)
+                .or_default(),
+        )
+    }
+
     /// Adds a message to the channel, writes it to the file corresponding to the day the message was sent, one file per day of messages, only created if a message is sent that day.
+    /// Messages are always written framed, see [`MESSAGE_FILE_HEADER`], files written before framing was added are rewritten framed before the message is appended to them.
     ///
     /// # Errors
     ///
@@ -76,40 +149,262 @@ impl Channel {
     /// * The message file does not exist and could not be created.
     /// * Was unable to write to the message file.
     pub async fn add_message(&self, message: &Message) -> Result {
-        let path_string = format!(
-            "{}/{}",
-            self.get_folder(),
-            message
-                .created
-                .date()
-                .signed_duration_since(Utc.timestamp(0, 0).date())
-                .num_milliseconds()
-                / 86400000
-        );
-        let path = Path::new(&path_string);
-        if path.parent().expect("must have parent").exists() {
-            let file = OpenOptions::new()
+        self.add_messages(std::slice::from_ref(message)).await
+    }
+
+    /// Adds messages to the channel in the given order, like [`Channel::add_message`] but with a single append to the file of each day the messages were sent on.
+    ///
+    /// # Errors
+    ///
+    /// Possible errors outlined by [`Channel::add_message`], messages written to the files of earlier days are kept if writing to a later one fails.
+    pub async fn add_messages(&self, messages: &[Message]) -> Result {
+        let folder = self.get_folder();
+        if fs::metadata(&folder).await.is_err() {
+            return Err(Error::ApiError(ApiError::ChannelNotFound));
+        }
+        let lock = Self::write_lock(self.id);
+        let _guard = lock.lock().await;
+        let mut start = 0;
+        while start < messages.len() {
+            let day = day_number(&messages[start].created);
+            let end = messages[start..]
+                .iter()
+                .position(|message| day_number(&message.created) != day)
+                .map_or(messages.len(), |len| start + len);
+            let path = format!("{}/{}", folder, day);
+            let mut file = OpenOptions::new()
                 .create(true)
+                .read(true)
                 .append(true)
-                .open(path)
+                .open(&path)
                 .await?;
-            let file = file.into_std().await;
-            bincode::serialize_into(&file, message)?;
-            Ok(())
+            let mut bytes = Vec::new();
+            if file.metadata().await?.len() == 0 {
+                bytes.extend_from_slice(MESSAGE_FILE_HEADER);
+            } else {
+                let mut header = [0; MESSAGE_FILE_HEADER.len()];
+                if file.read_exact(&mut header).await.is_err() || &header != MESSAGE_FILE_HEADER {
+                    self.frame_legacy_file(Path::new(&path)).await?;
+                    file = OpenOptions::new().append(true).open(&path).await?;
+                }
+            }
+            for message in &messages[start..end] {
+                bytes.extend(encode_record(message)?);
+            }
+            file.write_all(&bytes).await?;
+            file.flush().await?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// Rewrites a message file written before framing was added as a framed one, so that records of different layouts are never mixed in one file.
+    async fn frame_legacy_file(&self, path: &Path) -> Result {
+        let bytes = fs::read(path).await?;
+        let mut framed = MESSAGE_FILE_HEADER.to_vec();
+        for message in MessageReader::new(&bytes, path, self.id) {
+            framed.extend(encode_record(&message)?);
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, framed).await?;
+        fs::rename(tmp, path).await?;
+        Ok(())
+    }
+
+    /// Rewrites the channel's message files, removing empty files, duplicated messages and data that can no longer be read.
+    /// Edits of messages that no longer exist are removed from the edit file.
+    /// Files written before framing was added are rewritten framed, see [`MESSAGE_FILE_HEADER`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The channel's folder could not be read.
+    /// * A message file could not be read, rewritten or removed.
+    pub async fn compact(&self) -> Result<CompactionReport> {
+        let lock = Self::write_lock(self.id);
+        let _guard = lock.lock().await;
+        let mut report = CompactionReport::default();
+        let mut files = Vec::new();
+        let mut dir = fs::read_dir(self.get_folder()).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.path().is_file() {
+                if let Ok(file_num) = i64::from_str(&entry.file_name().to_string_lossy()) {
+                    files.push((file_num, entry.path()));
+                }
+            }
+        }
+        files.sort_by_key(|(n, _)| *n);
+        report.files_before = files.len();
+        let mut seen = HashSet::new();
+        for (_, path) in files {
+            let bytes = fs::read(&path).await?;
+            let mut compacted = MESSAGE_FILE_HEADER.to_vec();
+            for message in MessageReader::new(&bytes, &path, self.id) {
+                if seen.insert(message.id) {
+                    compacted.extend(encode_record(&message)?);
+                    report.messages += 1;
+                }
+            }
+            if compacted.len() == MESSAGE_FILE_HEADER.len() {
+                fs::remove_file(&path).await?;
+                continue;
+            }
+            if compacted != bytes {
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, compacted).await?;
+                fs::rename(tmp, &path).await?;
+            }
+            report.files_after += 1;
+        }
+        let path = self.edits_path();
+        if let Ok(bytes) = fs::read(&path).await {
+            let mut kept = MESSAGE_FILE_HEADER.to_vec();
+            for edit in decode_records::<EditRecord>(&bytes, Path::new(&path)) {
+                if seen.contains(&edit.message_id) {
+                    kept.extend(encode_record(&edit)?);
+                }
+            }
+            if kept.len() == MESSAGE_FILE_HEADER.len() {
+                fs::remove_file(&path).await?;
+            } else if kept != bytes {
+                let tmp = format!("{}.tmp", path);
+                fs::write(&tmp, kept).await?;
+                fs::rename(tmp, &path).await?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Rewrites the channel's message files without the messages for which `remove` returns true, returning the removed messages.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The channel's folder could not be read.
+    /// * A message file could not be read, rewritten or removed.
+    pub async fn remove_messages<F>(&self, mut remove: F) -> Result<Vec<Message>>
+    where
+        F: FnMut(&Message) -> bool,
+    {
+        let lock = Self::write_lock(self.id);
+        let _guard = lock.lock().await;
+        let mut removed = Vec::new();
+        let mut dir = fs::read_dir(self.get_folder()).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if !entry.path().is_file()
+                || i64::from_str(&entry.file_name().to_string_lossy()).is_err()
+            {
+                continue;
+            }
+            let path = entry.path();
+            let bytes = fs::read(&path).await?;
+            let mut kept = MESSAGE_FILE_HEADER.to_vec();
+            let mut changed = false;
+            for message in MessageReader::new(&bytes, &path, self.id) {
+                if remove(&message) {
+                    removed.push(message);
+                    changed = true;
+                } else {
+                    kept.extend(encode_record(&message)?);
+                }
+            }
+            if !changed {
+                continue;
+            }
+            if kept.len() == MESSAGE_FILE_HEADER.len() {
+                fs::remove_file(&path).await?;
+            } else {
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, kept).await?;
+                fs::rename(tmp, &path).await?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Edits the content of a message, keeping its old content in [`Message::edits`], returns the edited message.
+    /// The message's record is left as it is, the edit is appended to the channel's edit file and applied when the message is read.
+    /// Returns [`ApiError::MessageNotFound`] if the channel has no message with the given ID.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The edit file could not be created or written to.
+    pub async fn edit_message(&self, id: ID, content: String, editor: ID) -> Result<Message> {
+        let lock = Self::write_lock(self.id);
+        let _guard = lock.lock().await;
+        let mut message = self
+            .get_message(id)
+            .await
+            .ok_or(Error::ApiError(ApiError::MessageNotFound))?;
+        let edit = EditRecord {
+            message_id: id,
+            content,
+            time: Utc::now(),
+            editor,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.edits_path())
+            .await?;
+        let mut bytes = Vec::new();
+        if file.metadata().await?.len() == 0 {
+            bytes.extend_from_slice(MESSAGE_FILE_HEADER);
+        }
+        bytes.extend(encode_record(&edit)?);
+        file.write_all(&bytes).await?;
+        file.flush().await?;
+        edit.apply(&mut message);
+        Ok(message)
+    }
+
+    /// Gets the path of the file that edits of the channel's messages are stored in, framed like message files, see [`Channel::edit_message`].
+    fn edits_path(&self) -> String {
+        format!("{}/edits", self.get_folder())
+    }
+
+    /// Reads the edits made to the channel's messages, oldest first, keyed by the ID of the edited message.
+    async fn load_edits(&self) -> HashMap<ID, Vec<EditRecord>> {
+        let mut edits: HashMap<ID, Vec<EditRecord>> = HashMap::new();
+        let path = self.edits_path();
+        if let Ok(bytes) = fs::read(&path).await {
+            for edit in decode_records::<EditRecord>(&bytes, Path::new(&path)) {
+                edits.entry(edit.message_id).or_default().push(edit);
+            }
+        }
+        edits
+    }
+
+    /// Deletes a message from the channel's message files, see [`Channel::remove_messages`].
+    /// Returns [`ApiError::MessageNotFound`] if the channel has no message with the given ID.
+    pub async fn delete_message(&self, id: ID) -> Result {
+        if self
+            .remove_messages(|message| message.id == id)
+            .await?
+            .is_empty()
+        {
+            Err(Error::ApiError(ApiError::MessageNotFound))
         } else {
-            Err(Error::ApiError(ApiError::ChannelNotFound))
+            Ok(())
         }
     }
 
+    /// Writes a message to its channel, together with other messages sent to the channel at the same time if the [`WriteBatcher`] is enabled.
     pub async fn write_message(message: &Message) -> Result {
-        Self::new("".to_string(), message.channel_id, message.hub_id)
-            .add_message(message)
-            .await
+        WriteBatcher::shared().write(message).await
     }
 
     /// Gets the last messages sent, `max` indicates the maximum number of messages to return.
     pub async fn get_last_messages(&self, max: usize) -> Vec<Message> {
         let mut result: Vec<Message> = Vec::new();
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
@@ -123,8 +418,9 @@ impl Channel {
             files.reverse();
             for (_, file) in files.iter() {
                 let mut found = Vec::new();
-                if let Ok(file) = std::fs::File::open(file.path()) {
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         found.push(message);
                     }
                     found.reverse();
@@ -145,16 +441,20 @@ impl Channel {
         if ids.is_empty() {
             return result;
         }
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = tokio::fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
-                if entry.path().is_file() {
+                if entry.path().is_file()
+                    && i64::from_str(&entry.file_name().to_string_lossy()).is_ok()
+                {
                     files.push(entry)
                 }
             }
             for file in files.iter() {
-                if let Ok(file) = std::fs::File::open(file.path()) {
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if ids.contains(&message.id) {
                             result.push(message);
                             if ids.len() == result.len() {
@@ -168,6 +468,63 @@ impl Channel {
         result
     }
 
+    /// Counts the messages in the channel by reading every message file.
+    pub async fn count_messages(&self) -> usize {
+        let mut count = 0;
+        if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
+            while let Ok(Some(entry)) = dir.next_entry().await {
+                if entry.path().is_file()
+                    && i64::from_str(&entry.file_name().to_string_lossy()).is_ok()
+                {
+                    let path = entry.path();
+                    if let Ok(bytes) = std::fs::read(&path) {
+                        count += MessageReader::new(&bytes, &path, self.id).count();
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    /// Gets the last messages sent by the given user, newest first, `max` indicates the maximum number of messages to return.
+    pub async fn get_messages_by_sender(&self, sender: &ID, max: usize) -> Vec<Message> {
+        let mut result: Vec<Message> = Vec::new();
+        if max == 0 {
+            return result;
+        }
+        let edits = self.load_edits().await;
+        if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
+            let mut files = Vec::new();
+            while let Ok(Some(entry)) = dir.next_entry().await {
+                if entry.path().is_file() {
+                    if let Ok(file_num) = i64::from_str(&entry.file_name().to_string_lossy()) {
+                        files.push((file_num, entry))
+                    }
+                }
+            }
+            files.sort_by_key(|(n, _)| *n);
+            files.reverse();
+            for (_, file) in files.iter() {
+                let mut found = Vec::new();
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
+                        if &message.sender == sender {
+                            found.push(message);
+                        }
+                    }
+                    found.reverse();
+                    result.append(&mut found);
+                    if result.len() >= max {
+                        result.truncate(max);
+                        return result;
+                    }
+                }
+            }
+        }
+        result
+    }
+
     /// Gets a set of messages between two times given in milliseconds since Unix Epoch.
     ///
     /// # Arguments
@@ -187,6 +544,7 @@ impl Channel {
         if from > to {
             return result;
         }
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
@@ -215,9 +573,10 @@ impl Channel {
                 files.reverse() // Reverse the order of the list of files to search in the correct direction if `invert` is true.
             }
             for (_, file) in files {
-                if let Ok(file) = std::fs::File::open(file.path()) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
                     let mut filtered = Vec::new();
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if message.created >= from && message.created <= to {
                             filtered.push(message);
                         }
@@ -243,6 +602,7 @@ impl Channel {
     /// Gets all messages that were sent before the message with the given ID.
     pub async fn get_messages_before(&self, id: ID, max: usize) -> Vec<Message> {
         let mut result: Vec<Message> = Vec::new();
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
@@ -259,9 +619,10 @@ impl Channel {
                 if result.len() == max {
                     return result;
                 }
-                if let Ok(file) = std::fs::File::open(file.path()) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
                     let mut messages_in_file = Vec::new();
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if message.id == id {
                             match messages_in_file.len().cmp(&max) {
                                 std::cmp::Ordering::Greater => {
@@ -298,6 +659,7 @@ impl Channel {
     /// Gets all messages that were sent after the message with the given ID.
     pub async fn get_messages_after(&self, id: ID, max: usize) -> Vec<Message> {
         let mut result: Vec<Message> = Vec::new();
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
@@ -310,8 +672,9 @@ impl Channel {
             files.sort_by_key(|(n, _)| *n);
             let mut found = false;
             for (_, file) in files.iter() {
-                if let Ok(file) = std::fs::File::open(file.path()) {
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if found {
                             result.push(message);
                             let len = result.len();
@@ -331,9 +694,74 @@ impl Channel {
         result
     }
 
+    /// Gets a page of messages, oldest first, paginating on the position of messages in the channel's storage instead of their send time.
+    /// New messages are appended to the channel so positions do not change as messages are sent, the first page records the
+    /// number of messages in the channel and later pages given its cursor stop there, so messages sent while paging do not cause
+    /// duplicates or gaps. Edits are stored beside the message files and do not move messages either.
+    ///
+    /// Anything that removes messages shifts the positions of the messages after them, so a cursor taken before it can skip or repeat
+    /// messages. That is [`Channel::remove_messages`], used by [`Channel::delete_message`] and when expired messages are swept, and
+    /// [`Channel::compact`], which drops unreadable records and duplicate messages. Clients should start again from the first page when they
+    /// learn of a deletion in the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Position of the first message to return, 0 for the first page.
+    /// * `as_of` - Number of messages the channel had when the first page was taken, `None` for the first page.
+    /// * `max` - The maximum number of messages to return.
+    pub async fn get_messages_page(
+        &self,
+        position: u64,
+        as_of: Option<u64>,
+        max: usize,
+    ) -> MessagePage {
+        let mut messages = Vec::new();
+        let mut count = 0;
+        let edits = self.load_edits().await;
+        if let Ok(mut dir) = fs::read_dir(self.get_folder()).await {
+            let mut files = Vec::new();
+            while let Ok(Some(entry)) = dir.next_entry().await {
+                if entry.path().is_file() {
+                    if let Ok(file_num) = i64::from_str(&entry.file_name().to_string_lossy()) {
+                        files.push((file_num, entry))
+                    }
+                }
+            }
+            files.sort_by_key(|(n, _)| *n);
+            'files: for (_, file) in files.iter() {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
+                        if as_of.map_or(false, |as_of| count >= as_of) {
+                            break 'files;
+                        }
+                        if count >= position && messages.len() < max {
+                            messages.push(message);
+                        }
+                        count += 1;
+                    }
+                }
+            }
+        }
+        let as_of = as_of.unwrap_or(count).min(count);
+        let next = position + messages.len() as u64;
+        MessagePage {
+            messages,
+            next: if next < as_of {
+                Some(MessageCursor {
+                    position: next,
+                    as_of,
+                })
+            } else {
+                None
+            },
+        }
+    }
+
     /// Unlimited asynchronus version of [`get_messages_after`] for internal use.
     pub async fn get_all_messages_from(&self, id: ID) -> Vec<Message> {
         let mut result: Vec<Message> = Vec::new();
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = tokio::fs::read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
@@ -346,8 +774,9 @@ impl Channel {
             files.sort_by_key(|(n, _)| *n);
             let mut found = false;
             for (_, file) in files.iter() {
-                if let Ok(file) = std::fs::File::open(file.path()) {
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if found {
                             result.push(message);
                         } else if message.id == id {
@@ -363,16 +792,20 @@ impl Channel {
 
     /// Get the first message with the given ID.
     pub async fn get_message(&self, id: ID) -> Option<Message> {
+        let edits = self.load_edits().await;
         if let Ok(mut dir) = read_dir(self.get_folder()).await {
             let mut files = Vec::new();
             while let Ok(Some(entry)) = dir.next_entry().await {
-                if entry.path().is_file() {
+                if entry.path().is_file()
+                    && i64::from_str(&entry.file_name().to_string_lossy()).is_ok()
+                {
                     files.push(entry);
                 }
             }
             for file in files.iter() {
-                if let Ok(file) = std::fs::File::open(file.path()) {
-                    while let Ok(message) = bincode::deserialize_from::<_, Message>(&file) {
+                let path = file.path();
+                if let Ok(bytes) = std::fs::read(&path) {
+                    for message in MessageReader::new(&bytes, &path, self.id).with_edits(&edits) {
                         if message.id == id {
                             return Some(message);
                         }
@@ -384,90 +817,1005 @@ impl Channel {
     }
 }
 
-/// Represents a message.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[cfg_attr(feature = "graphql", derive(SimpleObject))]
-pub struct Message {
-    /// ID of the message, not actually guaranteed to be unique due to the performance that could be required to check this for every message sent.
-    pub id: ID,
-    /// ID of the hub the message was sent in.
-    pub hub_id: ID,
-    /// ID of the channel the message was sent in.
-    pub channel_id: ID,
-    /// ID of the user that sent the message.
-    pub sender: ID,
-    /// Date in milliseconds since Unix Epoch that the message was sent.
-    pub created: DateTime<Utc>,
-    /// The actual text of the message.
-    pub content: String,
+/// Result of compacting a channel's message files, see [`Channel::compact`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct CompactionReport {
+    /// Number of message files before compaction.
+    pub files_before: usize,
+    /// Number of message files left after compaction.
+    pub files_after: usize,
+    /// Number of messages left in the channel.
+    pub messages: usize,
+}
+
+/// Position to continue paginating from, see [`Channel::get_messages_page`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageCursor {
+    /// Position of the next message to return.
+    pub position: u64,
+    /// Number of messages the channel had when the first page was taken, messages past it are not returned.
+    pub as_of: u64,
 }
 
+/// Page of messages returned by [`Channel::get_messages_page`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessagePage {
+    /// Messages in the page, oldest first.
+    pub messages: Vec<Message>,
+    /// Cursor for the next page, `None` if this was the last page.
+    pub next: Option<MessageCursor>,
+}
+
+/// Number of records that could not be decoded while reading message files, see [`MessageReader`].
 #[cfg(feature = "server")]
-impl Message {
-    pub fn new(sender: ID, content: String, hub_id: ID, channel_id: ID) -> Self {
-        Self {
-            sender,
-            content,
-            channel_id,
-            hub_id,
-            created: Utc::now(),
-            id: new_id(),
-        }
-    }
+static CORRUPT_RECORDS: AtomicU64 = AtomicU64::new(0);
+
+/// Gets the number of corrupt message records that have been skipped since the server started.
+#[cfg(feature = "server")]
+pub fn corrupt_records() -> u64 {
+    CORRUPT_RECORDS.load(Ordering::SeqCst)
 }
 
-#[cfg(test)]
-pub(crate) mod test {
-    use super::*;
-    use crate::test::*;
+/// Bytes at the start of framed message files, in which every message is preceded by its length and a checksum of its encoding
+/// as little endian `u32`s. The first byte of each encoding is the version of the layout the message was encoded with.
+/// Files that do not start with it were written before framing was added and are a plain sequence of messages
+/// in the layout messages had at the time, which is kept for reading them.
+#[cfg(feature = "server")]
+pub const MESSAGE_FILE_HEADER: &[u8; 8] = b"WICRSMF1";
 
-    pub fn test_channel(hub: ID) -> Channel {
-        let channel = Channel {
-            id: *CHANNEL_ID,
-            hub_id: hub,
-            description: "test channel description".to_string(),
-            name: "test".to_string(),
-            created: utc(0),
-        };
-        std::fs::create_dir_all(channel.get_folder())
-            .expect("failed to create the channel directory");
-        channel
+/// Gets the number of days between the Unix epoch and the day a message was sent, which is the name of the file the message is stored in.
+#[cfg(feature = "server")]
+fn day_number(created: &DateTime<Utc>) -> i64 {
+    created
+        .date()
+        .signed_duration_since(Utc.timestamp(0, 0).date())
+        .num_milliseconds()
+        / 86400000
+}
+
+/// Value stored in the records of framed files, see [`MESSAGE_FILE_HEADER`].
+/// Bincode ignores `#[serde(default)]`, so a record can only be decoded with the layout it was encoded with. Changing the layout
+/// of a stored type requires a new version, with the decoder of every earlier version kept so that existing records can still be read.
+#[cfg(feature = "server")]
+trait Record: Sized {
+    /// Version of the layout that new records are encoded with.
+    const VERSION: u8;
+
+    /// Encodes the value in the layout of [`Record::VERSION`].
+    fn encode(&self) -> Result<Vec<u8>>;
+
+    /// Decodes a value encoded in the layout of the given version, `None` if the version is unknown or the bytes are not a valid encoding.
+    fn decode(version: u8, bytes: &[u8]) -> Option<Self>;
+}
+
+/// Layout of the messages in records of version 1, edits are stored separately, see [`Channel::edit_message`].
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize)]
+struct MessageRecordV1 {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    sender: ID,
+    created: DateTime<Utc>,
+    content: String,
+    flags: u32,
+    expires_at: Option<DateTime<Utc>>,
+    forwarded_from: Option<(ID, ID, ID, ID)>,
+    links: Vec<String>,
+}
+
+#[cfg(feature = "server")]
+impl Record for Message {
+    const VERSION: u8 = 1;
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&MessageRecordV1 {
+            id: self.id,
+            hub_id: self.hub_id,
+            channel_id: self.channel_id,
+            sender: self.sender,
+            created: self.created,
+            content: self.content.clone(),
+            flags: self.flags,
+            expires_at: self.expires_at,
+            forwarded_from: self.forwarded_from.map(|reference| {
+                (
+                    reference.hub_id,
+                    reference.channel_id,
+                    reference.message_id,
+                    reference.sender,
+                )
+            }),
+            links: self.links.clone(),
+        })?)
     }
 
-    pub fn test_message(hub: ID) -> Message {
-        Message {
-            sender: *USER_ID,
-            content: "test message".to_string(),
-            hub_id: hub,
-            channel_id: *CHANNEL_ID,
-            created: utc(0),
-            id: *MESSAGE_ID,
+    fn decode(version: u8, bytes: &[u8]) -> Option<Self> {
+        match version {
+            1 => {
+                let record: MessageRecordV1 = bincode::deserialize(bytes).ok()?;
+                Some(Self {
+                    id: record.id,
+                    hub_id: record.hub_id,
+                    channel_id: record.channel_id,
+                    sender: record.sender,
+                    created: record.created,
+                    content: record.content,
+                    flags: record.flags,
+                    expires_at: record.expires_at,
+                    forwarded_from: record.forwarded_from.map(
+                        |(hub_id, channel_id, message_id, sender)| MessageReference {
+                            hub_id,
+                            channel_id,
+                            message_id,
+                            sender,
+                        },
+                    ),
+                    links: record.links,
+                    edits: Vec::new(),
+                })
+            }
+            _ => None,
         }
     }
+}
 
-    pub async fn add_test_messages(hub: ID) -> Vec<Message> {
-        let mut messages = Vec::new();
-        for i in 0..100u128 {
-            let message = Message {
-                sender: *USER_ID,
-                content: "test message".to_string(),
-                hub_id: hub,
-                channel_id: *CHANNEL_ID,
-                created: utc(i as i64 + 86350),
-                id: ID::from_u128(i),
-            };
-            Channel::write_message(&message)
-                .await
-                .expect("failed to add a message");
-            messages.push(message);
-        }
-        messages
+/// Encodes a message or edit as a record of a framed file, see [`MESSAGE_FILE_HEADER`].
+#[cfg(feature = "server")]
+fn encode_record<T: Record>(value: &T) -> Result<Vec<u8>> {
+    let mut encoded = vec![T::VERSION];
+    encoded.extend(value.encode()?);
+    let mut record = Vec::with_capacity(encoded.len() + 8);
+    record.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+    record.extend_from_slice(&checksum(&encoded).to_le_bytes());
+    record.extend(encoded);
+    Ok(record)
+}
+
+/// Decodes the framed record starting at `offset` if its checksum matches, returning the value and the offset of the next record.
+#[cfg(feature = "server")]
+fn decode_record<T: Record>(bytes: &[u8], offset: usize) -> Option<(T, usize)> {
+    let start = offset.checked_add(8)?;
+    let length = u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?);
+    let expected = u32::from_le_bytes(bytes.get(offset + 4..start)?.try_into().ok()?);
+    let end = start.checked_add(length as usize)?;
+    let record = bytes.get(start..end)?;
+    if checksum(record) != expected {
+        return None;
     }
+    let (version, encoded) = record.split_first()?;
+    T::decode(*version, encoded).map(|value| (value, end))
+}
 
-    #[tokio::test]
-    async fn add_get_message() {
-        let channel = test_channel(new_id());
-        let message = test_message(channel.hub_id);
+/// Decodes all of the records of a framed file, records that can not be decoded are logged, counted in [`corrupt_records`] and skipped.
+#[cfg(feature = "server")]
+fn decode_records<T: Record>(bytes: &[u8], path: &Path) -> Vec<T> {
+    let decode = |offset| decode_record::<T>(bytes, offset);
+    let mut records = Vec::new();
+    if !bytes.starts_with(MESSAGE_FILE_HEADER) {
+        return records;
+    }
+    let mut offset = MESSAGE_FILE_HEADER.len();
+    while offset < bytes.len() {
+        if let Some((value, next)) = decode(offset) {
+            records.push(value);
+            offset = next;
+            continue;
+        }
+        warn!(
+            "Skipping corrupt record at byte {} of {}",
+            offset,
+            path.display()
+        );
+        CORRUPT_RECORDS.fetch_add(1, Ordering::SeqCst);
+        offset = (offset + 1..bytes.len())
+            .find(|offset| decode(*offset).is_some())
+            .unwrap_or(bytes.len());
+    }
+    records
+}
+
+/// 32 bit FNV-1a hash of a record, used to detect records that were damaged or only partially written.
+#[cfg(feature = "server")]
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Iterator over the messages stored in the contents of a message file, framed or not, see [`MESSAGE_FILE_HEADER`].
+/// When a record can not be decoded its offset is logged and counted in [`corrupt_records`], the reader then moves forward one byte
+/// at a time until a message of the same channel decodes, so a damaged record does not hide the messages that were written after it.
+#[cfg(feature = "server")]
+pub struct MessageReader<'a> {
+    bytes: &'a [u8],
+    path: &'a Path,
+    channel_id: ID,
+    offset: usize,
+    framed: bool,
+    edits: Option<&'a HashMap<ID, Vec<EditRecord>>>,
+}
+
+#[cfg(feature = "server")]
+impl<'a> MessageReader<'a> {
+    /// Creates a reader over `bytes`, the contents of the file at `path` which stores messages of the channel with the ID `channel_id`.
+    pub fn new(bytes: &'a [u8], path: &'a Path, channel_id: ID) -> Self {
+        let framed = bytes.starts_with(MESSAGE_FILE_HEADER);
+        Self {
+            bytes,
+            path,
+            channel_id,
+            offset: if framed { MESSAGE_FILE_HEADER.len() } else { 0 },
+            framed,
+            edits: None,
+        }
+    }
+
+    /// Applies the given edits, read from the channel's edit file, to the messages returned by the reader.
+    fn with_edits(mut self, edits: &'a HashMap<ID, Vec<EditRecord>>) -> Self {
+        self.edits = Some(edits);
+        self
+    }
+
+    /// Decodes the record starting at `offset`, returning the message and the offset of the next record.
+    /// Lengths read from unframed records are limited to the bytes left in the file so that corrupt ones can not cause huge allocations.
+    fn decode(&self, offset: usize) -> Option<(Message, usize)> {
+        use bincode::Options;
+        if self.framed {
+            return decode_record(self.bytes, offset);
+        }
+        let mut reader = &self.bytes[offset..];
+        bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(reader.len() as u64)
+            .deserialize_from::<_, LegacyMessage>(&mut reader)
+            .ok()
+            .map(|message| (message.into(), self.bytes.len() - reader.len()))
+    }
+}
+
+/// Layout of the messages in files written before framing was added, bincode ignores `#[serde(default)]` so these can not be read as [`Message`].
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyMessage {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    sender: ID,
+    created: DateTime<Utc>,
+    content: String,
+}
+
+#[cfg(feature = "server")]
+impl From<LegacyMessage> for Message {
+    fn from(message: LegacyMessage) -> Self {
+        Self {
+            id: message.id,
+            hub_id: message.hub_id,
+            channel_id: message.channel_id,
+            sender: message.sender,
+            created: message.created,
+            links: Self::links(&message.content),
+            content: message.content,
+            flags: 0,
+            expires_at: None,
+            forwarded_from: None,
+            edits: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl MessageReader<'_> {
+    /// Reads the next message as it was written, without applying edits.
+    fn next_record(&mut self) -> Option<Message> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        if let Some((message, next)) = self.decode(self.offset) {
+            self.offset = next;
+            return Some(message);
+        }
+        warn!(
+            "Skipping corrupt message record at byte {} of {}",
+            self.offset,
+            self.path.display()
+        );
+        CORRUPT_RECORDS.fetch_add(1, Ordering::SeqCst);
+        for offset in self.offset + 1..self.bytes.len() {
+            if let Some((message, next)) = self.decode(offset) {
+                if message.channel_id == self.channel_id {
+                    self.offset = next;
+                    return Some(message);
+                }
+            }
+        }
+        self.offset = self.bytes.len();
+        None
+    }
+}
+
+#[cfg(feature = "server")]
+impl Iterator for MessageReader<'_> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        let mut message = self.next_record()?;
+        if let Some(edits) = self.edits.and_then(|edits| edits.get(&message.id)) {
+            for edit in edits {
+                edit.apply(&mut message);
+            }
+        }
+        Some(message)
+    }
+}
+
+/// Edit of a message as stored in the channel's edit file, see [`Channel::edit_message`].
+/// This is the layout of version 1 of edit records, it must not be changed, see [`Record`].
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize)]
+struct EditRecord {
+    message_id: ID,
+    /// Content of the message after the edit.
+    content: String,
+    time: DateTime<Utc>,
+    editor: ID,
+}
+
+#[cfg(feature = "server")]
+impl Record for EditRecord {
+    const VERSION: u8 = 1;
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    fn decode(version: u8, bytes: &[u8]) -> Option<Self> {
+        match version {
+            1 => bincode::deserialize(bytes).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl EditRecord {
+    /// Replaces the content of the message with the edited content, adding its previous content to [`Message::edits`].
+    fn apply(&self, message: &mut Message) {
+        message.edits.push(MessageEdit {
+            content: std::mem::replace(&mut message.content, self.content.clone()),
+            time: self.time,
+            editor: self.editor,
+        });
+        message.links = Message::links(&message.content);
+    }
+}
+
+/// Sub-conversation started from a message, its messages are stored under the folder of the channel the message was sent in and use the thread's ID as their `channel_id`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Thread {
+    /// ID of the thread.
+    pub id: ID,
+    /// ID of the hub the thread is in.
+    pub hub_id: ID,
+    /// ID of the channel the thread was started in.
+    pub channel_id: ID,
+    /// ID of the message the thread was started from.
+    pub message_id: ID,
+    /// Name of the thread.
+    pub name: String,
+    /// ID of the user who started the thread.
+    pub creator: ID,
+    /// Date the thread was created.
+    pub created: DateTime<Utc>,
+    /// Number of messages that have been sent in the thread.
+    #[serde(default)]
+    pub reply_count: u64,
+    /// Last message that was sent in the thread.
+    #[serde(default)]
+    pub last_reply: Option<Message>,
+}
+
+/// Replies to a message across all of the threads started from it, lets clients show the reply count without loading the threads.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct ThreadSummary {
+    /// Number of messages sent in threads started from the message.
+    pub thread_count: u64,
+    /// Last message sent in any of the threads started from the message.
+    pub last_thread_message: Option<Message>,
+}
+
+#[cfg(feature = "server")]
+impl Thread {
+    /// Creates a new thread object with a random ID.
+    pub fn new(hub_id: ID, channel_id: ID, message_id: ID, name: String, creator: ID) -> Self {
+        Self {
+            id: new_id(),
+            hub_id,
+            channel_id,
+            message_id,
+            name,
+            creator,
+            created: Utc::now(),
+            reply_count: 0,
+            last_reply: None,
+        }
+    }
+
+    /// Records a message that was sent in the thread and saves the thread's info, updating this copy of the thread with the saved info.
+    /// The info is read again while holding the lock of the thread's channel so that replies sent at the same time are all counted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error for any of the reasons outlined by [`Thread::load`] and [`Thread::save`].
+    pub async fn record_reply(&mut self, message: Message) -> Result {
+        let lock = Channel::write_lock(self.id);
+        let _guard = lock.lock().await;
+        let mut thread = Self::load(self.hub_id, self.channel_id, self.id).await?;
+        thread.reply_count += 1;
+        if thread
+            .last_reply
+            .as_ref()
+            .map_or(true, |last| last.created <= message.created)
+        {
+            thread.last_reply = Some(message);
+        }
+        thread.save().await?;
+        *self = thread;
+        Ok(())
+    }
+
+    /// Gets the channel used to store and read the thread's messages.
+    pub fn channel(&self) -> Channel {
+        let mut channel = Channel::new(self.name.clone(), self.id, self.hub_id);
+        channel.parent = Some(self.channel_id);
+        channel.created = self.created;
+        channel.created_by = self.creator;
+        channel
+    }
+
+    /// Creates the thread's data folder and writes its info file.
+    /// The info is written to a temporary file that then replaces the info file, so the info file is never seen partially written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the folder could not be created or the info file could not be written.
+    pub async fn save(&self) -> Result {
+        let channel = self.channel();
+        channel.create_dir().await?;
+        let path = format!("{}/info", channel.get_folder());
+        let tmp = format!("{}.tmp", path);
+        let mut bytes = THREAD_FILE_HEADER.to_vec();
+        bytes.extend(encode_record(self)?);
+        fs::write(&tmp, bytes).await?;
+        fs::rename(tmp, path).await?;
+        Ok(())
+    }
+
+    /// Decodes the contents of a thread info file in any of the layouts threads have been written with, see [`THREAD_FILE_HEADER`].
+    fn decode_file(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(THREAD_FILE_HEADER) {
+            return decode_record(bytes, THREAD_FILE_HEADER.len())
+                .map(|(thread, _)| thread)
+                .ok_or_else(|| Error::OtherInternal("corrupt thread file".to_string()));
+        }
+        match bincode::deserialize::<LegacyThread>(bytes) {
+            Ok(thread) => Ok(thread.into()),
+            Err(_) => Ok(bincode::deserialize::<LegacyThreadWithoutReplies>(bytes)?.into()),
+        }
+    }
+
+    /// Loads a thread given the channel it is in and its ID.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the thread does not exist or its info file could not be read or deserialized.
+    pub async fn load(hub_id: ID, channel_id: ID, id: ID) -> Result<Self> {
+        let path = format!("{}/info", thread_folder(&hub_id, &channel_id, &id));
+        if !Path::new(&path).is_file() {
+            return Err(ApiError::ThreadNotFound.into());
+        }
+        Self::decode_file(&fs::read(path).await?)
+    }
+
+    /// Gets all of the threads that were started in the given channel, ordered oldest to newest.
+    pub async fn list(hub_id: ID, channel_id: ID) -> Vec<Self> {
+        let mut result = Vec::new();
+        let folder = format!(
+            "{}/threads",
+            Channel::new(String::new(), channel_id, hub_id).get_folder()
+        );
+        if let Ok(mut dir) = fs::read_dir(folder).await {
+            while let Ok(Some(entry)) = dir.next_entry().await {
+                if let Ok(bytes) = fs::read(entry.path().join("info")).await {
+                    if let Ok(thread) = Self::decode_file(&bytes) {
+                        result.push(thread);
+                    }
+                }
+            }
+        }
+        result.sort_by_key(|thread| thread.created);
+        result
+    }
+
+    /// Gets the number of replies and the last reply across all of the threads started from the given message.
+    pub async fn summary(hub_id: ID, channel_id: ID, message_id: ID) -> ThreadSummary {
+        let mut summary = ThreadSummary::default();
+        for thread in Self::list(hub_id, channel_id).await {
+            if thread.message_id != message_id {
+                continue;
+            }
+            summary.thread_count += thread.reply_count;
+            if let Some(reply) = thread.last_reply {
+                if summary
+                    .last_thread_message
+                    .as_ref()
+                    .map_or(true, |last| last.created < reply.created)
+                {
+                    summary.last_thread_message = Some(reply);
+                }
+            }
+        }
+        summary
+    }
+}
+
+/// Bytes at the start of thread info files, followed by the thread encoded as a single framed record, see [`MESSAGE_FILE_HEADER`].
+/// Files that do not start with it were written before thread files were versioned and are read in the layouts threads had at the time.
+#[cfg(feature = "server")]
+pub const THREAD_FILE_HEADER: &[u8; 8] = b"WICRSTHR";
+
+/// Layout of the threads in records of version 1, the last reply is stored as a message record so that it can be read after the layout of messages changes.
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize)]
+struct ThreadRecordV1 {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    message_id: ID,
+    name: String,
+    creator: ID,
+    created: DateTime<Utc>,
+    reply_count: u64,
+    /// Version of the layout of the last reply followed by its encoding, see [`Record`].
+    last_reply: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "server")]
+impl Record for Thread {
+    const VERSION: u8 = 1;
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let last_reply = match &self.last_reply {
+            Some(message) => {
+                let mut encoded = vec![Message::VERSION];
+                encoded.extend(message.encode()?);
+                Some(encoded)
+            }
+            None => None,
+        };
+        Ok(bincode::serialize(&ThreadRecordV1 {
+            id: self.id,
+            hub_id: self.hub_id,
+            channel_id: self.channel_id,
+            message_id: self.message_id,
+            name: self.name.clone(),
+            creator: self.creator,
+            created: self.created,
+            reply_count: self.reply_count,
+            last_reply,
+        })?)
+    }
+
+    fn decode(version: u8, bytes: &[u8]) -> Option<Self> {
+        match version {
+            1 => {
+                let record: ThreadRecordV1 = bincode::deserialize(bytes).ok()?;
+                let last_reply = match record.last_reply {
+                    Some(encoded) => {
+                        let (version, encoded) = encoded.split_first()?;
+                        Some(Message::decode(*version, encoded)?)
+                    }
+                    None => None,
+                };
+                Some(Self {
+                    id: record.id,
+                    hub_id: record.hub_id,
+                    channel_id: record.channel_id,
+                    message_id: record.message_id,
+                    name: record.name,
+                    creator: record.creator,
+                    created: record.created,
+                    reply_count: record.reply_count,
+                    last_reply,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Layout of the thread info files written before [`THREAD_FILE_HEADER`] was added, once threads kept their replies.
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyThread {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    message_id: ID,
+    name: String,
+    creator: ID,
+    created: DateTime<Utc>,
+    reply_count: u64,
+    last_reply: Option<LegacyThreadReply>,
+}
+
+/// Layout of the last reply of a [`LegacyThread`], references and edits are tuples of the fields of [`MessageReference`] and [`MessageEdit`].
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyThreadReply {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    sender: ID,
+    created: DateTime<Utc>,
+    content: String,
+    flags: u32,
+    expires_at: Option<DateTime<Utc>>,
+    forwarded_from: Option<(ID, ID, ID, ID)>,
+    links: Vec<String>,
+    edits: Vec<(String, DateTime<Utc>, ID)>,
+}
+
+/// Layout of the thread info files written before threads kept their replies.
+/// Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyThreadWithoutReplies {
+    id: ID,
+    hub_id: ID,
+    channel_id: ID,
+    message_id: ID,
+    name: String,
+    creator: ID,
+    created: DateTime<Utc>,
+}
+
+#[cfg(feature = "server")]
+impl From<LegacyThread> for Thread {
+    fn from(thread: LegacyThread) -> Self {
+        Self {
+            id: thread.id,
+            hub_id: thread.hub_id,
+            channel_id: thread.channel_id,
+            message_id: thread.message_id,
+            name: thread.name,
+            creator: thread.creator,
+            created: thread.created,
+            reply_count: thread.reply_count,
+            last_reply: thread.last_reply.map(|reply| Message {
+                id: reply.id,
+                hub_id: reply.hub_id,
+                channel_id: reply.channel_id,
+                sender: reply.sender,
+                created: reply.created,
+                content: reply.content,
+                flags: reply.flags,
+                expires_at: reply.expires_at,
+                forwarded_from: reply.forwarded_from.map(
+                    |(hub_id, channel_id, message_id, sender)| MessageReference {
+                        hub_id,
+                        channel_id,
+                        message_id,
+                        sender,
+                    },
+                ),
+                links: reply.links,
+                edits: reply
+                    .edits
+                    .into_iter()
+                    .map(|(content, time, editor)| MessageEdit {
+                        content,
+                        time,
+                        editor,
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<LegacyThreadWithoutReplies> for Thread {
+    fn from(thread: LegacyThreadWithoutReplies) -> Self {
+        Self {
+            id: thread.id,
+            hub_id: thread.hub_id,
+            channel_id: thread.channel_id,
+            message_id: thread.message_id,
+            name: thread.name,
+            creator: thread.creator,
+            created: thread.created,
+            reply_count: 0,
+            last_reply: None,
+        }
+    }
+}
+
+/// Get the path of the data folder of a thread.
+#[cfg(feature = "server")]
+pub fn thread_folder(hub_id: &ID, channel_id: &ID, thread_id: &ID) -> String {
+    format!(
+        "{}/threads/{}",
+        Channel::new(String::new(), *channel_id, *hub_id).get_folder(),
+        thread_id.to_string()
+    )
+}
+
+/// Where a forwarded message was first sent, see [`Message::forwarded_from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct MessageReference {
+    pub hub_id: ID,
+    pub channel_id: ID,
+    pub message_id: ID,
+    /// ID of the user that sent the original message.
+    pub sender: ID,
+}
+
+impl From<&Message> for MessageReference {
+    fn from(message: &Message) -> Self {
+        Self {
+            hub_id: message.hub_id,
+            channel_id: message.channel_id,
+            message_id: message.id,
+            sender: message.sender,
+        }
+    }
+}
+
+/// Previous version of an edited message, see [`Message::edits`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct MessageEdit {
+    /// Content of the message before the edit.
+    pub content: String,
+    /// Time at which the message was edited.
+    pub time: DateTime<Utc>,
+    /// ID of the user that edited the message.
+    pub editor: ID,
+}
+
+/// Represents a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+#[cfg_attr(feature = "graphql", graphql(complex))]
+pub struct Message {
+    /// ID of the message, not actually guaranteed to be unique due to the performance that could be required to check this for every message sent.
+    pub id: ID,
+    /// ID of the hub the message was sent in, nil for messages sent in direct message channels.
+    pub hub_id: ID,
+    /// ID of the channel the message was sent in.
+    pub channel_id: ID,
+    /// ID of the user that sent the message.
+    pub sender: ID,
+    /// Date in milliseconds since Unix Epoch that the message was sent.
+    pub created: DateTime<Utc>,
+    /// The actual text of the message.
+    pub content: String,
+    /// Attributes set by the sender, see [`Message::FLAG_SPOILER`] and the other `FLAG_` constants.
+    #[serde(default)]
+    pub flags: u32,
+    /// Time after which the message is deleted, `None` for messages that do not expire.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Message that this message is a copy of, `None` for messages that were not forwarded.
+    #[serde(default)]
+    pub forwarded_from: Option<MessageReference>,
+    /// Links found in the content when the message was sent, see [`Message::find_links`].
+    #[serde(default)]
+    pub links: Vec<String>,
+    /// Previous versions of the message, oldest first, empty if the message was never edited.
+    #[serde(default)]
+    pub edits: Vec<MessageEdit>,
+}
+
+impl Message {
+    /// The message's content should be hidden until the reader chooses to reveal it.
+    pub const FLAG_SPOILER: u32 = 1;
+    /// Clients should not notify users of the message.
+    pub const FLAG_SILENT: u32 = 1 << 1;
+    /// Clients should only show the message for the current session.
+    pub const FLAG_EPHEMERAL: u32 = 1 << 2;
+    /// All of the flags that a message can have.
+    pub const KNOWN_FLAGS: u32 = Self::FLAG_SPOILER | Self::FLAG_SILENT | Self::FLAG_EPHEMERAL;
+
+    /// Checks if the message has all of the given flags set.
+    pub fn has_flags(&self, flags: u32) -> bool {
+        self.flags & flags == flags
+    }
+
+    /// Gets the IDs of the users mentioned in the message, a mention is an `@` followed by the user's ID.
+    pub fn mentions(&self) -> HashSet<ID> {
+        self.content
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('@'))
+            .filter_map(|id| {
+                ID::parse_str(id.trim_end_matches(|c: char| c.is_ascii_punctuation())).ok()
+            })
+            .collect()
+    }
+
+    /// Finds the links in a message's content, a link is any word that starts with `http://` or `https://`, ignoring surrounding brackets and punctuation.
+    pub fn find_links(content: &str) -> impl Iterator<Item = &str> {
+        content
+            .split_whitespace()
+            .map(|word| {
+                word.trim_start_matches(|c: char| c.is_ascii_punctuation())
+                    .trim_end_matches(&[')', ']', '>', '"', '\'', ',', '.', ':', ';', '!', '?'][..])
+            })
+            .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+    }
+
+    /// Finds the attachment references in a message's content, an attachment reference is any word that starts with `attachment://`, ignoring surrounding brackets and punctuation.
+    pub fn find_attachments(content: &str) -> impl Iterator<Item = &str> {
+        content
+            .split_whitespace()
+            .map(|word| {
+                word.trim_start_matches(|c: char| c.is_ascii_punctuation())
+                    .trim_end_matches(&[')', ']', '>', '"', '\'', ',', '.', ':', ';', '!', '?'][..])
+            })
+            .filter(|word| word.starts_with("attachment://"))
+    }
+
+    /// Checks if users should be notified of the message, silent messages are still delivered to subscribed clients.
+    pub fn should_notify(&self) -> bool {
+        !self.has_flags(Self::FLAG_SILENT)
+    }
+}
+
+#[cfg(feature = "server")]
+impl Message {
+    /// Creates a new message sent now, the ID is generated according to [`crate::MESSAGE_ID_SCHEME`].
+    pub fn new(sender: ID, content: String, hub_id: ID, channel_id: ID, flags: u32) -> Self {
+        let links = Self::links(&content);
+        Self {
+            sender,
+            content,
+            channel_id,
+            hub_id,
+            created: Utc::now(),
+            id: new_id(),
+            flags,
+            expires_at: None,
+            forwarded_from: None,
+            links,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Gets the links to store for the given content, the first [`crate::MAX_MESSAGE_LINKS`] found by [`Message::find_links`].
+    fn links(content: &str) -> Vec<String> {
+        Self::find_links(content)
+            .take(crate::MAX_MESSAGE_LINKS)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::test::*;
+
+    pub fn test_channel(hub: ID) -> Channel {
+        let channel = Channel {
+            id: *CHANNEL_ID,
+            hub_id: hub,
+            description: "test channel description".to_string(),
+            name: "test".to_string(),
+            created: utc(0),
+            parent: None,
+            last_activity: utc(0),
+            created_by: *USER_ID,
+            updated_by: None,
+            updated: None,
+        };
+        std::fs::create_dir_all(channel.get_folder())
+            .expect("failed to create the channel directory");
+        channel
+    }
+
+    pub fn test_message(hub: ID) -> Message {
+        Message {
+            sender: *USER_ID,
+            content: "test message".to_string(),
+            hub_id: hub,
+            channel_id: *CHANNEL_ID,
+            created: utc(0),
+            id: *MESSAGE_ID,
+            flags: 0,
+            expires_at: None,
+            forwarded_from: None,
+            links: Vec::new(),
+            edits: Vec::new(),
+        }
+    }
+
+    /// Encodes a message the way messages were written before framing was added.
+    fn legacy_record(message: &Message) -> Vec<u8> {
+        bincode::serialize(&(
+            message.id,
+            message.hub_id,
+            message.channel_id,
+            message.sender,
+            message.created,
+            &message.content,
+        ))
+        .unwrap()
+    }
+
+    pub async fn add_test_messages(hub: ID) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for i in 0..100u128 {
+            let message = Message {
+                sender: *USER_ID,
+                content: "test message".to_string(),
+                hub_id: hub,
+                channel_id: *CHANNEL_ID,
+                created: utc(i as i64 + 86350),
+                id: ID::from_u128(i),
+                flags: 0,
+                expires_at: None,
+                forwarded_from: None,
+                links: Vec::new(),
+                edits: Vec::new(),
+            };
+            Channel::write_message(&message)
+                .await
+                .expect("failed to add a message");
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn message_links() {
+        let message = Message::new(
+            *USER_ID,
+            "read http://example.com, and (https://example.org/page) first".to_string(),
+            new_id(),
+            *CHANNEL_ID,
+            0,
+        );
+        assert_eq!(
+            vec![
+                "http://example.com".to_string(),
+                "https://example.org/page".to_string()
+            ],
+            message.links
+        );
+        let message = Message::new(
+            *USER_ID,
+            "nothing to see at example.com".to_string(),
+            new_id(),
+            *CHANNEL_ID,
+            0,
+        );
+        assert!(message.links.is_empty());
+    }
+
+    #[tokio::test]
+    async fn add_get_message() {
+        let channel = test_channel(new_id());
+        let message = test_message(channel.hub_id);
         channel
             .add_message(&message)
             .await
@@ -493,6 +1841,250 @@ pub(crate) mod test {
         );
     }
 
+    #[tokio::test]
+    async fn compact() {
+        let channel = test_channel(new_id());
+        let messages = add_test_messages(channel.hub_id).await;
+        channel
+            .add_message(&messages[3])
+            .await
+            .expect("failed to add a duplicate message");
+        let folder = channel.get_folder();
+        std::fs::write(format!("{}/5", folder), []).unwrap();
+        std::fs::write(format!("{}/7", folder), [1, 2, 3]).unwrap();
+        let report = channel.compact().await.expect("failed to compact");
+        assert_eq!(
+            CompactionReport {
+                files_before: 4,
+                files_after: 2,
+                messages: messages.len(),
+            },
+            report
+        );
+        assert_eq!(2, std::fs::read_dir(&folder).unwrap().count());
+        let first = messages.first().unwrap().created;
+        let last = messages.last().unwrap().created;
+        assert_eq!(
+            messages,
+            channel.get_messages_between(first, last, false, 200).await
+        );
+        assert_eq!(
+            CompactionReport {
+                files_before: 2,
+                ..report
+            },
+            channel.compact().await.expect("failed to compact again")
+        );
+    }
+
+    #[tokio::test]
+    async fn count_messages() {
+        let channel = test_channel(new_id());
+        assert_eq!(0, channel.count_messages().await);
+        let messages = add_test_messages(channel.hub_id).await;
+        assert_eq!(messages.len(), channel.count_messages().await);
+    }
+
+    #[tokio::test]
+    async fn skip_corrupt_records() {
+        let channel = test_channel(new_id());
+        let messages = add_test_messages(channel.hub_id).await;
+        let path = format!("{}/0", channel.get_folder());
+        let mut bytes = Vec::new();
+        for (i, message) in messages
+            .iter()
+            .filter(|m| m.created.timestamp() < 86400)
+            .enumerate()
+        {
+            bytes.extend(legacy_record(message));
+            if i == 0 {
+                bytes.extend_from_slice(&[0xff; 7]);
+            }
+        }
+        std::fs::write(&path, bytes).unwrap();
+        let before = corrupt_records();
+        let first = messages.first().unwrap().created;
+        let last = messages.last().unwrap().created;
+        assert_eq!(
+            messages,
+            channel.get_messages_between(first, last, false, 200).await
+        );
+        assert_eq!(messages.len(), channel.count_messages().await);
+        assert!(corrupt_records() >= before + 2);
+        channel.compact().await.expect("failed to compact");
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .starts_with(MESSAGE_FILE_HEADER));
+        assert_eq!(
+            messages,
+            channel.get_messages_between(first, last, false, 200).await
+        );
+    }
+
+    #[tokio::test]
+    async fn legacy_message_files() {
+        let channel = test_channel(new_id());
+        let mut messages = (0..3u128)
+            .map(|i| {
+                let mut message = test_message(channel.hub_id);
+                message.id = ID::from_u128(i);
+                message.created = utc(i as i64);
+                message.content = format!("see https://example.com/{}", i);
+                message.links = vec![format!("https://example.com/{}", i)];
+                message
+            })
+            .collect::<Vec<_>>();
+        let path = format!("{}/0", channel.get_folder());
+        std::fs::write(
+            &path,
+            messages.iter().flat_map(legacy_record).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(
+            messages,
+            channel
+                .get_messages_between(utc(0), utc(10), false, 10)
+                .await
+        );
+        let mut message = test_message(channel.hub_id);
+        message.id = ID::from_u128(3);
+        message.created = utc(5);
+        message.flags = Message::FLAG_SILENT;
+        channel
+            .add_message(&message)
+            .await
+            .expect("failed to add a message to a legacy file");
+        messages.push(message);
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .starts_with(MESSAGE_FILE_HEADER));
+        assert_eq!(
+            messages,
+            channel
+                .get_messages_between(utc(0), utc(10), false, 10)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn edit_message() {
+        let channel = test_channel(new_id());
+        let messages = add_test_messages(channel.hub_id).await;
+        let path = format!("{}/0", channel.get_folder());
+        let bytes = std::fs::read(&path).unwrap();
+        let edited = channel
+            .edit_message(
+                messages[0].id,
+                "see https://example.com".to_string(),
+                *OTHER_USER_ID,
+            )
+            .await
+            .expect("failed to edit the message");
+        assert_eq!(bytes, std::fs::read(&path).unwrap());
+        assert_eq!("see https://example.com", edited.content);
+        assert_eq!(vec!["https://example.com".to_string()], edited.links);
+        assert_eq!(
+            vec![MessageEdit {
+                content: "test message".to_string(),
+                time: edited.edits[0].time,
+                editor: *OTHER_USER_ID,
+            }],
+            edited.edits
+        );
+        assert_eq!(
+            Some(&edited),
+            channel.get_message(messages[0].id).await.as_ref()
+        );
+        assert_eq!(
+            vec![edited],
+            channel.get_messages_page(0, None, 1).await.messages
+        );
+        assert_eq!(messages.len(), channel.count_messages().await);
+        channel
+            .delete_message(messages[0].id)
+            .await
+            .expect("failed to delete the edited message");
+        channel.compact().await.expect("failed to compact");
+        assert!(!Path::new(&channel.edits_path()).exists());
+    }
+
+    #[tokio::test]
+    async fn record_versions() {
+        let channel = test_channel(new_id());
+        let message = test_message(channel.hub_id);
+        let mut unknown = message.clone();
+        unknown.id = new_id();
+        let mut encoded = encode_record(&unknown).unwrap();
+        encoded[8] = Message::VERSION + 1;
+        let sum = checksum(&encoded[8..]);
+        encoded[4..8].copy_from_slice(&sum.to_le_bytes());
+        let mut bytes = MESSAGE_FILE_HEADER.to_vec();
+        bytes.extend(encoded);
+        bytes.extend(encode_record(&message).unwrap());
+        std::fs::write(format!("{}/0", channel.get_folder()), bytes).unwrap();
+        let before = corrupt_records();
+        assert_eq!(vec![message], channel.get_last_messages(10).await);
+        assert!(corrupt_records() > before);
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes() {
+        let channel = test_channel(new_id());
+        let written: Vec<Message> = (0..50u128)
+            .map(|i| {
+                let mut message = test_message(channel.hub_id);
+                message.id = ID::from_u128(i);
+                message.content = format!("message {}", i);
+                message
+            })
+            .collect();
+        let writes = written.iter().map(|message| {
+            let channel = channel.clone();
+            async move { channel.add_message(message).await }
+        });
+        for result in futures::future::join_all(writes).await {
+            result.expect("failed to add a message");
+        }
+        assert_eq!(50, channel.count_messages().await);
+        let mut read = channel.get_last_messages(100).await;
+        read.sort_by_key(|message| message.id);
+        assert_eq!(written, read);
+    }
+
+    #[tokio::test]
+    async fn truncated_tail() {
+        let channel = test_channel(new_id());
+        let mut messages = add_test_messages(channel.hub_id).await;
+        let path = format!("{}/1", channel.get_folder());
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(MESSAGE_FILE_HEADER));
+        std::fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        messages.pop();
+        let first = messages.first().unwrap().created;
+        let before = corrupt_records();
+        assert_eq!(
+            messages,
+            channel
+                .get_messages_between(first, utc(86500), false, 200)
+                .await
+        );
+        assert!(corrupt_records() > before);
+        let mut message = test_message(channel.hub_id);
+        message.id = new_id();
+        message.created = utc(86450);
+        channel
+            .add_message(&message)
+            .await
+            .expect("failed to add a message after the truncated one");
+        messages.push(message);
+        assert_eq!(
+            messages,
+            channel
+                .get_messages_between(first, utc(86500), false, 200)
+                .await
+        );
+    }
+
     #[tokio::test]
     async fn get_messages_after() {
         let channel = test_channel(new_id());
@@ -512,6 +2104,73 @@ pub(crate) mod test {
         );
     }
 
+    #[tokio::test]
+    async fn thread_file_versions() {
+        let hub_id = new_id();
+        let mut thread = Thread::new(
+            hub_id,
+            *CHANNEL_ID,
+            new_id(),
+            "thread".to_string(),
+            *USER_ID,
+        );
+        thread.save().await.unwrap();
+        let path = format!("{}/info", thread_folder(&hub_id, &CHANNEL_ID, &thread.id));
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .starts_with(THREAD_FILE_HEADER));
+        let reply = Message::new(*USER_ID, "reply".to_string(), hub_id, thread.id, 0);
+        thread.record_reply(reply).await.unwrap();
+        assert_eq!(
+            thread,
+            Thread::load(hub_id, *CHANNEL_ID, thread.id).await.unwrap()
+        );
+        std::fs::write(&path, bincode::serialize(&thread).unwrap()).unwrap();
+        assert_eq!(
+            thread,
+            Thread::load(hub_id, *CHANNEL_ID, thread.id).await.unwrap()
+        );
+        let without_replies = (
+            thread.id,
+            thread.hub_id,
+            thread.channel_id,
+            thread.message_id,
+            &thread.name,
+            thread.creator,
+            thread.created,
+        );
+        std::fs::write(&path, bincode::serialize(&without_replies).unwrap()).unwrap();
+        let loaded = Thread::load(hub_id, *CHANNEL_ID, thread.id).await.unwrap();
+        assert_eq!(thread.name, loaded.name);
+        assert_eq!(0, loaded.reply_count);
+        assert_eq!(None, loaded.last_reply);
+    }
+
+    #[tokio::test]
+    async fn get_messages_page() {
+        let channel = test_channel(new_id());
+        let messages = add_test_messages(channel.hub_id).await;
+        let mut paged = Vec::new();
+        let mut page = channel.get_messages_page(0, None, 30).await;
+        while let Some(cursor) = page.next {
+            paged.append(&mut page.messages);
+            let new = Message::new(
+                *USER_ID,
+                "new message".to_string(),
+                channel.hub_id,
+                channel.id,
+                0,
+            );
+            channel.add_message(&new).await.unwrap();
+            page = channel
+                .get_messages_page(cursor.position, Some(cursor.as_of), 30)
+                .await;
+        }
+        paged.append(&mut page.messages);
+        assert_eq!(messages, paged);
+        assert_eq!(None, channel.get_messages_page(0, Some(0), 30).await.next);
+    }
+
     #[tokio::test]
     async fn get_messages_before() {
         let channel = test_channel(new_id());
@@ -1,4 +1,28 @@
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::RwLock};
+
+use crate::{
+    server::{OverflowPolicy, ScheduledTask},
+    ID,
+};
+
+lazy_static! {
+    static ref SERVER_ADMINS: RwLock<HashSet<ID>> = RwLock::new(HashSet::new());
+}
+
+/// Replaces the set of users that are server admins, see [`Config::server_admins`].
+pub fn set_server_admins(admins: impl IntoIterator<Item = ID>) {
+    *SERVER_ADMINS.write().expect("server admin lock poisoned") = admins.into_iter().collect();
+}
+
+/// Checks if a user is a server admin, server admins can use the administration endpoints and perform administrative actions in any hub.
+pub fn is_server_admin(user_id: &ID) -> bool {
+    SERVER_ADMINS
+        .read()
+        .expect("server admin lock poisoned")
+        .contains(user_id)
+}
 
 /// Configuration object for WICRS Server.
 #[derive(Serialize, Deserialize, Clone)]
@@ -7,6 +31,173 @@ pub struct Config {
     pub address: String,
     /// Base path for requests (for use with proxy `host:port/base_path`)
     pub base_path: String,
+    /// URL to which notifications for offline users are posted, see [`crate::notification::WebhookSink`].
+    #[serde(default)]
+    pub notification_webhook: Option<String>,
+    /// Member limit given to newly created hubs, no limit if not set.
+    #[serde(default)]
+    pub default_max_members: Option<u32>,
+    /// Maximum size of hub and channel descriptions in bytes, defaults to [`crate::MAX_DESCRIPTION_SIZE`].
+    #[serde(default = "default_max_description_size")]
+    pub max_description_size: usize,
+    /// Maximum size of a user status in bytes, defaults to [`crate::MAX_STATUS_SIZE`].
+    #[serde(default = "default_max_status_size")]
+    pub max_status_size: usize,
+    /// Start the server in maintenance mode, see [`crate::set_maintenance_mode`].
+    #[serde(default)]
+    pub maintenance_mode: bool,
+    /// IDs of the users allowed to use the server administration endpoints and perform administrative actions in any hub.
+    #[serde(default)]
+    pub server_admins: Vec<ID>,
+    /// Number of actors search indexing is split between, see [`crate::server::Server::with_message_servers`].
+    #[serde(default = "default_message_servers")]
+    pub message_servers: usize,
+    /// Number of messages that can wait to be indexed by each message server, defaults to [`crate::MAX_INDEX_QUEUE`], see [`crate::server::IndexQueue`].
+    #[serde(default = "default_index_queue_limit")]
+    pub index_queue_limit: usize,
+    /// Whether or not messages are indexed and can be searched, search indexes are not loaded at all if disabled.
+    #[serde(default = "default_search")]
+    pub search: bool,
+    /// Hub and channel IDs of the channels whose search indexes are loaded in the background on startup so that their first search is fast.
+    #[serde(default)]
+    pub warmup_channels: Vec<(ID, ID)>,
+    /// How long senders can delete their own messages for in milliseconds, defaults to [`crate::MESSAGE_DELETE_GRACE_PERIOD`].
+    #[serde(default = "default_delete_grace_period")]
+    pub delete_grace_period: i64,
+    /// Intervals of the server's periodic tasks.
+    #[serde(default)]
+    pub scheduled_tasks: ScheduledTasks,
+    /// Require the `authorization` header for the server information endpoint so that the server's version is not public.
+    #[serde(default)]
+    pub private_server_info: bool,
+    /// Maximum depth of GraphQL queries, 0 for no limit, defaults to [`crate::GRAPHQL_MAX_DEPTH`].
+    #[serde(default = "default_graphql_max_depth")]
+    pub graphql_max_depth: usize,
+    /// Maximum complexity of GraphQL queries, 0 for no limit, defaults to [`crate::GRAPHQL_MAX_COMPLEXITY`].
+    #[serde(default = "default_graphql_max_complexity")]
+    pub graphql_max_complexity: usize,
+    /// Maximum size of the websocket frames sent by clients in bytes, defaults to [`crate::WS_MAX_FRAME_SIZE`].
+    #[serde(default = "default_ws_max_frame_size")]
+    pub ws_max_frame_size: usize,
+    /// Number of frames that can wait to be sent to each websocket connection, defaults to [`crate::WS_SEND_QUEUE_SIZE`].
+    #[serde(default = "default_ws_send_queue_size")]
+    pub ws_send_queue_size: usize,
+    /// What happens when a websocket connection's send queue is full, drops the oldest frame by default.
+    #[serde(default)]
+    pub ws_overflow_policy: OverflowPolicy,
+    /// Number of hubs kept in memory so that they do not have to be read from disk for every request, defaults to [`crate::HUB_CACHE_SIZE`].
+    #[serde(default = "default_hub_cache_size")]
+    pub hub_cache_size: usize,
+    /// How long messages sent to the same channel are collected for before being written together in milliseconds, 0 to write every message on its own, see [`crate::server::WriteBatcher`].
+    #[serde(default)]
+    pub write_batch_interval: u64,
+    /// Number of collected messages at which they are written without waiting for the interval to end, defaults to [`crate::WRITE_BATCH_SIZE`].
+    #[serde(default = "default_write_batch_size")]
+    pub write_batch_size: usize,
+}
+
+/// Intervals in milliseconds at which the server runs its periodic tasks, 0 to never run a task.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduledTasks {
+    /// How often users who did not say they stopped typing are checked for, defaults to [`crate::TYPING_EXPIRY_INTERVAL`].
+    #[serde(default = "default_typing_expiry")]
+    pub typing_expiry: u64,
+    /// How often expired messages are deleted, defaults to [`crate::EXPIRY_SWEEP_INTERVAL`].
+    #[serde(default = "default_message_expiry")]
+    pub message_expiry: u64,
+    /// How often timed mutes and bans are checked for expiry, defaults to [`crate::MODERATION_EXPIRY_INTERVAL`].
+    #[serde(default = "default_moderation_expiry")]
+    pub moderation_expiry: u64,
+    /// How often the idempotency keys of hub creations are checked for expiry, defaults to [`crate::CREATE_KEY_EXPIRY_INTERVAL`].
+    #[serde(default = "default_create_key_expiry")]
+    pub create_key_expiry: u64,
+}
+
+impl ScheduledTasks {
+    /// Gets every task along with its interval.
+    pub fn intervals(&self) -> Vec<(ScheduledTask, u64)> {
+        vec![
+            (ScheduledTask::TypingExpiry, self.typing_expiry),
+            (ScheduledTask::MessageExpiry, self.message_expiry),
+            (ScheduledTask::ModerationExpiry, self.moderation_expiry),
+            (ScheduledTask::CreateKeyExpiry, self.create_key_expiry),
+        ]
+    }
+}
+
+impl Default for ScheduledTasks {
+    fn default() -> Self {
+        Self {
+            typing_expiry: crate::TYPING_EXPIRY_INTERVAL,
+            message_expiry: crate::EXPIRY_SWEEP_INTERVAL,
+            moderation_expiry: crate::MODERATION_EXPIRY_INTERVAL,
+            create_key_expiry: crate::CREATE_KEY_EXPIRY_INTERVAL,
+        }
+    }
+}
+
+fn default_max_description_size() -> usize {
+    crate::MAX_DESCRIPTION_SIZE
+}
+
+fn default_max_status_size() -> usize {
+    crate::MAX_STATUS_SIZE
+}
+
+fn default_message_servers() -> usize {
+    1
+}
+
+fn default_index_queue_limit() -> usize {
+    crate::MAX_INDEX_QUEUE
+}
+
+fn default_search() -> bool {
+    true
+}
+
+fn default_delete_grace_period() -> i64 {
+    crate::MESSAGE_DELETE_GRACE_PERIOD
+}
+
+fn default_typing_expiry() -> u64 {
+    crate::TYPING_EXPIRY_INTERVAL
+}
+
+fn default_message_expiry() -> u64 {
+    crate::EXPIRY_SWEEP_INTERVAL
+}
+
+fn default_moderation_expiry() -> u64 {
+    crate::MODERATION_EXPIRY_INTERVAL
+}
+
+fn default_create_key_expiry() -> u64 {
+    crate::CREATE_KEY_EXPIRY_INTERVAL
+}
+
+fn default_graphql_max_depth() -> usize {
+    crate::GRAPHQL_MAX_DEPTH
+}
+
+fn default_graphql_max_complexity() -> usize {
+    crate::GRAPHQL_MAX_COMPLEXITY
+}
+
+fn default_ws_max_frame_size() -> usize {
+    crate::WS_MAX_FRAME_SIZE
+}
+
+fn default_ws_send_queue_size() -> usize {
+    crate::WS_SEND_QUEUE_SIZE
+}
+
+fn default_hub_cache_size() -> usize {
+    crate::HUB_CACHE_SIZE
+}
+
+fn default_write_batch_size() -> usize {
+    crate::WRITE_BATCH_SIZE
 }
 
 impl Default for Config {
@@ -14,6 +205,27 @@ impl Default for Config {
         Self {
             address: "127.0.0.1:8080".to_string(),
             base_path: "/".to_string(),
+            notification_webhook: None,
+            default_max_members: None,
+            max_description_size: crate::MAX_DESCRIPTION_SIZE,
+            max_status_size: crate::MAX_STATUS_SIZE,
+            maintenance_mode: false,
+            server_admins: Vec::new(),
+            message_servers: default_message_servers(),
+            index_queue_limit: crate::MAX_INDEX_QUEUE,
+            search: true,
+            warmup_channels: Vec::new(),
+            delete_grace_period: crate::MESSAGE_DELETE_GRACE_PERIOD,
+            scheduled_tasks: ScheduledTasks::default(),
+            private_server_info: false,
+            graphql_max_depth: crate::GRAPHQL_MAX_DEPTH,
+            graphql_max_complexity: crate::GRAPHQL_MAX_COMPLEXITY,
+            ws_max_frame_size: crate::WS_MAX_FRAME_SIZE,
+            ws_send_queue_size: crate::WS_SEND_QUEUE_SIZE,
+            ws_overflow_policy: OverflowPolicy::default(),
+            hub_cache_size: crate::HUB_CACHE_SIZE,
+            write_batch_interval: 0,
+            write_batch_size: crate::WRITE_BATCH_SIZE,
         }
     }
 }
@@ -38,3 +250,25 @@ pub fn load_config(path: &str) -> Config {
     }
     config
 }
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::{error::ApiError, prelude::check_description_size};
+
+    #[test]
+    fn lowered_description_limit() {
+        let description = "a".repeat(64);
+        let default = Config::default();
+        assert!(check_description_size(&description, default.max_description_size).is_ok());
+        let config: Config = serde_json::from_str(
+            r#"{"address": "127.0.0.1:8080", "base_path": "/", "max_description_size": 32}"#,
+        )
+        .unwrap();
+        assert_eq!(crate::MAX_STATUS_SIZE, config.max_status_size);
+        assert_eq!(
+            Err(ApiError::TooBig),
+            check_description_size(&description, config.max_description_size)
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{hub::Hub, ID};
+
+/// Public information about a hub that chose to be listed in the directory.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DiscoverableHub {
+    pub id: ID,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+    pub member_count: usize,
+}
+
+impl From<&Hub> for DiscoverableHub {
+    fn from(hub: &Hub) -> Self {
+        Self {
+            id: hub.id,
+            name: hub.name.clone(),
+            description: hub.description.clone(),
+            tags: hub.tags.clone(),
+            category: hub.category.clone(),
+            member_count: hub.members.len(),
+        }
+    }
+}
+
+lazy_static! {
+    /// Discoverable hubs, built from the saved hubs the first time it is needed and kept up to date as hubs are saved and deleted.
+    static ref INDEX: RwLock<Option<HashMap<ID, DiscoverableHub>>> = RwLock::new(None);
+}
+
+/// Adds, updates or removes a hub in the directory depending on whether or not it is discoverable, called whenever a hub is saved.
+pub async fn update(hub: &Hub) {
+    if let Some(index) = INDEX.write().await.as_mut() {
+        if hub.discoverable {
+            index.insert(hub.id, hub.into());
+        } else {
+            index.remove(&hub.id);
+        }
+    }
+}
+
+/// Removes a hub from the directory, called when a hub is deleted.
+pub async fn remove(hub_id: &ID) {
+    if let Some(index) = INDEX.write().await.as_mut() {
+        index.remove(hub_id);
+    }
+}
+
+/// Searches the directory for hubs whose name or description contains `query` and that have the given tag and category, ignoring case, ordered by member count.
+pub async fn search(
+    query: &str,
+    tag: Option<&str>,
+    category: Option<&str>,
+    limit: usize,
+) -> Vec<DiscoverableHub> {
+    {
+        let mut index = INDEX.write().await;
+        if index.is_none() {
+            let mut built = HashMap::new();
+            for id in Hub::list_ids().await {
+                if let Ok(hub) = Hub::load(id).await {
+                    if hub.discoverable {
+                        built.insert(id, DiscoverableHub::from(&hub));
+                    }
+                }
+            }
+            *index = Some(built);
+        }
+    }
+    let query = query.to_lowercase();
+    let tag = tag.map(str::to_lowercase);
+    let category = category.map(str::to_lowercase);
+    let mut result = INDEX.read().await.as_ref().map_or_else(Vec::new, |index| {
+        index
+            .values()
+            .filter(|hub| {
+                hub.name.to_lowercase().contains(&query)
+                    || hub.description.to_lowercase().contains(&query)
+            })
+            .filter(|hub| {
+                tag.as_ref().map_or(true, |tag| {
+                    hub.tags.iter().any(|t| &t.to_lowercase() == tag)
+                })
+            })
+            .filter(|hub| {
+                category.as_ref().map_or(true, |category| {
+                    hub.category.as_ref().map(|c| c.to_lowercase()).as_ref() == Some(category)
+                })
+            })
+            .cloned()
+            .collect::<Vec<DiscoverableHub>>()
+    });
+    result.sort_by(|a, b| b.member_count.cmp(&a.member_count).then(a.id.cmp(&b.id)));
+    result.truncate(limit);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::HubCache;
+    use crate::test::*;
+
+    #[tokio::test]
+    async fn discoverable_only() {
+        let name = crate::new_id().to_string();
+        let mut public = test_hub();
+        public.name = name.clone();
+        public.discoverable = true;
+        public.tags = vec!["games".to_string()];
+        public.category = Some("Gaming".to_string());
+        public.save(&HubCache::new(0)).await.unwrap();
+        let mut private = test_hub();
+        private.name = name.clone();
+        private.save(&HubCache::new(0)).await.unwrap();
+        let found = search(&name, None, None, 10).await;
+        assert_eq!(vec![DiscoverableHub::from(&public)], found);
+        assert_eq!(
+            found,
+            search(&name, Some("GAMES"), Some("gaming"), 10).await
+        );
+        assert!(search(&name, Some("music"), None, 10).await.is_empty());
+        assert!(search(&name, None, Some("music"), 10).await.is_empty());
+        public.discoverable = false;
+        public.save(&HubCache::new(0)).await.unwrap();
+        assert!(search(&name, None, None, 10).await.is_empty());
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ID;
+
+#[cfg(feature = "server")]
+use tokio::io::AsyncWriteExt;
+
+#[cfg(feature = "server")]
+use crate::{
+    channel::{Channel, Message},
+    check_name_validity,
+    error::{ApiError, ApiResult, Result},
+    new_id,
+};
+
+/// Relative path of the folder in which direct message channels are stored (info and message files).
+#[cfg(not(test))]
+pub const DM_DATA_FOLDER: &str = "data/dms/";
+#[cfg(test)]
+pub const DM_DATA_FOLDER: &str = "test_data/dms/";
+
+/// Namespace used to derive the ID of a direct message channel from the IDs of its participants.
+#[cfg(feature = "server")]
+const DM_NAMESPACE: ID = ID::from_u128(0x6d2b_0b9e_4f3a_4c5e_9a1d_2e6f_7c8b_1a90);
+
+/// Private channel between two users that is not part of a hub. Messages sent in it have a nil `hub_id`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DmChannel {
+    /// ID of the channel, derived from the IDs of the participants.
+    pub id: ID,
+    /// IDs of the two participants, the lowest ID first.
+    pub users: (ID, ID),
+}
+
+#[cfg(feature = "server")]
+impl DmChannel {
+    /// Creates the direct message channel object for the given pair of users, the order of the users does not matter.
+    pub fn new(user_a: ID, user_b: ID) -> Self {
+        let users = if user_a <= user_b {
+            (user_a, user_b)
+        } else {
+            (user_b, user_a)
+        };
+        let mut name = users.0.as_bytes().to_vec();
+        name.extend_from_slice(users.1.as_bytes());
+        Self {
+            id: ID::new_v5(&DM_NAMESPACE, &name),
+            users,
+        }
+    }
+
+    /// Get the path of the channel's data folder, used for storing the info file and message files.
+    pub fn get_folder(&self) -> String {
+        dm_folder(&self.id)
+    }
+
+    /// Gets the channel used to store and read the channel's messages.
+    pub fn channel(&self) -> Channel {
+        Channel::new(String::new(), self.id, ID::nil())
+    }
+
+    /// Checks if the given user is one of the participants.
+    pub fn is_participant(&self, user_id: &ID) -> bool {
+        &self.users.0 == user_id || &self.users.1 == user_id
+    }
+
+    /// Returns an error if the given user is not one of the participants.
+    pub fn check_participant(&self, user_id: &ID) -> ApiResult {
+        if self.is_participant(user_id) {
+            Ok(())
+        } else {
+            Err(ApiError::NotParticipant)
+        }
+    }
+
+    /// Loads the direct message channel between the given users, creating it if it does not exist yet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The data folder for the channel could not be created.
+    /// * The info file could not be written.
+    pub async fn open(user_a: ID, user_b: ID) -> Result<Self> {
+        let dm = Self::new(user_a, user_b);
+        if let Ok(existing) = Self::load(dm.id).await {
+            return Ok(existing);
+        }
+        dm.channel().create_dir().await?;
+        let mut file = tokio::fs::File::create(info_path(&dm.id)).await?;
+        file.write_all(&bincode::serialize(&dm)?).await?;
+        file.flush().await?;
+        Ok(dm)
+    }
+
+    /// Loads a direct message channel from its info file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The channel does not exist.
+    /// * The info file could not be read or deserialized.
+    pub async fn load(id: ID) -> Result<Self> {
+        let path = info_path(&id);
+        if !std::path::Path::new(&path).is_file() {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(bincode::deserialize(&tokio::fs::read(path).await?)?)
+    }
+
+    /// Sends a message in the channel, the sender must be one of the participants.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The sender is not one of the participants.
+    /// * The flags are not valid, see [`Message::KNOWN_FLAGS`].
+    /// * The message could not be written.
+    pub async fn send_message(
+        &self,
+        sender: ID,
+        content: String,
+        flags: u32,
+    ) -> ApiResult<Message> {
+        self.check_participant(&sender)?;
+        write_message(self.id, sender, content, flags).await
+    }
+}
+
+/// Private conversation between a small group of users that is not part of a hub. Messages sent in it have a nil `hub_id`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GroupDm {
+    /// ID of the group.
+    pub id: ID,
+    /// IDs of the current members, limited to [`crate::MAX_GROUP_DM_MEMBERS`].
+    pub members: HashSet<ID>,
+    /// Name of the group.
+    pub name: String,
+}
+
+#[cfg(feature = "server")]
+impl GroupDm {
+    /// Creates a new group object, the creator is always added to the members.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The name fails to pass [`check_name_validity`].
+    /// * There are more than [`crate::MAX_GROUP_DM_MEMBERS`] members.
+    pub fn new(name: String, creator: ID, mut members: HashSet<ID>) -> ApiResult<Self> {
+        check_name_validity(&name)?;
+        members.insert(creator);
+        if members.len() > crate::MAX_GROUP_DM_MEMBERS {
+            return Err(ApiError::TooManyMembers);
+        }
+        Ok(Self {
+            id: new_id(),
+            members,
+            name,
+        })
+    }
+
+    /// Creates a new group and its data folder, see [`GroupDm::new`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The group is not valid for any of the reasons outlined by [`GroupDm::new`].
+    /// * The data folder for the group could not be created.
+    /// * The group could not be saved for any of the reasons outlined by [`GroupDm::save`].
+    pub async fn create(name: String, creator: ID, members: HashSet<ID>) -> Result<Self> {
+        let group = Self::new(name, creator, members)?;
+        group.channel().create_dir().await?;
+        group.save().await?;
+        Ok(group)
+    }
+
+    /// Saves the group's info file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the group could not be serialized or the info file could not be written.
+    pub async fn save(&self) -> Result {
+        let mut file = tokio::fs::File::create(group_info_path(&self.id)).await?;
+        file.write_all(&bincode::serialize(self)?).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Loads a group from its info file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The group does not exist.
+    /// * The info file could not be read or deserialized.
+    pub async fn load(id: ID) -> Result<Self> {
+        let path = group_info_path(&id);
+        if !std::path::Path::new(&path).is_file() {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(bincode::deserialize(&tokio::fs::read(path).await?)?)
+    }
+
+    /// Gets the channel used to store and read the group's messages.
+    pub fn channel(&self) -> Channel {
+        Channel::new(self.name.clone(), self.id, ID::nil())
+    }
+
+    /// Returns an error if the given user is not a member of the group.
+    pub fn check_member(&self, user_id: &ID) -> ApiResult {
+        if self.members.contains(user_id) {
+            Ok(())
+        } else {
+            Err(ApiError::NotParticipant)
+        }
+    }
+
+    /// Adds a user to the group, only members can add users.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user adding the new member is not a member.
+    /// * The group already has [`crate::MAX_GROUP_DM_MEMBERS`] members.
+    pub fn add_member(&mut self, actor_id: &ID, user_id: ID) -> ApiResult {
+        self.check_member(actor_id)?;
+        if !self.members.contains(&user_id) && self.members.len() >= crate::MAX_GROUP_DM_MEMBERS {
+            return Err(ApiError::TooManyMembers);
+        }
+        self.members.insert(user_id);
+        Ok(())
+    }
+
+    /// Removes a user from the group, only members can remove users, members can remove themselves to leave.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user removing the member is not a member.
+    /// * The user to remove is not a member.
+    pub fn remove_member(&mut self, actor_id: &ID, user_id: &ID) -> ApiResult {
+        self.check_member(actor_id)?;
+        if self.members.remove(user_id) {
+            Ok(())
+        } else {
+            Err(ApiError::NotParticipant)
+        }
+    }
+
+    /// Sends a message in the group, the sender must be a member.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The sender is not a member.
+    /// * The flags are not valid, see [`Message::KNOWN_FLAGS`].
+    /// * The message could not be written.
+    pub async fn send_message(
+        &self,
+        sender: ID,
+        content: String,
+        flags: u32,
+    ) -> ApiResult<Message> {
+        self.check_member(&sender)?;
+        write_message(self.id, sender, content, flags).await
+    }
+}
+
+/// Writes a message to the direct message or group channel with the given ID.
+#[cfg(feature = "server")]
+async fn write_message(
+    channel_id: ID,
+    sender: ID,
+    content: String,
+    flags: u32,
+) -> ApiResult<Message> {
+    if flags & !Message::KNOWN_FLAGS != 0 {
+        return Err(ApiError::InvalidMessageFlags);
+    }
+    let message = Message::new(sender, content, ID::nil(), channel_id, flags);
+    Channel::write_message(&message)
+        .await
+        .map_err(|_| ApiError::InternalError)?;
+    Ok(message)
+}
+
+/// Get the path of the data folder of the direct message channel with the given ID.
+#[cfg(feature = "server")]
+pub fn dm_folder(id: &ID) -> String {
+    format!("{}{}", DM_DATA_FOLDER, id.to_string())
+}
+
+/// Get the path of the info file of the direct message channel with the given ID.
+#[cfg(feature = "server")]
+fn info_path(id: &ID) -> String {
+    format!("{}/info", dm_folder(id))
+}
+
+/// Get the path of the info file of the group with the given ID.
+#[cfg(feature = "server")]
+fn group_info_path(id: &ID) -> String {
+    format!("{}/group", dm_folder(id))
+}
+
+#[cfg(feature = "server")]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn pair_id() {
+        assert_eq!(
+            DmChannel::new(*USER_ID, *OTHER_USER_ID),
+            DmChannel::new(*OTHER_USER_ID, *USER_ID)
+        );
+        assert_ne!(
+            DmChannel::new(*USER_ID, *OTHER_USER_ID).id,
+            DmChannel::new(*USER_ID, *USER_ID).id
+        );
+    }
+
+    #[tokio::test]
+    async fn participants_only() {
+        let dm = DmChannel::open(*USER_ID, *OTHER_USER_ID).await.unwrap();
+        assert_eq!(dm, DmChannel::load(dm.id).await.unwrap());
+        let first = dm
+            .send_message(*USER_ID, "hello".to_string(), 0)
+            .await
+            .unwrap();
+        let second = dm
+            .send_message(*OTHER_USER_ID, "hi".to_string(), 0)
+            .await
+            .unwrap();
+        let messages = dm.channel().get_last_messages(2).await;
+        assert!(messages.contains(&first));
+        assert!(messages.contains(&second));
+        let third_user = ID::from_u128(3);
+        assert_eq!(
+            ApiError::NotParticipant,
+            dm.send_message(third_user, "hey".to_string(), 0)
+                .await
+                .unwrap_err()
+        );
+        assert_eq!(
+            ApiError::NotParticipant,
+            dm.check_participant(&third_user).unwrap_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn group_dm() {
+        let third_user = ID::from_u128(3);
+        let mut members = HashSet::new();
+        members.insert(*OTHER_USER_ID);
+        let mut group = GroupDm::create("test group".to_string(), *USER_ID, members)
+            .await
+            .unwrap();
+        assert_eq!(
+            ApiError::NotParticipant,
+            group
+                .send_message(third_user, "hey".to_string(), 0)
+                .await
+                .unwrap_err()
+        );
+        group.add_member(&OTHER_USER_ID, third_user).unwrap();
+        group.save().await.unwrap();
+        let group = GroupDm::load(group.id).await.unwrap();
+        assert_eq!(3, group.members.len());
+        let message = group
+            .send_message(third_user, "hey".to_string(), 0)
+            .await
+            .unwrap();
+        for member in group.members.iter() {
+            group.check_member(member).unwrap();
+        }
+        assert_eq!(vec![message], group.channel().get_last_messages(10).await);
+    }
+
+    #[test]
+    fn group_dm_cap() {
+        let members = (0..crate::MAX_GROUP_DM_MEMBERS as u128)
+            .map(ID::from_u128)
+            .collect();
+        assert_eq!(
+            ApiError::TooManyMembers,
+            GroupDm::new("test group".to_string(), *USER_ID, members).unwrap_err()
+        );
+        let mut group = GroupDm::new("test group".to_string(), *USER_ID, HashSet::new()).unwrap();
+        for i in 1..crate::MAX_GROUP_DM_MEMBERS as u128 {
+            group.add_member(&USER_ID, ID::from_u128(i)).unwrap();
+        }
+        assert_eq!(
+            ApiError::TooManyMembers,
+            group.add_member(&USER_ID, *OTHER_USER_ID).unwrap_err()
+        );
+        group.remove_member(&USER_ID, &ID::from_u128(1)).unwrap();
+        group.add_member(&USER_ID, *OTHER_USER_ID).unwrap();
+    }
+}
@@ -56,16 +56,38 @@ pub enum ApiError {
     Muted,
     #[error("user is banned from that hub")]
     Banned,
+    #[error("user has not been approved by the hub's administrators yet")]
+    PendingVerification,
+    #[error("search is disabled on this server")]
+    SearchDisabled,
+    #[error("user has not accepted the hub's rules yet")]
+    RulesNotAccepted,
     #[error("hub does not exist")]
     HubNotFound,
     #[error("channel does not exist")]
     ChannelNotFound,
+    #[error("thread does not exist")]
+    ThreadNotFound,
     #[error("user does not have the \"{permission}\" hub permission")]
     MissingHubPermission { permission: HubPermission },
     #[error("user does not have the \"{permission}\" channel permission")]
     MissingChannelPermission { permission: ChannelPermission },
     #[error("user is not in the hub")]
     NotInHub,
+    #[error("user is not a participant in that conversation")]
+    NotParticipant,
+    #[error("conversation has too many members")]
+    TooManyMembers,
+    #[error("hub has reached its member limit")]
+    HubFull,
+    #[error("user is already in the hub")]
+    AlreadyInHub,
+    #[error("invite does not exist")]
+    InviteNotFound,
+    #[error("invite has expired or reached its usage limit")]
+    InviteExpired,
+    #[error("resume token is invalid or has expired")]
+    InvalidResumeToken,
     #[error("member does not exist")]
     MemberNotFound,
     #[error("message does not exist")]
@@ -74,6 +96,8 @@ pub enum ApiError {
     GroupNotFound,
     #[error("invalid name")]
     InvalidName,
+    #[error("the authorization header is required")]
+    NotAuthenticated,
     #[error("not authenticated for websocket")]
     WsNotAuthenticated,
     #[error("text object to big")]
@@ -82,6 +106,8 @@ pub enum ApiError {
     InvalidTime,
     #[error("text must use UTF-8 encoding")]
     InvalidText,
+    #[error("unknown message flags")]
+    InvalidMessageFlags,
     #[error("user already typing")]
     AlreadyTyping,
     #[error("user not typing")]
@@ -96,6 +122,12 @@ pub enum ApiError {
     Json { message: String },
     #[error("cannot perform that operation on the hub owner")]
     IsOwner,
+    #[error("server is in maintenance mode, only reads are allowed")]
+    Maintenance,
+    #[error("user is not a server admin")]
+    NotServerAdmin,
+    #[error("tags must be lowercase letters, digits or dashes, and there can only be a limited number of them")]
+    InvalidTags,
     #[error("{message}")]
     Other { message: String },
 }
@@ -1,10 +1,12 @@
-use std::{collections::HashSet, sync::Arc};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::{
-    channel::Channel,
+    channel::{Channel, Message, Thread, ThreadSummary},
     hub::{Hub, HubMember, PermissionGroup},
     permission::{ChannelPermission, ChannelPermissionSet, HubPermission, HubPermissionSet},
-    server::Server,
+    server::{HubCache, Server},
+    user::BlockList,
     ID,
 };
 use async_graphql::*;
@@ -17,6 +19,8 @@ pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
+    // Only changes if the client authenticates as another user.
+    #[graphql(cache_control(max_age = 3600, private))]
     async fn requester<'a>(&self, ctx: &'a Context<'_>) -> &'a ID {
         ctx.data_unchecked::<ID>()
     }
@@ -26,7 +30,7 @@ impl QueryRoot {
         ctx: &Context<'_>,
         #[graphql(desc = "ID of a hub.")] id: ID,
     ) -> Result<Hub> {
-        let hub = Hub::load(id).await?;
+        let hub = Hub::load_cached(id, ctx.data_unchecked::<HubCache>()).await?;
         Ok(hub.strip(self.requester(ctx).await?)?)
     }
 
@@ -37,7 +41,7 @@ impl QueryRoot {
     ) -> Result<Vec<Hub>> {
         let mut result = Vec::new();
         for id in ids {
-            let hub = Hub::load(id).await?;
+            let hub = Hub::load_cached(id, ctx.data_unchecked::<HubCache>()).await?;
             result.push(hub.strip(self.requester(ctx).await?)?);
         }
         Ok(result)
@@ -62,15 +66,55 @@ impl Channel {
         &self.description
     }
 
+    async fn last_activity(&self) -> &DateTime<Utc> {
+        &self.last_activity
+    }
+
+    async fn created_by(&self) -> &ID {
+        &self.created_by
+    }
+
+    async fn updated_by(&self) -> Option<&ID> {
+        self.updated_by.as_ref()
+    }
+
+    async fn updated(&self) -> Option<&DateTime<Utc>> {
+        self.updated.as_ref()
+    }
+
+    #[graphql(complexity = "max as usize * child_complexity")]
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            desc = "Only get messages sent after this message, get the last messages if not set."
+        )]
+        after: Option<ID>,
+        #[graphql(desc = "Maximum number of messages to get.")] max: u8,
+    ) -> Result<Vec<Message>> {
+        self.read_messages(
+            ctx.data_unchecked::<ID>(),
+            ctx.data_unchecked::<HubCache>(),
+            after,
+            max as usize,
+        )
+        .await
+    }
+
+    #[graphql(complexity = "limit as usize + child_complexity")]
     async fn search_messages(
         &self,
         ctx: &Context<'_>,
         #[graphql(desc = "Query that messages should match.")] query: String,
         #[graphql(desc = "Maximum number of messages to get.")] limit: u8,
+        #[graphql(desc = "Only get messages sent by this user.")] sender: Option<ID>,
     ) -> Vec<ID> {
-        if let Ok(ms_addr) = ctx
+        if let Ok(Some(ms_addr)) = ctx
             .data_unchecked::<Arc<Addr<Server>>>()
-            .call(crate::server::GetMessageServer)
+            .call(crate::server::GetMessageServer {
+                hub_id: self.hub_id,
+                channel_id: self.id,
+            })
             .await
         {
             ms_addr
@@ -79,6 +123,7 @@ impl Channel {
                     channel_id: self.id,
                     limit: limit as usize,
                     query,
+                    sender,
                 })
                 .await
                 .map_or(Vec::new(), |r| r.unwrap_or_default())
@@ -88,6 +133,60 @@ impl Channel {
     }
 }
 
+impl Channel {
+    /// Gets messages from the channel if the user can read it, oldest first, leaving out messages sent by users that the user has blocked.
+    async fn read_messages(
+        &self,
+        user_id: &ID,
+        cache: &HubCache,
+        after: Option<ID>,
+        max: usize,
+    ) -> Result<Vec<Message>> {
+        Hub::load_cached(self.hub_id, cache)
+            .await?
+            .get_channel(user_id, self.id)?;
+        let messages = if let Some(after) = after {
+            self.get_messages_after(after, max).await
+        } else {
+            self.get_last_messages(max).await
+        };
+        Ok(BlockList::load(user_id).await?.filter(messages))
+    }
+}
+
+/// Thread summaries of the messages resolved while executing a request, so that the thread fields of a message are resolved from a single [`Thread::summary`].
+#[derive(Default)]
+pub struct ThreadSummaries(tokio::sync::Mutex<HashMap<ID, ThreadSummary>>);
+
+impl ThreadSummaries {
+    async fn get(&self, message: &Message) -> ThreadSummary {
+        let mut summaries = self.0.lock().await;
+        if let Some(summary) = summaries.get(&message.id) {
+            return summary.clone();
+        }
+        let summary = Thread::summary(message.hub_id, message.channel_id, message.id).await;
+        summaries.insert(message.id, summary.clone());
+        summary
+    }
+}
+
+#[ComplexObject]
+impl Message {
+    async fn thread_count(&self, ctx: &Context<'_>) -> u64 {
+        ctx.data_unchecked::<ThreadSummaries>()
+            .get(self)
+            .await
+            .thread_count
+    }
+
+    async fn last_thread_message(&self, ctx: &Context<'_>) -> Option<Message> {
+        ctx.data_unchecked::<ThreadSummaries>()
+            .get(self)
+            .await
+            .last_thread_message
+    }
+}
+
 #[Object]
 impl Hub {
     async fn id(&self) -> &ID {
@@ -114,26 +213,66 @@ impl Hub {
         &self.description
     }
 
+    async fn discoverable(&self) -> bool {
+        self.discoverable
+    }
+
+    async fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    async fn category(&self) -> &Option<String> {
+        &self.category
+    }
+
+    async fn require_accept(&self) -> Option<ID> {
+        self.require_accept
+    }
+
+    async fn max_members(&self) -> Option<u32> {
+        self.max_members
+    }
+
+    async fn default_channel(&self) -> Option<&Channel> {
+        self.default_channel
+            .as_ref()
+            .and_then(|id| self.channels.get(id))
+    }
+
     async fn is_banned(
         &self,
         #[graphql(desc = "ID of user hub to check the ban status of.")] id: ID,
     ) -> bool {
-        self.bans.contains(&id)
+        self.is_user_banned(&id)
+    }
+
+    async fn bans(&self) -> Vec<ID> {
+        self.bans.keys().copied().collect()
     }
 
-    async fn bans(&self) -> &HashSet<ID> {
-        &self.bans
+    async fn ban_expires(
+        &self,
+        #[graphql(desc = "ID of the banned user.")] id: ID,
+    ) -> Option<DateTime<Utc>> {
+        self.bans.get(&id).copied().flatten()
     }
 
     async fn is_muted(
         &self,
         #[graphql(desc = "ID of the user to check the mute status of.")] id: ID,
     ) -> bool {
-        self.mutes.contains(&id)
+        self.is_user_muted(&id)
+    }
+
+    async fn mutes(&self) -> Vec<ID> {
+        self.mutes.keys().copied().collect()
     }
 
-    async fn mutes(&self) -> &HashSet<ID> {
-        &self.mutes
+    async fn mute_expires(
+        &self,
+        #[graphql(desc = "ID of the muted user.")] id: ID,
+    ) -> Option<DateTime<Utc>> {
+        self.mutes.get(&id).copied().flatten()
     }
 
     async fn channel(
@@ -264,6 +403,18 @@ impl PermissionGroup {
         &self.created
     }
 
+    async fn created_by(&self) -> &ID {
+        &self.created_by
+    }
+
+    async fn updated_by(&self) -> Option<&ID> {
+        self.updated_by.as_ref()
+    }
+
+    async fn updated(&self) -> Option<&DateTime<Utc>> {
+        self.updated.as_ref()
+    }
+
     async fn is_member(&self, id: ID) -> bool {
         self.members.contains(&id)
     }
@@ -337,6 +488,15 @@ impl HubMember {
         &self.groups
     }
 
+    async fn group_info(&self, ctx: &Context<'_>) -> Result<Vec<PermissionGroup>> {
+        let hub = Hub::load_cached(self.hub, ctx.data_unchecked::<HubCache>()).await?;
+        Ok(hub
+            .get_member_groups(&self.user_id)?
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
     async fn in_group(
         &self,
         #[graphql(desc = "ID of the permission group to check for membership.")] id: ID,
@@ -406,3 +566,52 @@ impl HubMember {
         result
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[tokio::test]
+    async fn channel_messages() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let cache = HubCache::new(crate::HUB_CACHE_SIZE);
+        hub.save(&cache).await.unwrap();
+        let sent = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "hello".to_string(), 0)
+            .await
+            .unwrap();
+        let channel = &hub.channels[&*CHANNEL_ID];
+        let messages = channel
+            .read_messages(&USER_ID, &cache, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![sent.id],
+            messages.iter().map(|m| m.id).collect::<Vec<ID>>()
+        );
+        assert!(channel
+            .read_messages(&OTHER_USER_ID, &cache, None, 10)
+            .await
+            .is_err());
+        let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
+        let query = format!(
+            "{{ hub(id: \"{}\") {{ channel(id: \"{}\") {{ messages(max: 10) {{ content threadCount lastThreadMessage {{ content }} }} }} }} }}",
+            hub.id, *CHANNEL_ID
+        );
+        let response = schema
+            .execute(
+                Request::new(query)
+                    .data(*USER_ID)
+                    .data(cache)
+                    .data(ThreadSummaries::default()),
+            )
+            .await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            serde_json::json!({"hub": {"channel": {"messages": [{"content": "hello", "threadCount": 0, "lastThreadMessage": null}]}}}),
+            response.data.into_json().unwrap()
+        );
+    }
+}
@@ -1,17 +1,29 @@
 use crate::{
+    channel::Message,
     check_name_validity, check_permission,
-    error::{ApiError, Error},
-    graphql_model::GraphQLSchema,
+    dm::{DmChannel, GroupDm},
+    error::ApiError,
+    graphql_model::{GraphQLSchema, ThreadSummaries},
     httpapi::{ok, Response},
-    hub::Hub,
+    hub::{ChannelInfoChange, Hub, WordFilter},
     new_id,
-    permission::{ChannelPermission, HubPermission, PermissionSetting},
+    permission::{
+        ChannelPermission, ChannelPermissions, HubPermission, PermissionSet, PermissionSetting,
+    },
     prelude::{
-        HttpChannelUpdate, HttpHubUpdate, HttpLastMessagesQuery, HttpMemberStatus,
-        HttpMessagesAfterQuery, HttpMessagesBeforeQuery, HttpMessagesBetweenQuery, HttpSendMessage,
-        WsHubUpdateType,
+        check_description_size, normalize_name, HttpChannelPermissionOverrides, HttpChannelSpec,
+        HttpChannelUpdate, HttpCloneHub, HttpCreateGroupDm, HttpCreateInvite, HttpCreateThread,
+        HttpEditMessage, HttpForwardMessage, HttpHubAdmins, HttpHubChanges, HttpHubChangesQuery,
+        HttpHubUpdate, HttpLastMessagesQuery, HttpMemberGroup, HttpMemberMessagesQuery,
+        HttpMemberSearchQuery, HttpMemberStatus, HttpMemberSummary, HttpMessagePageQuery,
+        HttpMessagePreview, HttpMessagesAfterQuery, HttpMessagesBeforeQuery,
+        HttpMessagesBetweenQuery, HttpSearchQuery, HttpSendMessage, HttpSetDraft,
+        HttpSimulatePermissions, HttpSimulatedPermissions, HttpWebsocketQuery,
+        HttpWordFilterUpdate, WsHubUpdateType,
     },
-    server::{ServerAddress, ServerNotification},
+    server::{GetMessageServer, HubCache, SearchMessageIndex, ServerAddress, ServerNotification},
+    user::{BlockList, Draft},
+    websocket::ConnectionOptions,
     ID,
 };
 use std::mem;
@@ -20,16 +32,64 @@ use warp::{ws::Ws, Reply};
 
 type Result<T> = std::result::Result<T, warp::Rejection>;
 
+/// Removes the messages sent by users that the given user has blocked.
+async fn filter_blocked(user_id: &ID, messages: Vec<Message>) -> Result<Vec<Message>> {
+    Ok(BlockList::load(user_id).await?.filter(messages))
+}
+
 pub mod hub {
     use super::*;
+    use chrono::{DateTime, Utc};
+    use lazy_static::lazy_static;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    /// Hubs a user created with an idempotency key along with when they were created, keyed by the key.
+    type CreateKeys = HashMap<String, (ID, DateTime<Utc>)>;
+
+    lazy_static! {
+        /// Keys of the hubs created with an idempotency key, keyed by the ID of the user that created them.
+        /// Each user's keys have their own lock so that keyed creations by different users do not wait for each other.
+        static ref CREATE_KEYS: std::sync::Mutex<HashMap<ID, Arc<Mutex<CreateKeys>>>> =
+            std::sync::Mutex::new(HashMap::new());
+    }
+
+    /// Checks if a hub created with an idempotency key at `created` is too old for its key to be used again.
+    fn create_key_expired(created: &DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        now.signed_duration_since(*created).num_milliseconds() >= crate::HUB_CREATE_KEY_LIFETIME
+    }
+
+    /// Drops the idempotency keys of hubs created more than [`crate::HUB_CREATE_KEY_LIFETIME`] milliseconds before `now`, along with users left without any keys.
+    /// Keys that are being used by a hub creation are left for the next time this is run.
+    pub fn expire_create_keys(now: DateTime<Utc>) {
+        CREATE_KEYS
+            .lock()
+            .expect("create key map poisoned")
+            .retain(|_, user_keys| {
+                let in_use = Arc::strong_count(user_keys) > 1;
+                match user_keys.try_lock() {
+                    Ok(mut keys) => {
+                        keys.retain(|_, (_, created)| !create_key_expired(created, now));
+                        in_use || !keys.is_empty()
+                    }
+                    Err(_) => true,
+                }
+            });
+    }
 
     /// Creates a hub, returning the ID of the new hub if successful.
     /// Also adds a default channel named "chat" that all users have access to by default.
+    /// Channels created later give the default group the permissions in `data.new_channel_permissions`, none if it is not set.
+    /// If the user already created a hub with the same idempotency key in the last [`crate::HUB_CREATE_KEY_LIFETIME`] milliseconds the ID of that hub is returned instead.
     ///
     /// # Arguments
     ///
     /// * `owner_id` - ID of the user who should be marked as the owner/creator of the hub.
+    /// * `idempotency_key` - Key that identifies retries of the same request, retries are not detected if it is not set.
     /// * `name` - The name of the new hub.
+    /// * `default_max_members` - Member limit to use if the request does not set one.
+    /// * `max_description_size` - Maximum size of the hub's description in bytes.
     ///
     /// # Errors
     ///
@@ -37,42 +97,131 @@ pub mod hub {
     ///
     /// * The user's data could not be saved for any of the reasons outlined in [`User::save`].
     /// * The hub failed to save for any of the reasons outlined in [`Hub::save`].
-    /// * The given name failed to pass the checks for any of the reasons outlined in [`check_name_validity`].
+    /// * The given name failed to pass the checks for any of the reasons outlined in [`normalize_name`].
     /// * The default channel could not be created for any of the reaons outlined in [`Hub::new_channel`].
-    pub async fn create(owner_id: ID, data: HttpHubUpdate) -> Result<impl Reply> {
-        let name = data.name.unwrap_or_default();
-        let description = data.description.unwrap_or_default();
-        check_name_validity(&name)?;
-        if description.as_bytes().len() > crate::MAX_DESCRIPTION_SIZE {
-            return Err(ApiError::TooBig.into());
+    /// * The idempotency key is bigger than [`crate::MAX_IDEMPOTENCY_KEY_SIZE`].
+    pub async fn create(
+        owner_id: ID,
+        idempotency_key: Option<String>,
+        data: HttpHubUpdate,
+        default_max_members: Option<u32>,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let idempotency_key = match idempotency_key {
+            Some(key) if key.len() > crate::MAX_IDEMPOTENCY_KEY_SIZE => {
+                return Err(ApiError::TooBig.into())
+            }
+            Some(key) => key,
+            None => {
+                return Ok(Response::Success(
+                    create_hub(
+                        owner_id,
+                        data,
+                        default_max_members,
+                        max_description_size,
+                        cache,
+                    )
+                    .await?,
+                ))
+            }
+        };
+        let user_keys = Arc::clone(
+            CREATE_KEYS
+                .lock()
+                .expect("create key map poisoned")
+                .entry(owner_id)
+                .or_default(),
+        );
+        let mut keys = user_keys.lock().await;
+        let now = Utc::now();
+        if let Some((id, created)) = keys.get(&idempotency_key) {
+            if !create_key_expired(created, now) {
+                return Ok(Response::Success(*id));
+            }
         }
+        let id = create_hub(
+            owner_id,
+            data,
+            default_max_members,
+            max_description_size,
+            cache,
+        )
+        .await?;
+        keys.insert(idempotency_key, (id, now));
+        Ok(Response::Success(id))
+    }
+
+    async fn create_hub(
+        owner_id: ID,
+        data: HttpHubUpdate,
+        default_max_members: Option<u32>,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<ID> {
+        let name = normalize_name(&data.name.unwrap_or_default())?;
+        let description = data.description.unwrap_or_default();
+        check_description_size(&description, max_description_size)?;
         let mut id = new_id();
-        while Hub::load(id).await.is_ok() {
+        while Hub::load_cached(id, &cache).await.is_ok() {
             id = new_id();
         }
         let mut new_hub = Hub::new(name, id, owner_id);
-        let channel_id = new_hub
+        new_hub.new_channel_permissions = vec![
+            (ChannelPermission::Read, Some(true)),
+            (ChannelPermission::Write, Some(true)),
+        ]
+        .into_iter()
+        .collect();
+        new_hub
             .new_channel(
                 &owner_id,
                 "chat".to_string(),
                 "A place to chat.".to_string(),
             )
             .await?;
-        if let Some(group) = new_hub.groups.get_mut(&new_hub.default_group) {
-            group.set_channel_permission(
-                channel_id,
-                crate::permission::ChannelPermission::Read,
-                Some(true),
-            );
-            group.set_channel_permission(
-                channel_id,
-                crate::permission::ChannelPermission::Write,
-                Some(true),
-            );
-        }
+        new_hub.new_channel_permissions = data.new_channel_permissions.unwrap_or_default();
         new_hub.description = description;
-        new_hub.save().await?;
-        Ok(Response::Success(id))
+        new_hub.max_members = data
+            .max_members
+            .or(default_max_members)
+            .filter(|max| *max > 0);
+        new_hub.save(&cache).await?;
+        Ok(id)
+    }
+
+    /// Creates a new hub with the same channels, permission groups and settings as an existing hub, returning the ID of the new hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub to use as a template.
+    /// * `user_id` - ID of the user who will own the new hub, must be in the template hub.
+    /// * `data` - Name of the new hub.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The template hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user is not in the template hub.
+    /// * The given name failed to pass the checks for any of the reasons outlined in [`normalize_name`].
+    /// * The data folder of one of the channels could not be created for any of the reasons outlined by [`Channel::create_dir`].
+    /// * The new hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn clone(
+        hub_id: ID,
+        user_id: ID,
+        data: HttpCloneHub,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&user_id)?;
+        let name = normalize_name(&data.name)?;
+        let new_hub = hub.clone_as_template(user_id, name);
+        for channel in new_hub.channels.values() {
+            channel.create_dir().await?;
+        }
+        new_hub.save(&cache).await?;
+        Ok(Response::Success(new_hub.id))
     }
 
     /// Gets a hub stripped of data the given user should not be able to see.
@@ -88,8 +237,8 @@ pub mod hub {
     ///
     /// * The user is not in the hub.
     /// * The hub failed to load for any of the reasons outlined in [`Hub::load`].
-    pub async fn get(hub_id: ID, user_id: ID) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
+    pub async fn get(hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
         if hub.members.contains_key(&user_id) {
             Ok(Response::Success(hub.strip(&user_id)?))
         } else {
@@ -97,901 +246,2876 @@ pub mod hub {
         }
     }
 
-    /// Deletes a hub.
+    /// Gets the owner and administrators of a hub.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to check for permission to perform the operation.
-    /// * `hub_id` - ID of the hub to delete.
+    /// * `hub_id` - ID of the hub to get the administrators of.
+    /// * `user_id` - ID of the user requesting the administrators, must be in the hub.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The user does not have permission to delete the hub.
-    /// * The hub's data files could not be deleted.
-    pub async fn delete(hub_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let member = hub.get_member(&user_id)?;
-        check_permission!(member, HubPermission::All, hub);
-        tokio::fs::remove_file(hub.get_info_path())
-            .await
-            .map_err(Error::from)?;
-        tokio::fs::remove_dir_all(hub.get_data_path())
-            .await
-            .map_err(Error::from)?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::HubDeleted,
-        ));
-        Ok(ok())
+    /// * The user is not in the hub.
+    pub async fn admins(hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&user_id)?;
+        Ok(Response::Success(HttpHubAdmins {
+            owner: hub.owner,
+            admins: hub.admins(),
+        }))
     }
 
-    /// Updates a hub's details, returning the previous values.
+    /// Gets the channels of a hub that the user can view, the ones in which a message was sent most recently first.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to check for permission to perform the operation.
-    /// * `hub_id` - The ID of the hub whose name is to be changed.
-    /// * `update` - The new hub info to be applied
+    /// * `hub_id` - ID of the hub to get the channels of.
+    /// * `user_id` - ID of the user requesting the channels.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * THe user is not in the hub.
-    /// * The user does not have the [`HubPermission::Administrate`] permission.
-    /// * The given name failed to pass the checks for any of the reasons outlined in [`check_name_validity`].
-    /// * The given description is bigger than [`crate::MAX_DESCRIPTION_SIZE`].
-    /// * The given group does not exist.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    pub async fn update(
-        hub_id: ID,
-        user_id: ID,
-        update: HttpHubUpdate,
-        server: ServerAddress,
-    ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        let member = hub.get_member(&user_id)?;
-        check_permission!(member, HubPermission::Administrate, hub);
-        let mut old = HttpHubUpdate::default();
-        if let Some(name) = update.name {
-            check_name_validity(&name)?;
-            old.name = Some(mem::replace(&mut hub.name, name));
-        }
-        if let Some(description) = update.description {
-            if description.as_bytes().len() > crate::MAX_DESCRIPTION_SIZE {
-                return Err(ApiError::TooBig.into());
-            }
-            old.description = Some(mem::replace(&mut hub.description, description));
-        }
-        if let Some(default_group) = update.default_group {
-            if hub.groups.contains_key(&default_group) {
-                old.default_group = Some(mem::replace(&mut hub.default_group, default_group));
-            } else {
-                return Err(ApiError::GroupNotFound.into());
-            }
-        }
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::HubUpdated,
-        ));
-        Ok(Response::Success(old))
+    /// * The channels could not be gotten for any of the reasons outlined by [`Hub::get_channels_by_activity`].
+    pub async fn recent_channels(hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        Ok(Response::Success(hub.get_channels_by_activity(&user_id)?))
     }
 
-    /// Adds the given user to a hub.
+    /// Gets all of the channels in a hub, including the ones the user cannot view, for use by hub administrators.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to add to the hub.
-    /// * `hub_id` - ID of the hub the user is to be added to.
+    /// * `hub_id` - ID of the hub to get the channels of.
+    /// * `user_id` - ID of the user requesting the channels.
     ///
     /// # Errors
     ///
-    /// * The user could not be added to the hub for any of the reasons outlined by [`User::join_hub`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    pub async fn join(hub_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        hub.user_join(user_id)?;
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::UserJoined(user_id),
-        ));
-        Ok(ok())
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channels could not be gotten for any of the reasons outlined by [`Hub::get_all_channels_admin`].
+    pub async fn all_channels(hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        Ok(Response::Success(
+            hub.get_all_channels_admin(&user_id)?.clone(),
+        ))
     }
 
-    /// Removes the given user from a hub.
+    /// Checks if a user would be allowed to do something that requires a hub permission, see [`Hub::can`].
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to remove from the hub.
-    /// * `hub_id` - ID of the hub the user is to be removed from.
+    /// * `hub_id` - ID of the hub to check in.
+    /// * `member_id` - ID of the user to check.
+    /// * `permission` - The permission to check for.
+    /// * `user_id` - ID of the user who is checking.
     ///
     /// # Errors
     ///
-    /// * The user could not be removed from the hub for any of the reasons outlined by [`User::leave_hub`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    pub async fn leave(hub_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        hub.user_leave(&user_id)?;
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::UserLeft(user_id),
-        ));
-        Ok(ok())
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user who is checking is not in the hub.
+    pub async fn can(
+        hub_id: ID,
+        member_id: ID,
+        permission: HubPermission,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&user_id)?;
+        Ok(Response::Success(hub.can(&member_id, permission)))
     }
-}
 
-pub mod member {
-    use super::*;
-
-    /// Gets the current status of a user in a hub
+    /// Checks if a user would be allowed to do something that requires a permission in a channel, see [`Hub::can_in_channel`].
     ///
     /// # Arguments
     ///
-    /// * `actor_id` - ID of the user who is checking.
-    /// * `hub_id` - The hub in which to check the user's status.
-    /// * `user_id` - The user whose status is to be checked.
+    /// * `hub_id` - ID of the hub to check in.
+    /// * `member_id` - ID of the user to check.
+    /// * `channel_id` - ID of the channel to check in.
+    /// * `permission` - The permission to check for.
+    /// * `user_id` - ID of the user who is checking.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user who is checking is not in the hub.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn status(hub_id: ID, user_id: ID, actor_id: ID) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        hub.check_membership(&actor_id)?;
-        let status = HttpMemberStatus {
-            member: hub.members.contains_key(&user_id),
-            banned: hub.bans.contains(&user_id),
-            muted: hub.mutes.contains(&user_id),
-        };
-        Ok(Response::Success(status))
+    /// * The user who is checking can not see the channel for any of the reasons outlined by [`Hub::get_channel`].
+    pub async fn can_in_channel(
+        hub_id: ID,
+        member_id: ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.get_channel(&user_id, channel_id)?;
+        Ok(Response::Success(
+            hub.can_in_channel(&member_id, channel_id, permission),
+        ))
     }
 
-    /// Gets the information on a member of a hub.
+    /// Searches the members of a hub for those whose nickname starts with the given prefix, ignoring case.
     ///
     /// # Arguments
     ///
-    /// * `actor_id` - ID of the user who is requesting the information.
-    /// * `hub_id` - Hub from which to get the information.
-    /// * `user_id` - ID of the user whose information is being requested.
+    /// * `hub_id` - ID of the hub to search.
+    /// * `user_id` - ID of the user who is searching.
+    /// * `query` - Prefix to look for and the maximum number of members to return.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The requesting user is not in the hub.
-    /// * The user whose information is being requested is not in the hub.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get(actor_id: ID, hub_id: ID, user_id: ID) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        hub.check_membership(&actor_id)?;
-        Ok(Response::Success(hub.get_member(&user_id)?.clone()))
+    /// * The user is not in the hub.
+    pub async fn search_members(
+        hub_id: ID,
+        user_id: ID,
+        query: HttpMemberSearchQuery,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&user_id)?;
+        let members = hub
+            .search_members(&query.prefix, query.limit)
+            .into_iter()
+            .map(|member| HttpMemberSummary {
+                user_id: member.user_id,
+                nick: member.nick.clone(),
+            })
+            .collect::<Vec<HttpMemberSummary>>();
+        Ok(Response::Success(members))
     }
 
-    /// Sets a nickname for a hub member
+    /// Gets the most recent messages a member sent across the channels of a hub that the moderator can read.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is making the change.
-    /// * `hub_id` - The hub in which the change is being made.
-    /// * `member_id` - The hub member whose permissions are being changed.
-    /// * `nick` - The new nickname.
+    /// * `hub_id` - ID of the hub to get the messages from.
+    /// * `user_id` - ID of the user whose messages are being requested.
+    /// * `moderator_id` - ID of the user requesting the messages.
+    /// * `query` - Maximum number of messages to get.
     ///
     /// # Errors
     ///
-    /// This function may return an error for any of the following reasons.
+    /// This function may return an error for any of the following reasons:
     ///
-    /// * The user making the change is not in the hub.
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn set_nick(
-        actor_id: ID,
+    /// * The messages could not be gotten for any of the reasons outlined by [`Hub::get_member_messages`].
+    pub async fn member_messages(
         hub_id: ID,
-        nick: String,
-        server: ServerAddress,
+        user_id: ID,
+        moderator_id: ID,
+        query: HttpMemberMessagesQuery,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        if !nick.is_empty() {
-            let mut hub = Hub::load(hub_id).await?;
-            let member = hub.get_member_mut(&actor_id)?;
-            member.nick = nick;
-            hub.save().await?;
-            let _ = server.send(ServerNotification::HubUpdated(
-                hub_id,
-                WsHubUpdateType::MemberNicknameChanged(actor_id),
-            ));
-            Ok(ok())
-        } else {
-            Err(ApiError::InvalidName.into())
-        }
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        Ok(Response::Success(
+            hub.get_member_messages(&moderator_id, &user_id, query.limit)
+                .await?,
+        ))
     }
 
-    /// Sets a hub wide permission for a hub member.
+    /// Gets a hub's word filter.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is making the change.
-    /// * `hub_id` - The hub in which the change is being made.
-    /// * `member_id` - The hub member whose permissions are being changed.
-    /// * `permission` - The permission whose setting is being changed.
-    /// * `value` - The new setting for the permission.
+    /// * `hub_id` - ID of the hub to get the word filter of.
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
     ///
     /// # Errors
     ///
-    /// This function may return an error for any of the following reasons.
+    /// This function may return an error for any of the following reasons:
     ///
-    /// * The user making the change is not in the hub.
-    /// * The user whose permission is being changed is not in the hub.
-    /// * The user making the change does not have permission to do so.
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn set_hub_permission(
-        actor_id: ID,
-        hub_id: ID,
-        member_id: ID,
-        permission: HubPermission,
-        value: PermissionSetting,
-        server: ServerAddress,
-    ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        {
-            let member = hub.get_member(&actor_id)?;
-            check_permission!(member, HubPermission::Administrate, hub);
-        }
-        let member = hub.get_member_mut(&member_id)?;
-        member.set_permission(permission, value);
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::UserHubPermissionChanged(member_id),
-        ));
-        Ok(ok())
+    /// * The user is not allowed to see the word filter for any of the reasons outlined by [`Hub::check_administrative`].
+    pub async fn get_word_filter(hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::Administrate)?;
+        Ok(Response::Success(hub.word_filter))
     }
 
-    /// Gets a hub wide permission for a hub member.
+    /// Replaces a hub's word filter.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is making the change.
-    /// * `hub_id` - The hub in which the change is being made.
-    /// * `member_id` - The hub member whose permissions are being changed.
-    /// * `permission` - The permission whose setting is being changed.
+    /// * `hub_id` - ID of the hub to set the word filter of.
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
+    /// * `update` - The new word filter.
+    /// * `server` - Address of the server actor used to notify clients of the change.
     ///
     /// # Errors
     ///
-    /// This function may return an error for any of the following reasons.
+    /// This function may return an error for any of the following reasons:
     ///
-    /// * The user checking the permission is not in the hub.
-    /// * The user whose permission is being checked is not in the hub.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get_hub_permission(
-        actor_id: ID,
+    /// * The word filter is invalid for any of the reasons outlined by [`WordFilter::new`].
+    /// * The word filter could not be set for any of the reasons outlined by [`Hub::set_word_filter`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn set_word_filter(
         hub_id: ID,
-        member_id: ID,
-        permission: HubPermission,
+        user_id: ID,
+        update: HttpWordFilterUpdate,
+        server: ServerAddress,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        hub.check_membership(&actor_id)?;
-        let member = hub.get_member(&member_id)?;
-        Ok(Response::Success(member.has_permission(permission, &hub)))
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.set_word_filter(&user_id, WordFilter::new(update.mode, update.words)?)?;
+        let change = hub.record_change(WsHubUpdateType::HubUpdated);
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
     }
 
-    /// Sets a channel specific permission for a hub member.
+    /// Sets the channel that clients should show users when they open a hub.
     ///
     /// # Arguments
     ///
-    /// * `server` - Address of the server to notify of the change.
-    /// * `user_id` - ID of the user who is making the change.
-    /// * `hub_id` - The hub in which the change is being made.
-    /// * `member_id` - The hub member whose permissions are being changed.
-    /// * `channel_id` - The channel that the change should apply to.
-    /// * `permission` - The permission whose setting is being changed.
-    /// * `value` - The new setting for the permission.
+    /// * `hub_id` - ID of the hub to set the default channel of.
+    /// * `channel_id` - ID of the channel that should become the default.
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
+    /// * `server` - Address of the server actor used to notify clients of the change.
     ///
     /// # Errors
     ///
-    /// This function may return an error for any of the following reasons.
+    /// This function may return an error for any of the following reasons:
     ///
-    /// * The user making the change is not in the hub.
-    /// * The user whose permission is being changed is not in the hub.
-    /// * The user making the change does not have permission to do so.
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn set_channel_permission(
-        actor_id: ID,
+    /// * The default channel could not be set for any of the reasons outlined by [`Hub::set_default_channel`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn set_default_channel(
         hub_id: ID,
-        member_id: ID,
         channel_id: ID,
-        permission: ChannelPermission,
-        value: PermissionSetting,
+        user_id: ID,
         server: ServerAddress,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        {
-            let member = hub.get_member(&actor_id)?;
-            check_permission!(member, HubPermission::Administrate, hub);
-        }
-        let member = hub.get_member_mut(&member_id)?;
-        member.set_channel_permission(channel_id, permission, value);
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::UserChannelPermissionChanged(member_id, channel_id),
-        ));
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.set_default_channel(&user_id, channel_id)?;
+        let change = hub.record_change(WsHubUpdateType::HubUpdated);
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
         Ok(ok())
     }
 
-    /// Gets a channel specific permission for a hub member.
+    /// Makes another member the owner of a hub, returning the ID of the previous owner.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is doing the check.
-    /// * `hub_id` - The hub in which the check is being made.
-    /// * `member_id` - The hub member whose permissions are being checked.
+    /// * `hub_id` - ID of the hub to transfer.
+    /// * `new_owner` - ID of the member that should become the owner.
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
+    /// * `server` - Address of the server actor used to notify clients of the change.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be transferred for any of the reasons outlined by [`Hub::transfer_ownership`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn transfer_ownership(
+        hub_id: ID,
+        new_owner: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        let old_owner = hub.transfer_ownership(&user_id, new_owner)?;
+        let change = hub.record_change(WsHubUpdateType::OwnershipTransferred(new_owner));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(Response::Success(old_owner))
+    }
+
+    /// Deletes a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
+    /// * `hub_id` - ID of the hub to delete.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not allowed to delete the hub for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be deleted for any of the reasons outlined by [`Hub::delete`].
+    pub async fn delete(
+        hub_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::All)?;
+        hub.delete(&cache).await?;
+        let change = hub.record_change(WsHubUpdateType::HubDeleted);
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Updates a hub's details, returning the previous values.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to check for permission to perform the operation.
+    /// * `hub_id` - The ID of the hub whose name is to be changed.
+    /// * `update` - The new hub info to be applied
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not allowed to update the hub for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * The given name failed to pass the checks for any of the reasons outlined in [`normalize_name`].
+    /// * The given description is bigger than `max_description_size`.
+    /// * The given tags failed to pass the checks for any of the reasons outlined in [`check_tags_validity`](crate::prelude::check_tags_validity).
+    /// * The given category failed to pass the checks for any of the reasons outlined in [`check_name_validity`].
+    /// * The given group does not exist.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn update(
+        hub_id: ID,
+        user_id: ID,
+        update: HttpHubUpdate,
+        server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::Administrate)?;
+        let mut old = HttpHubUpdate::default();
+        if let Some(name) = update.name {
+            old.name = Some(mem::replace(&mut hub.name, normalize_name(&name)?));
+        }
+        if let Some(description) = update.description {
+            check_description_size(&description, max_description_size)?;
+            old.description = Some(mem::replace(&mut hub.description, description));
+        }
+        if let Some(default_group) = update.default_group {
+            if hub.groups.contains_key(&default_group) {
+                old.default_group = Some(mem::replace(&mut hub.default_group, default_group));
+            } else {
+                return Err(ApiError::GroupNotFound.into());
+            }
+        }
+        if let Some(verification) = update.verification {
+            old.verification = Some(mem::replace(&mut hub.verification, verification));
+        }
+        if let Some(max_members) = update.max_members {
+            let max_members = if max_members == 0 {
+                None
+            } else {
+                Some(max_members)
+            };
+            old.max_members =
+                Some(mem::replace(&mut hub.max_members, max_members).unwrap_or_default());
+        }
+        if let Some(discoverable) = update.discoverable {
+            old.discoverable = Some(mem::replace(&mut hub.discoverable, discoverable));
+        }
+        if let Some(tags) = update.tags {
+            old.tags = Some(hub.set_tags(tags)?);
+        }
+        if let Some(category) = update.category {
+            old.category = Some(hub.set_category(category)?.unwrap_or_default());
+        }
+        if let Some(require_accept) = update.require_accept {
+            old.require_accept = Some(hub.set_require_accept(require_accept).unwrap_or_default());
+        }
+        if let Some(new_channel_permissions) = update.new_channel_permissions {
+            old.new_channel_permissions = Some(mem::replace(
+                &mut hub.new_channel_permissions,
+                new_channel_permissions,
+            ));
+        }
+        let change = hub.record_change(WsHubUpdateType::HubUpdated);
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(Response::Success(old))
+    }
+
+    /// Adds the given user to a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to add to the hub.
+    /// * `hub_id` - ID of the hub the user is to be added to.
+    ///
+    /// # Errors
+    ///
+    /// * The user could not be added to the hub for any of the reasons outlined by [`User::join_hub`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn join(
+        hub_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.user_join(user_id)?;
+        let change = hub.record_change(WsHubUpdateType::UserJoined(user_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Accepts the rules of a hub, required before sending messages in hubs with [`Hub::require_accept`] set.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub whose rules are being accepted.
+    /// * `user_id` - ID of the user accepting the rules.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The rules could not be accepted for any of the reasons outlined by [`Hub::accept_rules`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn accept(
+        hub_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.accept_rules(&user_id)?;
+        let change = hub.record_change(WsHubUpdateType::UserAcceptedRules(user_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Removes the given user from a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to remove from the hub.
+    /// * `hub_id` - ID of the hub the user is to be removed from.
+    ///
+    /// # Errors
+    ///
+    /// * The user could not be removed from the hub for any of the reasons outlined by [`User::leave_hub`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn leave(
+        hub_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.user_leave(&user_id)?;
+        let change = hub.record_change(WsHubUpdateType::UserLeft(user_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Creates an invite to a hub, returning the invite's code.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub the invite is for.
+    /// * `user_id` - ID of the user creating the invite.
+    /// * `data` - When the invite expires and how many times it can be used.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The invite could not be created for any of the reasons outlined by [`Hub::create_invite`].
+    /// * The invite could not be saved for any of the reasons outlined by [`HubInvite::save`](crate::hub::HubInvite::save).
+    pub async fn create_invite(
+        hub_id: ID,
+        user_id: ID,
+        data: HttpCreateInvite,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let invite = hub.create_invite(&user_id, data.expires_at, data.max_uses)?;
+        invite.save().await?;
+        Ok(Response::Success(invite.code))
+    }
+
+    /// Revokes an invite to a hub so that it can no longer be used.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub the invite is for.
+    /// * `code` - Code of the invite to revoke.
+    /// * `user_id` - ID of the user revoking the invite.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The invite could not be revoked for any of the reasons outlined by [`Hub::revoke_invite`].
+    pub async fn revoke_invite(
+        hub_id: ID,
+        code: String,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.revoke_invite(&user_id, &code).await?;
+        Ok(ok())
+    }
+
+    /// Gets the changes made to a hub since the given time, used by clients to sync incrementally after reconnecting.
+    /// If the hub's change log does not go back far enough `full_refetch` is set and no changes are returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub to get the changes of.
+    /// * `user_id` - ID of the user requesting the changes.
+    /// * `query` - Time after which changes should be returned.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn changes(
+        hub_id: ID,
+        user_id: ID,
+        query: HttpHubChangesQuery,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&user_id)?;
+        let changes = hub.changes_since(query.since);
+        Ok(Response::Success(HttpHubChanges {
+            full_refetch: changes.is_none(),
+            changes: changes.unwrap_or_default(),
+        }))
+    }
+}
+
+pub mod invite {
+    use super::*;
+
+    /// Adds the user to the hub an invite is for, returning the ID of the hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code of the invite being used.
+    /// * `user_id` - ID of the user accepting the invite.
+    /// * `server` - Address of the server to notify of the new member.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the reasons outlined by [`Hub::use_invite`].
+    pub async fn accept(
+        code: String,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let (hub, change) = Hub::use_invite(&code, user_id, &cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub.id, change));
+        Ok(Response::Success(hub.id))
+    }
+}
+
+pub mod member {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    /// Gets the current status of a user in a hub
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is checking.
+    /// * `hub_id` - The hub in which to check the user's status.
+    /// * `user_id` - The user whose status is to be checked.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user who is checking is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn status(
+        hub_id: ID,
+        user_id: ID,
+        actor_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&actor_id)?;
+        let status = HttpMemberStatus {
+            member: hub.members.contains_key(&user_id),
+            banned: hub.is_user_banned(&user_id),
+            muted: hub.is_user_muted(&user_id),
+        };
+        Ok(Response::Success(status))
+    }
+
+    /// Gets the information on a member of a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is requesting the information.
+    /// * `hub_id` - Hub from which to get the information.
+    /// * `user_id` - ID of the user whose information is being requested.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The requesting user is not in the hub.
+    /// * The user whose information is being requested is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get(actor_id: ID, hub_id: ID, user_id: ID, cache: HubCache) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&actor_id)?;
+        Ok(Response::Success(hub.get_member(&user_id)?.clone()))
+    }
+
+    /// Gets the IDs and names of the permission groups a member of a hub is part of.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is requesting the information.
+    /// * `hub_id` - Hub from which to get the information.
+    /// * `user_id` - ID of the user whose groups are being requested.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The requesting user is not in the hub.
+    /// * The user whose groups are being requested is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_groups(
+        actor_id: ID,
+        hub_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&actor_id)?;
+        let groups = hub
+            .get_member_groups(&user_id)?
+            .into_iter()
+            .map(|group| HttpMemberGroup {
+                id: group.id,
+                name: group.name.clone(),
+            })
+            .collect::<Vec<HttpMemberGroup>>();
+        Ok(Response::Success(groups))
+    }
+
+    /// Sets a nickname for a hub member
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being changed.
+    /// * `nick` - The new nickname.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn set_nick(
+        actor_id: ID,
+        hub_id: ID,
+        nick: String,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        if !nick.is_empty() {
+            let mut hub = Hub::load_cached(hub_id, &cache).await?;
+            let member = hub.get_member_mut(&actor_id)?;
+            member.nick = nick;
+            let change = hub.record_change(WsHubUpdateType::MemberNicknameChanged(actor_id));
+            hub.save(&cache).await?;
+            let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+            Ok(ok())
+        } else {
+            Err(ApiError::InvalidName.into())
+        }
+    }
+
+    /// Sets a hub wide permission for a hub member.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being changed.
+    /// * `permission` - The permission whose setting is being changed.
+    /// * `value` - The new setting for the permission.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The user whose permission is being changed is not in the hub.
+    /// * The user making the change does not have permission to do so.
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn set_hub_permission(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        permission: HubPermission,
+        value: PermissionSetting,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&actor_id, HubPermission::Administrate)?;
+        let member = hub.get_member_mut(&member_id)?;
+        member.set_permission(permission, value);
+        let change = hub.record_change(WsHubUpdateType::UserHubPermissionChanged(member_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Sets several hub wide and channel specific permissions for a hub member at once, if any of them is invalid none are applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being changed.
+    /// * `permissions` - The permission settings to apply.
+    /// * `server` - Address of the server to notify of the change.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The permissions could not be set for any of the reasons outlined by [`Hub::set_member_permissions`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn set_permissions(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        permissions: Vec<PermissionSet>,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.set_member_permissions(&actor_id, &member_id, &permissions)?;
+        let change = hub.record_change(WsHubUpdateType::UserPermissionsChanged(member_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Checks which permissions a hub member would have if their groups and permission settings were changed, without changing anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is running the simulation.
+    /// * `hub_id` - The hub in which the simulation is run.
+    /// * `member_id` - The hub member whose permissions are being simulated.
+    /// * `simulation` - The hypothetical change and the permissions to check for.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user running the simulation does not have the [`HubPermission::Administrate`] permission.
+    /// * One of the groups does not exist.
+    /// * One of the channels to check in does not exist.
+    /// * The permission settings could not be applied for any of the reasons outlined by [`Hub::set_member_permissions`].
+    pub async fn simulate_permissions(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        simulation: HttpSimulatePermissions,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&actor_id, HubPermission::Administrate)?;
+        if simulation
+            .channel
+            .iter()
+            .any(|(channel_id, _)| !hub.channels.contains_key(channel_id))
+        {
+            return Err(ApiError::ChannelNotFound.into());
+        }
+        if let Some(groups) = simulation.groups {
+            if groups
+                .iter()
+                .any(|group_id| !hub.groups.contains_key(group_id))
+            {
+                return Err(ApiError::GroupNotFound.into());
+            }
+            let mut member = hub.get_member(&member_id)?.clone();
+            for group in hub.groups.values_mut() {
+                member.leave_group(group);
+                if groups.contains(&group.id) {
+                    member.join_group(group);
+                }
+            }
+            hub.members.insert(member_id, member);
+        }
+        hub.set_member_permissions(&actor_id, &member_id, &simulation.overrides)?;
+        let member = hub.get_member(&member_id)?;
+        Ok(Response::Success(HttpSimulatedPermissions {
+            hub: simulation
+                .hub
+                .into_iter()
+                .map(|permission| (permission, member.has_permission(permission, &hub)))
+                .collect(),
+            channel: simulation
+                .channel
+                .into_iter()
+                .map(|(channel_id, permission)| {
+                    (
+                        channel_id,
+                        permission,
+                        member.has_channel_permission(channel_id, permission, &hub),
+                    )
+                })
+                .collect(),
+        }))
+    }
+
+    /// Removes all of a hub member's individual permission settings so that they only get permissions from their groups.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being reset.
+    /// * `server` - Address of the server to notify of the change.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The user whose permissions are being reset is not in the hub.
+    /// * The user making the change does not have permission to do so.
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn clear_permissions(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&actor_id, HubPermission::Administrate)?;
+        hub.get_member_mut(&member_id)?.clear_overrides();
+        let change = hub.record_change(WsHubUpdateType::UserPermissionsCleared(member_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Gets a hub wide permission for a hub member.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being changed.
+    /// * `permission` - The permission whose setting is being changed.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user checking the permission is not in the hub.
+    /// * The user whose permission is being checked is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_hub_permission(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        permission: HubPermission,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_membership(&actor_id)?;
+        let member = hub.get_member(&member_id)?;
+        Ok(Response::Success(member.has_permission(permission, &hub)))
+    }
+
+    /// Sets a channel specific permission for a hub member.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - Address of the server to notify of the change.
+    /// * `user_id` - ID of the user who is making the change.
+    /// * `hub_id` - The hub in which the change is being made.
+    /// * `member_id` - The hub member whose permissions are being changed.
+    /// * `channel_id` - The channel that the change should apply to.
+    /// * `permission` - The permission whose setting is being changed.
+    /// * `value` - The new setting for the permission.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The user whose permission is being changed is not in the hub.
+    /// * The user making the change does not have permission to do so.
+    /// * The channel does not exist.
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn set_channel_permission(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+        value: PermissionSetting,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.set_member_channel_permission(&actor_id, &member_id, channel_id, permission, value)?;
+        let change = hub.record_change(WsHubUpdateType::UserChannelPermissionChanged(
+            member_id, channel_id,
+        ));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Gets a channel specific permission for a hub member.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is doing the check.
+    /// * `hub_id` - The hub in which the check is being made.
+    /// * `member_id` - The hub member whose permissions are being checked.
     /// * `channel_id` - The channel that the check should be made.
     /// * `permission` - The permission whose setting is being checked.
     ///
     /// # Errors
     ///
-    /// This function may return an error for any of the following reasons.
+    /// This function may return an error for any of the following reasons.
+    ///
+    /// * The user doing the check is not in the hub.
+    /// * The user whose permission is being checked is not in the hub.
+    /// * The user making the check does not have permission to do so.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_channel_permission(
+        actor_id: ID,
+        hub_id: ID,
+        member_id: ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        {
+            let member = hub.get_member(&actor_id)?;
+            check_permission!(member, channel_id, ChannelPermission::Read, hub);
+        }
+        let member = hub.get_member(&member_id)?;
+        Ok(Response::Success(
+            member.has_channel_permission(channel_id, permission, &hub),
+        ))
+    }
+
+    /// Handles kicking, banning, muting, unbanning and unmuting users in/from hubs.
+    async fn hub_user_op(
+        server: ServerAddress,
+        actor_id: ID,
+        hub_id: ID,
+        user_id: ID,
+        op: HubPermission,
+        expires_at: Option<DateTime<Utc>>,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let duration = match expires_at {
+            Some(expires_at) if expires_at <= Utc::now() => {
+                return Err(ApiError::InvalidTime.into())
+            }
+            expires_at => expires_at.map(|expires_at| expires_at - Utc::now()),
+        };
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&actor_id, op)?;
+        let update_type = match op {
+            HubPermission::Kick => {
+                hub.kick_user(&user_id)?;
+                WsHubUpdateType::UserKicked(user_id)
+            }
+            HubPermission::Ban => {
+                hub.ban_user(user_id, duration)?;
+                WsHubUpdateType::UserBanned(user_id)
+            }
+            HubPermission::Unban => {
+                hub.unban_user(&user_id);
+                WsHubUpdateType::UserUnbanned(user_id)
+            }
+            HubPermission::Mute => {
+                hub.mute_user(user_id, duration)?;
+                WsHubUpdateType::UserMuted(user_id)
+            }
+            HubPermission::Unmute => {
+                hub.unmute_user(&user_id);
+                WsHubUpdateType::UserUnmuted(user_id)
+            }
+            _ => return Err(ApiError::InternalError.into()),
+        };
+        let change = hub.record_change(update_type);
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+
+    /// Maps the different possible options for [`hub_user_op`] to separate functions.
+    macro_rules! action_fns {
+      ($($(#[$attr:meta])* => ($fnName:ident, $variant:ident)),*) => {
+        $(
+          $(#[$attr])*
+          pub async fn $fnName(actor_id: ID, hub_id: ID, user_id: ID, server: ServerAddress, cache: HubCache) -> Result<impl Reply> {
+              hub_user_op(server, actor_id, hub_id, user_id, HubPermission::$variant, None, cache).await
+          }
+        )*
+      }
+    }
+
+    action_fns! {
+        /// Kicks a user from a hub.
+        ///
+        /// # Arguments
+        ///
+        /// * `actor_id` - ID of the user who is doing the kicking.
+        /// * `hub_id` - Hub from which the user is being kicked.
+        /// * `user_id` - ID of the user who is to be kicked.
+        ///
+        /// # Errors
+        ///
+        /// This function may fail for any of the following reasons:
+        ///
+        /// * The user doing the kicking is not in the hub
+        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+        /// * The user to be kicked is not in the hub.
+        /// * The user doing the kicking does not have permission to kick other users.
+        /// * The kick failed for any of the reasons outlined by [`Hub::kick_user`].
+        => (kick, Kick),
+        /// Unbans a user from a hub.
+        ///
+        /// # Arguments
+        ///
+        /// * `actor_id` - ID of the user who is unbanning.
+        /// * `hub_id` - Hub from which the user is being unbanned.
+        /// * `user_id` - ID of the user who is to be unbanned.
+        ///
+        /// # Errors
+        ///
+        /// This function may fail for any of the following reasons:
+        ///
+        /// * The user performing the unban is not in the hub
+        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+        /// * The user doing the unban does not have permission to unban other users.
+        /// * The unban failed for any of the reasons outlined by [`Hub::unban_user`].
+        => (unban, Unban),
+        /// Unmutes a user in a hub.
+        ///
+        /// # Arguments
+        ///
+        /// * `actor_id` - ID of the user who is unmuting.
+        /// * `hub_id` - Hub in which the user is being unmuted.
+        /// * `user_id` - ID of the user who is to be unmuted.
+        ///
+        /// # Errors
+        ///
+        /// This function may fail for any of the following reasons:
+        ///
+        /// * The user performing the unmute is not in the hub
+        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+        /// * The user performing the unmute does not have permission to unmute other users.
+        /// * The unmute failed for any of the reasons outlined by [`Hub::unmute_user`].
+        => (unmute, Unmute)
+    }
+
+    /// Bans a user from a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is performing the ban.
+    /// * `hub_id` - Hub from which the user is being banned.
+    /// * `user_id` - ID of the user who is to be banned.
+    /// * `expires_at` - Time at which the ban is lifted, the ban is permanent if it is `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail for any of the following reasons:
+    ///
+    /// * The user performing the ban is not in the hub
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user performing the ban does not have permission to ban other users.
+    /// * `expires_at` is not in the future.
+    /// * The ban failed for any of the reasons outlined by [`Hub::ban_user`].
+    pub async fn ban(
+        actor_id: ID,
+        hub_id: ID,
+        user_id: ID,
+        expires_at: Option<DateTime<Utc>>,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        hub_user_op(
+            server,
+            actor_id,
+            hub_id,
+            user_id,
+            HubPermission::Ban,
+            expires_at,
+            cache,
+        )
+        .await
+    }
+
+    /// Mutes a user in a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is muting.
+    /// * `hub_id` - Hub in which the user is being muted.
+    /// * `user_id` - ID of the user who is to be muted.
+    /// * `expires_at` - Time at which the mute is lifted, the mute is permanent if it is `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail for any of the following reasons:
+    ///
+    /// * The user performing the mute is not in the hub
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user performing the mute does not have permission to mute other users.
+    /// * `expires_at` is not in the future.
+    /// * The mute failed for any of the reasons outlined by [`Hub::mute_user`].
+    pub async fn mute(
+        actor_id: ID,
+        hub_id: ID,
+        user_id: ID,
+        expires_at: Option<DateTime<Utc>>,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        hub_user_op(
+            server,
+            actor_id,
+            hub_id,
+            user_id,
+            HubPermission::Mute,
+            expires_at,
+            cache,
+        )
+        .await
+    }
+
+    /// Approves a member of a hub that is pending verification.
+    ///
+    /// # Arguments
+    ///
+    /// * `actor_id` - ID of the user who is approving.
+    /// * `hub_id` - Hub in which the user is being approved.
+    /// * `user_id` - ID of the user who is to be approved.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The approval failed for any of the reasons outlined by [`Hub::approve_member`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    pub async fn approve(
+        actor_id: ID,
+        hub_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.approve_member(&actor_id, &user_id)?;
+        let change = hub.record_change(WsHubUpdateType::UserApproved(user_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+}
+
+pub mod channel {
+    use super::*;
+
+    /// Creates a text channel in a hub.
+    /// Returns the ID of the new channel if successful.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to check for permission to create the channel.
+    /// * `hub_id` - ID of the hub in which the channel should be created.
+    /// * `name` - Name for the new channel.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * THe user is not in the hub.
+    /// * The name failed to pass the checks for any of the reasons outlined in [`normalize_name`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The user does not have permission to create new channels.
+    /// * The channel could not be created for any of the reasons outlined by [`Hub::new_channel`].
+    pub async fn create(
+        hub_id: ID,
+        user_id: ID,
+        data: HttpChannelUpdate,
+        server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let name = normalize_name(&data.name.unwrap_or_default())?;
+        let description = data.description.unwrap_or_default();
+        check_description_size(&description, max_description_size)?;
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel_id = hub.new_channel(&user_id, name, description).await?;
+        let change = hub.record_change(WsHubUpdateType::ChannelCreated(channel_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(Response::Success(channel_id))
+    }
+
+    /// Creates several text channels in a hub with a single load and save of the hub.
+    /// Every channel is validated before any are created so either all or none of them are created.
+    /// Returns the IDs of the new channels in the order they were given if successful.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub in which the channels should be created.
+    /// * `user_id` - ID of the user to check for permission to create the channels.
+    /// * `channels` - Names, descriptions and permissions of the new channels.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * One of the names failed to pass the checks for any of the reasons outlined in [`normalize_name`].
+    /// * One of the descriptions is bigger than `max_description_size`.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The user does not have permission to create new channels.
+    /// * Permissions are given and the user does not have the [`HubPermission::Administrate`] permission.
+    /// * Permissions are given for a group or member that is not in the hub.
+    /// * A channel could not be created for any of the reasons outlined by [`Hub::new_channel`].
+    pub async fn create_batch(
+        hub_id: ID,
+        user_id: ID,
+        channels: Vec<HttpChannelSpec>,
+        server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut specs = Vec::with_capacity(channels.len());
+        for spec in channels {
+            check_description_size(&spec.description, max_description_size)?;
+            specs.push((
+                normalize_name(&spec.name)?,
+                spec.description,
+                spec.permissions,
+            ));
+        }
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::ManageChannels)?;
+        for (_, _, permissions) in &specs {
+            if !permissions.groups.is_empty() || !permissions.members.is_empty() {
+                hub.check_administrative(&user_id, HubPermission::Administrate)?;
+            }
+            if permissions
+                .groups
+                .keys()
+                .any(|group_id| !hub.groups.contains_key(group_id))
+            {
+                return Err(ApiError::GroupNotFound.into());
+            }
+            for member_id in permissions.members.keys() {
+                hub.get_member(member_id)?;
+            }
+        }
+        let mut channel_ids = Vec::with_capacity(specs.len());
+        let mut changes = Vec::with_capacity(specs.len());
+        for (name, description, permissions) in specs {
+            let channel_id = hub.new_channel(&user_id, name, description).await?;
+            for (group_id, settings) in permissions.groups {
+                if let Some(group) = hub.groups.get_mut(&group_id) {
+                    for (permission, value) in settings {
+                        group.set_channel_permission(channel_id, permission, value);
+                    }
+                    group.record_update(user_id);
+                }
+            }
+            for (member_id, settings) in permissions.members {
+                let member = hub.get_member_mut(&member_id)?;
+                for (permission, value) in settings {
+                    member.set_channel_permission(channel_id, permission, value);
+                }
+            }
+            changes.push(hub.record_change(WsHubUpdateType::ChannelCreated(channel_id)));
+            channel_ids.push(channel_id);
+        }
+        hub.save(&cache).await?;
+        for change in changes {
+            let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        }
+        Ok(Response::Success(channel_ids))
+    }
+
+    /// Gets a channel's information.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user that is requesting the information.
+    /// * `hub_id` - ID of the hub the channel is in.
+    /// * `channel_id` - ID of the channel to get.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channel does not exist.
+    pub async fn get(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        Ok(Response::Success(
+            hub.get_channel(&user_id, channel_id)?.clone(),
+        ))
+    }
+
+    /// Changes the info of a channel.
+    /// Returns the previous info of the channel if successful.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to check for permission to rename the channel.
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to be renamed.
+    /// * `update` - New info for the channel.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * THe user is not in the hub.
+    /// * The name failed to pass the checks for any of the reasons outlined in [`normalize_name`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The user does not have permission to manage the channel.
+    /// * The given description is bigger than `max_description_size`.
+    /// * The channel could not be renamed for any of the reasons outlined by [`Hub::rename_channel`].
+    pub async fn update(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        update: HttpChannelUpdate,
+        server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_channel_administrative(&user_id, channel_id, ChannelPermission::Manage)?;
+        let channel = hub
+            .channels
+            .get_mut(&channel_id)
+            .map_or_else(|| Err(ApiError::ChannelNotFound), Ok)?;
+        let mut old = HttpChannelUpdate::default();
+        if let Some(name) = update.name {
+            old.name = Some(mem::replace(&mut channel.name, normalize_name(&name)?));
+        }
+        if let Some(description) = update.description {
+            check_description_size(&description, max_description_size)?;
+            old.description = Some(mem::replace(&mut channel.description, description));
+        }
+        if old.name.is_some() || old.description.is_some() {
+            channel.record_update(user_id);
+            hub.record_channel_info_change(
+                channel_id,
+                ChannelInfoChange {
+                    time: chrono::Utc::now(),
+                    changed_by: user_id,
+                    old_name: old.name.clone(),
+                    old_description: old.description.clone(),
+                },
+            );
+        }
+        let change = hub.record_change(WsHubUpdateType::ChannelUpdated(channel_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(Response::Success(old))
+    }
+
+    /// Gets the changes made to the name and description of a channel, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to get the history of.
+    /// * `user_id` - ID of the user to check for permission to see the history.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user is not allowed to see the history for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * The channel does not exist.
+    pub async fn history(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::Administrate)?;
+        if !hub.channels.contains_key(&channel_id) {
+            return Err(ApiError::ChannelNotFound.into());
+        }
+        Ok(Response::Success(
+            hub.channel_history
+                .get(&channel_id)
+                .cloned()
+                .unwrap_or_default(),
+        ))
+    }
+
+    /// Gets the permissions that permission groups and hub members explicitly allow or deny in a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to get the permission settings for.
+    /// * `user_id` - ID of the user to check for permission to see the settings.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The user is not allowed to see the settings for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * The channel does not exist.
+    pub async fn permissions(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.check_administrative(&user_id, HubPermission::Administrate)?;
+        if !hub.channels.contains_key(&channel_id) {
+            return Err(ApiError::ChannelNotFound.into());
+        }
+        let set = |permissions: Option<&ChannelPermissions>| {
+            permissions
+                .map(|permissions| {
+                    permissions
+                        .iter()
+                        .filter(|(_, setting)| setting.is_some())
+                        .map(|(permission, setting)| (*permission, *setting))
+                        .collect::<ChannelPermissions>()
+                })
+                .filter(|permissions| !permissions.is_empty())
+        };
+        let groups = hub
+            .groups
+            .values()
+            .filter_map(|group| Some((group.id, set(group.channel_permissions.get(&channel_id))?)))
+            .collect();
+        let members = hub
+            .members
+            .values()
+            .filter_map(|member| {
+                Some((
+                    member.user_id,
+                    set(member.channel_permissions.get(&channel_id))?,
+                ))
+            })
+            .collect();
+        Ok(Response::Success(HttpChannelPermissionOverrides {
+            groups,
+            members,
+        }))
+    }
+
+    /// Gets the user's draft in a channel, `None` if they do not have one.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to get the draft for.
+    /// * `user_id` - ID of the user whose draft to get.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The draft could not be loaded for any of the reasons outlined by [`Draft::load`].
+    pub async fn get_draft(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        Hub::load_cached(hub_id, &cache)
+            .await?
+            .get_channel(&user_id, channel_id)?;
+        Ok(Response::Success(
+            Draft::load(&user_id, &hub_id, &channel_id).await?,
+        ))
+    }
+
+    /// Saves the user's draft in a channel, replacing the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel the draft is for.
+    /// * `user_id` - ID of the user saving the draft.
+    /// * `data` - Content of the draft.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The content is bigger than [`crate::MAX_MESSAGE_SIZE`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The draft could not be saved for any of the reasons outlined by [`Draft::save`].
+    pub async fn set_draft(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        data: HttpSetDraft,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let draft = Draft::new(data.content)?;
+        Hub::load_cached(hub_id, &cache)
+            .await?
+            .get_channel(&user_id, channel_id)?;
+        draft.save(&user_id, &hub_id, &channel_id).await?;
+        Ok(ok())
+    }
+
+    /// Deletes the user's draft in a channel, usually once the message has been sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel the draft is for.
+    /// * `user_id` - ID of the user whose draft to delete.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the reasons outlined by [`Draft::delete`].
+    pub async fn delete_draft(hub_id: ID, channel_id: ID, user_id: ID) -> Result<impl Reply> {
+        Draft::delete(&user_id, &hub_id, &channel_id).await?;
+        Ok(ok())
+    }
+
+    /// Compacts the message files of a channel, returning how many files and messages are left.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to compact.
+    /// * `user_id` - ID of the user to check for permission to compact the channel.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channel could not be compacted for any of the reasons outlined by [`Hub::compact_channel`].
+    pub async fn compact(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        Ok(Response::Success(
+            hub.compact_channel(&user_id, channel_id).await?,
+        ))
+    }
+
+    /// Deletes a text channel in a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user to check for permission to delete channels.
+    /// * `hub_id` - ID of the hub that has the channel.
+    /// * `channel_id` - ID of the channel to be deleted.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * THe user is not in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
+    /// * The user does not have permission to delete channels.
+    /// * The channel could not be deleted for any of the reasons outlined by [`Hub::delete_channel`].
+    pub async fn delete(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.delete_channel(&user_id, channel_id).await?;
+        let change = hub.record_change(WsHubUpdateType::ChannelDeleted(channel_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(ok())
+    }
+}
+
+pub mod message {
+    use super::*;
+
+    /// Gets a message from a text channel in a hub.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the message.
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `message_id` - ID of the message to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel could not be found in the hub.
+    /// * The message could not be found.
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get(
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let blocks = BlockList::load(&user_id).await?;
+        match channel.get_message(message_id).await {
+            Some(message) if !blocks.is_blocked(&message.sender) => Ok(Response::Success(message)),
+            _ => Err(ApiError::MessageNotFound.into()),
+        }
+    }
+
+    /// Checks if a message exists and the user can read it without returning its content, `false` if the message does not exist or its sender is blocked by the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `message_id` - ID of the message to look for.
+    /// * `user_id` - ID of the user who is checking for the message.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn exists(
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let blocks = BlockList::load(&user_id).await?;
+        let exists = channel
+            .get_message(message_id)
+            .await
+            .map_or(false, |message| !blocks.is_blocked(&message.sender));
+        Ok(Response::Success(exists))
+    }
+
+    /// Gets messages sent after a given message.
+    /// If successful they are returned in an array. The array is orderd oldest message to newest
+    /// If the given message is not found or there are no messages after the given message, an empty array is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the message.
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `from` - ID of the message to start from.
+    /// * `max` - The maximum number of messages to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel could not be found in the hub.
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_after(
+        hub_id: ID,
+        channel_id: ID,
+        query: HttpMessagesAfterQuery,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        Ok(Response::Success(
+            filter_blocked(
+                &user_id,
+                channel.get_messages_after(query.from, query.max).await,
+            )
+            .await?,
+        ))
+    }
+
+    /// Gets a page of messages, oldest first, see [`Channel::get_messages_page`].
+    /// Unlike paginating by time or by message ID, pages taken with the returned cursor stay consistent with the first page
+    /// when messages are sent or edited while paging, but not when messages are deleted, expire or the channel is compacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the messages.
+    /// * `hub_id` - ID of the hub where the channel is located.
+    /// * `channel_id` - ID of the channel to get messages from.
+    /// * `query` - Cursor returned with the previous page and the maximum number of messages to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_page(
+        hub_id: ID,
+        channel_id: ID,
+        query: HttpMessagePageQuery,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let mut page = channel
+            .get_messages_page(query.position, query.as_of, query.max)
+            .await;
+        page.messages = filter_blocked(&user_id, page.messages).await?;
+        Ok(Response::Success(page))
+    }
+
+    /// Gets messages sent before a given message.
+    /// If successful they are returned in an array. The array is orderd oldest message to newest
+    /// If there are no messages before the given message or the given message is not found, an empty array is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the message.
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `from` - ID of the message to go up to.
+    /// * `max` - The maximum number of messages to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel could not be found in the hub.
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_before(
+        hub_id: ID,
+        channel_id: ID,
+        query: HttpMessagesBeforeQuery,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        Ok(Response::Success(
+            filter_blocked(
+                &user_id,
+                channel.get_messages_before(query.to, query.max).await,
+            )
+            .await?,
+        ))
+    }
+
+    /// Gets messages sent before a given message.
+    /// If successful they are returned in an array. The array is orderd oldest message to newest
+    /// If there are no messages before the given message or the given message is not found, an empty array is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the message.
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `from` - ID of the message to go up to.
+    /// * `max` - The maximum number of messages to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel could not be found in the hub.
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_last(
+        hub_id: ID,
+        channel_id: ID,
+        query: HttpLastMessagesQuery,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        Ok(Response::Success(
+            filter_blocked(&user_id, channel.get_last_messages(query.max).await).await?,
+        ))
+    }
+
+    /// Gets a set of messages between two times (both in milliseconds since Unix Epoch).
+    /// If successful they are returned in an array. The array is orderd oldest message to newest
+    /// unless the `invert` argument is `true` in which case the order is newest to oldest message.
+    /// If there are no messages in the given time frame, an empty array is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user who is requesting the message.
+    /// * `hub_id` - ID of the hub where the message is located.
+    /// * `channel_id` - ID of the channel where the message is located.
+    /// * `from` - Earliest time a message can be sent to be included in the results  (UTC millis).
+    /// * `to` - Latest time a message can be sent to be included in the results (UTC millis).
+    /// * `invert` - If true the search is done from newest message to oldest message, if false the search is done from oldest message to newest message.
+    /// * `max` - The maximum number of messages to retreive.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel could not be found in the hub.
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn get_between(
+        hub_id: ID,
+        channel_id: ID,
+        query: HttpMessagesBetweenQuery,
+        user_id: ID,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        Ok(Response::Success(
+            filter_blocked(
+                &user_id,
+                channel
+                    .get_messages_between(query.from, query.to, query.new_to_old, query.max)
+                    .await,
+            )
+            .await?,
+        ))
+    }
+
+    /// Sends a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - Address of the server to notify of the message.
+    /// * `user_id` - ID of the user who is sending the message.
+    /// * `hub_id` - Hub where the message should be sent.
+    /// * `channel_id` - Channel where the message should be sent.
+    /// * `message` - Body of the messaeg, its flags and optionally when it should expire.
+    ///
+    /// # Errors
+    ///
+    /// * The user sending the message is not in the hub.
+    /// * The user does not have permission to send messages in the hub.
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The message could not be sent for any of the reasons outlined by [`Channel::add_message`].
+    pub async fn send(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        data: HttpSendMessage,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(ApiError::TooBig.into());
+        }
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let message = hub
+            .send_expiring_message(
+                user_id,
+                channel_id,
+                data.message,
+                data.flags,
+                data.expires_at,
+            )
+            .await?;
+        let id = message.id;
+        let _ = server.send(ServerNotification::NewMessage(message));
+        Ok(Response::Success(id))
+    }
+
+    /// Forwards a message from a channel the user can read, returning the ID of the copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - Hub where the copy should be sent.
+    /// * `channel_id` - Channel where the copy should be sent.
+    /// * `user_id` - ID of the user forwarding the message.
+    /// * `data` - Location of the message to forward.
+    /// * `server` - Address of the server to notify of the copy.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * Either hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The channel of the original message could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The original message does not exist.
+    /// * The copy could not be sent for any of the reasons outlined by [`Hub::forward_message`].
+    pub async fn forward(
+        hub_id: ID,
+        channel_id: ID,
+        user_id: ID,
+        data: HttpForwardMessage,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let original = Hub::load_cached(data.hub_id, &cache)
+            .await?
+            .get_channel(&user_id, data.channel_id)?
+            .get_message(data.message_id)
+            .await
+            .ok_or(ApiError::MessageNotFound)?;
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let message = hub.forward_message(user_id, channel_id, &original).await?;
+        let id = message.id;
+        let _ = server.send(ServerNotification::NewMessage(message));
+        Ok(Response::Success(id))
+    }
+
+    /// Deletes a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub the message was sent in.
+    /// * `channel_id` - ID of the channel the message was sent in.
+    /// * `message_id` - ID of the message to delete.
+    /// * `user_id` - ID of the user deleting the message.
+    /// * `grace_period` - How long senders can delete their own messages for in milliseconds.
+    /// * `server` - Server to notify of the deletion so that the message is removed from the search index and subscribed clients are told.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The message could not be deleted for any of the reasons outlined by [`Hub::delete_message`].
+    pub async fn delete(
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        user_id: ID,
+        grace_period: i64,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.delete_message(&user_id, channel_id, message_id, grace_period)
+            .await?;
+        let _ = server.send(ServerNotification::MessageDeleted(
+            hub_id, channel_id, message_id,
+        ));
+        Ok(ok())
+    }
+
+    /// Edits a message, returns the edited message.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub the message was sent in.
+    /// * `channel_id` - ID of the channel the message was sent in.
+    /// * `message_id` - ID of the message to edit.
+    /// * `user_id` - ID of the user editing the message.
+    /// * `data` - New content of the message.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The new content is bigger than [`crate::MAX_MESSAGE_SIZE`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The message could not be edited for any of the reasons outlined by [`Hub::edit_message`].
+    pub async fn edit(
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        user_id: ID,
+        data: HttpEditMessage,
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> Result<impl Reply> {
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(ApiError::TooBig.into());
+        }
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let message = hub
+            .edit_message(&user_id, channel_id, message_id, data.message)
+            .await?;
+        let _ = server.send(ServerNotification::MessageEdited(message.clone()));
+        Ok(Response::Success(message))
+    }
+
+    /// Checks a message as if it was being sent without sending it, returning the filtered content and the members it mentions.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - Hub where the message would be sent.
+    /// * `channel_id` - Channel where the message would be sent.
+    /// * `user_id` - ID of the user who would send the message.
+    /// * `data` - The message and its flags.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
     ///
-    /// * The user doing the check is not in the hub.
-    /// * The user whose permission is being checked is not in the hub.
-    /// * The user making the check does not have permission to do so.
+    /// * The message is bigger than [`crate::MAX_MESSAGE_SIZE`].
+    /// * The message would expire in the past.
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get_channel_permission(
-        actor_id: ID,
+    /// * The message could not be sent for any of the reasons outlined by [`Hub::preview_message`].
+    pub async fn preview(
         hub_id: ID,
-        member_id: ID,
         channel_id: ID,
-        permission: ChannelPermission,
+        user_id: ID,
+        data: HttpSendMessage,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(ApiError::TooBig.into());
+        }
+        if data
+            .expires_at
+            .map_or(false, |expires_at| expires_at <= chrono::Utc::now())
         {
-            let member = hub.get_member(&actor_id)?;
-            check_permission!(member, channel_id, ChannelPermission::Read, hub);
+            return Err(ApiError::InvalidTime.into());
         }
-        let member = hub.get_member(&member_id)?;
-        Ok(Response::Success(
-            member.has_channel_permission(channel_id, permission, &hub),
-        ))
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let message = hub.preview_message(user_id, channel_id, data.message, data.flags)?;
+        let mentions = message
+            .mentions()
+            .into_iter()
+            .filter(|id| hub.members.contains_key(id))
+            .collect();
+        Ok(Response::Success(HttpMessagePreview {
+            content: message.content,
+            mentions,
+            flags: message.flags,
+        }))
     }
 
-    /// Handles kicking, banning, muting, unbanning and unmuting users in/from hubs.
-    async fn hub_user_op(
-        server: ServerAddress,
-        actor_id: ID,
+    /// Searches a channel for messages matching a query, returning the IDs of the messages found.
+    ///
+    /// # Arguments
+    ///
+    /// * `hub_id` - ID of the hub the channel is in.
+    /// * `channel_id` - ID of the channel to search.
+    /// * `query` - The query, maximum number of results and optionally the ID of the user whose messages to search.
+    /// * `user_id` - ID of the user who is searching.
+    /// * `server` - Address of the server, used to reach the search index.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
+    /// * The query could not be parsed or the search index could not be read.
+    /// * Search is disabled on the server.
+    pub async fn search(
         hub_id: ID,
+        channel_id: ID,
+        query: HttpSearchQuery,
         user_id: ID,
-        op: HubPermission,
+        server: ServerAddress,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        let member = hub.get_member(&actor_id)?;
-        check_permission!(member, op, hub);
-        match op {
-            HubPermission::Kick => {
-                hub.kick_user(&user_id)?;
-                let _ = server.send(ServerNotification::HubUpdated(
-                    hub_id,
-                    WsHubUpdateType::UserKicked(user_id),
-                ));
-            }
-            HubPermission::Ban => {
-                hub.ban_user(user_id)?;
-                let _ = server.send(ServerNotification::HubUpdated(
-                    hub_id,
-                    WsHubUpdateType::UserBanned(user_id),
-                ));
-            }
-            HubPermission::Unban => {
-                hub.unban_user(&user_id);
-                let _ = server.send(ServerNotification::HubUpdated(
-                    hub_id,
-                    WsHubUpdateType::UserUnbanned(user_id),
-                ));
-            }
-            HubPermission::Mute => {
-                hub.mute_user(user_id)?;
-                let _ = server.send(ServerNotification::HubUpdated(
-                    hub_id,
-                    WsHubUpdateType::UserMuted(user_id),
-                ));
-            }
-            HubPermission::Unmute => {
-                hub.unmute_user(&user_id);
-                let _ = server.send(ServerNotification::HubUpdated(
-                    hub_id,
-                    WsHubUpdateType::UserUnmuted(user_id),
-                ));
-            }
-            _ => return Err(ApiError::InternalError.into()),
-        }
-        hub.save().await?;
-        Ok(ok())
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        hub.get_channel(&user_id, channel_id)?;
+        let message_server = server
+            .call(GetMessageServer { hub_id, channel_id })
+            .await
+            .map_err(|_| ApiError::InternalError)?
+            .ok_or(ApiError::SearchDisabled)?;
+        let ids = message_server
+            .call(SearchMessageIndex {
+                hub_id,
+                channel_id,
+                limit: query.limit,
+                query: query.query,
+                sender: query.sender,
+            })
+            .await
+            .map_err(|_| ApiError::InternalError)??;
+        Ok(Response::Success(ids))
     }
+}
 
-    /// Maps the different possible options for [`hub_user_op`] to separate functions.
-    macro_rules! action_fns {
-      ($($(#[$attr:meta])* => ($fnName:ident, $variant:ident)),*) => {
-        $(
-          $(#[$attr])*
-          pub async fn $fnName(actor_id: ID, hub_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
-              hub_user_op(server, actor_id, hub_id, user_id, HubPermission::$variant).await
-          }
-        )*
-      }
+pub mod dm {
+    use super::*;
+
+    /// Opens the direct message channel between the requesting user and another user, creating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `other_id` - ID of the user to open the channel with.
+    /// * `user_id` - ID of the user opening the channel.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the reasons outlined by [`DmChannel::open`].
+    pub async fn open(other_id: ID, user_id: ID) -> Result<impl Reply> {
+        Ok(Response::Success(DmChannel::open(user_id, other_id).await?))
     }
 
-    action_fns! {
-        /// Kicks a user from a hub.
-        ///
-        /// # Arguments
-        ///
-        /// * `actor_id` - ID of the user who is doing the kicking.
-        /// * `hub_id` - Hub from which the user is being kicked.
-        /// * `user_id` - ID of the user who is to be kicked.
-        ///
-        /// # Errors
-        ///
-        /// This function may fail for any of the following reasons:
-        ///
-        /// * The user doing the kicking is not in the hub
-        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-        /// * The user to be kicked is not in the hub.
-        /// * The user doing the kicking does not have permission to kick other users.
-        /// * The kick failed for any of the reasons outlined by [`Hub::kick_user`].
-        => (kick, Kick),
-        /// Bans a user from a hub.
-        ///
-        /// # Arguments
-        ///
-        /// * `actor_id` - ID of the user who is performing the ban.
-        /// * `hub_id` - Hub from which the user is being banned.
-        /// * `user_id` - ID of the user who is to be banned.
-        ///
-        /// # Errors
-        ///
-        /// This function may fail for any of the following reasons:
-        ///
-        /// * The user performing the ban is not in the hub
-        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-        /// * The user performing the ban does not have permission to ban other users.
-        /// * The ban failed for any of the reasons outlined by [`Hub::ban_user`].
-        => (ban, Ban),
-        /// Unbans a user from a hub.
-        ///
-        /// # Arguments
-        ///
-        /// * `actor_id` - ID of the user who is unbanning.
-        /// * `hub_id` - Hub from which the user is being unbanned.
-        /// * `user_id` - ID of the user who is to be unbanned.
-        ///
-        /// # Errors
-        ///
-        /// This function may fail for any of the following reasons:
-        ///
-        /// * The user performing the unban is not in the hub
-        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-        /// * The user doing the unban does not have permission to unban other users.
-        /// * The unban failed for any of the reasons outlined by [`Hub::unban_user`].
-        => (unban, Unban),
-        /// Mutes a user in a hub.
-        ///
-        /// # Arguments
-        ///
-        /// * `actor_id` - ID of the user who is muting.
-        /// * `hub_id` - Hub in which the user is being muted.
-        /// * `user_id` - ID of the user who is to be muted.
-        ///
-        /// # Errors
-        ///
-        /// This function may fail for any of the following reasons:
-        ///
-        /// * The user performing the mute is not in the hub
-        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-        /// * The user performing the mute does not have permission to mute other users.
-        /// * The mute failed for any of the reasons outlined by [`Hub::mute_user`].
-        => (mute, Mute),
-        /// Unmutes a user in a hub.
-        ///
-        /// # Arguments
-        ///
-        /// * `actor_id` - ID of the user who is unmuting.
-        /// * `hub_id` - Hub in which the user is being unmuted.
-        /// * `user_id` - ID of the user who is to be unmuted.
-        ///
-        /// # Errors
-        ///
-        /// This function may fail for any of the following reasons:
-        ///
-        /// * The user performing the unmute is not in the hub
-        /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-        /// * The user performing the unmute does not have permission to unmute other users.
-        /// * The unmute failed for any of the reasons outlined by [`Hub::unmute_user`].
-        => (unmute, Unmute)
+    /// Gets a direct message channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `dm_id` - ID of the channel to get.
+    /// * `user_id` - ID of the user requesting the channel.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The channel could not be loaded for any of the reasons outlined by [`DmChannel::load`].
+    /// * The user is not one of the participants.
+    pub async fn get(dm_id: ID, user_id: ID) -> Result<impl Reply> {
+        let dm = DmChannel::load(dm_id).await?;
+        dm.check_participant(&user_id)?;
+        Ok(Response::Success(dm))
     }
-}
 
-pub mod channel {
-    use super::*;
+    /// Gets the last messages sent in a direct message channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `dm_id` - ID of the channel to get the messages from.
+    /// * `query` - Maximum number of messages to get.
+    /// * `user_id` - ID of the user requesting the messages.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The channel could not be loaded for any of the reasons outlined by [`DmChannel::load`].
+    /// * The user is not one of the participants.
+    pub async fn get_last(
+        dm_id: ID,
+        query: HttpLastMessagesQuery,
+        user_id: ID,
+    ) -> Result<impl Reply> {
+        let dm = DmChannel::load(dm_id).await?;
+        dm.check_participant(&user_id)?;
+        Ok(Response::Success(
+            filter_blocked(&user_id, dm.channel().get_last_messages(query.max).await).await?,
+        ))
+    }
 
-    /// Creates a text channel in a hub.
-    /// Returns the ID of the new channel if successful.
+    /// Gets messages sent in a direct message channel before a given message.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to check for permission to create the channel.
-    /// * `hub_id` - ID of the hub in which the channel should be created.
-    /// * `name` - Name for the new channel.
+    /// * `dm_id` - ID of the channel to get the messages from.
+    /// * `query` - ID of the message to go up to and the maximum number of messages to get.
+    /// * `user_id` - ID of the user requesting the messages.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * THe user is not in the hub.
-    /// * The name failed to pass the checks for any of the reasons outlined in [`check_name_validity`].
-    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    /// * The user does not have permission to create new channels.
-    /// * The channel could not be created for any of the reasons outlined by [`Hub::new_channel`].
-    pub async fn create(
-        hub_id: ID,
+    /// * The channel could not be loaded for any of the reasons outlined by [`DmChannel::load`].
+    /// * The user is not one of the participants.
+    pub async fn get_before(
+        dm_id: ID,
+        query: HttpMessagesBeforeQuery,
         user_id: ID,
-        data: HttpChannelUpdate,
+    ) -> Result<impl Reply> {
+        let dm = DmChannel::load(dm_id).await?;
+        dm.check_participant(&user_id)?;
+        Ok(Response::Success(
+            filter_blocked(
+                &user_id,
+                dm.channel().get_messages_before(query.to, query.max).await,
+            )
+            .await?,
+        ))
+    }
+
+    /// Sends a message in a direct message channel, returns the ID of the new message.
+    ///
+    /// # Arguments
+    ///
+    /// * `dm_id` - ID of the channel to send the message in.
+    /// * `user_id` - ID of the user sending the message.
+    /// * `data` - The message to send.
+    /// * `server` - Address of the server actor used to notify the participants.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The message is too big.
+    /// * The channel could not be loaded for any of the reasons outlined by [`DmChannel::load`].
+    /// * The message could not be sent for any of the reasons outlined by [`DmChannel::send_message`].
+    pub async fn send(
+        dm_id: ID,
+        user_id: ID,
+        data: HttpSendMessage,
         server: ServerAddress,
     ) -> Result<impl Reply> {
-        let name = data.name.unwrap_or_default();
-        let description = data.description.unwrap_or_default();
-        check_name_validity(&name)?;
-        if description.as_bytes().len() > crate::MAX_DESCRIPTION_SIZE {
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
             return Err(ApiError::TooBig.into());
         }
-        let mut hub = Hub::load(hub_id).await?;
-        let channel_id = hub.new_channel(&user_id, name, description).await?;
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::ChannelCreated(channel_id),
-        ));
-        Ok(Response::Success(channel_id))
+        let dm = DmChannel::load(dm_id).await?;
+        let message = dm.send_message(user_id, data.message, data.flags).await?;
+        let id = message.id;
+        let participants = vec![dm.users.0, dm.users.1].into_iter().collect();
+        let _ = server.send(ServerNotification::NewDirectMessage(message, participants));
+        Ok(Response::Success(id))
     }
 
-    /// Gets a channel's information.
+    /// Creates a group conversation, returns the new group.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user that is requesting the information.
-    /// * `hub_id` - ID of the hub the channel is in.
-    /// * `channel_id` - ID of the channel to get.
+    /// * `user_id` - ID of the user creating the group, always a member of the new group.
+    /// * `data` - Name and other members of the group.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the reasons outlined by [`GroupDm::create`].
+    pub async fn create_group(user_id: ID, data: HttpCreateGroupDm) -> Result<impl Reply> {
+        Ok(Response::Success(
+            GroupDm::create(data.name, user_id, data.members).await?,
+        ))
+    }
+
+    /// Gets a group conversation.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - ID of the group to get.
+    /// * `user_id` - ID of the user requesting the group.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The group could not be loaded for any of the reasons outlined by [`GroupDm::load`].
+    /// * The user is not a member of the group.
+    pub async fn get_group(group_id: ID, user_id: ID) -> Result<impl Reply> {
+        let group = GroupDm::load(group_id).await?;
+        group.check_member(&user_id)?;
+        Ok(Response::Success(group))
+    }
+
+    /// Gets the last messages sent in a group conversation.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - ID of the group to get the messages from.
+    /// * `query` - Maximum number of messages to get.
+    /// * `user_id` - ID of the user requesting the messages.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The group could not be loaded for any of the reasons outlined by [`GroupDm::load`].
+    /// * The user is not a member of the group.
+    pub async fn get_group_last(
+        group_id: ID,
+        query: HttpLastMessagesQuery,
+        user_id: ID,
+    ) -> Result<impl Reply> {
+        let group = GroupDm::load(group_id).await?;
+        group.check_member(&user_id)?;
+        Ok(Response::Success(
+            filter_blocked(&user_id, group.channel().get_last_messages(query.max).await).await?,
+        ))
+    }
+
+    /// Adds a user to a group conversation.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - ID of the group to add the user to.
+    /// * `member_id` - ID of the user to add.
+    /// * `user_id` - ID of the member adding the user.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The channel does not exist.
-    pub async fn get(hub_id: ID, channel_id: ID, user_id: ID) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        Ok(Response::Success(
-            hub.get_channel(&user_id, channel_id)?.clone(),
-        ))
+    /// * The group could not be loaded for any of the reasons outlined by [`GroupDm::load`].
+    /// * The user could not be added for any of the reasons outlined by [`GroupDm::add_member`].
+    /// * The group could not be saved for any of the reasons outlined by [`GroupDm::save`].
+    pub async fn add_group_member(group_id: ID, member_id: ID, user_id: ID) -> Result<impl Reply> {
+        let mut group = GroupDm::load(group_id).await?;
+        group.add_member(&user_id, member_id)?;
+        group.save().await?;
+        Ok(ok())
     }
 
-    /// Changes the info of a channel.
-    /// Returns the previous info of the channel if successful.
+    /// Removes a user from a group conversation.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to check for permission to rename the channel.
-    /// * `hub_id` - ID of the hub that has the channel.
-    /// * `channel_id` - ID of the channel to be renamed.
-    /// * `update` - New info for the channel.
+    /// * `group_id` - ID of the group to remove the user from.
+    /// * `member_id` - ID of the user to remove.
+    /// * `user_id` - ID of the member removing the user.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * THe user is not in the hub.
-    /// * The name failed to pass the checks for any of the reasons outlined in [`check_name_validity`].
-    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    /// * The user does not have permission to manage the channel.
-    /// * The given description is bigger than [`crate::MAX_DESCRIPTION_SIZE`].
-    /// * The channel could not be renamed for any of the reasons outlined by [`Hub::rename_channel`].
-    pub async fn update(
-        hub_id: ID,
-        channel_id: ID,
+    /// * The group could not be loaded for any of the reasons outlined by [`GroupDm::load`].
+    /// * The user could not be removed for any of the reasons outlined by [`GroupDm::remove_member`].
+    /// * The group could not be saved for any of the reasons outlined by [`GroupDm::save`].
+    pub async fn remove_group_member(
+        group_id: ID,
+        member_id: ID,
         user_id: ID,
-        update: HttpChannelUpdate,
-        server: ServerAddress,
     ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        let member = hub.get_member(&user_id)?;
-        check_permission!(member, channel_id, ChannelPermission::Manage, hub);
-        let channel = hub
-            .channels
-            .get_mut(&channel_id)
-            .map_or_else(|| Err(ApiError::ChannelNotFound), Ok)?;
-        let mut old = HttpChannelUpdate::default();
-        if let Some(name) = update.name {
-            check_name_validity(&name)?;
-            old.name = Some(mem::replace(&mut channel.name, name));
-        }
-        if let Some(description) = update.description {
-            if description.as_bytes().len() > crate::MAX_DESCRIPTION_SIZE {
-                return Err(ApiError::TooBig.into());
-            }
-            old.description = Some(mem::replace(&mut channel.description, description));
-        }
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::ChannelUpdated(channel_id),
-        ));
-        Ok(Response::Success(old))
+        let mut group = GroupDm::load(group_id).await?;
+        group.remove_member(&user_id, &member_id)?;
+        group.save().await?;
+        Ok(ok())
     }
 
-    /// Deletes a text channel in a hub.
+    /// Sends a message in a group conversation, returns the ID of the new message.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user to check for permission to delete channels.
-    /// * `hub_id` - ID of the hub that has the channel.
-    /// * `channel_id` - ID of the channel to be deleted.
+    /// * `group_id` - ID of the group to send the message in.
+    /// * `user_id` - ID of the user sending the message.
+    /// * `data` - The message to send.
+    /// * `server` - Address of the server actor used to notify the members.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * THe user is not in the hub.
-    /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
-    /// * The user does not have permission to delete channels.
-    /// * The channel could not be deleted for any of the reasons outlined by [`Hub::delete_channel`].
-    pub async fn delete(
-        hub_id: ID,
-        channel_id: ID,
+    /// * The message is too big.
+    /// * The group could not be loaded for any of the reasons outlined by [`GroupDm::load`].
+    /// * The message could not be sent for any of the reasons outlined by [`GroupDm::send_message`].
+    pub async fn send_group(
+        group_id: ID,
         user_id: ID,
+        data: HttpSendMessage,
         server: ServerAddress,
     ) -> Result<impl Reply> {
-        let mut hub = Hub::load(hub_id).await?;
-        hub.delete_channel(&user_id, channel_id).await?;
-        hub.save().await?;
-        let _ = server.send(ServerNotification::HubUpdated(
-            hub_id,
-            WsHubUpdateType::ChannelDeleted(channel_id),
-        ));
-        Ok(ok())
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(ApiError::TooBig.into());
+        }
+        let group = GroupDm::load(group_id).await?;
+        let message = group
+            .send_message(user_id, data.message, data.flags)
+            .await?;
+        let id = message.id;
+        let _ = server.send(ServerNotification::NewDirectMessage(message, group.members));
+        Ok(Response::Success(id))
     }
 }
 
-pub mod message {
+pub mod thread {
     use super::*;
 
-    /// Gets a message from a text channel in a hub.
+    /// Starts a thread from a message, returning the new thread.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is requesting the message.
-    /// * `hub_id` - ID of the hub where the message is located.
-    /// * `channel_id` - ID of the channel where the message is located.
-    /// * `message_id` - ID of the message to retreive.
+    /// * `hub_id` - ID of the hub the message is in.
+    /// * `channel_id` - ID of the channel the message is in.
+    /// * `message_id` - ID of the message to start the thread from.
+    /// * `user_id` - ID of the user starting the thread.
+    /// * `data` - Name of the thread.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The channel could not be found in the hub.
-    /// * The message could not be found.
-    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The thread could not be created for any of the reasons outlined by [`Hub::create_thread`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get(
+    pub async fn create(
         hub_id: ID,
         channel_id: ID,
         message_id: ID,
         user_id: ID,
+        data: HttpCreateThread,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
-        channel.get_message(message_id).await.map_or_else(
-            || Err(ApiError::MessageNotFound.into()),
-            |m| Ok(Response::Success(m)),
-        )
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let thread = hub
+            .create_thread(&user_id, channel_id, message_id, data.name)
+            .await?;
+        Ok(Response::Success(thread))
     }
 
-    /// Gets messages sent after a given message.
-    /// If successful they are returned in an array. The array is orderd oldest message to newest
-    /// If the given message is not found or there are no messages after the given message, an empty array is returned.
+    /// Gets the threads started in a channel.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is requesting the message.
-    /// * `hub_id` - ID of the hub where the message is located.
-    /// * `channel_id` - ID of the channel where the message is located.
-    /// * `from` - ID of the message to start from.
-    /// * `max` - The maximum number of messages to retreive.
+    /// * `hub_id` - ID of the hub the channel is in.
+    /// * `channel_id` - ID of the channel.
+    /// * `user_id` - ID of the user requesting the threads.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The channel could not be found in the hub.
     /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get_after(
+    pub async fn list(
         hub_id: ID,
         channel_id: ID,
-        query: HttpMessagesAfterQuery,
         user_id: ID,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
         Ok(Response::Success(
-            channel.get_messages_after(query.from, query.max).await,
+            hub.get_threads(&user_id, channel_id).await?,
         ))
     }
 
-    /// Gets messages sent before a given message.
-    /// If successful they are returned in an array. The array is orderd oldest message to newest
-    /// If there are no messages before the given message or the given message is not found, an empty array is returned.
+    /// Gets the number of replies and the last reply in the threads started from a message.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is requesting the message.
-    /// * `hub_id` - ID of the hub where the message is located.
-    /// * `channel_id` - ID of the channel where the message is located.
-    /// * `from` - ID of the message to go up to.
-    /// * `max` - The maximum number of messages to retreive.
+    /// * `hub_id` - ID of the hub the message is in.
+    /// * `channel_id` - ID of the channel the message is in.
+    /// * `message_id` - ID of the message the threads were started from.
+    /// * `user_id` - ID of the user requesting the summary.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The channel could not be found in the hub.
-    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The summary could not be gotten for any of the reasons outlined by [`Hub::get_thread_summary`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get_before(
+    pub async fn summary(
         hub_id: ID,
         channel_id: ID,
-        query: HttpMessagesBeforeQuery,
+        message_id: ID,
         user_id: ID,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
         Ok(Response::Success(
-            channel.get_messages_before(query.to, query.max).await,
+            hub.get_thread_summary(&user_id, channel_id, message_id)
+                .await?,
         ))
     }
 
-    /// Gets messages sent before a given message.
-    /// If successful they are returned in an array. The array is orderd oldest message to newest
-    /// If there are no messages before the given message or the given message is not found, an empty array is returned.
+    /// Gets the last messages sent in a thread.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is requesting the message.
-    /// * `hub_id` - ID of the hub where the message is located.
-    /// * `channel_id` - ID of the channel where the message is located.
-    /// * `from` - ID of the message to go up to.
-    /// * `max` - The maximum number of messages to retreive.
+    /// * `hub_id` - ID of the hub the thread is in.
+    /// * `channel_id` - ID of the channel the thread was started in.
+    /// * `thread_id` - ID of the thread.
+    /// * `query` - Maximum number of messages to get.
+    /// * `user_id` - ID of the user requesting the messages.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The channel could not be found in the hub.
-    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The thread could not be gotten for any of the reasons outlined by [`Hub::get_thread`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
     pub async fn get_last(
         hub_id: ID,
         channel_id: ID,
+        thread_id: ID,
         query: HttpLastMessagesQuery,
         user_id: ID,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let thread = hub.get_thread(&user_id, channel_id, thread_id).await?;
         Ok(Response::Success(
-            channel.get_last_messages(query.max).await,
+            filter_blocked(
+                &user_id,
+                thread.channel().get_last_messages(query.max).await,
+            )
+            .await?,
         ))
     }
 
-    /// Gets a set of messages between two times (both in milliseconds since Unix Epoch).
-    /// If successful they are returned in an array. The array is orderd oldest message to newest
-    /// unless the `invert` argument is `true` in which case the order is newest to oldest message.
-    /// If there are no messages in the given time frame, an empty array is returned.
+    /// Sends a message in a thread, returning the ID of the message.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who is requesting the message.
-    /// * `hub_id` - ID of the hub where the message is located.
-    /// * `channel_id` - ID of the channel where the message is located.
-    /// * `from` - Earliest time a message can be sent to be included in the results  (UTC millis).
-    /// * `to` - Latest time a message can be sent to be included in the results (UTC millis).
-    /// * `invert` - If true the search is done from newest message to oldest message, if false the search is done from oldest message to newest message.
-    /// * `max` - The maximum number of messages to retreive.
+    /// * `hub_id` - ID of the hub the thread is in.
+    /// * `channel_id` - ID of the channel the thread was started in.
+    /// * `thread_id` - ID of the thread.
+    /// * `user_id` - ID of the user sending the message.
+    /// * `data` - The message and its flags.
     ///
     /// # Errors
     ///
     /// This function may return an error for any of the following reasons:
     ///
-    /// * The user is not in the hub.
-    /// * The channel could not be found in the hub.
-    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The message is bigger than [`crate::MAX_MESSAGE_SIZE`].
+    /// * The message could not be sent for any of the reasons outlined by [`Hub::send_thread_message`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    pub async fn get_between(
+    pub async fn send(
         hub_id: ID,
         channel_id: ID,
-        query: HttpMessagesBetweenQuery,
+        thread_id: ID,
         user_id: ID,
+        data: HttpSendMessage,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        let hub = Hub::load(hub_id).await?;
-        let channel = Hub::get_channel(&hub, &user_id, channel_id)?;
-        Ok(Response::Success(
-            channel
-                .get_messages_between(query.from, query.to, query.new_to_old, query.max)
-                .await,
-        ))
+        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(ApiError::TooBig.into());
+        }
+        let lock = Hub::lock(hub_id);
+        let _guard = lock.read().await;
+        let hub = Hub::load_cached(hub_id, &cache).await?;
+        let message = hub
+            .send_thread_message(user_id, channel_id, thread_id, data.message, data.flags)
+            .await?;
+        Ok(Response::Success(message.id))
     }
+}
 
-    /// Sends a message.
+pub mod group {
+    use super::*;
+
+    /// Renames a permission group, returning the group's old name.
     ///
     /// # Arguments
     ///
-    /// * `server` - Address of the server to notify of the message.
-    /// * `user_id` - ID of the user who is sending the message.
-    /// * `hub_id` - Hub where the message should be sent.
-    /// * `channel_id` - Channel where the message should be sent.
-    /// * `message` - Body of the messaeg.
+    /// * `hub_id` - The hub the group is in.
+    /// * `group_id` - The group to rename.
+    /// * `user_id` - ID of the user who is making the change.
+    /// * `name` - The new name of the group.
+    /// * `server` - Address of the server to notify of the change.
     ///
     /// # Errors
     ///
-    /// * The user sending the message is not in the hub.
-    /// * The user does not have permission to send messages in the hub.
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The group could not be renamed for any of the reasons outlined by [`Hub::rename_group`].
+    /// * The hub could not be saved for any of the reasons outlined by [`Hub::save`].
     /// * The hub could not be loaded for any of the reasons outlined by [`Hub::load`].
-    /// * The message could not be sent for any of the reasons outlined by [`Channel::add_message`].
-    pub async fn send(
+    pub async fn rename(
         hub_id: ID,
-        channel_id: ID,
+        group_id: ID,
         user_id: ID,
-        data: HttpSendMessage,
+        name: String,
         server: ServerAddress,
+        cache: HubCache,
     ) -> Result<impl Reply> {
-        if data.message.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
-            return Err(ApiError::TooBig.into());
+        let mut hub = Hub::load_cached(hub_id, &cache).await?;
+        let old = hub.rename_group(&user_id, group_id, name)?;
+        let change = hub.record_change(WsHubUpdateType::GroupRenamed(group_id));
+        hub.save(&cache).await?;
+        let _ = server.send(ServerNotification::HubUpdated(hub_id, change));
+        Ok(Response::Success(old))
+    }
+}
+
+pub mod user {
+    use super::*;
+
+    /// Gets the IDs of the users the requesting user has blocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user whose block list to get.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the reasons outlined by [`BlockList::load`].
+    pub async fn get_blocks(user_id: ID) -> Result<impl Reply> {
+        Ok(Response::Success(BlockList::load(&user_id).await?.blocked))
+    }
+
+    /// Blocks a user, hiding their messages from the requesting user.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocked_id` - ID of the user to block.
+    /// * `user_id` - ID of the user doing the blocking.
+    /// * `server` - Address of the server actor, used to stop delivering the blocked user's messages.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The block list could not be loaded for any of the reasons outlined by [`BlockList::load`].
+    /// * The block list could not be saved for any of the reasons outlined by [`BlockList::save`].
+    pub async fn block(blocked_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
+        let mut blocks = BlockList::load(&user_id).await?;
+        blocks.blocked.insert(blocked_id);
+        blocks.save(&user_id).await?;
+        let _ = server.send(ServerNotification::BlocksUpdated(user_id, blocks));
+        Ok(ok())
+    }
+
+    /// Unblocks a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocked_id` - ID of the user to unblock.
+    /// * `user_id` - ID of the user doing the unblocking.
+    /// * `server` - Address of the server actor, used to resume delivering the unblocked user's messages.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error for any of the following reasons:
+    ///
+    /// * The block list could not be loaded for any of the reasons outlined by [`BlockList::load`].
+    /// * The block list could not be saved for any of the reasons outlined by [`BlockList::save`].
+    pub async fn unblock(blocked_id: ID, user_id: ID, server: ServerAddress) -> Result<impl Reply> {
+        let mut blocks = BlockList::load(&user_id).await?;
+        blocks.blocked.remove(&blocked_id);
+        blocks.save(&user_id).await?;
+        let _ = server.send(ServerNotification::BlocksUpdated(user_id, blocks));
+        Ok(ok())
+    }
+}
+
+pub mod discovery {
+    use super::*;
+    use crate::prelude::HttpDiscoverQuery;
+
+    /// Searches the public directory of hubs, see [`crate::discovery::search`].
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Text the hub's name or description should contain, a tag and category the hub should have and the maximum number of hubs to get.
+    pub async fn search(_user_id: ID, query: HttpDiscoverQuery) -> Result<impl Reply> {
+        Ok(Response::Success(
+            crate::discovery::search(
+                &query.query,
+                query.tag.as_deref(),
+                query.category.as_deref(),
+                query.limit,
+            )
+            .await,
+        ))
+    }
+}
+
+pub mod admin {
+    use super::*;
+    use crate::{
+        prelude::{HttpHubListQuery, HttpHubSummary, HttpMaintenanceMode, HttpServerStats},
+        server::{GetConnectionCount, GetServerState, Server},
+    };
+    use chrono::Utc;
+    use lazy_static::lazy_static;
+    use tokio::sync::Mutex;
+
+    lazy_static! {
+        static ref STATS_CACHE: Mutex<Option<HttpServerStats>> = Mutex::new(None);
+    }
+
+    /// Gets totals for the whole server, only the connection count, deferred indexing count and warmup progress are always up to date, the rest are cached for [`crate::SERVER_STATS_LIFETIME`].
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - Address of the server actor, used to count the open connections.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server actor could not be reached.
+    pub async fn stats(server: ServerAddress) -> Result<impl Reply> {
+        let connections = server
+            .call(GetConnectionCount)
+            .await
+            .map_err(|_| ApiError::InternalError)?;
+        let mut cache = STATS_CACHE.lock().await;
+        let stale = cache.as_ref().map_or(true, |stats| {
+            Utc::now()
+                .signed_duration_since(stats.counted)
+                .num_milliseconds()
+                > crate::SERVER_STATS_LIFETIME
+        });
+        if stale {
+            *cache = Some(Server::count_totals().await);
         }
-        let hub = Hub::load(hub_id).await?;
-        let message = hub.send_message(user_id, channel_id, data.message).await?;
-        let id = message.id;
-        let _ = server.send(ServerNotification::NewMessage(message));
-        Ok(Response::Success(id))
+        let mut stats = cache.clone().expect("stats were just counted");
+        stats.connections = connections;
+        stats.deferred_indexing = crate::server::deferred_indexing();
+        stats.corrupt_records = crate::channel::corrupt_records();
+        let (warmup_done, warmup_total) = crate::server::warmup_progress();
+        stats.warmup_done = warmup_done;
+        stats.warmup_total = warmup_total;
+        Ok(Response::Success(stats))
+    }
+
+    /// Gets the sizes of the server's in-memory state for diagnostics, see [`crate::prelude::HttpServerState`].
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - Address of the server actor whose state is described.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server actor could not be reached.
+    pub async fn state(server: ServerAddress) -> Result<impl Reply> {
+        let state = server
+            .call(GetServerState)
+            .await
+            .map_err(|_| ApiError::InternalError)?;
+        Ok(Response::Success(state))
+    }
+
+    /// Turns maintenance mode on or off without restarting the server, see [`crate::set_maintenance_mode`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Whether the server should be in maintenance mode.
+    pub async fn set_maintenance(data: HttpMaintenanceMode) -> Result<impl Reply> {
+        crate::set_maintenance_mode(data.enabled);
+        Ok(ok())
+    }
+
+    /// Lists the hubs on the server ordered by ID, hubs that fail to load are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - How many hubs to skip and the maximum number of hubs to list.
+    pub async fn hubs(query: HttpHubListQuery) -> Result<impl Reply> {
+        let mut ids = Hub::list_ids().await;
+        ids.sort();
+        let mut hubs = Vec::new();
+        for id in ids.into_iter().skip(query.offset).take(query.limit) {
+            if let Ok(hub) = Hub::load(id).await {
+                hubs.push(HttpHubSummary {
+                    id,
+                    name: hub.name,
+                    owner: hub.owner,
+                    member_count: hub.members.len(),
+                    created: hub.created,
+                });
+            }
+        }
+        Ok(Response::Success(hubs))
     }
 }
 
+/// Executes a GraphQL request, successful responses get a `Cache-Control` header if the cache hints of the queried fields allow caching.
 pub async fn graphql(
     server: ServerAddress,
+    cache: HubCache,
     user_id: ID,
     (schema, request): (GraphQLSchema, async_graphql::Request),
 ) -> Result<impl Reply> {
-    Ok(async_graphql_warp::Response::from(
-        schema.execute(request.data(server).data(user_id)).await,
-    ))
+    let response = schema
+        .execute(
+            request
+                .data(server)
+                .data(cache)
+                .data(user_id)
+                .data(ThreadSummaries::default()),
+        )
+        .await;
+    let cache_control = response.cache_control.value().filter(|_| response.is_ok());
+    let reply = async_graphql_warp::Response::from(response).into_response();
+    Ok(match cache_control {
+        Some(cache_control) => {
+            warp::reply::with_header(reply, "cache-control", cache_control).into_response()
+        }
+        None => reply,
+    })
 }
 
-pub async fn websocket(server: ServerAddress, user_id: ID, ws: Ws) -> Result<impl Reply> {
+pub async fn websocket(
+    server: ServerAddress,
+    cache: HubCache,
+    user_id: ID,
+    query: HttpWebsocketQuery,
+    max_frame_size: usize,
+    ws: Ws,
+) -> Result<impl Reply> {
     Ok(ws.on_upgrade(move |websocket| async move {
-        let _ = crate::websocket::handle_connection(websocket, user_id, server).await;
+        let _ = crate::websocket::handle_connection(
+            websocket,
+            user_id,
+            ConnectionOptions {
+                compress: query.compress,
+                max_frame_size,
+                hub_cache: cache,
+            },
+            server,
+        )
+        .await;
     }))
 }
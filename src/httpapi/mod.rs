@@ -1,6 +1,6 @@
 use crate::config::Config;
 use crate::error::{ApiError, Error, Result};
-use crate::server::ServerAddress;
+use crate::server::{HubCache, ServerAddress};
 use serde::{Deserialize, Serialize};
 use std::marker::Send;
 use std::net::SocketAddr;
@@ -11,8 +11,8 @@ use warp::Reply;
 pub mod handlers;
 pub mod routes;
 
-pub async fn start(config: Config, server: ServerAddress) -> Result {
-    let http_server = warp::serve(routes::routes(server, &config)).run(
+pub async fn start(config: Config, server: ServerAddress, cache: HubCache) -> Result {
+    let http_server = warp::serve(routes::routes(server, cache, &config)).run(
         config
             .address
             .parse::<SocketAddr>()
@@ -36,32 +36,59 @@ pub const fn ok() -> Response<&'static str> {
 
 impl Reject for ApiError {}
 
-impl Reject for Error {}
+/// Internal errors are turned into [`ApiError`]s as soon as they are rejected so that [`ApiError`] is the only error type requests are rejected with.
+/// Errors that do not have an [`ApiError`] equivalent are logged since the client only gets [`ApiError::InternalError`].
+impl From<Error> for warp::Rejection {
+    fn from(error: Error) -> Self {
+        let api_error = ApiError::from(&error);
+        if api_error == ApiError::InternalError {
+            error!("Request failed: {}", error);
+        }
+        warp::reject::custom(api_error)
+    }
+}
 
 impl From<&ApiError> for StatusCode {
     fn from(error: &ApiError) -> Self {
         match error {
             ApiError::Banned
             | ApiError::Muted
+            | ApiError::PendingVerification
+            | ApiError::RulesNotAccepted
+            | ApiError::HubFull
+            | ApiError::InviteExpired
+            | ApiError::InvalidResumeToken
+            | ApiError::NotParticipant
             | ApiError::IsOwner
+            | ApiError::NotServerAdmin
             | ApiError::WsNotAuthenticated
             | ApiError::MissingChannelPermission { permission: _ }
             | ApiError::MissingHubPermission { permission: _ } => Self::FORBIDDEN,
             ApiError::ChannelNotFound
+            | ApiError::ThreadNotFound
             | ApiError::GroupNotFound
             | ApiError::MemberNotFound
             | ApiError::MessageNotFound
             | ApiError::HubNotFound
+            | ApiError::InviteNotFound
             | ApiError::NotFound
             | ApiError::NotInHub => Self::NOT_FOUND,
             ApiError::Http { message: _ }
             | ApiError::Json { message: _ }
             | ApiError::Id
             | ApiError::InvalidText
+            | ApiError::InvalidMessageFlags
+            | ApiError::TooManyMembers
             | ApiError::TooBig
             | ApiError::InvalidTime
+            | ApiError::InvalidTags
             | ApiError::InvalidName => Self::BAD_REQUEST,
-            ApiError::AlreadyTyping | ApiError::NotTyping => Self::CONFLICT,
+            ApiError::NotAuthenticated => Self::UNAUTHORIZED,
+            ApiError::AlreadyTyping | ApiError::NotTyping | ApiError::AlreadyInHub => {
+                Self::CONFLICT
+            }
+            ApiError::Maintenance => Self::SERVICE_UNAVAILABLE,
+            ApiError::SearchDisabled => Self::NOT_IMPLEMENTED,
             ApiError::InternalError | ApiError::Other { message: _ } => Self::INTERNAL_SERVER_ERROR,
         }
     }
@@ -73,12 +100,6 @@ impl Reply for ApiError {
     }
 }
 
-impl Reply for &Error {
-    fn into_response(self) -> warp::reply::Response {
-        ApiError::from(self).into_response()
-    }
-}
-
 impl<T: Send + Serialize> warp::reply::Reply for Response<T> {
     fn into_response(self) -> warp::reply::Response {
         let mut response = warp::reply::Response::new(warp::hyper::Body::from(
@@ -7,12 +7,15 @@ use std::sync::Arc;
 
 use lazy_static::lazy_static;
 
-use crate::error::{ApiError, Error};
+use crate::error::ApiError;
 use crate::graphql_model::GraphQLSchema;
 use crate::httpapi::handlers;
-use crate::prelude::{HttpServerInfo, HttpSetPermission};
+use crate::prelude::{HttpPermissionSchema, HttpServerInfo, HttpSetPermission};
 use crate::ID;
-use crate::{graphql_model::QueryRoot, server::ServerAddress};
+use crate::{
+    graphql_model::QueryRoot,
+    server::{HubCache, ServerAddress},
+};
 use warp::http::Method;
 use warp::path;
 use warp::Reply;
@@ -23,6 +26,7 @@ use super::Response;
 lazy_static! {
     static ref SERVER_INFO: HttpServerInfo = HttpServerInfo {
         version: env!("CARGO_PKG_VERSION").to_string(),
+        message_id_scheme: crate::MESSAGE_ID_SCHEME,
     };
 }
 
@@ -36,40 +40,73 @@ fn full_path(path: &str) -> warp::filters::BoxedFilter<()> {
 
 pub fn routes(
     server: ServerAddress,
+    cache: HubCache,
     config: &crate::config::Config,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
-        .extension(ApolloTracing)
-        .finish();
+    let schema = schema(config);
 
     let cors = warp::cors()
         .allow_header("content-type")
         .allow_header("authorization")
         .allow_header("cache-control")
-        .allow_methods([Method::GET, Method::PUT, Method::POST, Method::DELETE])
+        .allow_header("idempotency-key")
+        .allow_methods([
+            Method::GET,
+            Method::PUT,
+            Method::POST,
+            Method::PATCH,
+            Method::DELETE,
+        ])
         .allow_any_origin()
         .build();
     let log = warp::log("wicrs_server::httpapi");
 
-    api(server, schema, &config.base_path)
+    api(server, cache, schema, config)
         .recover(handle_rejection)
         .with(log)
         .with(cors)
 }
 
+/// Builds the GraphQL schema with the query limits set in the config.
+fn schema(config: &crate::config::Config) -> GraphQLSchema {
+    let mut builder =
+        Schema::build(QueryRoot, EmptyMutation, EmptySubscription).extension(ApolloTracing);
+    if config.graphql_max_depth > 0 {
+        builder = builder.limit_depth(config.graphql_max_depth);
+    }
+    if config.graphql_max_complexity > 0 {
+        builder = builder.limit_complexity(config.graphql_max_complexity);
+    }
+    builder.finish()
+}
+
 fn api(
     server: ServerAddress,
+    cache: HubCache,
     schema: GraphQLSchema,
-    base_path: &str,
+    config: &crate::config::Config,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let base_path = &config.base_path;
     let schema_sdl = schema.sdl();
     full_path(base_path).and(path!("api" / ..)).and(
-        rest(Arc::clone(&server))
-            .or(websocket(Arc::clone(&server)))
-            .or(graphql(server, schema))
-            .or(graphql_schema(schema_sdl))
-            .or(graphql_playground(base_path.to_string()))
-            .or(server_info()),
+        rest(
+            Arc::clone(&server),
+            cache.clone(),
+            config.default_max_members,
+            config.max_description_size,
+            config.delete_grace_period,
+        )
+        .or(websocket(
+            Arc::clone(&server),
+            cache.clone(),
+            config.ws_max_frame_size,
+        ))
+        .or(admin::admin(Arc::clone(&server)))
+        .or(graphql(server, cache, schema))
+        .or(graphql_schema(schema_sdl))
+        .or(graphql_playground(base_path.to_string()))
+        .or(server_info(config.private_server_info))
+        .or(permission_schema()),
     )
 }
 
@@ -77,56 +114,187 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
     if err.is_not_found() {
         Ok(ApiError::NotFound.into_response())
     } else if let Some(e) = err.find::<ApiError>() {
-        Ok(dbg!(e).to_owned().into_response())
-    } else if let Some(e) = err.find::<Error>() {
-        Ok(ApiError::from(dbg!(e)).into_response())
+        debug!("Request failed: {}", e);
+        Ok(e.to_owned().into_response())
     } else {
-        dbg!(err);
+        error!("Unhandled rejection: {:?}", err);
         Ok(ApiError::InternalError.into_response())
     }
 }
 
-fn rest(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    hub::hub(Arc::clone(&server))
-        .or(channel::channel(Arc::clone(&server)))
-        .or(member::member(Arc::clone(&server)))
-        .or(message::message(Arc::clone(&server)))
+fn rest(
+    server: ServerAddress,
+    cache: HubCache,
+    default_max_members: Option<u32>,
+    max_description_size: usize,
+    delete_grace_period: i64,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    writable().and(
+        hub::hub(
+            Arc::clone(&server),
+            default_max_members,
+            max_description_size,
+            cache.clone(),
+        )
+        .or(channel::channel(
+            Arc::clone(&server),
+            max_description_size,
+            cache.clone(),
+        ))
+        .or(member::member(Arc::clone(&server), cache.clone()))
+        .or(invite::invite(Arc::clone(&server), cache.clone()))
+        .or(message::message(
+            Arc::clone(&server),
+            delete_grace_period,
+            cache.clone(),
+        ))
+        .or(dm::dm(Arc::clone(&server)))
+        .or(thread::thread(cache.clone()))
+        .or(discover())
+        .or(group::group(Arc::clone(&server), cache))
+        .or(user::user(Arc::clone(&server))),
+    )
+}
+
+fn discover() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    path!("discover")
+        .and(warp::get())
+        .and(auth())
+        .and(warp::query())
+        .and_then(handlers::discovery::search)
+}
+
+/// Rejects requests that could change data while the server is in maintenance mode.
+fn writable() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and_then(|method: Method| async move {
+            if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+                Ok(())
+            } else {
+                crate::check_maintenance_mode().map_err(warp::reject::custom)
+            }
+        })
+        .untuple_one()
 }
 
 fn auth() -> impl Filter<Extract = (ID,), Error = warp::Rejection> + Clone {
     warp::header("authorization")
 }
 
+/// Only lets through requests from the users listed in [`crate::config::Config::server_admins`].
+fn server_admin() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    auth()
+        .and_then(|user_id: ID| async move {
+            if crate::config::is_server_admin(&user_id) {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(ApiError::NotServerAdmin))
+            }
+        })
+        .untuple_one()
+}
+
 fn with_server(
     server: ServerAddress,
 ) -> impl Filter<Extract = (ServerAddress,), Error = Infallible> + Clone {
     warp::any().map(move || Arc::clone(&server))
 }
 
-fn server_info() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    path!("info").map(move || SERVER_INFO.clone()).and_then(
-        |server_info: HttpServerInfo| async move {
+fn with_hub_cache(
+    cache: HubCache,
+) -> impl Filter<Extract = (HubCache,), Error = Infallible> + Clone {
+    warp::any().map(move || cache.clone())
+}
+
+fn with_max_description_size(
+    max_description_size: usize,
+) -> impl Filter<Extract = (usize,), Error = Infallible> + Clone {
+    warp::any().map(move || max_description_size)
+}
+
+fn with_delete_grace_period(
+    delete_grace_period: i64,
+) -> impl Filter<Extract = (i64,), Error = Infallible> + Clone {
+    warp::any().map(move || delete_grace_period)
+}
+
+/// Only lets through requests that have the `authorization` header if `required` is true, otherwise lets everything through.
+fn authenticated_if(required: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<ID>("authorization")
+        .and_then(move |user_id: Option<ID>| async move {
+            if required && user_id.is_none() {
+                Err(warp::reject::custom(ApiError::NotAuthenticated))
+            } else {
+                Ok(())
+            }
+        })
+        .untuple_one()
+}
+
+/// Parses the body of the request as JSON, using the default value of `T` if the body is empty.
+fn optional_json<T: serde::de::DeserializeOwned + Default + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::body::bytes().and_then(|body: warp::hyper::body::Bytes| async move {
+        if body.is_empty() {
+            Ok(T::default())
+        } else {
+            serde_json::from_slice(&body).map_err(|error| {
+                warp::reject::custom(ApiError::Json {
+                    message: error.to_string(),
+                })
+            })
+        }
+    })
+}
+
+fn server_info(private: bool) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    path!("info")
+        .and(authenticated_if(private))
+        .map(move || SERVER_INFO.clone())
+        .and_then(|server_info: HttpServerInfo| async move {
             Ok::<Response<HttpServerInfo>, Rejection>(Response::Success(server_info))
-        },
-    )
+        })
 }
 
+fn permission_schema() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    path!("permissions" / "schema")
+        .and(warp::get())
+        .and_then(|| async move {
+            Ok::<Response<HttpPermissionSchema>, Rejection>(Response::Success(
+                HttpPermissionSchema::default(),
+            ))
+        })
+}
+
+/// Text frames bigger than `max_frame_size` bytes are answered with [`ApiError::TooBig`]. Connections are closed when a client sends a frame
+/// or message bigger than twice that, without it being read into memory, the margin lets clients that go slightly over the limit be told why.
 fn websocket(
     server: ServerAddress,
+    cache: HubCache,
+    max_frame_size: usize,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let limit = max_frame_size.saturating_mul(2);
     path!("websocket")
         .and(with_server(server))
+        .and(with_hub_cache(cache))
         .and(auth())
-        .and(warp::ws())
+        .and(warp::query())
+        .and(warp::any().map(move || max_frame_size))
+        .and(
+            warp::ws()
+                .map(move |ws: warp::ws::Ws| ws.max_frame_size(limit).max_message_size(limit)),
+        )
         .and_then(handlers::websocket)
 }
 
 fn graphql(
     server: ServerAddress,
+    cache: HubCache,
     schema: GraphQLSchema,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     path!("graphql")
         .and(with_server(server))
+        .and(with_hub_cache(cache))
         .and(auth())
         .and(async_graphql_warp::graphql(schema))
         .and_then(handlers::graphql)
@@ -158,135 +326,480 @@ fn graphql_playground(
 
 mod hub {
     use super::*;
+    use crate::permission::{ChannelPermission, HubPermission};
     use handlers::hub;
 
-    fn create() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn create(
+        default_max_members: Option<u32>,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::path::end()
             .and(warp::post())
             .and(auth())
+            .and(warp::header::optional("idempotency-key"))
             .and(warp::body::json())
+            .and(warp::any().map(move || default_max_members))
+            .and(with_max_description_size(max_description_size))
+            .and(with_hub_cache(cache))
             .and_then(hub::create)
     }
 
     fn update(
         server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID)
             .and(warp::put())
             .and(auth())
             .and(warp::body::json())
             .and(with_server(server))
+            .and(with_max_description_size(max_description_size))
+            .and(with_hub_cache(cache))
             .and_then(hub::update)
     }
 
-    fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-        path!(ID).and(warp::get()).and(auth()).and_then(hub::get)
+    fn get(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID)
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::get)
+    }
+
+    fn admins(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "admins")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::admins)
+    }
+
+    fn recent_channels(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "channels" / "recent")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::recent_channels)
+    }
+
+    fn all_channels(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "channels" / "all")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::all_channels)
+    }
+
+    fn can(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "can" / ID / HubPermission)
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::can)
+    }
+
+    fn can_in_channel(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "can" / ID / ID / ChannelPermission)
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::can_in_channel)
+    }
+
+    fn search_members(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "members" / "search")
+            .and(warp::get())
+            .and(auth())
+            .and(warp::query())
+            .and(with_hub_cache(cache))
+            .and_then(hub::search_members)
+    }
+
+    fn member_messages(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "member" / ID / "messages")
+            .and(warp::get())
+            .and(auth())
+            .and(warp::query())
+            .and(with_hub_cache(cache))
+            .and_then(hub::member_messages)
+    }
+
+    fn get_word_filter(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "word_filter")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::get_word_filter)
+    }
+
+    fn set_word_filter(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "word_filter")
+            .and(warp::put())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(hub::set_word_filter)
+    }
+
+    fn set_default_channel(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "default_channel" / ID)
+            .and(warp::put())
+            .and(auth())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(hub::set_default_channel)
+    }
+
+    fn transfer_ownership(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "owner" / ID)
+            .and(warp::put())
+            .and(auth())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(hub::transfer_ownership)
+    }
+
+    fn clone(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "clone")
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(hub::clone)
+    }
+
+    fn changes(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "changes")
+            .and(warp::get())
+            .and(auth())
+            .and(warp::query())
+            .and(with_hub_cache(cache))
+            .and_then(hub::changes)
+    }
+
+    fn create_invite(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "invite")
+            .and(warp::post())
+            .and(auth())
+            .and(optional_json())
+            .and(with_hub_cache(cache))
+            .and_then(hub::create_invite)
+    }
+
+    fn revoke_invite(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "invite" / String)
+            .and(warp::delete())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(hub::revoke_invite)
     }
 
     fn delete(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID)
             .and(warp::delete())
             .and(auth())
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(hub::delete)
     }
 
-    fn join(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn join(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / "join")
             .and(warp::post())
             .and(auth())
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(hub::join)
     }
 
     fn leave(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / "leave")
             .and(warp::post())
             .and(auth())
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(hub::leave)
     }
 
+    fn accept(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "accept")
+            .and(warp::post())
+            .and(auth())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(hub::accept)
+    }
+
     pub fn hub(
         server: ServerAddress,
+        default_max_members: Option<u32>,
+        max_description_size: usize,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!("hub" / ..).and(
-            join(Arc::clone(&server))
-                .or(leave(Arc::clone(&server)))
-                .or(get())
-                .or(delete(Arc::clone(&server)))
-                .or(update(Arc::clone(&server)))
-                .or(create()),
+            join(Arc::clone(&server), cache.clone())
+                .or(leave(Arc::clone(&server), cache.clone()))
+                .or(accept(Arc::clone(&server), cache.clone()))
+                .or(get(cache.clone()))
+                .or(admins(cache.clone()))
+                .or(changes(cache.clone()))
+                .or(clone(cache.clone()))
+                .or(all_channels(cache.clone()))
+                .or(recent_channels(cache.clone()))
+                .or(can(cache.clone()))
+                .or(can_in_channel(cache.clone()))
+                .or(search_members(cache.clone()))
+                .or(member_messages(cache.clone()))
+                .or(get_word_filter(cache.clone()))
+                .or(set_word_filter(Arc::clone(&server), cache.clone()))
+                .or(set_default_channel(Arc::clone(&server), cache.clone()))
+                .or(transfer_ownership(Arc::clone(&server), cache.clone()))
+                .or(create_invite(cache.clone()))
+                .or(revoke_invite(cache.clone()))
+                .or(delete(Arc::clone(&server), cache.clone()))
+                .or(update(
+                    Arc::clone(&server),
+                    max_description_size,
+                    cache.clone(),
+                ))
+                .or(create(
+                    default_max_members,
+                    max_description_size,
+                    cache.clone(),
+                )),
         )
     }
 }
 
+mod invite {
+    use super::*;
+    use handlers::invite;
+
+    fn accept(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(String / "accept")
+            .and(warp::post())
+            .and(auth())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(invite::accept)
+    }
+
+    pub fn invite(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("invite" / ..).and(accept(server, cache.clone()))
+    }
+}
+
 mod message {
     use super::*;
     use handlers::message;
 
-    fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID / ID)
             .and(warp::get())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(message::get)
     }
 
-    fn get_after() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn exists(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / ID / "exists")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(message::exists)
+    }
+
+    fn get_after(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID / "after")
             .and(warp::get())
             .and(warp::query())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(message::get_after)
     }
 
-    fn get_before() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get_page(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "page")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(message::get_page)
+    }
+
+    fn get_before(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID / "before")
             .and(warp::get())
             .and(warp::query())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(message::get_before)
     }
 
-    fn get_last() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get_last(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID / "last")
             .and(warp::get())
             .and(warp::query())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(message::get_last)
     }
 
-    fn get_between() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get_between(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID / "between")
             .and(warp::get())
             .and(warp::query())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(message::get_between)
     }
 
-    fn send(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn send(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID)
             .and(warp::post())
             .and(auth())
             .and(warp::body::json())
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(message::send)
     }
 
+    fn delete(
+        server: ServerAddress,
+        delete_grace_period: i64,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / ID)
+            .and(warp::delete())
+            .and(auth())
+            .and(with_delete_grace_period(delete_grace_period))
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(message::delete)
+    }
+
+    fn edit(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / ID)
+            .and(warp::patch())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(message::edit)
+    }
+
+    fn forward(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "forward")
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(message::forward)
+    }
+
+    fn preview(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "preview")
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(message::preview)
+    }
+
+    fn search(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "search")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(message::search)
+    }
+
     pub fn message(
         server: ServerAddress,
+        delete_grace_period: i64,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!("message" / ..).and(
-            send(Arc::clone(&server))
-                .or(get_between())
-                .or(get_after())
-                .or(get_before())
-                .or(get_last())
-                .or(get()),
+            send(Arc::clone(&server), cache.clone())
+                .or(forward(Arc::clone(&server), cache.clone()))
+                .or(delete(
+                    Arc::clone(&server),
+                    delete_grace_period,
+                    cache.clone(),
+                ))
+                .or(edit(Arc::clone(&server), cache.clone()))
+                .or(preview(cache.clone()))
+                .or(search(Arc::clone(&server), cache.clone()))
+                .or(get_between(cache.clone()))
+                .or(get_after(cache.clone()))
+                .or(get_page(cache.clone()))
+                .or(get_before(cache.clone()))
+                .or(get_last(cache.clone()))
+                .or(exists(cache.clone()))
+                .or(get(cache.clone())),
         )
     }
 }
@@ -295,53 +808,150 @@ mod channel {
     use super::*;
     use handlers::channel;
 
-    fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID)
             .and(warp::get())
             .and(auth())
+            .and(with_hub_cache(cache))
             .and_then(channel::get)
     }
 
     fn create(
         server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID)
             .and(warp::post())
             .and(auth())
             .and(warp::body::json())
             .and(with_server(server))
+            .and(with_max_description_size(max_description_size))
+            .and(with_hub_cache(cache))
             .and_then(channel::create)
     }
 
+    fn create_batch(
+        server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "batch")
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and(with_max_description_size(max_description_size))
+            .and(with_hub_cache(cache))
+            .and_then(channel::create_batch)
+    }
+
     fn update(
         server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID)
             .and(warp::put())
             .and(auth())
             .and(warp::body::json())
             .and(with_server(server))
+            .and(with_max_description_size(max_description_size))
+            .and(with_hub_cache(cache))
             .and_then(channel::update)
     }
 
     fn delete(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!(ID / ID)
             .and(warp::delete())
             .and(auth())
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(channel::delete)
     }
 
+    fn get_draft(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "draft")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(channel::get_draft)
+    }
+
+    fn set_draft(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "draft")
+            .and(warp::put())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(channel::set_draft)
+    }
+
+    fn delete_draft() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "draft")
+            .and(warp::delete())
+            .and(auth())
+            .and_then(channel::delete_draft)
+    }
+
+    fn history(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "history")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(channel::history)
+    }
+
+    fn permissions(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "permissions")
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(channel::permissions)
+    }
+
+    fn compact(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "compact")
+            .and(warp::post())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(channel::compact)
+    }
+
     pub fn channel(
         server: ServerAddress,
+        max_description_size: usize,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!("channel" / ..).and(
-            get()
-                .or(delete(Arc::clone(&server)))
-                .or(update(Arc::clone(&server)))
-                .or(create(Arc::clone(&server))),
+            get(cache.clone())
+                .or(get_draft(cache.clone()))
+                .or(set_draft(cache.clone()))
+                .or(delete_draft())
+                .or(history(cache.clone()))
+                .or(permissions(cache.clone()))
+                .or(compact(cache.clone()))
+                .or(create_batch(
+                    Arc::clone(&server),
+                    max_description_size,
+                    cache.clone(),
+                ))
+                .or(delete(Arc::clone(&server), cache.clone()))
+                .or(update(
+                    Arc::clone(&server),
+                    max_description_size,
+                    cache.clone(),
+                ))
+                .or(create(
+                    Arc::clone(&server),
+                    max_description_size,
+                    cache.clone(),
+                )),
         )
     }
 }
@@ -350,99 +960,183 @@ mod member {
     use super::*;
     use crate::{
         permission::{ChannelPermission, HubPermission},
-        prelude::HttpSetNick,
+        prelude::{HttpModerationExpiry, HttpSetNick},
     };
     use handlers::member;
 
-    fn status() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn status(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::get()
             .and(auth())
             .and(path!(ID / ID / "status"))
+            .and(with_hub_cache(cache))
             .and_then(member::status)
     }
 
-    fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::get()
             .and(auth())
             .and(path!(ID / ID))
+            .and(with_hub_cache(cache))
             .and_then(member::get)
     }
 
+    fn get_groups(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        warp::get()
+            .and(auth())
+            .and(path!(ID / ID / "groups"))
+            .and(with_hub_cache(cache))
+            .and_then(member::get_groups)
+    }
+
     fn set_nick(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::put()
             .and(auth())
             .and(path!(ID / "set_nick"))
             .and(warp::body::json().map(|n: HttpSetNick| n.nick))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::set_nick)
     }
 
-    fn kick(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn kick(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::post()
             .and(auth())
             .and(path!(ID / ID / "kick"))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::kick)
     }
 
-    fn mute(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn mute(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::post()
             .and(auth())
             .and(path!(ID / ID / "mute"))
+            .and(optional_json().map(|body: HttpModerationExpiry| body.expires_at))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::mute)
     }
 
-    fn ban(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn ban(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::post()
             .and(auth())
             .and(path!(ID / ID / "ban"))
+            .and(optional_json().map(|body: HttpModerationExpiry| body.expires_at))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::ban)
     }
 
     fn unban(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::post()
             .and(auth())
             .and(path!(ID / ID / "unban"))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::unban)
     }
 
     fn unmute(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::post()
             .and(auth())
             .and(path!(ID / ID / "unmute"))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::unmute)
     }
 
-    fn set_hub_permission(
+    fn approve(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-        warp::put()
+        warp::post()
             .and(auth())
-            .and(path!(ID / ID / "hub_permission" / HubPermission))
-            .and(warp::body::json().map(|s: HttpSetPermission| s.setting))
+            .and(path!(ID / ID / "approve"))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(member::approve)
+    }
+
+    fn set_hub_permission(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        warp::put()
+            .and(auth())
+            .and(path!(ID / ID / "hub_permission" / HubPermission))
+            .and(warp::body::json().map(|s: HttpSetPermission| s.setting))
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::set_hub_permission)
     }
 
-    fn get_hub_permission() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn set_permissions(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        warp::put()
+            .and(auth())
+            .and(path!(ID / ID / "permissions" / "batch"))
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(member::set_permissions)
+    }
+
+    fn simulate_permissions(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        warp::post()
+            .and(auth())
+            .and(path!(ID / ID / "permissions" / "simulate"))
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(member::simulate_permissions)
+    }
+
+    fn clear_permissions(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        warp::delete()
+            .and(auth())
+            .and(path!(ID / ID / "permissions"))
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(member::clear_permissions)
+    }
+
+    fn get_hub_permission(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::get()
             .and(auth())
             .and(path!(ID / ID / "hub_permission" / HubPermission))
+            .and(with_hub_cache(cache))
             .and_then(member::get_hub_permission)
     }
 
     fn set_channel_permission(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::put()
             .and(auth())
@@ -451,34 +1145,1516 @@ mod member {
             ))
             .and(warp::body::json().map(|s: HttpSetPermission| s.setting))
             .and(with_server(server))
+            .and(with_hub_cache(cache))
             .and_then(member::set_channel_permission)
     }
 
-    fn get_channel_permission() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    fn get_channel_permission(
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         warp::get()
             .and(auth())
             .and(path!(
                 ID / ID / "channel_permission" / ID / ChannelPermission
             ))
+            .and(with_hub_cache(cache))
             .and_then(member::get_channel_permission)
     }
 
     pub fn member(
         server: ServerAddress,
+        cache: HubCache,
     ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         path!("member" / ..).and(
-            get()
-                .or(status())
-                .or(set_nick(Arc::clone(&server)))
-                .or(kick(Arc::clone(&server)))
-                .or(mute(Arc::clone(&server)))
-                .or(ban(Arc::clone(&server)))
-                .or(unmute(Arc::clone(&server)))
-                .or(unban(Arc::clone(&server)))
-                .or(get_hub_permission())
-                .or(set_hub_permission(Arc::clone(&server)))
-                .or(get_channel_permission())
-                .or(set_channel_permission(Arc::clone(&server))),
+            get(cache.clone())
+                .or(status(cache.clone()))
+                .or(get_groups(cache.clone()))
+                .or(set_nick(Arc::clone(&server), cache.clone()))
+                .or(kick(Arc::clone(&server), cache.clone()))
+                .or(mute(Arc::clone(&server), cache.clone()))
+                .or(ban(Arc::clone(&server), cache.clone()))
+                .or(unmute(Arc::clone(&server), cache.clone()))
+                .or(unban(Arc::clone(&server), cache.clone()))
+                .or(approve(Arc::clone(&server), cache.clone()))
+                .or(get_hub_permission(cache.clone()))
+                .or(set_hub_permission(Arc::clone(&server), cache.clone()))
+                .or(set_permissions(Arc::clone(&server), cache.clone()))
+                .or(simulate_permissions(cache.clone()))
+                .or(clear_permissions(Arc::clone(&server), cache.clone()))
+                .or(get_channel_permission(cache.clone()))
+                .or(set_channel_permission(Arc::clone(&server), cache.clone())),
         )
     }
 }
+
+mod dm {
+    use super::*;
+    use handlers::dm;
+
+    fn open() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("open" / ID)
+            .and(warp::post())
+            .and(auth())
+            .and_then(dm::open)
+    }
+
+    fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID).and(warp::get()).and(auth()).and_then(dm::get)
+    }
+
+    fn get_last() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "last")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and_then(dm::get_last)
+    }
+
+    fn get_before() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / "before")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and_then(dm::get_before)
+    }
+
+    fn send(server: ServerAddress) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID)
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and_then(dm::send)
+    }
+
+    fn create_group() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group")
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and_then(dm::create_group)
+    }
+
+    fn get_group() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ID)
+            .and(warp::get())
+            .and(auth())
+            .and_then(dm::get_group)
+    }
+
+    fn get_group_last() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ID / "last")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and_then(dm::get_group_last)
+    }
+
+    fn add_group_member() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ID / "add" / ID)
+            .and(warp::post())
+            .and(auth())
+            .and_then(dm::add_group_member)
+    }
+
+    fn remove_group_member() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ID / "remove" / ID)
+            .and(warp::post())
+            .and(auth())
+            .and_then(dm::remove_group_member)
+    }
+
+    fn send_group(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ID)
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_server(server))
+            .and_then(dm::send_group)
+    }
+
+    pub fn dm(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("dm" / ..).and(
+            create_group()
+                .or(send_group(Arc::clone(&server)))
+                .or(get_group_last())
+                .or(add_group_member())
+                .or(remove_group_member())
+                .or(get_group())
+                .or(open())
+                .or(send(Arc::clone(&server)))
+                .or(get_last())
+                .or(get_before())
+                .or(get()),
+        )
+    }
+}
+
+mod thread {
+    use super::*;
+    use handlers::thread;
+
+    fn create(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "create" / ID)
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(thread::create)
+    }
+
+    fn list(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID)
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(thread::list)
+    }
+
+    fn get_last(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / ID / "last")
+            .and(warp::get())
+            .and(warp::query())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(thread::get_last)
+    }
+
+    fn summary(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / "summary" / ID)
+            .and(warp::get())
+            .and(auth())
+            .and(with_hub_cache(cache))
+            .and_then(thread::summary)
+    }
+
+    fn send(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID / ID)
+            .and(warp::post())
+            .and(auth())
+            .and(warp::body::json())
+            .and(with_hub_cache(cache))
+            .and_then(thread::send)
+    }
+
+    pub fn thread(cache: HubCache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("thread" / ..).and(
+            create(cache.clone())
+                .or(list(cache.clone()))
+                .or(summary(cache.clone()))
+                .or(get_last(cache.clone()))
+                .or(send(cache.clone())),
+        )
+    }
+}
+
+mod admin {
+    use super::*;
+    use handlers::admin;
+
+    fn stats(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("stats")
+            .and(warp::get())
+            .and(server_admin())
+            .and(with_server(server))
+            .and_then(admin::stats)
+    }
+
+    fn state(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("debug" / "state")
+            .and(warp::get())
+            .and(server_admin())
+            .and(with_server(server))
+            .and_then(admin::state)
+    }
+
+    fn hubs() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("hubs")
+            .and(warp::get())
+            .and(server_admin())
+            .and(warp::query())
+            .and_then(admin::hubs)
+    }
+
+    fn maintenance() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("maintenance")
+            .and(warp::put())
+            .and(server_admin())
+            .and(warp::body::json())
+            .and_then(admin::set_maintenance)
+    }
+
+    pub fn admin(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("admin" / ..).and(
+            stats(Arc::clone(&server))
+                .or(state(server))
+                .or(hubs())
+                .or(maintenance()),
+        )
+    }
+}
+
+mod group {
+    use super::*;
+    use crate::prelude::HttpGroupUpdate;
+    use handlers::group;
+
+    fn rename(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!(ID / ID)
+            .and(warp::put())
+            .and(auth())
+            .and(warp::body::json().map(|u: HttpGroupUpdate| u.name))
+            .and(with_server(server))
+            .and(with_hub_cache(cache))
+            .and_then(group::rename)
+    }
+
+    pub fn group(
+        server: ServerAddress,
+        cache: HubCache,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("group" / ..).and(rename(server, cache.clone()))
+    }
+}
+
+mod user {
+    use super::*;
+    use handlers::user;
+
+    fn get_blocks() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("blocks")
+            .and(warp::get())
+            .and(auth())
+            .and_then(user::get_blocks)
+    }
+
+    fn block(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("blocks" / ID)
+            .and(warp::put())
+            .and(auth())
+            .and(with_server(server))
+            .and_then(user::block)
+    }
+
+    fn unblock(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("blocks" / ID)
+            .and(warp::delete())
+            .and(auth())
+            .and(with_server(server))
+            .and_then(user::unblock)
+    }
+
+    pub fn user(
+        server: ServerAddress,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        path!("user" / ..).and(
+            get_blocks()
+                .or(block(Arc::clone(&server)))
+                .or(unblock(Arc::clone(&server))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Starts a server, returning its address and the hub cache it uses.
+    async fn test_server() -> (ServerAddress, HubCache) {
+        use crate::server::Server;
+        use xactor::Actor;
+        let server = Server::new().await.unwrap();
+        let cache = server.hub_cache();
+        (Arc::new(server.start().await.unwrap()), cache)
+    }
+
+    /// Starts a server and saves a test hub that the given users have joined, see [`crate::hub::test::test_hub`].
+    async fn test_server_with_hub(members: &[ID]) -> (ServerAddress, HubCache, crate::hub::Hub) {
+        let (server, cache) = test_server().await;
+        let mut hub = crate::hub::test::test_hub();
+        for member in members {
+            hub.user_join(*member).unwrap();
+        }
+        hub.save(&cache).await.unwrap();
+        (server, cache, hub)
+    }
+
+    #[tokio::test]
+    async fn info_reports_id_scheme() {
+        let response = warp::test::request()
+            .path("/info")
+            .reply(&server_info(false))
+            .await;
+        let info: Response<HttpServerInfo> = serde_json::from_slice(response.body()).unwrap();
+        match info {
+            Response::Success(info) => {
+                assert_eq!(crate::MESSAGE_ID_SCHEME, info.message_id_scheme)
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn private_server_info() {
+        let filter = server_info(true).recover(handle_rejection);
+        let response = warp::test::request().path("/info").reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::UNAUTHORIZED, response.status());
+        let response = warp::test::request()
+            .path("/info")
+            .header("authorization", crate::test::USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+    }
+
+    #[tokio::test]
+    async fn graphql_limits() {
+        let config = crate::config::Config {
+            graphql_max_depth: 3,
+            graphql_max_complexity: 20,
+            ..Default::default()
+        };
+        let schema = schema(&config);
+        // The hub does not exist, so the queries would fail differently if they were executed.
+        let hub_id = crate::new_id();
+        let schema = &schema;
+        let errors = move |query: String| async move {
+            schema
+                .execute(async_graphql::Request::new(query).data(*crate::test::USER_ID))
+                .await
+                .errors
+                .into_iter()
+                .map(|error| error.message)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            vec!["Query is nested too deep.".to_string()],
+            errors(format!(
+                "{{ hub(id: \"{}\") {{ defaultChannel {{ name }} owner {{ groupInfo {{ name }} }} }} }}",
+                hub_id
+            ))
+            .await
+        );
+        assert_eq!(
+            vec!["Query is too complex.".to_string()],
+            errors(format!(
+                "{{ hub(id: \"{}\") {{ allChannels {{ searchMessages(query: \"a\", limit: 100) }} }} }}",
+                hub_id
+            ))
+            .await
+        );
+        assert_eq!(
+            1,
+            errors(format!("{{ hub(id: \"{}\") {{ name }} }}", hub_id))
+                .await
+                .len()
+        );
+    }
+
+    #[tokio::test]
+    async fn graphql_cache_control() {
+        let (server, cache) = test_server().await;
+        let filter = graphql(server, cache, schema(&crate::config::Config::default()))
+            .recover(handle_rejection);
+        let query = |query: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/graphql")
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&serde_json::json!({ "query": query }))
+        };
+        let response = query("{ requester }").reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        assert_eq!(
+            "max-age=3600, private",
+            response.headers()["cache-control"].to_str().unwrap()
+        );
+        let response = query(&format!(
+            "{{ hub(id: \"{}\") {{ name }} }}",
+            crate::new_id()
+        ))
+        .reply(&filter)
+        .await;
+        assert!(response.headers().get("cache-control").is_none());
+    }
+
+    #[tokio::test]
+    async fn internal_errors_rejected_as_api_errors() {
+        use crate::error::Error;
+        use warp::http::StatusCode;
+        let cases = vec![
+            (
+                Error::ApiError(ApiError::HubNotFound),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                Error::Id(ID::parse_str("not an id").unwrap_err()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (Error::GetIndexReader, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (error, status) in cases {
+            let expected = ApiError::from(&error);
+            let rejection = Rejection::from(error);
+            assert_eq!(Some(&expected), rejection.find::<ApiError>());
+            let response = handle_rejection(rejection).await.unwrap().into_response();
+            assert_eq!(status, response.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_size_limits() {
+        use crate::prelude::{WsClientMessage, WsServerMessage};
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        let max_frame_size = crate::MAX_MESSAGE_SIZE * 2;
+        let mut client = warp::test::ws()
+            .path("/websocket")
+            .header("authorization", crate::test::USER_ID.to_string())
+            .handshake(websocket(server, cache, max_frame_size))
+            .await
+            .unwrap();
+        client.send_text(crate::test::USER_ID.to_string()).await;
+        let frame = client.recv().await.unwrap();
+        match serde_json::from_str(frame.to_str().unwrap()).unwrap() {
+            WsServerMessage::Connected { .. } => {}
+            other => panic!("expected to be connected, got {:?}", other),
+        }
+        let send = |message: String| {
+            serde_json::to_string(&WsClientMessage::SendMessage {
+                hub_id: hub.id,
+                channel_id: *crate::test::CHANNEL_ID,
+                message,
+                flags: 0,
+            })
+            .unwrap()
+        };
+        for frame in vec![
+            send("a".repeat(max_frame_size)),
+            send("a".repeat(crate::MAX_MESSAGE_SIZE + 1)),
+        ] {
+            client.send_text(frame).await;
+            let response = client.recv().await.unwrap();
+            match serde_json::from_str(response.to_str().unwrap()).unwrap() {
+                WsServerMessage::Error(ApiError::TooBig) => {}
+                other => panic!("expected the frame to be rejected, got {:?}", other),
+            }
+        }
+        let channel = &hub.channels[&*crate::test::CHANNEL_ID];
+        assert!(channel.get_last_messages(1).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode() {
+        use crate::prelude::HttpMaintenanceMode;
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        let filter = writable()
+            .and(thread::thread(cache))
+            .or(admin::admin(server))
+            .recover(handle_rejection);
+        let set_maintenance = |user: ID, enabled: bool| {
+            warp::test::request()
+                .method("PUT")
+                .path("/admin/maintenance")
+                .header("authorization", user.to_string())
+                .json(&HttpMaintenanceMode { enabled })
+        };
+        let response = set_maintenance(*crate::test::USER_ID, true)
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        assert!(crate::check_maintenance_mode().is_ok());
+        let response = set_maintenance(*crate::test::SERVER_ADMIN_ID, true)
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let read = warp::test::request()
+            .method("GET")
+            .path(&format!("/thread/{}/{}", hub.id, *crate::test::CHANNEL_ID))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        let send = warp::test::request()
+            .method("POST")
+            .path(&format!(
+                "/thread/{}/{}/{}",
+                hub.id,
+                *crate::test::CHANNEL_ID,
+                crate::new_id()
+            ))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .json(&crate::prelude::HttpSendMessage::default())
+            .reply(&filter)
+            .await;
+        let response = set_maintenance(*crate::test::SERVER_ADMIN_ID, false)
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        assert!(crate::check_maintenance_mode().is_ok());
+        assert_eq!(warp::http::StatusCode::OK, read.status());
+        assert_eq!(warp::http::StatusCode::SERVICE_UNAVAILABLE, send.status());
+    }
+
+    #[tokio::test]
+    async fn search_disabled() {
+        use crate::server::Server;
+        use xactor::Actor;
+        let server = Server::with_message_servers(0).await.unwrap();
+        let cache = server.hub_cache();
+        let server = Arc::new(server.start().await.unwrap());
+        let hub = crate::hub::test::test_hub();
+        hub.save(&cache).await.unwrap();
+        let filter = message::message(server, crate::MESSAGE_DELETE_GRACE_PERIOD, cache)
+            .recover(handle_rejection);
+        let send = warp::test::request()
+            .method("POST")
+            .path(&format!("/message/{}/{}", hub.id, *crate::test::CHANNEL_ID))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .json(&crate::prelude::HttpSendMessage {
+                message: "hello".to_string(),
+                flags: 0,
+                expires_at: None,
+            })
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, send.status());
+        let search = warp::test::request()
+            .path(&format!(
+                "/message/{}/{}/search?query=hello&limit=10",
+                hub.id,
+                *crate::test::CHANNEL_ID
+            ))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::NOT_IMPLEMENTED, search.status());
+    }
+
+    #[tokio::test]
+    async fn admin_stats() {
+        let (server, _) = test_server().await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        let filter = admin::admin(server).recover(handle_rejection);
+        let response = warp::test::request()
+            .path("/admin/stats")
+            .header("authorization", crate::test::SERVER_ADMIN_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let stats: Response<crate::prelude::HttpServerStats> =
+            serde_json::from_slice(response.body()).unwrap();
+        match stats {
+            Response::Success(stats) => assert_eq!(0, stats.connections),
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = warp::test::request()
+            .path("/admin/stats")
+            .header("authorization", crate::test::OTHER_USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+    }
+
+    #[tokio::test]
+    async fn admin_debug_state() {
+        use crate::{prelude::HttpServerState, server::client_command};
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        for connection_id in 1..=2 {
+            server
+                .call(client_command::SubscribeChannel {
+                    user_id: *crate::test::USER_ID,
+                    hub_id: hub.id,
+                    channel_id: *crate::test::CHANNEL_ID,
+                    connection_id,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        server
+            .call(client_command::SubscribeHub {
+                user_id: *crate::test::USER_ID,
+                hub_id: hub.id,
+                connection_id: 1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let filter = admin::admin(server).recover(handle_rejection);
+        let response = warp::test::request()
+            .path("/admin/debug/state")
+            .header("authorization", crate::test::SERVER_ADMIN_ID.to_string())
+            .reply(&filter)
+            .await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<HttpServerState>::Success(state) => {
+                assert_eq!(0, state.connections);
+                assert_eq!(2, state.subscribed_connections);
+                assert_eq!(1, state.subscribed_channels);
+                assert_eq!(2, state.channel_subscriptions);
+                assert_eq!(1, state.subscribed_hubs);
+                assert_eq!(1, state.hub_subscriptions);
+                assert_eq!(vec![0], state.index_queues);
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = warp::test::request()
+            .path("/admin/debug/state")
+            .header("authorization", crate::test::OTHER_USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+    }
+
+    #[tokio::test]
+    async fn idempotent_hub_creation() {
+        use crate::prelude::HttpHubUpdate;
+        let (server, cache) = test_server().await;
+        let filter =
+            hub::hub(server, None, crate::MAX_DESCRIPTION_SIZE, cache).recover(handle_rejection);
+        let create = |key: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/hub")
+                .header("authorization", crate::test::USER_ID.to_string())
+                .header("idempotency-key", key)
+                .json(&HttpHubUpdate {
+                    name: Some("idempotent".to_string()),
+                    ..Default::default()
+                })
+        };
+        let mut ids = Vec::new();
+        for key in ["first", "first", "second"].iter() {
+            let response = create(key).reply(&filter).await;
+            match serde_json::from_slice(response.body()).unwrap() {
+                Response::<ID>::Success(id) => ids.push(id),
+                Response::Error(err) => panic!("{}", err),
+            }
+        }
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        crate::hub::Hub::load(ids[0]).await.unwrap();
+        handlers::hub::expire_create_keys(
+            chrono::Utc::now() + chrono::Duration::milliseconds(crate::HUB_CREATE_KEY_LIFETIME),
+        );
+        let response = create("first").reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => assert_ne!(ids[0], id),
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = create(&"k".repeat(crate::MAX_IDEMPOTENCY_KEY_SIZE + 1))
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[tokio::test]
+    async fn admin_hubs() {
+        use crate::prelude::HttpHubSummary;
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        let other = crate::hub::Hub::new(
+            "other".to_string(),
+            crate::new_id(),
+            *crate::test::OTHER_USER_ID,
+        );
+        other.save(&cache).await.unwrap();
+        let filter = admin::admin(server).recover(handle_rejection);
+        let response = warp::test::request()
+            .path(&format!("/admin/hubs?limit={}", usize::MAX))
+            .header("authorization", crate::test::SERVER_ADMIN_ID.to_string())
+            .reply(&filter)
+            .await;
+        let hubs = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Vec<HttpHubSummary>>::Success(hubs) => hubs,
+            Response::Error(err) => panic!("{}", err),
+        };
+        for expected in [&hub, &other].iter() {
+            let summary = hubs
+                .iter()
+                .find(|summary| summary.id == expected.id)
+                .expect("hub missing from the listing");
+            assert_eq!(expected.name, summary.name);
+            assert_eq!(expected.owner, summary.owner);
+            assert_eq!(expected.members.len(), summary.member_count);
+        }
+        let response = warp::test::request()
+            .path("/admin/hubs?limit=1&offset=1")
+            .header("authorization", crate::test::SERVER_ADMIN_ID.to_string())
+            .reply(&filter)
+            .await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Vec<HttpHubSummary>>::Success(page) => assert_eq!(1, page.len()),
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = warp::test::request()
+            .path("/admin/hubs?limit=10")
+            .header("authorization", crate::test::USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+    }
+
+    #[tokio::test]
+    async fn server_admin_deletes_hub() {
+        let (server, cache, hub) = test_server_with_hub(&[*crate::test::OTHER_USER_ID]).await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        let filter =
+            hub::hub(server, None, crate::MAX_DESCRIPTION_SIZE, cache).recover(handle_rejection);
+        let response = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/hub/{}", hub.id))
+            .header("authorization", crate::test::OTHER_USER_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        assert!(crate::hub::Hub::load(hub.id).await.is_ok());
+        let response = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/hub/{}", hub.id))
+            .header("authorization", crate::test::SERVER_ADMIN_ID.to_string())
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        assert!(crate::hub::Hub::load(hub.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_admin_moderates_hub() {
+        let kicked = crate::new_id();
+        let (server, cache, hub) =
+            test_server_with_hub(&[*crate::test::OTHER_USER_ID, kicked]).await;
+        crate::config::set_server_admins(vec![*crate::test::SERVER_ADMIN_ID]);
+        let filter = hub::hub(
+            Arc::clone(&server),
+            None,
+            crate::MAX_DESCRIPTION_SIZE,
+            cache.clone(),
+        )
+        .or(member::member(server, cache.clone()))
+        .recover(handle_rejection);
+        let kick = |user: ID| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!("/member/{}/{}/kick", hub.id, kicked))
+                .header("authorization", user.to_string())
+        };
+        let transfer = |user: ID| {
+            warp::test::request()
+                .method("PUT")
+                .path(&format!(
+                    "/hub/{}/owner/{}",
+                    hub.id,
+                    *crate::test::OTHER_USER_ID
+                ))
+                .header("authorization", user.to_string())
+        };
+        let response = kick(*crate::test::OTHER_USER_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        let response = transfer(*crate::test::OTHER_USER_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        let response = kick(*crate::test::SERVER_ADMIN_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let response = transfer(*crate::test::SERVER_ADMIN_ID).reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(old_owner) => assert_eq!(*crate::test::USER_ID, old_owner),
+            Response::Error(err) => panic!("{}", err),
+        }
+        let hub = crate::hub::Hub::load_cached(hub.id, &cache).await.unwrap();
+        assert_eq!(*crate::test::OTHER_USER_ID, hub.owner);
+        assert!(!hub.is_member(&kicked));
+        assert!(!hub.is_member(&crate::test::SERVER_ADMIN_ID));
+    }
+
+    #[tokio::test]
+    async fn normalized_names() {
+        use crate::prelude::{HttpChannelUpdate, HttpHubUpdate};
+        let (server, cache) = test_server().await;
+        let filter = hub::hub(
+            Arc::clone(&server),
+            None,
+            crate::MAX_DESCRIPTION_SIZE,
+            cache.clone(),
+        )
+        .or(channel::channel(server, crate::MAX_DESCRIPTION_SIZE, cache))
+        .recover(handle_rejection);
+        let response = warp::test::request()
+            .method("POST")
+            .path("/hub")
+            .header("authorization", crate::test::USER_ID.to_string())
+            .json(&HttpHubUpdate {
+                name: Some("  My \t Hub  ".to_string()),
+                ..Default::default()
+            })
+            .reply(&filter)
+            .await;
+        let hub_id = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => id,
+            Response::Error(err) => panic!("{}", err),
+        };
+        let create_channel = |name: &str| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!("/channel/{}", hub_id))
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&HttpChannelUpdate {
+                    name: Some(name.to_string()),
+                    description: None,
+                })
+        };
+        let response = create_channel(" general \n\n chat ").reply(&filter).await;
+        let channel_id = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => id,
+            Response::Error(err) => panic!("{}", err),
+        };
+        let hub = crate::hub::Hub::load(hub_id).await.unwrap();
+        assert_eq!("My Hub", hub.name);
+        assert_eq!("general chat", hub.channels[&channel_id].name);
+        let response = warp::test::request()
+            .method("POST")
+            .path(&format!("/hub/{}/clone", hub_id))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .json(&crate::prelude::HttpCloneHub {
+                name: "  My  Clone  ".to_string(),
+            })
+            .reply(&filter)
+            .await;
+        let clone_id = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => id,
+            Response::Error(err) => panic!("{}", err),
+        };
+        assert_eq!(
+            "My Clone",
+            crate::hub::Hub::load(clone_id).await.unwrap().name
+        );
+        let response = create_channel(" \t ").reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+        let response = create_channel("").reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[tokio::test]
+    async fn message_exists() {
+        let (server, cache, hub) = test_server_with_hub(&[*crate::test::OTHER_USER_ID]).await;
+        let message = hub
+            .send_message(
+                *crate::test::USER_ID,
+                *crate::test::CHANNEL_ID,
+                "hello".to_string(),
+                0,
+            )
+            .await
+            .unwrap();
+        let filter = message::message(server, crate::MESSAGE_DELETE_GRACE_PERIOD, cache)
+            .recover(handle_rejection);
+        let exists = |user: ID, message_id: ID| {
+            warp::test::request()
+                .path(&format!(
+                    "/message/{}/{}/{}/exists",
+                    hub.id,
+                    *crate::test::CHANNEL_ID,
+                    message_id
+                ))
+                .header("authorization", user.to_string())
+        };
+        for (message_id, expected) in [(message.id, true), (crate::new_id(), false)].iter() {
+            let response = exists(*crate::test::USER_ID, *message_id)
+                .reply(&filter)
+                .await;
+            match serde_json::from_slice(response.body()).unwrap() {
+                Response::<bool>::Success(found) => assert_eq!(*expected, found),
+                Response::Error(err) => panic!("{}", err),
+            }
+        }
+        let response = exists(*crate::test::OTHER_USER_ID, message.id)
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        assert!(!String::from_utf8_lossy(response.body()).contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn preview_message() {
+        use crate::prelude::{HttpMessagePreview, HttpSendMessage};
+        let (server, cache, hub) = test_server_with_hub(&[*crate::test::OTHER_USER_ID]).await;
+        let filter = message::message(server, crate::MESSAGE_DELETE_GRACE_PERIOD, cache)
+            .recover(handle_rejection);
+        let preview = |message: String| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!(
+                    "/message/{}/{}/preview",
+                    hub.id,
+                    *crate::test::CHANNEL_ID
+                ))
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&HttpSendMessage {
+                    message,
+                    flags: 0,
+                    expires_at: None,
+                })
+        };
+        let response = preview("a".repeat(crate::MAX_MESSAGE_SIZE + 1))
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+        let content = format!(
+            "hi @{}, not @{}",
+            *crate::test::OTHER_USER_ID,
+            crate::new_id()
+        );
+        let response = preview(content.clone()).reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<HttpMessagePreview>::Success(preview) => {
+                assert_eq!(content, preview.content);
+                assert_eq!(
+                    vec![*crate::test::OTHER_USER_ID],
+                    preview.mentions.into_iter().collect::<Vec<ID>>()
+                );
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+        let channel = &hub.channels[&*crate::test::CHANNEL_ID];
+        assert!(channel.get_last_messages(1).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn drafts() {
+        use crate::{prelude::HttpSetDraft, user::Draft};
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        let filter =
+            channel::channel(server, crate::MAX_DESCRIPTION_SIZE, cache).recover(handle_rejection);
+        let path = format!("/channel/{}/{}/draft", hub.id, *crate::test::CHANNEL_ID);
+        let request = |method: &str| {
+            warp::test::request()
+                .method(method)
+                .path(&path)
+                .header("authorization", crate::test::USER_ID.to_string())
+        };
+        let response = request("PUT")
+            .json(&HttpSetDraft {
+                content: "half written".to_string(),
+            })
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let response = request("GET").reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Option<Draft>>::Success(draft) => {
+                assert_eq!("half written", draft.unwrap().content)
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = request("PUT")
+            .json(&HttpSetDraft {
+                content: "a".repeat(crate::MAX_MESSAGE_SIZE + 1),
+            })
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+        let response = request("DELETE").reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let response = request("GET").reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Option<Draft>>::Success(draft) => assert_eq!(None, draft),
+            Response::Error(err) => panic!("{}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_history() {
+        use crate::{hub::ChannelInfoChange, prelude::HttpChannelUpdate};
+        let (server, cache, hub) = test_server_with_hub(&[*crate::test::OTHER_USER_ID]).await;
+        let filter = channel::channel(
+            Arc::clone(&server),
+            crate::MAX_DESCRIPTION_SIZE,
+            cache.clone(),
+        )
+        .recover(handle_rejection);
+        let original = hub.channels[&*crate::test::CHANNEL_ID].name.clone();
+        for name in &["first", "second"] {
+            let response = warp::test::request()
+                .method("PUT")
+                .path(&format!("/channel/{}/{}", hub.id, *crate::test::CHANNEL_ID))
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&HttpChannelUpdate {
+                    name: Some(name.to_string()),
+                    description: None,
+                })
+                .reply(&filter)
+                .await;
+            assert_eq!(warp::http::StatusCode::OK, response.status());
+        }
+        let history = |user_id: ID| {
+            warp::test::request()
+                .method("GET")
+                .path(&format!(
+                    "/channel/{}/{}/history",
+                    hub.id,
+                    *crate::test::CHANNEL_ID
+                ))
+                .header("authorization", user_id.to_string())
+        };
+        let response = history(*crate::test::USER_ID).reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Vec<ChannelInfoChange>>::Success(changes) => {
+                assert_eq!(
+                    vec![Some(original), Some("first".to_string())],
+                    changes
+                        .iter()
+                        .map(|change| change.old_name.clone())
+                        .collect::<Vec<Option<String>>>()
+                );
+                assert!(changes[0].time <= changes[1].time);
+                assert!(changes
+                    .iter()
+                    .all(|change| change.changed_by == *crate::test::USER_ID));
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = history(*crate::test::OTHER_USER_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        let response = warp::test::request()
+            .method("GET")
+            .path(&format!("/hub/{}", hub.id))
+            .header("authorization", crate::test::OTHER_USER_ID.to_string())
+            .reply(&hub::hub(server, None, crate::MAX_DESCRIPTION_SIZE, cache))
+            .await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<crate::hub::Hub>::Success(hub) => assert!(hub.channel_history.is_empty()),
+            Response::Error(err) => panic!("{}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_permission_overrides() {
+        use crate::{
+            permission::{ChannelPermission, ChannelPermissions},
+            prelude::HttpChannelPermissionOverrides,
+        };
+        let (server, cache) = test_server().await;
+        let mut hub = crate::hub::test::test_hub();
+        hub.user_join(*crate::test::OTHER_USER_ID).unwrap();
+        let member = hub.get_member_mut(&crate::test::OTHER_USER_ID).unwrap();
+        member.set_channel_permission(
+            *crate::test::CHANNEL_ID,
+            ChannelPermission::Write,
+            Some(false),
+        );
+        member.set_channel_permission(*crate::test::CHANNEL_ID, ChannelPermission::Read, None);
+        hub.groups
+            .get_mut(&crate::test::GROUP_ID)
+            .unwrap()
+            .set_channel_permission(
+                *crate::test::CHANNEL_ID,
+                ChannelPermission::Read,
+                Some(true),
+            );
+        hub.save(&cache).await.unwrap();
+        let filter =
+            channel::channel(server, crate::MAX_DESCRIPTION_SIZE, cache).recover(handle_rejection);
+        let overrides = |user_id: ID| {
+            warp::test::request()
+                .path(&format!(
+                    "/channel/{}/{}/permissions",
+                    hub.id,
+                    *crate::test::CHANNEL_ID
+                ))
+                .header("authorization", user_id.to_string())
+        };
+        let response = overrides(*crate::test::USER_ID).reply(&filter).await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<HttpChannelPermissionOverrides>::Success(overrides) => {
+                let single = |permission, setting| {
+                    vec![(permission, Some(setting))]
+                        .into_iter()
+                        .collect::<ChannelPermissions>()
+                };
+                assert_eq!(
+                    vec![(
+                        *crate::test::GROUP_ID,
+                        single(ChannelPermission::Read, true)
+                    )]
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>(),
+                    overrides.groups
+                );
+                assert_eq!(
+                    vec![(
+                        *crate::test::OTHER_USER_ID,
+                        single(ChannelPermission::Write, false)
+                    )]
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>(),
+                    overrides.members
+                );
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+        let response = overrides(*crate::test::OTHER_USER_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+    }
+
+    #[tokio::test]
+    async fn delete_while_sending() {
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        let filter = hub::hub(
+            Arc::clone(&server),
+            None,
+            crate::MAX_DESCRIPTION_SIZE,
+            cache.clone(),
+        )
+        .or(message::message(
+            server,
+            crate::MESSAGE_DELETE_GRACE_PERIOD,
+            cache,
+        ))
+        .recover(handle_rejection);
+        let send = || {
+            warp::test::request()
+                .method("POST")
+                .path(&format!("/message/{}/{}", hub.id, *crate::test::CHANNEL_ID))
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&crate::prelude::HttpSendMessage {
+                    message: "hello".to_string(),
+                    flags: 0,
+                    expires_at: None,
+                })
+        };
+        let delete = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/hub/{}", hub.id))
+            .header("authorization", crate::test::USER_ID.to_string());
+        let (deleted, sent) = futures::join!(delete.reply(&filter), send().reply(&filter));
+        assert_eq!(warp::http::StatusCode::OK, deleted.status());
+        assert!(
+            sent.status() == warp::http::StatusCode::OK
+                || sent.status() == warp::http::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            warp::http::StatusCode::NOT_FOUND,
+            send().reply(&filter).await.status()
+        );
+    }
+
+    #[tokio::test]
+    async fn permission_schema_lists_all() {
+        use crate::permission::{ChannelPermission, HubPermission};
+        // Exhaustive matches so that new variants have to be added here, and to `VARIANTS`.
+        fn hub_index(permission: HubPermission) -> usize {
+            match permission {
+                HubPermission::All => 0,
+                HubPermission::ReadChannels => 1,
+                HubPermission::WriteChannels => 2,
+                HubPermission::Administrate => 3,
+                HubPermission::ManageChannels => 4,
+                HubPermission::Mute => 5,
+                HubPermission::Unmute => 6,
+                HubPermission::Kick => 7,
+                HubPermission::Ban => 8,
+                HubPermission::Unban => 9,
+            }
+        }
+        fn channel_index(permission: ChannelPermission) -> usize {
+            match permission {
+                ChannelPermission::Write => 0,
+                ChannelPermission::Read => 1,
+                ChannelPermission::Manage => 2,
+                ChannelPermission::All => 3,
+                ChannelPermission::CreateThread => 4,
+                ChannelPermission::EmbedLinks => 5,
+                ChannelPermission::SendAttachments => 6,
+            }
+        }
+        let response = warp::test::request()
+            .path("/permissions/schema")
+            .reply(&permission_schema())
+            .await;
+        let schema: Response<HttpPermissionSchema> =
+            serde_json::from_slice(response.body()).unwrap();
+        let schema = match schema {
+            Response::Success(schema) => schema,
+            Response::Error(err) => panic!("{}", err),
+        };
+        let mut hub = schema.hub.iter().map(|p| hub_index(*p)).collect::<Vec<_>>();
+        hub.sort_unstable();
+        assert_eq!((0..10).collect::<Vec<_>>(), hub);
+        let mut channel = schema
+            .channel
+            .iter()
+            .map(|p| channel_index(*p))
+            .collect::<Vec<_>>();
+        channel.sort_unstable();
+        assert_eq!((0..7).collect::<Vec<_>>(), channel);
+        assert_eq!(schema.channel.len(), schema.channel_to_hub.len());
+    }
+
+    #[tokio::test]
+    async fn open_dm_twice() {
+        use crate::{channel::Message, dm::DmChannel};
+        let (server, _) = test_server().await;
+        let filter = dm::dm(server).recover(handle_rejection);
+        let (user_a, user_b) = (crate::new_id(), crate::new_id());
+        let open = |user: ID, other: ID| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!("/dm/open/{}", other))
+                .header("authorization", user.to_string())
+        };
+        let first = match serde_json::from_slice(open(user_a, user_b).reply(&filter).await.body())
+            .unwrap()
+        {
+            Response::<DmChannel>::Success(dm) => dm,
+            Response::Error(err) => panic!("{}", err),
+        };
+        let response = warp::test::request()
+            .method("POST")
+            .path(&format!("/dm/{}", first.id))
+            .header("authorization", user_a.to_string())
+            .json(&serde_json::json!({ "message": "hello" }))
+            .reply(&filter)
+            .await;
+        let message_id = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => id,
+            Response::Error(err) => panic!("{}", err),
+        };
+        for (user, other) in [(user_a, user_b), (user_b, user_a)].iter() {
+            match serde_json::from_slice(open(*user, *other).reply(&filter).await.body()).unwrap() {
+                Response::<DmChannel>::Success(dm) => assert_eq!(first, dm),
+                Response::Error(err) => panic!("{}", err),
+            }
+        }
+        let response = warp::test::request()
+            .path(&format!("/dm/{}/last?max=10", first.id))
+            .header("authorization", user_b.to_string())
+            .reply(&filter)
+            .await;
+        match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Vec<Message>>::Success(messages) => {
+                assert_eq!(
+                    vec![message_id],
+                    messages.iter().map(|m| m.id).collect::<Vec<_>>()
+                )
+            }
+            Response::Error(err) => panic!("{}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_channel_batch() {
+        use crate::{hub::Hub, permission::ChannelPermission};
+        let (server, cache, hub) = test_server_with_hub(&[]).await;
+        let filter =
+            channel::channel(server, crate::MAX_DESCRIPTION_SIZE, cache).recover(handle_rejection);
+        let batch = |channels: serde_json::Value| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!("/channel/{}/batch", hub.id))
+                .header("authorization", crate::test::USER_ID.to_string())
+                .json(&channels)
+        };
+        let response = batch(serde_json::json!([
+            { "name": "rules", "description": "read first" },
+            { "name": "general" },
+            { "name": " " }
+        ]))
+        .reply(&filter)
+        .await;
+        assert_eq!(warp::http::StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            hub.channels.len(),
+            Hub::load(hub.id).await.unwrap().channels.len()
+        );
+        let response = batch(serde_json::json!([
+            { "name": "rules", "description": "read first" },
+            {
+                "name": "general",
+                "permissions": {
+                    "groups": { (crate::test::GROUP_ID.to_string()): { "Write": true } },
+                    "members": {}
+                }
+            }
+        ]))
+        .reply(&filter)
+        .await;
+        let ids = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<Vec<ID>>::Success(ids) => ids,
+            Response::Error(err) => panic!("{}", err),
+        };
+        assert_eq!(2, ids.len());
+        let loaded = Hub::load(hub.id).await.unwrap();
+        assert_eq!(hub.channels.len() + 2, loaded.channels.len());
+        assert_eq!("rules", loaded.channels[&ids[0]].name);
+        assert_eq!("read first", loaded.channels[&ids[0]].description);
+        assert_eq!("general", loaded.channels[&ids[1]].name);
+        assert_eq!(
+            Some(&Some(true)),
+            loaded.groups[&crate::test::GROUP_ID]
+                .channel_permissions
+                .get(&ids[1])
+                .and_then(|permissions| permissions.get(&ChannelPermission::Write))
+        );
+    }
+
+    #[tokio::test]
+    async fn channel_audit_fields() {
+        use crate::{hub::Hub, permission::ChannelPermission};
+        let (server, cache, mut hub) = test_server_with_hub(&[*crate::test::OTHER_USER_ID]).await;
+        let filter = channel::channel(server, crate::MAX_DESCRIPTION_SIZE, cache.clone())
+            .recover(handle_rejection);
+        let response = warp::test::request()
+            .method("POST")
+            .path(&format!("/channel/{}", hub.id))
+            .header("authorization", crate::test::USER_ID.to_string())
+            .json(&serde_json::json!({ "name": "audited" }))
+            .reply(&filter)
+            .await;
+        let channel_id = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<ID>::Success(id) => id,
+            Response::Error(err) => panic!("{}", err),
+        };
+        let mut hub = Hub::load(hub.id).await.unwrap();
+        let channel = &hub.channels[&channel_id];
+        assert_eq!(*crate::test::USER_ID, channel.created_by);
+        assert_eq!(None, channel.updated_by);
+        assert_eq!(None, channel.updated);
+        hub.get_member_mut(&crate::test::OTHER_USER_ID)
+            .unwrap()
+            .set_channel_permission(channel_id, ChannelPermission::Manage, Some(true));
+        hub.save(&cache).await.unwrap();
+        let response = warp::test::request()
+            .method("PUT")
+            .path(&format!("/channel/{}/{}", hub.id, channel_id))
+            .header("authorization", crate::test::OTHER_USER_ID.to_string())
+            .json(&serde_json::json!({ "description": "now with a description" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(warp::http::StatusCode::OK, response.status());
+        let channel = Hub::load(hub.id).await.unwrap().channels[&channel_id].clone();
+        assert_eq!(*crate::test::USER_ID, channel.created_by);
+        assert_eq!(Some(*crate::test::OTHER_USER_ID), channel.updated_by);
+        assert!(channel.updated.is_some());
+    }
+
+    #[tokio::test]
+    async fn simulate_permissions() {
+        use crate::{
+            hub::{Hub, PermissionGroup},
+            permission::{ChannelPermission, HubPermission, HubPermissionSet, PermissionSet},
+            prelude::{HttpSimulatePermissions, HttpSimulatedPermissions},
+        };
+        let (server, cache) = test_server().await;
+        let mut hub = crate::hub::test::test_hub();
+        hub.user_join(*crate::test::OTHER_USER_ID).unwrap();
+        let mut writers = PermissionGroup::new("writers".to_string(), crate::new_id());
+        writers.set_channel_permission(
+            *crate::test::CHANNEL_ID,
+            ChannelPermission::Write,
+            Some(true),
+        );
+        writers.set_channel_permission(
+            *crate::test::CHANNEL_ID,
+            ChannelPermission::Read,
+            Some(true),
+        );
+        let writers_id = writers.id;
+        hub.groups.insert(writers_id, writers);
+        hub.save(&cache).await.unwrap();
+        let overrides = vec![PermissionSet::Hub(HubPermissionSet {
+            permission: HubPermission::ManageChannels,
+            setting: Some(true),
+        })];
+        let simulation = HttpSimulatePermissions {
+            groups: Some(vec![*crate::test::GROUP_ID, writers_id]),
+            overrides: overrides.clone(),
+            hub: vec![HubPermission::ManageChannels, HubPermission::Administrate],
+            channel: vec![
+                (*crate::test::CHANNEL_ID, ChannelPermission::Write),
+                (*crate::test::CHANNEL_ID, ChannelPermission::All),
+            ],
+        };
+        let filter = member::member(server, cache).recover(handle_rejection);
+        let simulate = |user_id: ID| {
+            warp::test::request()
+                .method("POST")
+                .path(&format!(
+                    "/member/{}/{}/permissions/simulate",
+                    hub.id,
+                    *crate::test::OTHER_USER_ID
+                ))
+                .header("authorization", user_id.to_string())
+                .json(&simulation)
+        };
+        let response = simulate(*crate::test::OTHER_USER_ID).reply(&filter).await;
+        assert_eq!(warp::http::StatusCode::FORBIDDEN, response.status());
+        let response = simulate(*crate::test::USER_ID).reply(&filter).await;
+        let simulated = match serde_json::from_slice(response.body()).unwrap() {
+            Response::<HttpSimulatedPermissions>::Success(simulated) => simulated,
+            Response::Error(err) => panic!("{}", err),
+        };
+        assert_eq!(hub, Hub::load(hub.id).await.unwrap());
+        let mut applied = hub.clone();
+        let mut member = applied
+            .get_member(&crate::test::OTHER_USER_ID)
+            .unwrap()
+            .clone();
+        member.join_group(applied.groups.get_mut(&writers_id).unwrap());
+        applied.members.insert(*crate::test::OTHER_USER_ID, member);
+        applied
+            .set_member_permissions(
+                &crate::test::USER_ID,
+                &crate::test::OTHER_USER_ID,
+                &overrides,
+            )
+            .unwrap();
+        let member = applied.get_member(&crate::test::OTHER_USER_ID).unwrap();
+        assert_eq!(
+            HttpSimulatedPermissions {
+                hub: simulation
+                    .hub
+                    .iter()
+                    .map(|permission| (*permission, member.has_permission(*permission, &applied)))
+                    .collect(),
+                channel: simulation
+                    .channel
+                    .iter()
+                    .map(|(channel_id, permission)| {
+                        (
+                            *channel_id,
+                            *permission,
+                            member.has_channel_permission(*channel_id, *permission, &applied),
+                        )
+                    })
+                    .collect(),
+            },
+            simulated
+        );
+        assert_eq!(
+            vec![
+                (HubPermission::ManageChannels, true),
+                (HubPermission::Administrate, false)
+            ],
+            simulated.hub
+        );
+        assert!(simulated.channel[0].2);
+        assert!(!simulated.channel[1].2);
+    }
+}
@@ -1,18 +1,25 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 #[cfg(feature = "server")]
-use std::mem;
+use std::{mem, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+#[cfg(feature = "server")]
+use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "server")]
 use tokio::io::AsyncReadExt;
 #[cfg(feature = "server")]
 use tokio::io::AsyncWriteExt;
+#[cfg(feature = "server")]
+use tokio::sync::RwLock;
 
-use crate::channel::Message;
+#[cfg(feature = "server")]
+use crate::channel::{CompactionReport, Thread, ThreadSummary};
+use crate::channel::{Message, MessageReference};
 use crate::{
     channel::Channel,
     permission::{ChannelPermissions, HubPermissions},
+    prelude::{channel_permission_fallback, WsHubUpdateType},
     ID,
 };
 
@@ -22,11 +29,27 @@ use crate::check_permission;
 use crate::{
     check_name_validity,
     error::Result,
-    error::{ApiError, ApiResult},
+    error::{ApiError, ApiResult, Error},
     new_id,
-    permission::{ChannelPermission, HubPermission, PermissionSetting},
+    permission::{ChannelPermission, HubPermission, PermissionSet, PermissionSetting},
+    prelude::check_tags_validity,
+    server::HubCache,
 };
 
+#[cfg(feature = "server")]
+lazy_static::lazy_static! {
+    /// Locks that keep operations on a hub from running while it is being deleted, see [`Hub::lock`].
+    /// Each lock holds whether the hub has been deleted, the locks of deleted hubs are kept so that operations that were waiting for the deletion see it.
+    static ref HUB_LOCKS: std::sync::Mutex<HashMap<ID, Arc<RwLock<bool>>>> =
+        std::sync::Mutex::new(HashMap::new());
+    /// Times at which messages were last sent in each channel since its hub was last saved, keyed by hub and then channel ID.
+    /// Sending a message does not save the hub, so these are applied to channels when the hub is loaded and dropped once the hub is saved with them, see [`Channel::last_activity`].
+    static ref LAST_ACTIVITY: std::sync::Mutex<HashMap<ID, HashMap<ID, DateTime<Utc>>>> =
+        std::sync::Mutex::new(HashMap::new());
+    /// Lock held while invites are used or revoked so that concurrent uses can not go over an invite's usage limit.
+    static ref INVITE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
 /// Relative path of the folder in which Hub information files (`${ID}`) files are stored.
 #[cfg(not(test))]
 pub const HUB_INFO_FOLDER: &str = "data/hubs/info/";
@@ -37,6 +60,20 @@ pub const HUB_INFO_FOLDER: &str = "test_data/hubs/info/";
 pub const HUB_DATA_FOLDER: &str = "data/hubs/data/";
 #[cfg(test)]
 pub const HUB_DATA_FOLDER: &str = "test_data/hubs/data/";
+/// Relative path of the folder in which hub invite files (`${CODE}`) are stored.
+#[cfg(not(test))]
+pub const INVITE_FOLDER: &str = "data/invites/";
+#[cfg(test)]
+pub const INVITE_FOLDER: &str = "test_data/invites/";
+
+/// Bytes at the start of hub info files, followed by the version of the layout the hub is encoded with, see [`HUB_FORMAT_VERSION`].
+/// Files that do not start with it were written before hub files were versioned and are read in the layout hubs had at the time.
+#[cfg(feature = "server")]
+pub const HUB_FILE_HEADER: &[u8; 8] = b"WICRSHUB";
+/// Version of the layout hubs are written with. Bincode ignores `#[serde(default)]`, so a field can only be added to [`Hub`] or
+/// the types stored in it together with a new version, [`Hub::load`] has to keep a decoder for the layout of every earlier version.
+#[cfg(feature = "server")]
+pub const HUB_FORMAT_VERSION: u8 = 1;
 
 /// Represents a member of a hub that maps to a user.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -114,6 +151,12 @@ impl HubMember {
         channel_permissions.insert(permission, value);
     }
 
+    /// Removes all of the member's individual hub and channel permission settings, leaving only the permissions they get from their groups.
+    pub fn clear_overrides(&mut self) {
+        self.hub_permissions.clear();
+        self.channel_permissions.clear();
+    }
+
     /// Checks if the hub member has the `HubPermission::All` permission or if they inherit it from a permission group they are in.
     pub fn has_all_permissions(&self) -> bool {
         if let Some(value) = self.hub_permissions.get(&HubPermission::All) {
@@ -186,7 +229,7 @@ impl HubMember {
                         return false;
                     }
                     None => {
-                        if self.has_permission(permission.into(), hub) {
+                        if self.has_permission(channel_permission_fallback(permission), hub) {
                             return true;
                         }
                     }
@@ -220,6 +263,15 @@ pub struct PermissionGroup {
     pub channel_permissions: HashMap<ID, ChannelPermissions>,
     /// Time in milliseconds since Unix Epoch that the group was created.
     pub created: DateTime<Utc>,
+    /// ID of the user that created the group, nil if it was created before this was recorded.
+    #[serde(default)]
+    pub created_by: ID,
+    /// ID of the user that last changed the group, `None` if it was never changed.
+    #[serde(default)]
+    pub updated_by: Option<ID>,
+    /// Time at which the group was last changed, `None` if it was never changed.
+    #[serde(default)]
+    pub updated: Option<DateTime<Utc>>,
 }
 
 #[cfg(feature = "server")]
@@ -233,9 +285,18 @@ impl PermissionGroup {
             members: Vec::new(),
             hub_permissions: HashMap::new(),
             channel_permissions: HashMap::new(),
+            created_by: ID::nil(),
+            updated_by: None,
+            updated: None,
         }
     }
 
+    /// Records that the given user changed the group now.
+    pub fn record_update(&mut self, user_id: ID) {
+        self.updated_by = Some(user_id);
+        self.updated = Some(Utc::now());
+    }
+
     /// Adds a hub member to the group, maps to `HubMember::join_group`.
     pub fn add_member(&mut self, user: &mut HubMember) {
         user.join_group(self)
@@ -301,7 +362,8 @@ impl PermissionGroup {
             }
             if let Some(value) = channel.get(&permission) {
                 if value == &Some(true)
-                    || (value == &None && self.has_permission(permission.into()))
+                    || (value == &None
+                        && self.has_permission(channel_permission_fallback(permission)))
                 {
                     return true;
                 }
@@ -311,6 +373,203 @@ impl PermissionGroup {
     }
 }
 
+/// What to do with a message that contains a word from a hub's word filter.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordFilterMode {
+    /// Refuse to send the message.
+    Reject,
+    /// Replace the filtered words with asterisks before storing the message.
+    Mask,
+}
+
+impl Default for WordFilterMode {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+/// List of words that are not allowed in messages sent in a hub.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct WordFilter {
+    /// What to do with messages that contain filtered words.
+    pub mode: WordFilterMode,
+    /// Filtered words, stored in lowercase, matching is case insensitive and only done on whole words.
+    pub words: HashSet<String>,
+}
+
+#[cfg(feature = "server")]
+impl WordFilter {
+    /// Creates a word filter, the words are converted to lowercase.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the words is empty or contains characters that are not alphanumeric.
+    pub fn new(mode: WordFilterMode, words: Vec<String>) -> ApiResult<Self> {
+        let mut set = HashSet::new();
+        for word in words {
+            if word.is_empty() || !word.chars().all(char::is_alphanumeric) {
+                return Err(ApiError::InvalidText);
+            }
+            set.insert(word.to_lowercase());
+        }
+        Ok(Self { mode, words: set })
+    }
+
+    /// Gets the byte ranges of all of the filtered words found in the given text.
+    fn find_words(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        if self.words.is_empty() {
+            return found;
+        }
+        let mut start = None;
+        for (i, c) in text
+            .char_indices()
+            .chain(std::iter::once((text.len(), ' ')))
+        {
+            if c.is_alphanumeric() {
+                if start.is_none() {
+                    start = Some(i);
+                }
+            } else if let Some(start) = start.take() {
+                if self.words.contains(&text[start..i].to_lowercase()) {
+                    found.push((start, i));
+                }
+            }
+        }
+        found
+    }
+
+    /// Checks a message's content against the filter, returns the content that should be stored.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`ApiError::InvalidText`] if the content contains a filtered word and the filter is in [`WordFilterMode::Reject`] mode.
+    pub fn apply(&self, content: String) -> ApiResult<String> {
+        let found = self.find_words(&content);
+        if found.is_empty() {
+            return Ok(content);
+        }
+        match self.mode {
+            WordFilterMode::Reject => Err(ApiError::InvalidText),
+            WordFilterMode::Mask => Ok(content
+                .char_indices()
+                .map(|(i, c)| {
+                    if found.iter().any(|(start, end)| i >= *start && i < *end) {
+                        '*'
+                    } else {
+                        c
+                    }
+                })
+                .collect()),
+        }
+    }
+}
+
+/// A change made to a hub, kept in the hub's change log so that clients can catch up on changes they missed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HubChange {
+    /// Sequence number of the change, increases by one for every change made to the hub.
+    pub sequence: u64,
+    /// Time at which the change was made.
+    pub time: DateTime<Utc>,
+    /// What was changed.
+    pub update_type: WsHubUpdateType,
+}
+
+/// A change to the name or description of a channel, kept in [`Hub::channel_history`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelInfoChange {
+    /// Time at which the change was made.
+    pub time: DateTime<Utc>,
+    /// ID of the user who made the change.
+    pub changed_by: ID,
+    /// Name of the channel before the change, `None` if it was not renamed.
+    pub old_name: Option<String>,
+    /// Description of the channel before the change, `None` if it was not changed.
+    pub old_description: Option<String>,
+}
+
+/// A link that lets users join a hub without an administrator having to know their ID, see [`Hub::create_invite`] and [`Hub::use_invite`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HubInvite {
+    /// Code that identifies the invite, made of [`crate::INVITE_CODE_LENGTH`] letters and digits.
+    pub code: String,
+    /// ID of the hub the invite is for.
+    pub hub_id: ID,
+    /// ID of the user who created the invite.
+    pub created_by: ID,
+    /// Time at which the invite was created.
+    pub created_at: DateTime<Utc>,
+    /// Time after which the invite can no longer be used, `None` if it never expires.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Number of times the invite can be used, `None` if there is no limit.
+    pub max_uses: Option<u32>,
+    /// Number of times the invite has been used.
+    pub uses: u32,
+}
+
+#[cfg(feature = "server")]
+impl HubInvite {
+    /// Gets the path of the file the invite with the given code is stored in.
+    pub fn get_path(code: &str) -> String {
+        format!("{}{}", INVITE_FOLDER, code)
+    }
+
+    /// Checks that the invite has not expired and has not reached its usage limit.
+    pub fn is_usable(&self) -> bool {
+        self.expires_at.map_or(true, |expires| expires > Utc::now())
+            && self.max_uses.map_or(true, |max_uses| self.uses < max_uses)
+    }
+
+    /// Loads the invite with the given code.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * There is no invite with that code.
+    /// * The invite's file could not be read or deserialized.
+    pub async fn load(code: &str) -> Result<Self> {
+        if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ApiError::InviteNotFound.into());
+        }
+        match tokio::fs::read(Self::get_path(code)).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Err(ApiError::InviteNotFound.into())
+            }
+            result => Ok(bincode::deserialize(&result?)?),
+        }
+    }
+
+    /// Saves the invite to its own file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the invite folder could not be created or the file could not be written.
+    pub async fn save(&self) -> Result {
+        tokio::fs::create_dir_all(INVITE_FOLDER).await?;
+        let mut file = tokio::fs::File::create(Self::get_path(&self.code)).await?;
+        file.write_all(&bincode::serialize(self)?).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Deletes the invite's file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the invite has already been deleted or its file could not be removed.
+    pub async fn delete(&self) -> Result {
+        match tokio::fs::remove_file(Self::get_path(&self.code)).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Err(ApiError::InviteNotFound.into())
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
 /// Represents a group of users, permission groups and channels.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Hub {
@@ -318,10 +577,10 @@ pub struct Hub {
     pub channels: HashMap<ID, Channel>,
     /// Map of hub members to their corresponding user's IDs.
     pub members: HashMap<ID, HubMember>,
-    /// List of IDs of all users that are banned from the hub.
-    pub bans: HashSet<ID>,
-    /// List of IDs of all the users who cannot send **any** messages in the hub.
-    pub mutes: HashSet<ID>,
+    /// Map of the users that are banned from the hub to the time at which their ban expires, `None` for permanent bans.
+    pub bans: HashMap<ID, Option<DateTime<Utc>>>,
+    /// Map of the users who cannot send **any** messages in the hub to the time at which their mute expires, `None` for permanent mutes.
+    pub mutes: HashMap<ID, Option<DateTime<Utc>>>,
     /// Description of the hub.
     pub description: String,
     /// ID of the user who owns the hub, also the creator.
@@ -336,6 +595,51 @@ pub struct Hub {
     pub id: ID,
     /// Time the hub was created in milliseconds since Unix Epoch.
     pub created: DateTime<Utc>,
+    /// Most recent changes made to the hub, oldest first, holds at most [`crate::MAX_HUB_CHANGES`] changes.
+    #[serde(default)]
+    pub changes: VecDeque<HubChange>,
+    /// Time of the newest change that has been dropped from the change log, `None` if no changes have been dropped.
+    #[serde(default)]
+    pub changes_truncated: Option<DateTime<Utc>>,
+    /// Sequence number of the last change made to the hub.
+    #[serde(default)]
+    pub last_sequence: u64,
+    /// Words that are not allowed in messages sent in the hub.
+    #[serde(default)]
+    pub word_filter: WordFilter,
+    /// Whether or not new members have to be approved before they can send messages.
+    #[serde(default)]
+    pub verification: bool,
+    /// Members that joined while verification was on and have not been approved yet.
+    #[serde(default)]
+    pub pending: HashSet<ID>,
+    /// ID of the channel clients should show users when they open the hub.
+    #[serde(default)]
+    pub default_channel: Option<ID>,
+    /// Maximum number of members the hub can have, no limit if `None`.
+    #[serde(default)]
+    pub max_members: Option<u32>,
+    /// Whether or not the hub is listed in the public directory, see [`crate::discovery`].
+    #[serde(default)]
+    pub discoverable: bool,
+    /// Tags users can filter the public directory by, see [`crate::prelude::is_valid_tag`].
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Category the hub is listed under in the public directory.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// ID of the message containing the hub's rules, if set members have to accept them before they can send messages.
+    #[serde(default)]
+    pub require_accept: Option<ID>,
+    /// Members that have accepted the current rules.
+    #[serde(default)]
+    pub accepted: HashSet<ID>,
+    /// Channel permissions the default group is given in every new channel, for example read and write to make new channels open to everyone.
+    #[serde(default)]
+    pub new_channel_permissions: ChannelPermissions,
+    /// Changes made to the names and descriptions of the hub's channels, oldest first.
+    #[serde(default)]
+    pub channel_history: HashMap<ID, VecDeque<ChannelInfoChange>>,
 }
 
 #[cfg(feature = "server")]
@@ -343,6 +647,7 @@ impl Hub {
     /// Creates a new hub given the ID of the user who should be the owner, the name and the ID the hub should have.
     pub fn new(name: String, id: ID, creator: ID) -> Self {
         let mut everyone = PermissionGroup::new(String::from("everyone"), new_id());
+        everyone.created_by = creator;
         let mut owner = HubMember::new(creator, id);
         let mut members = HashMap::new();
         let mut groups = HashMap::new();
@@ -357,15 +662,86 @@ impl Hub {
             description: String::new(),
             default_group: everyone.id,
             owner: creator,
-            bans: HashSet::new(),
-            mutes: HashSet::new(),
+            bans: HashMap::new(),
+            mutes: HashMap::new(),
             channels: HashMap::new(),
             members,
             created: Utc::now(),
+            changes: VecDeque::new(),
+            changes_truncated: None,
+            last_sequence: 0,
+            word_filter: WordFilter::default(),
+            verification: false,
+            pending: HashSet::new(),
+            default_channel: None,
+            max_members: None,
+            discoverable: false,
+            tags: Vec::new(),
+            category: None,
+            require_accept: None,
+            accepted: HashSet::new(),
+            new_channel_permissions: HashMap::new(),
+            channel_history: HashMap::new(),
+        }
+    }
+
+    /// Creates a new hub owned by the given user with the same channels, permission groups and settings as this hub.
+    /// Channels and groups get new IDs, the new hub has no messages and the new owner is its only member.
+    /// The channel data folders are not created, see [`Channel::create_dir`].
+    pub fn clone_as_template(&self, new_owner: ID, new_name: String) -> Hub {
+        let mut hub = Hub::new(new_name, new_id(), new_owner);
+        let channel_ids: HashMap<ID, ID> = self.channels.keys().map(|id| (*id, new_id())).collect();
+        for channel in self.channels.values() {
+            let id = channel_ids[&channel.id];
+            let mut new_channel = Channel::new(channel.name.clone(), id, hub.id);
+            new_channel.description = channel.description.clone();
+            new_channel.created_by = new_owner;
+            hub.channels.insert(id, new_channel);
+        }
+        let mut groups = HashMap::new();
+        let mut default_group = None;
+        for group in self.groups.values() {
+            let mut new_group = PermissionGroup::new(group.name.clone(), new_id());
+            new_group.created_by = new_owner;
+            new_group.hub_permissions = group.hub_permissions.clone();
+            new_group.channel_permissions = group
+                .channel_permissions
+                .iter()
+                .filter_map(|(channel, permissions)| {
+                    channel_ids
+                        .get(channel)
+                        .map(|id| (*id, permissions.clone()))
+                })
+                .collect();
+            if group.id == self.default_group {
+                default_group = Some(new_group.id);
+            }
+            groups.insert(new_group.id, new_group);
         }
+        if let Some(default_group) = default_group {
+            hub.groups = groups;
+            hub.default_group = default_group;
+            if let (Some(owner), Some(group)) = (
+                hub.members.get_mut(&new_owner),
+                hub.groups.get_mut(&default_group),
+            ) {
+                owner.groups.clear();
+                owner.join_group(group);
+            }
+        }
+        hub.description = self.description.clone();
+        hub.default_channel = self
+            .default_channel
+            .and_then(|id| channel_ids.get(&id).copied());
+        hub.word_filter = self.word_filter.clone();
+        hub.verification = self.verification;
+        hub.max_members = self.max_members;
+        hub.new_channel_permissions = self.new_channel_permissions.clone();
+        hub
     }
 
     /// Creates a new channel while checking that the given user has permission to do so.
+    /// If the hub does not have a default channel the new channel becomes the default.
     ///
     /// # Errors
     ///
@@ -383,13 +759,13 @@ impl Hub {
         description: String,
     ) -> ApiResult<ID> {
         check_name_validity(&name)?;
-        let member = self.get_member(member_id)?;
-        check_permission!(member, HubPermission::ManageChannels, self);
+        self.check_administrative(member_id, HubPermission::ManageChannels)?;
         let mut id = new_id();
         while self.channels.contains_key(&id) {
             id = new_id();
         }
         let mut channel = Channel::new(name, id, self.id);
+        channel.created_by = *member_id;
         if let Err(e) = channel.create_dir().await {
             return Err(ApiError::from(&e));
         }
@@ -400,8 +776,16 @@ impl Hub {
                 Some(true),
             );
         }
+        if let Some(group) = self.groups.get_mut(&self.default_group) {
+            for (permission, value) in &self.new_channel_permissions {
+                group.set_channel_permission(id, *permission, *value);
+            }
+        }
         channel.description = description;
         self.channels.insert(id, channel);
+        if self.default_channel.is_none() {
+            self.default_channel = Some(id);
+        }
         Ok(id)
     }
 
@@ -417,6 +801,48 @@ impl Hub {
         }
     }
 
+    /// Gets the channels the user can read, the ones with the most recent activity first, see [`Channel::last_activity`].
+    pub fn get_channels_by_activity(&self, member_id: &ID) -> ApiResult<Vec<&Channel>> {
+        let member = self.get_member(member_id)?;
+        let mut channels = self
+            .channels
+            .values()
+            .filter(|channel| {
+                member.has_channel_permission(channel.id, ChannelPermission::Read, self)
+            })
+            .collect::<Vec<&Channel>>();
+        channels.sort_by(|a, b| b.last_activity.cmp(&a.last_activity));
+        Ok(channels)
+    }
+
+    /// Gets the most recent messages a user sent in the channels of the hub that the moderator can read, newest first.
+    /// At most `limit` messages are read from each channel.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The moderator is not in the hub.
+    /// * The moderator does not have the [`HubPermission::Kick`] permission.
+    pub async fn get_member_messages(
+        &self,
+        moderator_id: &ID,
+        user_id: &ID,
+        limit: usize,
+    ) -> ApiResult<Vec<Message>> {
+        let moderator = self.get_member(moderator_id)?;
+        check_permission!(moderator, HubPermission::Kick, self);
+        let mut result = Vec::new();
+        for channel in self.channels.values() {
+            if moderator.has_channel_permission(channel.id, ChannelPermission::Read, self) {
+                result.append(&mut channel.get_messages_by_sender(user_id, limit).await);
+            }
+        }
+        result.sort_by(|a, b| b.created.cmp(&a.created));
+        result.truncate(limit);
+        Ok(result)
+    }
+
     /// Gets a mutable reference to the channel.
     /// Returns an error if the channel could not be found or the user did not have permission to view the channel.
     pub fn get_channel_mut(&mut self, member_id: &ID, channel_id: ID) -> ApiResult<&mut Channel> {
@@ -429,481 +855,2974 @@ impl Hub {
         }
     }
 
-    pub async fn send_message(
+    /// Checks that the given user can send a message with the given flags in a channel, returning the content after it has gone through the word filter.
+    fn check_send(
         &self,
-        sender: ID,
+        sender: &ID,
         channel_id: ID,
         content: String,
-    ) -> ApiResult<Message> {
-        if self.mutes.contains(&sender) {
+        flags: u32,
+    ) -> ApiResult<String> {
+        if flags & !Message::KNOWN_FLAGS != 0 {
+            return ApiResult::Err(ApiError::InvalidMessageFlags);
+        }
+        if self.is_user_muted(sender) {
             return ApiResult::Err(ApiError::Muted);
         }
-        let member = self.get_member(&sender)?;
+        if self.pending.contains(sender) {
+            return ApiResult::Err(ApiError::PendingVerification);
+        }
+        if self.require_accept.is_some() && sender != &self.owner && !self.accepted.contains(sender)
+        {
+            return ApiResult::Err(ApiError::RulesNotAccepted);
+        }
+        let member = self.get_member(sender)?;
         check_permission!(member, channel_id, ChannelPermission::Write, self);
-        let message = Message::new(sender, content, self.id, channel_id);
-        Channel::write_message(&message)
-            .await
-            .map_err(|_| ApiError::InternalError)?;
-        Ok(message)
+        if Message::find_links(&content).next().is_some()
+            && !self.has_write_derived_permission(member, channel_id, ChannelPermission::EmbedLinks)
+        {
+            return Err(ApiError::MissingChannelPermission {
+                permission: ChannelPermission::EmbedLinks,
+            });
+        }
+        if Message::find_attachments(&content).next().is_some()
+            && !self.has_write_derived_permission(
+                member,
+                channel_id,
+                ChannelPermission::SendAttachments,
+            )
+        {
+            return Err(ApiError::MissingChannelPermission {
+                permission: ChannelPermission::SendAttachments,
+            });
+        }
+        self.word_filter.apply(content)
     }
 
-    /// Checks if the user with the given ID is in the hub.
-    pub fn is_member(&self, member_id: &ID) -> bool {
-        self.members.contains_key(member_id)
+    /// Checks a channel permission that everyone who can write in a channel has unless it is explicitly denied to them, or to one of their groups without another of their groups allowing it.
+    fn has_write_derived_permission(
+        &self,
+        member: &HubMember,
+        channel_id: ID,
+        permission: ChannelPermission,
+    ) -> bool {
+        let setting = |permissions: &HashMap<ID, ChannelPermissions>| {
+            permissions
+                .get(&channel_id)
+                .and_then(|permissions| permissions.get(&permission))
+                .copied()
+                .flatten()
+        };
+        let setting = setting(&member.channel_permissions).or_else(|| {
+            member
+                .groups
+                .iter()
+                .filter_map(|group| self.groups.get(group))
+                .filter_map(|group| setting(&group.channel_permissions))
+                .max()
+        });
+        setting != Some(false)
+            || member.has_channel_permission(channel_id, ChannelPermission::All, self)
     }
 
-    /// Checks if the user with the given ID is in the hub, if not in hub also checks if banned.
-    pub fn check_membership(&self, member_id: &ID) -> ApiResult<()> {
-        if self.is_member(member_id) {
-            Ok(())
-        } else {
-            Err(if self.bans.contains(member_id) {
-                ApiError::Banned
-            } else {
-                ApiError::NotInHub
-            })
-        }
+    /// Runs the same checks as [`Hub::send_message`] and returns the message that would be sent, without writing it.
+    pub fn preview_message(
+        &self,
+        sender: ID,
+        channel_id: ID,
+        content: String,
+        flags: u32,
+    ) -> ApiResult<Message> {
+        let content = self.check_send(&sender, channel_id, content, flags)?;
+        Ok(Message::new(sender, content, self.id, channel_id, flags))
     }
 
-    /// Gets a reference to the hub member, returns an error if the member could not be found.
-    pub fn get_member(&self, member_id: &ID) -> ApiResult<&HubMember> {
-        if let Some(member) = self.members.get(member_id) {
-            Ok(member)
-        } else {
-            Err(ApiError::MemberNotFound)
-        }
+    pub async fn send_message(
+        &self,
+        sender: ID,
+        channel_id: ID,
+        content: String,
+        flags: u32,
+    ) -> ApiResult<Message> {
+        self.send_expiring_message(sender, channel_id, content, flags, None)
+            .await
     }
 
-    /// Gets a mutable reference to the hub member, returns an error if the member could not be found.
-    pub fn get_member_mut(&mut self, member_id: &ID) -> ApiResult<&mut HubMember> {
-        if let Some(member) = self.members.get_mut(member_id) {
-            Ok(member)
-        } else {
-            Err(ApiError::MemberNotFound)
+    /// Sends a message that is deleted by the [`crate::server::Server`]'s expiry sweep once `expires_at` has passed, never if it is `None`.
+    /// Returns [`ApiError::InvalidTime`] if the expiry is not in the future, otherwise fails for the same reasons as [`Hub::send_message`].
+    pub async fn send_expiring_message(
+        &self,
+        sender: ID,
+        channel_id: ID,
+        content: String,
+        flags: u32,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> ApiResult<Message> {
+        if expires_at.map_or(false, |expires_at| expires_at <= Utc::now()) {
+            return Err(ApiError::InvalidTime);
         }
+        let mut message = self.preview_message(sender, channel_id, content, flags)?;
+        message.expires_at = expires_at;
+        self.write_message(&message).await?;
+        Ok(message)
     }
 
-    /// Changes the description of a channel while checking that the given user has permission to do so.
+    /// Writes a message to its channel and records the channel's activity, see [`Channel::last_activity`].
+    async fn write_message(&self, message: &Message) -> ApiResult {
+        Channel::write_message(message)
+            .await
+            .map_err(|_| ApiError::InternalError)?;
+        let mut activity = LAST_ACTIVITY.lock().expect("activity map poisoned");
+        let last = activity
+            .entry(self.id)
+            .or_default()
+            .entry(message.channel_id)
+            .or_insert(message.created);
+        *last = (*last).max(message.created);
+        Ok(())
+    }
+
+    /// Sends a copy of a message from another channel, the copy references the original message, or the message it was forwarded from if it is itself a copy.
+    /// Checking that the sender can read the original message is up to the caller, otherwise fails for the same reasons as [`Hub::send_message`].
+    pub async fn forward_message(
+        &self,
+        sender: ID,
+        channel_id: ID,
+        original: &Message,
+    ) -> ApiResult<Message> {
+        let mut message =
+            self.preview_message(sender, channel_id, original.content.clone(), original.flags)?;
+        message.forwarded_from = Some(
+            original
+                .forwarded_from
+                .unwrap_or_else(|| MessageReference::from(original)),
+        );
+        self.write_message(&message).await?;
+        Ok(message)
+    }
+
+    /// Deletes a message, returning the deleted message.
+    /// Senders can delete their own messages for `grace_period` milliseconds after sending them, after that only users with [`ChannelPermission::Manage`] can.
     ///
     /// # Errors
     ///
     /// This function will return an error in the following situations, but is not
     /// limited to just these cases:
     ///
-    /// * Description is bigger than [`crate::MAX_DESCRIPTION_SIZE`].
-    /// * The user it not in the hub.
-    /// * The user does not have permission to view the channel.
-    /// * The user does not have permission to configure the channel.
-    /// * The channel does not exist.
-    pub async fn change_channel_description(
-        &mut self,
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The message does not exist in the channel.
+    /// * The user is not allowed to delete the message.
+    /// * The message files could not be rewritten.
+    pub async fn delete_message(
+        &self,
         user_id: &ID,
         channel_id: ID,
-        new_description: String,
-    ) -> ApiResult<String> {
-        if new_description.as_bytes().len() > crate::MAX_DESCRIPTION_SIZE {
-            Err(ApiError::TooBig)
-        } else if let Some(user) = self.members.get(user_id) {
-            check_permission!(user, channel_id, ChannelPermission::Manage, self);
-            if let Some(channel) = self.channels.get_mut(&channel_id) {
-                Ok(mem::replace(&mut channel.description, new_description))
-            } else {
-                Err(ApiError::ChannelNotFound)
-            }
-        } else {
-            Err(ApiError::NotInHub)
+        message_id: ID,
+        grace_period: i64,
+    ) -> ApiResult<Message> {
+        let channel = self.get_channel(user_id, channel_id)?;
+        let message = channel
+            .get_message(message_id)
+            .await
+            .ok_or(ApiError::MessageNotFound)?;
+        let own_recent = &message.sender == user_id
+            && Utc::now().signed_duration_since(message.created)
+                <= chrono::Duration::milliseconds(grace_period);
+        if !own_recent {
+            let member = self.get_member(user_id)?;
+            check_permission!(member, channel_id, ChannelPermission::Manage, self);
         }
+        channel
+            .delete_message(message_id)
+            .await
+            .map_err(|e| ApiError::from(&e))?;
+        Ok(message)
     }
 
-    /// Renames a channel while checking that the given user has permission to do so.
+    /// Edits a message, returning the edited message, see [`Channel::edit_message`].
+    /// Senders can edit their own messages, only users with [`ChannelPermission::Manage`] can edit messages sent by others.
+    /// The new content is checked the same way as the content of a new message sent by the editor.
     ///
     /// # Errors
     ///
     /// This function will return an error in the following situations, but is not
     /// limited to just these cases:
     ///
-    /// * Failed to pass [`check_name_validity`].
-    /// * The user it not in the hub.
-    /// * The user does not have permission to view the channel.
-    /// * The user does not have permission to configure the channel.
-    /// * The channel does not exist.
-    pub async fn rename_channel(
-        &mut self,
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The message does not exist in the channel.
+    /// * The user is not allowed to edit the message.
+    /// * The new content could not be sent by the user for any of the reasons outlined by [`Hub::preview_message`].
+    /// * The message file could not be rewritten.
+    pub async fn edit_message(
+        &self,
         user_id: &ID,
         channel_id: ID,
-        new_name: String,
-    ) -> ApiResult<String> {
-        check_name_validity(&new_name)?;
-        if let Some(user) = self.members.get(user_id) {
-            check_permission!(user, channel_id, ChannelPermission::Manage, self);
-            if let Some(channel) = self.channels.get_mut(&channel_id) {
-                Ok(mem::replace(&mut channel.name, new_name))
-            } else {
-                Err(ApiError::ChannelNotFound)
-            }
-        } else {
-            Err(ApiError::NotInHub)
+        message_id: ID,
+        content: String,
+    ) -> ApiResult<Message> {
+        let channel = self.get_channel(user_id, channel_id)?;
+        let message = channel
+            .get_message(message_id)
+            .await
+            .ok_or(ApiError::MessageNotFound)?;
+        if &message.sender != user_id {
+            let member = self.get_member(user_id)?;
+            check_permission!(member, channel_id, ChannelPermission::Manage, self);
         }
+        let content = self.check_send(user_id, channel_id, content, message.flags)?;
+        channel
+            .edit_message(message_id, content, *user_id)
+            .await
+            .map_err(|e| ApiError::from(&e))
     }
 
-    /// Deletes a channel while checking that the given user has permission to do so.
+    /// Starts a thread from a message while checking that the given user has permission to do so.
     ///
     /// # Errors
     ///
     /// This function will return an error in the following situations, but is not
     /// limited to just these cases:
     ///
-    /// * The user is not in the hub.
-    /// * The channel does not exist.
-    /// * THe user does not have permission to view the channel.
-    /// * The user does not have permission to delete the channel.
-    pub async fn delete_channel(&mut self, user_id: &ID, channel_id: ID) -> ApiResult {
-        if let Some(user) = self.members.get(user_id) {
-            check_permission!(user, HubPermission::ManageChannels, self);
-            if self.channels.remove(&channel_id).is_some() {
-                Ok(())
-            } else {
-                Err(ApiError::ChannelNotFound)
-            }
-        } else {
-            Err(ApiError::NotInHub)
+    /// * The name failed to pass [`check_name_validity`].
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The user does not have the [`ChannelPermission::CreateThread`] permission in the channel.
+    /// * The message does not exist in the channel.
+    /// * The thread could not be saved.
+    pub async fn create_thread(
+        &self,
+        member_id: &ID,
+        channel_id: ID,
+        message_id: ID,
+        name: String,
+    ) -> ApiResult<Thread> {
+        check_name_validity(&name)?;
+        let channel = self.get_channel(member_id, channel_id)?;
+        let member = self.get_member(member_id)?;
+        check_permission!(member, channel_id, ChannelPermission::CreateThread, self);
+        if channel.get_message(message_id).await.is_none() {
+            return Err(ApiError::MessageNotFound);
         }
+        let thread = Thread::new(self.id, channel_id, message_id, name, *member_id);
+        thread.save().await.map_err(|e| ApiError::from(&e))?;
+        Ok(thread)
     }
 
-    /// Gets the file path to be used for storing the hub's data.
-    pub fn get_info_path(&self) -> String {
-        format!("{}{}", HUB_INFO_FOLDER, self.id.to_string())
-    }
-
-    /// Gets the path of the directory in which channel folders should be stored.
-    pub fn get_data_path(&self) -> String {
-        format!("{}{}/", HUB_DATA_FOLDER, self.id.to_string())
-    }
-
-    /// Saves the hub's data to disk.
+    /// Gets a thread in a channel, the user must be able to read the channel.
     ///
     /// # Errors
     ///
     /// This function will return an error in the following situations, but is not
     /// limited to just these cases:
     ///
-    /// * The hub data could not be serialized.
-    /// * The hub info folder does not exist and could not be created.
-    /// * The data could not be written to the disk.
-    pub async fn save(&self) -> Result {
-        tokio::fs::create_dir_all(HUB_INFO_FOLDER).await?;
-        let mut file = tokio::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(self.get_info_path())
-            .await?;
-        let bytes = bincode::serialize(self)?;
-        let mut buf: &[u8] = bytes.as_slice();
-        file.write_buf(&mut buf).await?;
-        file.flush().await?;
-        Ok(())
+    /// * The channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    /// * The thread could not be loaded for any of the reasons outlined by [`Thread::load`].
+    pub async fn get_thread(
+        &self,
+        member_id: &ID,
+        channel_id: ID,
+        thread_id: ID,
+    ) -> ApiResult<Thread> {
+        self.get_channel(member_id, channel_id)?;
+        Thread::load(self.id, channel_id, thread_id)
+            .await
+            .map_err(|e| ApiError::from(&e))
+    }
+
+    /// Gets the threads started in a channel, the user must be able to read the channel.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    pub async fn get_threads(&self, member_id: &ID, channel_id: ID) -> ApiResult<Vec<Thread>> {
+        self.get_channel(member_id, channel_id)?;
+        Ok(Thread::list(self.id, channel_id).await)
     }
 
-    /// Loads a hub's data given its ID.
+    /// Sends a message in a thread, the same checks as [`Hub::send_message`] are done against the channel the thread is in.
     ///
     /// # Errors
     ///
     /// This function will return an error in the following situations, but is not
     /// limited to just these cases:
     ///
-    /// * There is no hub with that ID.
-    /// * The hub's data file was corrupt and could not be deserialized.
-    pub async fn load(id: ID) -> Result<Self> {
-        let filename = format!("{}{}", HUB_INFO_FOLDER, id.to_string());
-        let path = std::path::Path::new(&filename);
-        if !path.exists() {
-            return Err(ApiError::HubNotFound.into());
-        }
-        let mut file = tokio::fs::OpenOptions::new().read(true).open(path).await?;
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).await?;
-        Ok(bincode::deserialize(&buf)?)
+    /// * The message could not be sent in the thread's channel for any of the reasons outlined by [`Hub::send_message`].
+    /// * The thread could not be gotten for any of the reasons outlined by [`Hub::get_thread`].
+    pub async fn send_thread_message(
+        &self,
+        sender: ID,
+        channel_id: ID,
+        thread_id: ID,
+        content: String,
+        flags: u32,
+    ) -> ApiResult<Message> {
+        let content = self.check_send(&sender, channel_id, content, flags)?;
+        let mut thread = self.get_thread(&sender, channel_id, thread_id).await?;
+        let message = Message::new(sender, content, self.id, thread.id, flags);
+        thread
+            .channel()
+            .add_message(&message)
+            .await
+            .map_err(|_| ApiError::InternalError)?;
+        thread
+            .record_reply(message.clone())
+            .await
+            .map_err(|e| ApiError::from(&e))?;
+        Ok(message)
     }
 
-    /// Adds a user to a hub, creating and returning the resulting hub member.
+    /// Gets the number of replies and the last reply in the threads started from a message, the user must be able to read the channel.
     ///
     /// # Errors
     ///
-    /// This function will return an error in the following situations, but is not
-    /// limited to just this case:
-    ///
-    /// * The default permission group could not be found.
-    pub fn user_join(&mut self, user_id: ID) -> ApiResult<HubMember> {
-        let mut member = HubMember::new(user_id, self.id);
-        if let Some(group) = self.groups.get_mut(&self.default_group) {
-            group.add_member(&mut member);
-            self.members.insert(member.user_id, member.clone());
-            Ok(member)
-        } else {
-            Err(ApiError::GroupNotFound)
-        }
+    /// This function will return an error if the channel could not be gotten for any of the reasons outlined by [`Hub::get_channel`].
+    pub async fn get_thread_summary(
+        &self,
+        member_id: &ID,
+        channel_id: ID,
+        message_id: ID,
+    ) -> ApiResult<ThreadSummary> {
+        self.get_channel(member_id, channel_id)?;
+        Ok(Thread::summary(self.id, channel_id, message_id).await)
     }
 
-    /// Removes the given user from the hub.
+    /// Replaces the hub's word filter while checking that the given user has permission to do so.
     ///
     /// # Errors
     ///
-    /// This function will return an error in the following situations, but is not
-    /// limited to just these cases:
+    /// This function will return an error in the following situations:
     ///
     /// * The user is not in the hub.
-    /// * One of the permission groups the user was in could not be found in the hub.
-    pub fn user_leave(&mut self, user_id: &ID) -> ApiResult {
-        if user_id == &self.owner {
-            return ApiResult::Err(ApiError::IsOwner);
-        }
-        if let Some(member) = self.members.get_mut(user_id) {
-            if let Some(group) = self.groups.get_mut(&self.default_group) {
-                member.leave_group(group);
-                self.members.remove(user_id);
-                Ok(())
-            } else {
-                Err(ApiError::GroupNotFound)
-            }
-        } else {
-            Err(ApiError::NotInHub)
-        }
+    /// * The user does not have the [`HubPermission::Administrate`] permission.
+    pub fn set_word_filter(&mut self, member_id: &ID, word_filter: WordFilter) -> ApiResult {
+        self.check_administrative(member_id, HubPermission::Administrate)?;
+        self.word_filter = word_filter;
+        Ok(())
     }
 
-    /// Kicks the given user from the hub, forcing them to leave.
+    /// Approves a member that is pending verification, allowing them to send messages.
     ///
     /// # Errors
     ///
-    /// This function will return an error in the following situations, but is not
-    /// limited to just these cases:
+    /// This function will return an error in the following situations:
     ///
-    /// * The user could not be removed from the hub for any of the reasons outlined in [`Hub::user_leave`].
-    /// * The user's data failed to load for any of the reasons outlined in [`User::load`].
-    /// * The user's data failed to save for any of the reasons outlined in [`User::save`].
-    pub fn kick_user(&mut self, user_id: &ID) -> ApiResult {
-        if user_id == &self.owner {
-            return ApiResult::Err(ApiError::IsOwner);
-        } else if self.members.contains_key(user_id) {
-            self.user_leave(user_id)?;
-            self.members.remove(user_id);
-        }
+    /// * The approving user is not in the hub.
+    /// * The approving user does not have the [`HubPermission::Administrate`] permission.
+    /// * The user to approve is not in the hub.
+    pub fn approve_member(&mut self, actor_id: &ID, user_id: &ID) -> ApiResult {
+        self.check_administrative(actor_id, HubPermission::Administrate)?;
+        self.get_member(user_id)?;
+        self.pending.remove(user_id);
         Ok(())
     }
 
-    /// Kicks the given user and adds them to the banned list.
+    /// Records that the given member has accepted the hub's rules, allowing them to send messages.
     ///
     /// # Errors
     ///
-    /// Possible errors outlined by [`Hub::kick_user`].
-    pub fn ban_user(&mut self, user_id: ID) -> ApiResult {
-        self.kick_user(&user_id)?;
-        self.bans.insert(user_id);
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not in the hub.
+    pub fn accept_rules(&mut self, user_id: &ID) -> ApiResult {
+        self.get_member(user_id)?;
+        self.accepted.insert(*user_id);
         Ok(())
     }
 
-    /// Removes the given user from the banned lis.
-    pub fn unban_user(&mut self, user_id: &ID) {
-        self.bans.remove(user_id);
+    /// Sets the message containing the hub's rules, a nil ID removes the requirement.
+    /// Members have to accept the rules again if they changed.
+    /// Returns the previous rules message.
+    pub fn set_require_accept(&mut self, message_id: ID) -> Option<ID> {
+        let message_id = if message_id.is_nil() {
+            None
+        } else {
+            Some(message_id)
+        };
+        if message_id != self.require_accept {
+            self.accepted.clear();
+        }
+        mem::replace(&mut self.require_accept, message_id)
     }
 
-    /// Adds the given user to the mute list, preventing them from sending messages.
-    pub fn mute_user(&mut self, user_id: ID) -> ApiResult {
-        if user_id == self.owner {
-            return ApiResult::Err(ApiError::IsOwner);
-        }
-        self.mutes.insert(user_id);
-        Ok(())
+    /// Checks if the hub has reached its member limit.
+    pub fn is_full(&self) -> bool {
+        self.max_members
+            .map_or(false, |max| self.members.len() >= max as usize)
     }
 
-    /// Removes the given user from the mutes list, allowing them to send messages.
-    pub fn unmute_user(&mut self, user_id: &ID) {
-        self.mutes.remove(user_id);
+    /// Gets the IDs of the members other than the owner that have the [`HubPermission::Administrate`] permission, in the order they joined.
+    /// At most [`crate::MAX_LISTED_ADMINS`] are returned.
+    pub fn admins(&self) -> Vec<ID> {
+        let mut admins = self
+            .members
+            .values()
+            .filter(|member| {
+                member.user_id != self.owner
+                    && member.has_permission(HubPermission::Administrate, self)
+            })
+            .collect::<Vec<&HubMember>>();
+        admins.sort_by_key(|member| member.joined);
+        admins
+            .into_iter()
+            .take(crate::MAX_LISTED_ADMINS)
+            .map(|member| member.user_id)
+            .collect()
     }
 
-    /// Gets a list of the channels that the given user has permission to view.
-    ///
-    /// # Errors
-    ///
-    /// This function will only return an error if the given user is not in the hub.
-    pub fn get_channels_for_user(&self, user_id: &ID) -> ApiResult<HashMap<ID, Channel>> {
-        let hub_im = self.clone();
-        if let Some(user) = self.members.get(user_id) {
-            let mut result = HashMap::new();
-            for channel in self.channels.clone() {
-                if user.has_channel_permission(channel.0, ChannelPermission::Read, &hub_im) {
-                    result.insert(channel.0, channel.1.clone());
-                }
-            }
-            Ok(result)
+    /// Checks if the user with the given ID is in the hub.
+    pub fn is_member(&self, member_id: &ID) -> bool {
+        self.members.contains_key(member_id)
+    }
+
+    /// Checks if the user with the given ID is in the hub, if not in hub also checks if banned.
+    pub fn check_membership(&self, member_id: &ID) -> ApiResult<()> {
+        if self.is_member(member_id) {
+            Ok(())
         } else {
-            Err(ApiError::MemberNotFound)
+            Err(if self.is_user_banned(member_id) {
+                ApiError::Banned
+            } else {
+                ApiError::NotInHub
+            })
         }
     }
 
-    /// Returns a hub object with only the items that the given user is allowed to view.
-    /// Only hides channels that the user does not have permission to view.
-    ///
-    /// # Errors
-    ///
-    /// Possible errors are outlined by [`Hub::get_channels_for_user`].
-    pub fn strip(&self, user_id: &ID) -> ApiResult<Self> {
-        let mut hub = self.clone();
-        hub.channels = self.get_channels_for_user(user_id)?;
-        Ok(hub)
+    /// Checks if the given user would be allowed to do something that requires the given hub permission, the same way it is checked when they try to do it.
+    /// Banned users and users that are not in the hub are never allowed, muted members and members pending verification can not write in channels.
+    pub fn can(&self, user_id: &ID, permission: HubPermission) -> bool {
+        if self.is_user_banned(user_id) {
+            return false;
+        }
+        if permission == HubPermission::WriteChannels
+            && (self.is_user_muted(user_id) || self.pending.contains(user_id))
+        {
+            return false;
+        }
+        self.members
+            .get(user_id)
+            .map_or(false, |member| member.has_permission(permission, self))
     }
-}
-
-#[cfg(feature = "server")]
-#[cfg(test)]
-pub(crate) mod test {
-    use super::*;
-    use crate::channel::test::*;
-    use crate::test::*;
 
-    pub fn test_group() -> PermissionGroup {
-        let hub_permissions = HashMap::new();
-        let mut channel_permissions = HashMap::new();
-        channel_permissions.insert(*CHANNEL_ID, HashMap::new());
-        PermissionGroup {
-            id: *GROUP_ID,
-            name: "test group".to_string(),
-            members: vec![*USER_ID],
-            hub_permissions,
-            channel_permissions,
-            created: utc(0),
+    /// Checks if the given user would be allowed to do something that requires the given permission in a channel, the same way it is checked when they try to do it.
+    /// Works like [`Hub::can`], but is always false for channels that do not exist.
+    pub fn can_in_channel(
+        &self,
+        user_id: &ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+    ) -> bool {
+        if self.is_user_banned(user_id) || !self.channels.contains_key(&channel_id) {
+            return false;
+        }
+        if permission == ChannelPermission::Write
+            && (self.is_user_muted(user_id) || self.pending.contains(user_id))
+        {
+            return false;
         }
+        self.members.get(user_id).map_or(false, |member| {
+            member.has_channel_permission(channel_id, permission, self)
+        })
     }
 
-    pub fn test_member(hub: ID) -> HubMember {
-        HubMember {
-            user_id: *USER_ID,
-            joined: utc(0),
-            nick: "test".to_string(),
-            hub,
-            groups: vec![*GROUP_ID],
-            hub_permissions: HashMap::new(),
-            channel_permissions: HashMap::new(),
+    /// Gets a reference to the hub member, returns an error if the member could not be found.
+    pub fn get_member(&self, member_id: &ID) -> ApiResult<&HubMember> {
+        if let Some(member) = self.members.get(member_id) {
+            Ok(member)
+        } else {
+            Err(ApiError::MemberNotFound)
         }
     }
 
-    pub fn test_hub() -> Hub {
-        let id = new_id();
-        let mut channels = HashMap::new();
-        let channel = test_channel(id);
-        channels.insert(channel.id, channel);
-        let mut members = HashMap::new();
-        members.insert(*USER_ID, test_member(id));
-        let mut groups = HashMap::new();
-        groups.insert(*GROUP_ID, test_group());
-        Hub {
-            channels,
-            members,
-            bans: HashSet::new(),
-            mutes: HashSet::new(),
-            description: "test hub description".to_string(),
-            owner: *USER_ID,
-            groups,
-            default_group: *GROUP_ID,
-            name: "test hub".to_string(),
-            id,
-            created: utc(0),
-        }
+    /// Gets the permission groups that the given hub member is part of, returns an error if the member could not be found.
+    pub fn get_member_groups(&self, member_id: &ID) -> ApiResult<Vec<&PermissionGroup>> {
+        Ok(self
+            .get_member(member_id)?
+            .groups
+            .iter()
+            .filter_map(|id| self.groups.get(id))
+            .collect())
     }
 
-    #[tokio::test]
-    async fn save_load() {
-        let hub = test_hub();
-        hub.save().await.expect("Failed to save the hub");
-        assert_eq!(
-            hub,
-            Hub::load(hub.id).await.expect("Failed to load the hub")
-        );
+    /// Gets up to `limit` members whose nickname starts with `prefix`, ignoring case, sorted by nickname.
+    pub fn search_members(&self, prefix: &str, limit: usize) -> Vec<&HubMember> {
+        let prefix = prefix.to_lowercase();
+        let mut found = self
+            .members
+            .values()
+            .filter(|member| member.nick.to_lowercase().starts_with(&prefix))
+            .collect::<Vec<&HubMember>>();
+        found.sort_by(|a, b| a.nick.cmp(&b.nick).then(a.user_id.cmp(&b.user_id)));
+        found.truncate(limit);
+        found
     }
 
-    #[test]
-    fn join_leave() {
-        let mut hub = test_hub();
-        assert_eq!(
-            ApiError::NotInHub,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
-        );
-        hub.user_join(*OTHER_USER_ID).unwrap();
-        hub.check_membership(&OTHER_USER_ID).unwrap();
-        hub.user_leave(&OTHER_USER_ID).unwrap();
-        assert_eq!(
-            ApiError::NotInHub,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
-        );
+    /// Gets a mutable reference to the hub member, returns an error if the member could not be found.
+    pub fn get_member_mut(&mut self, member_id: &ID) -> ApiResult<&mut HubMember> {
+        if let Some(member) = self.members.get_mut(member_id) {
+            Ok(member)
+        } else {
+            Err(ApiError::MemberNotFound)
+        }
     }
 
-    #[test]
-    fn ban() {
-        let mut hub = test_hub();
-        assert_eq!(
-            ApiError::NotInHub,
+    /// Changes the description of a channel while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * Description is bigger than `max_size`.
+    /// * The user it not in the hub.
+    /// * The user does not have permission to view the channel.
+    /// * The user does not have permission to configure the channel.
+    /// * The channel does not exist.
+    pub async fn change_channel_description(
+        &mut self,
+        user_id: &ID,
+        channel_id: ID,
+        new_description: String,
+        max_size: usize,
+    ) -> ApiResult<String> {
+        if new_description.as_bytes().len() > max_size {
+            return Err(ApiError::TooBig);
+        }
+        self.check_channel_administrative(user_id, channel_id, ChannelPermission::Manage)?;
+        if let Some(channel) = self.channels.get_mut(&channel_id) {
+            Ok(mem::replace(&mut channel.description, new_description))
+        } else {
+            Err(ApiError::ChannelNotFound)
+        }
+    }
+
+    /// Renames a channel while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * Failed to pass [`check_name_validity`].
+    /// * The user it not in the hub.
+    /// * The user does not have permission to view the channel.
+    /// * The user does not have permission to configure the channel.
+    /// * The channel does not exist.
+    pub async fn rename_channel(
+        &mut self,
+        user_id: &ID,
+        channel_id: ID,
+        new_name: String,
+    ) -> ApiResult<String> {
+        check_name_validity(&new_name)?;
+        self.check_channel_administrative(user_id, channel_id, ChannelPermission::Manage)?;
+        if let Some(channel) = self.channels.get_mut(&channel_id) {
+            Ok(mem::replace(&mut channel.name, new_name))
+        } else {
+            Err(ApiError::ChannelNotFound)
+        }
+    }
+
+    /// Compacts the message files of a channel while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The user is not in the hub.
+    /// * The user does not have the [`HubPermission::Administrate`] permission.
+    /// * The channel does not exist.
+    /// * The channel could not be compacted for any of the reasons outlined by [`Channel::compact`].
+    pub async fn compact_channel(
+        &self,
+        user_id: &ID,
+        channel_id: ID,
+    ) -> ApiResult<CompactionReport> {
+        self.check_administrative(user_id, HubPermission::Administrate)?;
+        self.channels
+            .get(&channel_id)
+            .ok_or(ApiError::ChannelNotFound)?
+            .compact()
+            .await
+            .map_err(|e| ApiError::from(&e))
+    }
+
+    /// Deletes a channel while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The user is not in the hub.
+    /// * The channel does not exist.
+    /// * THe user does not have permission to view the channel.
+    /// * The user does not have permission to delete the channel.
+    pub async fn delete_channel(&mut self, user_id: &ID, channel_id: ID) -> ApiResult {
+        self.check_administrative(user_id, HubPermission::ManageChannels)?;
+        if self.channels.remove(&channel_id).is_some() {
+            if self.default_channel == Some(channel_id) {
+                self.default_channel = None;
+            }
+            self.channel_history.remove(&channel_id);
+            self.prune_channel_permissions();
+            Ok(())
+        } else {
+            Err(ApiError::ChannelNotFound)
+        }
+    }
+
+    /// Removes the channel permission entries of all members and groups that are for channels no longer in the hub.
+    pub fn prune_channel_permissions(&mut self) {
+        let channels = &self.channels;
+        for member in self.members.values_mut() {
+            member
+                .channel_permissions
+                .retain(|channel_id, _| channels.contains_key(channel_id));
+        }
+        for group in self.groups.values_mut() {
+            group
+                .channel_permissions
+                .retain(|channel_id, _| channels.contains_key(channel_id));
+        }
+    }
+
+    /// Applies several permission settings to a member at once while checking that the given user has permission to do so.
+    /// All of the settings are validated before any are applied, so either all or none of them take effect.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The user making the change does not have the [`HubPermission::Administrate`] permission.
+    /// * The member whose permissions are being changed is not in the hub.
+    /// * One of the channel permission settings is for a channel that does not exist.
+    pub fn set_member_permissions(
+        &mut self,
+        actor_id: &ID,
+        member_id: &ID,
+        permissions: &[PermissionSet],
+    ) -> ApiResult {
+        self.check_administrative(actor_id, HubPermission::Administrate)?;
+        self.get_member(member_id)?;
+        for set in permissions {
+            if let PermissionSet::Channel(set) = set {
+                if !self.channels.contains_key(&set.channel) {
+                    return Err(ApiError::ChannelNotFound);
+                }
+            }
+        }
+        let member = self.get_member_mut(member_id)?;
+        for set in permissions {
+            match set {
+                PermissionSet::Hub(set) => member.set_permission(set.permission, set.setting),
+                PermissionSet::Channel(set) => {
+                    member.set_channel_permission(set.channel, set.permission, set.setting)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a channel specific permission for a member while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user making the change is not in the hub.
+    /// * The user making the change does not have the [`HubPermission::Administrate`] permission.
+    /// * The member whose permission is being changed is not in the hub.
+    /// * The channel does not exist.
+    pub fn set_member_channel_permission(
+        &mut self,
+        actor_id: &ID,
+        member_id: &ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+        value: PermissionSetting,
+    ) -> ApiResult {
+        self.check_administrative(actor_id, HubPermission::Administrate)?;
+        if !self.channels.contains_key(&channel_id) {
+            return Err(ApiError::ChannelNotFound);
+        }
+        self.get_member_mut(member_id)?
+            .set_channel_permission(channel_id, permission, value);
+        Ok(())
+    }
+
+    /// Renames a permission group while checking that the given user has permission to do so, returning the group's old name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not in the hub.
+    /// * The user does not have the [`HubPermission::Administrate`] permission.
+    /// * The new name failed to pass [`check_name_validity`].
+    /// * The group does not exist.
+    pub fn rename_group(
+        &mut self,
+        member_id: &ID,
+        group_id: ID,
+        name: String,
+    ) -> ApiResult<String> {
+        self.check_administrative(member_id, HubPermission::Administrate)?;
+        check_name_validity(&name)?;
+        if let Some(group) = self.groups.get_mut(&group_id) {
+            group.record_update(*member_id);
+            Ok(std::mem::replace(&mut group.name, name))
+        } else {
+            Err(ApiError::GroupNotFound)
+        }
+    }
+
+    /// Sets the hub's default channel while checking that the given user has permission to do so.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not in the hub.
+    /// * The user does not have the [`HubPermission::ManageChannels`] permission.
+    /// * The channel does not exist.
+    pub fn set_default_channel(&mut self, member_id: &ID, channel_id: ID) -> ApiResult {
+        self.check_administrative(member_id, HubPermission::ManageChannels)?;
+        if !self.channels.contains_key(&channel_id) {
+            return Err(ApiError::ChannelNotFound);
+        }
+        self.default_channel = Some(channel_id);
+        Ok(())
+    }
+
+    /// Adds a change to the hub's change log with the next sequence number, dropping the oldest change if the log is full.
+    pub fn record_change(&mut self, update_type: WsHubUpdateType) -> HubChange {
+        self.last_sequence += 1;
+        let change = HubChange {
+            sequence: self.last_sequence,
+            time: Utc::now(),
+            update_type,
+        };
+        if self.changes.len() >= crate::MAX_HUB_CHANGES {
+            if let Some(dropped) = self.changes.pop_front() {
+                self.changes_truncated = Some(dropped.time);
+            }
+        }
+        self.changes.push_back(change.clone());
+        change
+    }
+
+    /// Adds a change to the history of a channel, dropping the oldest change once the channel has [`crate::MAX_CHANNEL_HISTORY`] of them.
+    pub fn record_channel_info_change(&mut self, channel_id: ID, change: ChannelInfoChange) {
+        let history = self.channel_history.entry(channel_id).or_default();
+        if history.len() >= crate::MAX_CHANNEL_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(change);
+    }
+
+    /// Gets the changes made to the hub after the given time, oldest first.
+    /// Returns `None` if some of the changes made after that time have already been dropped from the change log,
+    /// in that case the whole hub needs to be fetched again.
+    pub fn changes_since(&self, since: DateTime<Utc>) -> Option<Vec<HubChange>> {
+        if let Some(truncated) = self.changes_truncated {
+            if since < truncated {
+                return None;
+            }
+        }
+        Some(
+            self.changes
+                .iter()
+                .filter(|change| change.time > since)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Gets the file path to be used for storing the hub's data.
+    pub fn get_info_path(&self) -> String {
+        format!("{}{}", HUB_INFO_FOLDER, self.id.to_string())
+    }
+
+    /// Gets the path of the directory in which channel folders should be stored.
+    pub fn get_data_path(&self) -> String {
+        format!("{}{}/", HUB_DATA_FOLDER, self.id.to_string())
+    }
+
+    /// Gets the lock of the hub with the given ID, which holds whether the hub has been deleted. [`Hub::delete`] holds it for writing and
+    /// [`Hub::save`] for reading, other operations that must not run while the hub is being deleted should hold it for reading and load
+    /// the hub after taking it, but must not save the hub while holding it.
+    pub fn lock(id: ID) -> Arc<RwLock<bool>> {
+        Arc::clone(
+            HUB_LOCKS
+                .lock()
+                .expect("hub lock map poisoned")
+                .entry(id)
+                .or_default(),
+        )
+    }
+
+    /// Deletes the hub's info file and data folder, waiting for operations holding the hub's lock to finish first, and removes it from the given cache and the directory.
+    /// The hub can not be saved again once it has been deleted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The hub has already been deleted.
+    /// * The hub's files could not be removed.
+    pub async fn delete(&self, cache: &HubCache) -> Result {
+        let lock = Self::lock(self.id);
+        let mut deleted = lock.write().await;
+        if *deleted {
+            return Err(ApiError::HubNotFound.into());
+        }
+        match tokio::fs::remove_file(self.get_info_path()).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ApiError::HubNotFound.into())
+            }
+            result => result?,
+        }
+        *deleted = true;
+        tokio::fs::remove_dir_all(self.get_data_path()).await?;
+        LAST_ACTIVITY
+            .lock()
+            .expect("activity map poisoned")
+            .remove(&self.id);
+        cache.remove(&self.id).await;
+        crate::discovery::remove(&self.id).await;
+        Ok(())
+    }
+
+    /// Saves the hub's data to disk and updates its copy in the given cache, see [`HubCache::save`].
+    /// Messages sent since the hub was loaded are included in the saved channels' [`Channel::last_activity`].
+    /// Holds the hub's lock for reading, see [`Hub::lock`], so that a hub being deleted is not written back.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The hub has been deleted.
+    /// * The hub could not be written for any of the reasons outlined by [`Hub::write`].
+    pub async fn save(&self, cache: &HubCache) -> Result {
+        let lock = Self::lock(self.id);
+        let deleted = lock.read().await;
+        if *deleted {
+            return Err(ApiError::HubNotFound.into());
+        }
+        let mut hub = self.clone();
+        hub.apply_last_activity();
+        cache.save(&hub).await?;
+        hub.forget_saved_activity();
+        crate::discovery::update(&hub).await;
+        Ok(())
+    }
+
+    /// Writes the hub's data to disk in the layout of [`HUB_FORMAT_VERSION`] without updating the [`HubCache`] or checking that the hub was not deleted, use [`Hub::save`] instead.
+    /// The data is written to a temporary file that then replaces the hub's info file, so a failed write does not leave a partial hub behind.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The hub data could not be serialized.
+    /// * The hub info folder does not exist and could not be created.
+    /// * The data could not be written to the disk.
+    pub(crate) async fn write(&self) -> Result {
+        tokio::fs::create_dir_all(HUB_INFO_FOLDER).await?;
+        let mut bytes = HUB_FILE_HEADER.to_vec();
+        bytes.push(HUB_FORMAT_VERSION);
+        bincode::serialize_into(&mut bytes, self)?;
+        let path = self.get_info_path();
+        let tmp = format!("{}.tmp", path);
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(&bytes).await?;
+        file.flush().await?;
+        tokio::fs::rename(tmp, path).await?;
+        Ok(())
+    }
+
+    /// Loads a hub's data given its ID, dropping any channel permission entries left over from deleted channels.
+    /// Hubs written in the layout of an earlier version are upgraded, they are written in the current layout the next time they are saved.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * There is no hub with that ID.
+    /// * The hub's data file was corrupt and could not be deserialized.
+    /// * The hub's data file was written by a newer version of the server.
+    pub async fn load(id: ID) -> Result<Self> {
+        let filename = format!("{}{}", HUB_INFO_FOLDER, id.to_string());
+        let path = std::path::Path::new(&filename);
+        if !path.exists() {
+            return Err(ApiError::HubNotFound.into());
+        }
+        // The hub may have been deleted since checking that it exists.
+        let mut file = match tokio::fs::OpenOptions::new().read(true).open(path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ApiError::HubNotFound.into())
+            }
+            result => result?,
+        };
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).await?;
+        let mut hub = Self::decode(&buf)?;
+        hub.prune_channel_permissions();
+        hub.apply_last_activity();
+        Ok(hub)
+    }
+
+    /// Decodes the contents of a hub info file in any of the layouts hubs have been written with, see [`HUB_FILE_HEADER`].
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let encoded = match bytes.strip_prefix(&HUB_FILE_HEADER[..]) {
+            Some(encoded) => encoded,
+            None => return Ok(bincode::deserialize::<LegacyHub>(bytes)?.into()),
+        };
+        match encoded.split_first() {
+            Some((&1, encoded)) => Ok(bincode::deserialize(encoded)?),
+            Some((version, _)) => Err(Error::OtherInternal(format!(
+                "unsupported hub file version {}",
+                version
+            ))),
+            None => Err(Error::OtherInternal("empty hub file".to_string())),
+        }
+    }
+
+    /// Gets a hub from the given cache, loading it with [`Hub::load`] and caching it if it is not cached.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the hub is not cached and could not be loaded for any of the reasons outlined by [`Hub::load`].
+    pub async fn load_cached(id: ID, cache: &HubCache) -> Result<Self> {
+        if let Some(mut hub) = cache.get(&id).await {
+            hub.apply_last_activity();
+            return Ok(hub);
+        }
+        let generation = cache.generation();
+        let hub = Self::load(id).await?;
+        cache.insert_loaded(&hub, generation).await;
+        Ok(hub)
+    }
+
+    /// Updates the channels' [`Channel::last_activity`] with the times at which messages were last sent in them.
+    fn apply_last_activity(&mut self) {
+        let activity = LAST_ACTIVITY.lock().expect("activity map poisoned");
+        if let Some(activity) = activity.get(&self.id) {
+            for channel in self.channels.values_mut() {
+                if let Some(last) = activity.get(&channel.id) {
+                    channel.last_activity = channel.last_activity.max(*last);
+                }
+            }
+        }
+    }
+
+    /// Drops the recorded message times that were saved with the hub, along with those of channels that no longer exist.
+    fn forget_saved_activity(&self) {
+        let mut activity = LAST_ACTIVITY.lock().expect("activity map poisoned");
+        if let Some(channels) = activity.get_mut(&self.id) {
+            channels.retain(|channel_id, last| {
+                self.channels
+                    .get(channel_id)
+                    .map_or(false, |channel| *last > channel.last_activity)
+            });
+            if channels.is_empty() {
+                activity.remove(&self.id);
+            }
+        }
+    }
+
+    /// Checks that a user can perform an administrative action that needs the given permission.
+    /// Server admins can perform administrative actions in any hub, even ones they are not in, see [`crate::config::is_server_admin`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not a server admin and is not in the hub.
+    /// * The user is not a server admin and does not have the permission.
+    pub fn check_administrative(&self, user_id: &ID, permission: HubPermission) -> ApiResult {
+        if crate::config::is_server_admin(user_id) {
+            return Ok(());
+        }
+        let member = self.get_member(user_id)?;
+        check_permission!(member, permission, self);
+        Ok(())
+    }
+
+    /// Checks that a user can perform an administrative action in a channel that needs the given permission, see [`Hub::check_administrative`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not a server admin and is not in the hub.
+    /// * The user is not a server admin and does not have the permission in the channel.
+    pub fn check_channel_administrative(
+        &self,
+        user_id: &ID,
+        channel_id: ID,
+        permission: ChannelPermission,
+    ) -> ApiResult {
+        if crate::config::is_server_admin(user_id) {
+            return Ok(());
+        }
+        let member = self.get_member(user_id)?;
+        check_permission!(member, channel_id, permission, self);
+        Ok(())
+    }
+
+    /// Makes another member the owner of the hub, returning the ID of the previous owner.
+    /// The previous owner loses the [`HubPermission::All`] permission they were given when they became the owner.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not allowed to transfer the hub for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * The new owner is not in the hub.
+    pub fn transfer_ownership(&mut self, actor_id: &ID, new_owner: ID) -> ApiResult<ID> {
+        self.check_administrative(actor_id, HubPermission::All)?;
+        self.get_member_mut(&new_owner)?
+            .set_permission(HubPermission::All, Some(true));
+        let old_owner = mem::replace(&mut self.owner, new_owner);
+        if old_owner != new_owner {
+            if let Ok(member) = self.get_member_mut(&old_owner) {
+                member.hub_permissions.remove(&HubPermission::All);
+            }
+        }
+        Ok(old_owner)
+    }
+
+    /// Replaces the hub's tags after checking that they are valid, duplicates are removed, returns the old tags.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the tags failed to pass [`check_tags_validity`].
+    pub fn set_tags(&mut self, mut tags: Vec<String>) -> ApiResult<Vec<String>> {
+        check_tags_validity(&tags)?;
+        let mut seen = HashSet::new();
+        tags.retain(|tag| seen.insert(tag.clone()));
+        Ok(mem::replace(&mut self.tags, tags))
+    }
+
+    /// Sets the hub's category after checking that it is a valid name, an empty category removes it, returns the old category.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the category failed to pass [`check_name_validity`].
+    pub fn set_category(&mut self, category: String) -> ApiResult<Option<String>> {
+        let category = if category.is_empty() {
+            None
+        } else {
+            check_name_validity(&category)?;
+            Some(category)
+        };
+        Ok(mem::replace(&mut self.category, category))
+    }
+
+    /// Gets the IDs of every hub on the server.
+    pub async fn list_ids() -> Vec<ID> {
+        let mut result = Vec::new();
+        if let Ok(mut dir) = tokio::fs::read_dir(HUB_INFO_FOLDER).await {
+            while let Ok(Some(entry)) = dir.next_entry().await {
+                if let Ok(id) = ID::parse_str(&entry.file_name().to_string_lossy()) {
+                    result.push(id);
+                }
+            }
+        }
+        result
+    }
+
+    /// Adds a user to a hub, creating and returning the resulting hub member.
+    /// If the hub has verification turned on the new member will not be able to send messages until approved with [`Hub::approve_member`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The hub has reached its member limit.
+    /// * The default permission group could not be found.
+    pub fn user_join(&mut self, user_id: ID) -> ApiResult<HubMember> {
+        if self.is_full() {
+            return Err(ApiError::HubFull);
+        }
+        let mut member = HubMember::new(user_id, self.id);
+        if let Some(group) = self.groups.get_mut(&self.default_group) {
+            group.add_member(&mut member);
+            self.members.insert(member.user_id, member.clone());
+            if self.verification {
+                self.pending.insert(member.user_id);
+            }
+            Ok(member)
+        } else {
+            Err(ApiError::GroupNotFound)
+        }
+    }
+
+    /// Creates an invite to the hub, the invite is not saved.
+    /// The invite never expires if `expires_at` is `None` and can be used any number of times if `max_uses` is `None` or 0.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not allowed to create invites for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * `expires_at` is not in the future.
+    pub fn create_invite(
+        &self,
+        creator_id: &ID,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> ApiResult<HubInvite> {
+        self.check_administrative(creator_id, HubPermission::Administrate)?;
+        let created_at = Utc::now();
+        if expires_at.map_or(false, |expires| expires <= created_at) {
+            return Err(ApiError::InvalidTime);
+        }
+        Ok(HubInvite {
+            code: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(crate::INVITE_CODE_LENGTH)
+                .map(char::from)
+                .collect(),
+            hub_id: self.id,
+            created_by: *creator_id,
+            created_at,
+            expires_at,
+            max_uses: max_uses.filter(|max_uses| *max_uses > 0),
+            uses: 0,
+        })
+    }
+
+    /// Adds a user to the hub an invite is for, counting the use and saving both the hub, through the given cache, and the invite.
+    /// Returns the hub the user joined and the change recorded for it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The invite could not be loaded for any of the reasons outlined by [`HubInvite::load`].
+    /// * The invite has expired or has reached its usage limit.
+    /// * The hub the invite is for has been deleted.
+    /// * The user is banned from the hub or is already in it.
+    /// * The user could not join the hub for any of the reasons outlined by [`Hub::user_join`].
+    /// * The hub or the invite could not be saved.
+    pub async fn use_invite(
+        code: &str,
+        user_id: ID,
+        cache: &HubCache,
+    ) -> Result<(Self, HubChange)> {
+        let _guard = INVITE_LOCK.lock().await;
+        let mut invite = HubInvite::load(code).await?;
+        if !invite.is_usable() {
+            return Err(ApiError::InviteExpired.into());
+        }
+        let mut hub = match Self::load_cached(invite.hub_id, cache).await {
+            Err(Error::ApiError(ApiError::HubNotFound)) => {
+                invite.delete().await?;
+                return Err(ApiError::InviteNotFound.into());
+            }
+            result => result?,
+        };
+        if hub.is_user_banned(&user_id) {
+            return Err(ApiError::Banned.into());
+        }
+        if hub.is_member(&user_id) {
+            return Err(ApiError::AlreadyInHub.into());
+        }
+        hub.user_join(user_id)?;
+        let change = hub.record_change(WsHubUpdateType::UserJoined(user_id));
+        hub.save(cache).await?;
+        invite.uses += 1;
+        invite.save().await?;
+        Ok((hub, change))
+    }
+
+    /// Deletes one of the hub's invites so that it can no longer be used.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The user is not allowed to revoke invites for any of the reasons outlined by [`Hub::check_administrative`].
+    /// * There is no invite to the hub with that code.
+    /// * The invite's file could not be removed.
+    pub async fn revoke_invite(&self, user_id: &ID, code: &str) -> Result {
+        self.check_administrative(user_id, HubPermission::Administrate)?;
+        let _guard = INVITE_LOCK.lock().await;
+        let invite = HubInvite::load(code).await?;
+        if invite.hub_id != self.id {
+            return Err(ApiError::InviteNotFound.into());
+        }
+        invite.delete().await
+    }
+
+    /// Removes the given user from the hub.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The user is not in the hub.
+    /// * One of the permission groups the user was in could not be found in the hub.
+    pub fn user_leave(&mut self, user_id: &ID) -> ApiResult {
+        if user_id == &self.owner {
+            return ApiResult::Err(ApiError::IsOwner);
+        }
+        if let Some(member) = self.members.get_mut(user_id) {
+            if let Some(group) = self.groups.get_mut(&self.default_group) {
+                member.leave_group(group);
+                self.members.remove(user_id);
+                self.pending.remove(user_id);
+                self.accepted.remove(user_id);
+                Ok(())
+            } else {
+                Err(ApiError::GroupNotFound)
+            }
+        } else {
+            Err(ApiError::NotInHub)
+        }
+    }
+
+    /// Kicks the given user from the hub, forcing them to leave.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    /// * The user could not be removed from the hub for any of the reasons outlined in [`Hub::user_leave`].
+    /// * The user's data failed to load for any of the reasons outlined in [`User::load`].
+    /// * The user's data failed to save for any of the reasons outlined in [`User::save`].
+    pub fn kick_user(&mut self, user_id: &ID) -> ApiResult {
+        if user_id == &self.owner {
+            return ApiResult::Err(ApiError::IsOwner);
+        } else if self.members.contains_key(user_id) {
+            self.user_leave(user_id)?;
+            self.members.remove(user_id);
+        }
+        Ok(())
+    }
+
+    /// Kicks the given user and adds them to the banned list, the ban expires after `duration` or never if it is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Possible errors outlined by [`Hub::kick_user`].
+    pub fn ban_user(&mut self, user_id: ID, duration: Option<Duration>) -> ApiResult {
+        self.kick_user(&user_id)?;
+        self.bans
+            .insert(user_id, duration.map(|duration| Utc::now() + duration));
+        Ok(())
+    }
+
+    /// Removes the given user from the banned lis.
+    pub fn unban_user(&mut self, user_id: &ID) {
+        self.bans.remove(user_id);
+    }
+
+    /// Adds the given user to the mute list, preventing them from sending messages, the mute expires after `duration` or never if it is `None`.
+    pub fn mute_user(&mut self, user_id: ID, duration: Option<Duration>) -> ApiResult {
+        if user_id == self.owner {
+            return ApiResult::Err(ApiError::IsOwner);
+        }
+        self.mutes
+            .insert(user_id, duration.map(|duration| Utc::now() + duration));
+        Ok(())
+    }
+
+    /// Removes the given user from the mutes list, allowing them to send messages.
+    pub fn unmute_user(&mut self, user_id: &ID) {
+        self.mutes.remove(user_id);
+    }
+
+    /// Checks if the given user is banned from the hub, bans that have expired count as lifted even before they are removed.
+    pub fn is_user_banned(&self, user_id: &ID) -> bool {
+        self.bans.get(user_id).map_or(false, |expires| {
+            expires.map_or(true, |expires| expires > Utc::now())
+        })
+    }
+
+    /// Checks if the given user is muted in the hub, mutes that have expired count as lifted even before they are removed.
+    pub fn is_user_muted(&self, user_id: &ID) -> bool {
+        self.mutes.get(user_id).map_or(false, |expires| {
+            expires.map_or(true, |expires| expires > Utc::now())
+        })
+    }
+
+    /// Removes the mutes and bans that have expired, returns the IDs of the users that were unmuted or unbanned.
+    pub fn expire_timed_moderation(&mut self) -> Vec<ID> {
+        let now = Utc::now();
+        let mut expired = Vec::new();
+        for list in [&mut self.bans, &mut self.mutes].iter_mut() {
+            list.retain(|user_id, expires| {
+                if expires.map_or(true, |expires| expires > now) {
+                    return true;
+                }
+                if !expired.contains(user_id) {
+                    expired.push(*user_id);
+                }
+                false
+            });
+        }
+        expired
+    }
+
+    /// Gets a list of the channels that the given user has permission to view.
+    ///
+    /// # Errors
+    ///
+    /// This function will only return an error if the given user is not in the hub.
+    pub fn get_channels_for_user(&self, user_id: &ID) -> ApiResult<HashMap<ID, Channel>> {
+        let hub_im = self.clone();
+        if let Some(user) = self.members.get(user_id) {
+            let mut result = HashMap::new();
+            for channel in self.channels.clone() {
+                if user.has_channel_permission(channel.0, ChannelPermission::Read, &hub_im) {
+                    result.insert(channel.0, channel.1.clone());
+                }
+            }
+            Ok(result)
+        } else {
+            Err(ApiError::MemberNotFound)
+        }
+    }
+
+    /// Gets all of the channels in the hub, including those that the given user does not have permission to view.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations:
+    ///
+    /// * The user is not in the hub.
+    /// * The user does not have the [`HubPermission::Administrate`] permission.
+    pub fn get_all_channels_admin(&self, member_id: &ID) -> ApiResult<&HashMap<ID, Channel>> {
+        self.check_administrative(member_id, HubPermission::Administrate)?;
+        Ok(&self.channels)
+    }
+
+    /// Returns a hub object with only the items that the given user is allowed to view.
+    /// Hides channels that the user does not have permission to view, the change log and the history of channel names and descriptions.
+    /// The word filter is only included for users that can change it.
+    ///
+    /// # Errors
+    ///
+    /// Possible errors are outlined by [`Hub::get_channels_for_user`].
+    pub fn strip(&self, user_id: &ID) -> ApiResult<Self> {
+        let mut hub = self.clone();
+        hub.channels = self.get_channels_for_user(user_id)?;
+        // The change log is only served through `changes_since`.
+        hub.changes.clear();
+        // Channel history can include channels the user can not view, it is only served to admins through its own endpoint.
+        hub.channel_history.clear();
+        if self
+            .check_administrative(user_id, HubPermission::Administrate)
+            .is_err()
+        {
+            hub.word_filter = WordFilter::default();
+        }
+        Ok(hub)
+    }
+}
+
+/// Layout of hubs written before hub files were versioned, see [`HUB_FILE_HEADER`]. Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyHub {
+    channels: HashMap<ID, LegacyChannel>,
+    members: HashMap<ID, LegacyHubMember>,
+    bans: HashSet<ID>,
+    mutes: HashSet<ID>,
+    description: String,
+    owner: ID,
+    groups: HashMap<ID, LegacyPermissionGroup>,
+    default_group: ID,
+    name: String,
+    id: ID,
+    created: DateTime<Utc>,
+}
+
+/// Layout of channels in [`LegacyHub`]. Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyChannel {
+    id: ID,
+    hub_id: ID,
+    description: String,
+    name: String,
+    created: DateTime<Utc>,
+}
+
+/// Layout of hub members in [`LegacyHub`]. Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyHubMember {
+    user_id: ID,
+    joined: DateTime<Utc>,
+    hub: ID,
+    nick: String,
+    groups: Vec<ID>,
+    hub_permissions: HubPermissions,
+    channel_permissions: HashMap<ID, ChannelPermissions>,
+}
+
+/// Layout of permission groups in [`LegacyHub`]. Must not be changed.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct LegacyPermissionGroup {
+    id: ID,
+    name: String,
+    members: Vec<ID>,
+    hub_permissions: HubPermissions,
+    channel_permissions: HashMap<ID, ChannelPermissions>,
+    created: DateTime<Utc>,
+}
+
+#[cfg(feature = "server")]
+impl From<LegacyHub> for Hub {
+    /// Upgrades a hub written before hub files were versioned, bans and mutes become permanent and everything added since then is unset.
+    fn from(hub: LegacyHub) -> Self {
+        let channels = hub
+            .channels
+            .into_iter()
+            .map(|(id, channel)| {
+                (
+                    id,
+                    Channel {
+                        id: channel.id,
+                        hub_id: channel.hub_id,
+                        description: channel.description,
+                        name: channel.name,
+                        created: channel.created,
+                        parent: None,
+                        last_activity: channel.created,
+                        created_by: ID::nil(),
+                        updated_by: None,
+                        updated: None,
+                    },
+                )
+            })
+            .collect();
+        let members = hub
+            .members
+            .into_iter()
+            .map(|(id, member)| {
+                (
+                    id,
+                    HubMember {
+                        user_id: member.user_id,
+                        joined: member.joined,
+                        hub: member.hub,
+                        nick: member.nick,
+                        groups: member.groups,
+                        hub_permissions: member.hub_permissions,
+                        channel_permissions: member.channel_permissions,
+                    },
+                )
+            })
+            .collect();
+        let groups = hub
+            .groups
+            .into_iter()
+            .map(|(id, group)| {
+                (
+                    id,
+                    PermissionGroup {
+                        id: group.id,
+                        name: group.name,
+                        members: group.members,
+                        hub_permissions: group.hub_permissions,
+                        channel_permissions: group.channel_permissions,
+                        created: group.created,
+                        created_by: ID::nil(),
+                        updated_by: None,
+                        updated: None,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            channels,
+            members,
+            bans: hub.bans.into_iter().map(|id| (id, None)).collect(),
+            mutes: hub.mutes.into_iter().map(|id| (id, None)).collect(),
+            description: hub.description,
+            owner: hub.owner,
+            groups,
+            default_group: hub.default_group,
+            name: hub.name,
+            id: hub.id,
+            created: hub.created,
+            changes: VecDeque::new(),
+            changes_truncated: None,
+            last_sequence: 0,
+            word_filter: WordFilter::default(),
+            verification: false,
+            pending: HashSet::new(),
+            default_channel: None,
+            max_members: None,
+            discoverable: false,
+            tags: Vec::new(),
+            category: None,
+            require_accept: None,
+            accepted: HashSet::new(),
+            new_channel_permissions: HashMap::new(),
+            channel_history: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::channel::test::*;
+    use crate::test::*;
+
+    pub fn test_group() -> PermissionGroup {
+        let hub_permissions = HashMap::new();
+        let mut channel_permissions = HashMap::new();
+        channel_permissions.insert(*CHANNEL_ID, HashMap::new());
+        PermissionGroup {
+            id: *GROUP_ID,
+            name: "test group".to_string(),
+            members: vec![*USER_ID],
+            hub_permissions,
+            channel_permissions,
+            created: utc(0),
+            created_by: *USER_ID,
+            updated_by: None,
+            updated: None,
+        }
+    }
+
+    pub fn test_member(hub: ID) -> HubMember {
+        HubMember {
+            user_id: *USER_ID,
+            joined: utc(0),
+            nick: "test".to_string(),
+            hub,
+            groups: vec![*GROUP_ID],
+            hub_permissions: HashMap::new(),
+            channel_permissions: HashMap::new(),
+        }
+    }
+
+    pub fn test_hub() -> Hub {
+        let id = new_id();
+        let mut channels = HashMap::new();
+        let channel = test_channel(id);
+        channels.insert(channel.id, channel);
+        let mut members = HashMap::new();
+        members.insert(*USER_ID, test_member(id));
+        let mut groups = HashMap::new();
+        groups.insert(*GROUP_ID, test_group());
+        Hub {
+            channels,
+            members,
+            bans: HashMap::new(),
+            mutes: HashMap::new(),
+            description: "test hub description".to_string(),
+            owner: *USER_ID,
+            groups,
+            default_group: *GROUP_ID,
+            name: "test hub".to_string(),
+            id,
+            created: utc(0),
+            changes: VecDeque::new(),
+            changes_truncated: None,
+            last_sequence: 0,
+            word_filter: WordFilter::default(),
+            verification: false,
+            pending: HashSet::new(),
+            default_channel: None,
+            max_members: None,
+            discoverable: false,
+            tags: Vec::new(),
+            category: None,
+            require_accept: None,
+            accepted: HashSet::new(),
+            new_channel_permissions: HashMap::new(),
+            channel_history: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn save_load() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0))
+            .await
+            .expect("Failed to save the hub");
+        assert_eq!(
+            hub,
+            Hub::load(hub.id).await.expect("Failed to load the hub")
+        );
+    }
+
+    #[tokio::test]
+    async fn load_legacy_hub() {
+        let mut hub = test_hub();
+        hub.bans.insert(*OTHER_USER_ID, None);
+        hub.mutes.insert(*SERVER_ADMIN_ID, None);
+        // Encoded as tuples so that the test does not depend on the structs used to read the old layout.
+        let legacy = (
+            hub.channels
+                .iter()
+                .map(|(id, c)| (*id, (c.id, c.hub_id, &c.description, &c.name, c.created)))
+                .collect::<HashMap<_, _>>(),
+            hub.members
+                .iter()
+                .map(|(id, m)| {
+                    (
+                        *id,
+                        (
+                            m.user_id,
+                            m.joined,
+                            m.hub,
+                            &m.nick,
+                            &m.groups,
+                            &m.hub_permissions,
+                            &m.channel_permissions,
+                        ),
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+            hub.bans.keys().collect::<HashSet<_>>(),
+            hub.mutes.keys().collect::<HashSet<_>>(),
+            &hub.description,
+            hub.owner,
+            hub.groups
+                .iter()
+                .map(|(id, g)| {
+                    (
+                        *id,
+                        (
+                            g.id,
+                            &g.name,
+                            &g.members,
+                            &g.hub_permissions,
+                            &g.channel_permissions,
+                            g.created,
+                        ),
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+            hub.default_group,
+            &hub.name,
+            hub.id,
+            hub.created,
+        );
+        std::fs::create_dir_all(HUB_INFO_FOLDER).unwrap();
+        std::fs::write(hub.get_info_path(), bincode::serialize(&legacy).unwrap()).unwrap();
+        for channel in hub.channels.values_mut() {
+            channel.last_activity = channel.created;
+            channel.created_by = ID::nil();
+        }
+        for group in hub.groups.values_mut() {
+            group.created_by = ID::nil();
+        }
+        let loaded = Hub::load(hub.id).await.expect("failed to load the hub");
+        assert_eq!(hub, loaded);
+        loaded
+            .save(&HubCache::new(0))
+            .await
+            .expect("failed to save the hub");
+        assert!(std::fs::read(hub.get_info_path())
+            .unwrap()
+            .starts_with(HUB_FILE_HEADER));
+        assert_eq!(
+            hub,
+            Hub::load(hub.id).await.expect("failed to reload the hub")
+        );
+    }
+
+    #[test]
+    fn join_leave() {
+        let mut hub = test_hub();
+        assert_eq!(
+            ApiError::NotInHub,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.check_membership(&OTHER_USER_ID).unwrap();
+        hub.user_leave(&OTHER_USER_ID).unwrap();
+        assert_eq!(
+            ApiError::NotInHub,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn ban() {
+        let mut hub = test_hub();
+        assert_eq!(
+            ApiError::NotInHub,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.ban_user(*OTHER_USER_ID, None).unwrap();
+        assert_eq!(
+            ApiError::Banned,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.unban_user(&OTHER_USER_ID);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.ban_user(*OTHER_USER_ID, None).unwrap();
+        assert_eq!(
+            ApiError::Banned,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+        assert_eq!(ApiError::IsOwner, hub.ban_user(*USER_ID, None).unwrap_err());
+        hub.check_membership(&USER_ID).unwrap();
+        assert!(!hub.bans.contains_key(&USER_ID));
+    }
+
+    #[test]
+    fn kick() {
+        let mut hub = test_hub();
+        assert_eq!(
+            ApiError::NotInHub,
+            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.kick_user(&OTHER_USER_ID).unwrap();
+        assert_eq!(
+            ApiError::NotInHub,
             hub.check_membership(&OTHER_USER_ID).unwrap_err()
         );
-        hub.ban_user(*OTHER_USER_ID).unwrap();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(ApiError::IsOwner, hub.kick_user(&USER_ID).unwrap_err());
+        hub.get_member(&USER_ID).unwrap();
+    }
+
+    #[test]
+    fn timed_moderation() {
+        let mut hub = test_hub();
+        let banned = ID::from_u128(3);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.user_join(banned).unwrap();
+        hub.mute_user(*OTHER_USER_ID, Some(Duration::hours(1)))
+            .unwrap();
+        hub.ban_user(banned, Some(Duration::hours(1))).unwrap();
+        hub.ban_user(ID::from_u128(4), None).unwrap();
+        assert!(hub.is_user_muted(&OTHER_USER_ID));
+        assert!(hub.is_user_banned(&banned));
+        assert!(hub.expire_timed_moderation().is_empty());
+        let expired = Some(Utc::now() - Duration::seconds(1));
+        hub.mutes.insert(*OTHER_USER_ID, expired);
+        hub.bans.insert(banned, expired);
+        assert!(!hub.is_user_muted(&OTHER_USER_ID));
+        assert!(!hub.is_user_banned(&banned));
+        assert_eq!(
+            vec![*OTHER_USER_ID, banned]
+                .into_iter()
+                .collect::<HashSet<ID>>(),
+            hub.expire_timed_moderation().into_iter().collect()
+        );
+        assert!(hub.mutes.is_empty());
+        assert!(hub.is_user_banned(&ID::from_u128(4)));
+        assert_eq!(1, hub.bans.len());
+    }
+
+    #[tokio::test]
+    async fn invites() {
+        let cache = HubCache::new(crate::HUB_CACHE_SIZE);
+        let mut hub = test_hub();
+        hub.save(&cache).await.unwrap();
+        assert_eq!(
+            ApiError::MemberNotFound,
+            hub.create_invite(&OTHER_USER_ID, None, None).unwrap_err()
+        );
+        assert_eq!(
+            ApiError::InvalidTime,
+            hub.create_invite(&USER_ID, Some(utc(0)), None).unwrap_err()
+        );
+        let invite = hub.create_invite(&USER_ID, None, Some(1)).unwrap();
+        assert_eq!(crate::INVITE_CODE_LENGTH, invite.code.len());
+        invite.save().await.unwrap();
+        let (joined, change) = Hub::use_invite(&invite.code, *OTHER_USER_ID, &cache)
+            .await
+            .unwrap();
+        assert!(joined.is_member(&OTHER_USER_ID));
+        assert_eq!(
+            WsHubUpdateType::UserJoined(*OTHER_USER_ID),
+            change.update_type
+        );
+        assert_eq!(1, HubInvite::load(&invite.code).await.unwrap().uses);
+        assert_eq!(
+            ApiError::InviteExpired,
+            ApiError::from(
+                &Hub::use_invite(&invite.code, ID::from_u128(3), &cache)
+                    .await
+                    .unwrap_err()
+            )
+        );
+        hub = joined;
+        let invite = hub.create_invite(&USER_ID, None, None).unwrap();
+        invite.save().await.unwrap();
+        assert_eq!(
+            ApiError::AlreadyInHub,
+            ApiError::from(
+                &Hub::use_invite(&invite.code, *OTHER_USER_ID, &cache)
+                    .await
+                    .unwrap_err()
+            )
+        );
+        assert_eq!(
+            ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            },
+            ApiError::from(
+                &hub.revoke_invite(&OTHER_USER_ID, &invite.code)
+                    .await
+                    .unwrap_err()
+            )
+        );
+        hub.revoke_invite(&USER_ID, &invite.code).await.unwrap();
+        assert_eq!(
+            ApiError::InviteNotFound,
+            ApiError::from(
+                &Hub::use_invite(&invite.code, ID::from_u128(3), &cache)
+                    .await
+                    .unwrap_err()
+            )
+        );
+        assert_eq!(
+            ApiError::InviteNotFound,
+            ApiError::from(&HubInvite::load("../info").await.unwrap_err())
+        );
+    }
+
+    #[tokio::test]
+    async fn mute() {
+        let mut hub = test_hub();
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+        hub.mute_user(*OTHER_USER_ID, None).unwrap();
+        assert_eq!(
+            ApiError::Muted,
+            hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+                .await
+                .unwrap_err()
+        );
+        hub.unmute_user(&OTHER_USER_ID);
+        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            ApiError::IsOwner,
+            hub.mute_user(*USER_ID, None).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn changes_since() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.record_change(WsHubUpdateType::UserJoined(*OTHER_USER_ID));
+        let changes = hub.changes_since(utc(0)).unwrap();
+        assert_eq!(1, changes.len());
+        assert_eq!(
+            WsHubUpdateType::UserJoined(*OTHER_USER_ID),
+            changes[0].update_type
+        );
+        assert!(hub.changes_since(changes[0].time).unwrap().is_empty());
+        for _ in 0..crate::MAX_HUB_CHANGES {
+            hub.record_change(WsHubUpdateType::HubUpdated);
+        }
+        assert_eq!(crate::MAX_HUB_CHANGES, hub.changes.len());
+        assert!(hub.changes_since(utc(0)).is_none());
+    }
+
+    #[test]
+    fn change_sequence() {
+        let mut hub = test_hub();
+        let first = hub.record_change(WsHubUpdateType::UserJoined(*OTHER_USER_ID));
+        let second = hub.record_change(WsHubUpdateType::UserMuted(*OTHER_USER_ID));
+        let third = hub.record_change(WsHubUpdateType::HubUpdated);
+        assert!(first.sequence < second.sequence);
+        assert!(second.sequence < third.sequence);
+        assert_eq!(third.sequence, hub.last_sequence);
+        assert_eq!(
+            vec![first.sequence, second.sequence, third.sequence],
+            hub.changes
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn all_channels_admin() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            },
+            hub.get_all_channels_admin(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.get_member_mut(&OTHER_USER_ID)
+            .unwrap()
+            .set_permission(HubPermission::Administrate, Some(true));
+        assert!(hub
+            .get_all_channels_admin(&OTHER_USER_ID)
+            .unwrap()
+            .contains_key(&CHANNEL_ID));
+        assert!(!hub
+            .get_channels_for_user(&OTHER_USER_ID)
+            .unwrap()
+            .contains_key(&CHANNEL_ID));
+    }
+
+    #[test]
+    fn word_filter_reject() {
+        let filter = WordFilter::new(WordFilterMode::Reject, vec!["Bad".to_string()]).unwrap();
+        assert_eq!(
+            ApiError::InvalidText,
+            filter.apply("this is bad.".to_string()).unwrap_err()
+        );
+        assert_eq!(
+            ApiError::InvalidText,
+            filter.apply("BAD".to_string()).unwrap_err()
+        );
+        assert_eq!(
+            "badge badly",
+            filter.apply("badge badly".to_string()).unwrap()
+        );
+        assert_eq!(
+            ApiError::InvalidText,
+            WordFilter::new(WordFilterMode::Reject, vec!["two words".to_string()]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn word_filter_mask() {
+        let filter = WordFilter::new(WordFilterMode::Mask, vec!["bad".to_string()]).unwrap();
+        assert_eq!(
+            "***, not badge but ***!",
+            filter.apply("Bad, not badge but bad!".to_string()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn word_filter_send() {
+        let mut hub = test_hub();
+        hub.set_word_filter(
+            &USER_ID,
+            WordFilter::new(WordFilterMode::Mask, vec!["bad".to_string()]).unwrap(),
+        )
+        .unwrap();
+        let message = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "a bad message".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!("a *** message", message.content);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            },
+            hub.set_word_filter(&OTHER_USER_ID, WordFilter::default())
+                .unwrap_err()
+        );
+        assert_eq!(
+            WordFilter::default(),
+            hub.strip(&OTHER_USER_ID).unwrap().word_filter
+        );
+        assert_eq!(hub.word_filter, hub.strip(&USER_ID).unwrap().word_filter);
+    }
+
+    #[tokio::test]
+    async fn verification() {
+        let mut hub = test_hub();
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        hub.verification = true;
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert!(hub.pending.contains(&OTHER_USER_ID));
+        assert_eq!(
+            ApiError::PendingVerification,
+            hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+                .await
+                .unwrap_err()
+        );
+        assert_eq!(
+            ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            },
+            hub.approve_member(&OTHER_USER_ID, &OTHER_USER_ID)
+                .unwrap_err()
+        );
+        hub.approve_member(&USER_ID, &OTHER_USER_ID).unwrap();
+        assert!(!hub.pending.contains(&OTHER_USER_ID));
+        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn require_accept() {
+        let mut hub = test_hub();
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        hub.set_require_accept(*MESSAGE_ID);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            ApiError::RulesNotAccepted,
+            hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+                .await
+                .unwrap_err()
+        );
+        hub.accept_rules(&OTHER_USER_ID).unwrap();
+        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+        hub.set_require_accept(new_id());
+        assert!(hub.accepted.is_empty());
+        hub.set_require_accept(ID::nil());
+        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn default_channel() {
+        let mut hub = Hub::new("test".to_string(), new_id(), *USER_ID);
+        let first = hub
+            .new_channel(&USER_ID, "first".to_string(), String::new())
+            .await
+            .unwrap();
+        assert_eq!(Some(first), hub.default_channel);
+        let second = hub
+            .new_channel(&USER_ID, "second".to_string(), String::new())
+            .await
+            .unwrap();
+        assert_eq!(Some(first), hub.default_channel);
+        hub.delete_channel(&USER_ID, first).await.unwrap();
+        assert_eq!(None, hub.default_channel);
+        hub.set_default_channel(&USER_ID, second).unwrap();
+        assert_eq!(Some(second), hub.default_channel);
+        assert_eq!(
+            ApiError::ChannelNotFound,
+            hub.set_default_channel(&USER_ID, first).unwrap_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn new_channel_permissions() {
+        let mut hub = test_hub();
+        hub.new_channel_permissions = vec![
+            (ChannelPermission::Read, Some(true)),
+            (ChannelPermission::Write, Some(true)),
+        ]
+        .into_iter()
+        .collect();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let channel_id = hub
+            .new_channel(&USER_ID, "open".to_string(), String::new())
+            .await
+            .unwrap();
+        assert!(hub.can_in_channel(&OTHER_USER_ID, channel_id, ChannelPermission::Read));
+        assert!(hub.can_in_channel(&OTHER_USER_ID, channel_id, ChannelPermission::Write));
+        assert!(!hub.can_in_channel(&OTHER_USER_ID, *CHANNEL_ID, ChannelPermission::Write));
+    }
+
+    #[test]
+    fn embed_links() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let member = hub.get_member_mut(&OTHER_USER_ID).unwrap();
+        member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        let link = "see (https://example.com) for more".to_string();
+        assert!(hub
+            .preview_message(*OTHER_USER_ID, *CHANNEL_ID, link.clone(), 0)
+            .is_ok());
+        hub.get_member_mut(&OTHER_USER_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::EmbedLinks, Some(false));
+        assert_eq!(
+            Err(ApiError::MissingChannelPermission {
+                permission: ChannelPermission::EmbedLinks
+            }),
+            hub.preview_message(*OTHER_USER_ID, *CHANNEL_ID, link.clone(), 0)
+                .map(|_| ())
+        );
+        assert!(hub
+            .preview_message(*OTHER_USER_ID, *CHANNEL_ID, "no links here".to_string(), 0)
+            .is_ok());
+        assert!(hub.preview_message(*USER_ID, *CHANNEL_ID, link, 0).is_ok());
+    }
+
+    #[test]
+    fn send_attachments() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let member = hub.get_member_mut(&OTHER_USER_ID).unwrap();
+        member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        let attachment = "look at this: <attachment://cat.png>".to_string();
+        assert!(hub
+            .preview_message(*OTHER_USER_ID, *CHANNEL_ID, attachment.clone(), 0)
+            .is_ok());
+        hub.get_member_mut(&OTHER_USER_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::SendAttachments, Some(false));
+        assert_eq!(
+            Err(ApiError::MissingChannelPermission {
+                permission: ChannelPermission::SendAttachments
+            }),
+            hub.preview_message(*OTHER_USER_ID, *CHANNEL_ID, attachment.clone(), 0)
+                .map(|_| ())
+        );
+        assert!(hub
+            .preview_message(
+                *OTHER_USER_ID,
+                *CHANNEL_ID,
+                "https://example.com".to_string(),
+                0
+            )
+            .is_ok());
+        assert!(hub
+            .preview_message(*USER_ID, *CHANNEL_ID, attachment, 0)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn last_activity() {
+        let mut hub = test_hub();
+        let quiet = hub
+            .new_channel(&USER_ID, "quiet".to_string(), String::new())
+            .await
+            .unwrap();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        assert_eq!(
+            vec![quiet, *CHANNEL_ID],
+            hub.get_channels_by_activity(&USER_ID)
+                .unwrap()
+                .iter()
+                .map(|channel| channel.id)
+                .collect::<Vec<ID>>()
+        );
+        let message = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "hello".to_string(), 0)
+            .await
+            .unwrap();
+        let hub = Hub::load(hub.id).await.unwrap();
+        assert_eq!(message.created, hub.channels[&*CHANNEL_ID].last_activity);
+        assert_eq!(
+            vec![*CHANNEL_ID, quiet],
+            hub.get_channels_by_activity(&USER_ID)
+                .unwrap()
+                .iter()
+                .map(|channel| channel.id)
+                .collect::<Vec<ID>>()
+        );
+        hub.save(&HubCache::new(0)).await.unwrap();
+        assert!(!LAST_ACTIVITY.lock().unwrap().contains_key(&hub.id));
+        let hub = Hub::load(hub.id).await.unwrap();
+        assert_eq!(message.created, hub.channels[&*CHANNEL_ID].last_activity);
+    }
+
+    #[tokio::test]
+    async fn forward_message() {
+        let mut hub = test_hub();
+        let original = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "forward me".to_string(), 0)
+            .await
+            .unwrap();
+        let other = hub
+            .new_channel(&USER_ID, "other".to_string(), String::new())
+            .await
+            .unwrap();
+        let copy = hub
+            .forward_message(*USER_ID, other, &original)
+            .await
+            .unwrap();
+        let reference = MessageReference {
+            hub_id: hub.id,
+            channel_id: *CHANNEL_ID,
+            message_id: original.id,
+            sender: *USER_ID,
+        };
+        let stored = hub.channels[&other].get_message(copy.id).await.unwrap();
+        assert_eq!(copy, stored);
+        assert_eq!("forward me", stored.content);
+        assert_eq!(other, stored.channel_id);
+        assert_eq!(Some(reference), stored.forwarded_from);
+        let again = hub
+            .forward_message(*USER_ID, *CHANNEL_ID, &stored)
+            .await
+            .unwrap();
+        assert_eq!(Some(reference), again.forwarded_from);
+    }
+
+    #[tokio::test]
+    async fn delete_during_saves() {
+        let cache = HubCache::new(crate::HUB_CACHE_SIZE);
+        let hub = test_hub();
+        hub.save(&cache).await.unwrap();
+        let saves = futures::future::join_all((0..20).map(|_| hub.save(&cache)));
+        let (_, deleted) = futures::join!(saves, hub.delete(&cache));
+        deleted.unwrap();
+        assert!(Hub::load(hub.id).await.is_err());
+        assert!(!cache.contains(&hub.id).await);
+        assert!(hub.save(&cache).await.is_err());
+        assert!(Hub::load(hub.id).await.is_err());
+        assert!(hub.delete(&cache).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_grace_period() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let group = hub.groups.get_mut(&*GROUP_ID).unwrap();
+        group.set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        group.set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        let channel = hub.channels.get(&*CHANNEL_ID).unwrap().clone();
+        let recent = hub
+            .send_message(*OTHER_USER_ID, *CHANNEL_ID, "oops".to_string(), 0)
+            .await
+            .unwrap();
+        hub.delete_message(&OTHER_USER_ID, *CHANNEL_ID, recent.id, 60_000)
+            .await
+            .unwrap();
+        assert!(channel.get_message(recent.id).await.is_none());
+        let mut old = Message::new(*OTHER_USER_ID, "old".to_string(), hub.id, *CHANNEL_ID, 0);
+        old.created = Utc::now() - chrono::Duration::minutes(5);
+        Channel::write_message(&old).await.unwrap();
+        assert_eq!(
+            ApiError::MissingChannelPermission {
+                permission: ChannelPermission::Manage
+            },
+            hub.delete_message(&OTHER_USER_ID, *CHANNEL_ID, old.id, 60_000)
+                .await
+                .unwrap_err()
+        );
+        hub.delete_message(&USER_ID, *CHANNEL_ID, old.id, 0)
+            .await
+            .unwrap();
+        assert!(channel.get_message(old.id).await.is_none());
         assert_eq!(
-            ApiError::Banned,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+            ApiError::MessageNotFound,
+            hub.delete_message(&USER_ID, *CHANNEL_ID, old.id, 0)
+                .await
+                .unwrap_err()
         );
-        hub.unban_user(&OTHER_USER_ID);
+    }
+
+    #[tokio::test]
+    async fn edit_message() {
+        let mut hub = test_hub();
         hub.user_join(*OTHER_USER_ID).unwrap();
-        hub.ban_user(*OTHER_USER_ID).unwrap();
+        let group = hub.groups.get_mut(&*GROUP_ID).unwrap();
+        group.set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        group.set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        let channel = hub.channels.get(&*CHANNEL_ID).unwrap().clone();
+        let message = hub
+            .send_message(*OTHER_USER_ID, *CHANNEL_ID, "helo".to_string(), 0)
+            .await
+            .unwrap();
+        let edited = hub
+            .edit_message(
+                &OTHER_USER_ID,
+                *CHANNEL_ID,
+                message.id,
+                "hello https://example.com".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!("hello https://example.com", edited.content);
+        assert_eq!(vec!["https://example.com".to_string()], edited.links);
+        assert_eq!(1, edited.edits.len());
+        assert_eq!("helo", edited.edits[0].content);
+        assert_eq!(*OTHER_USER_ID, edited.edits[0].editor);
+        assert_eq!(Some(edited), channel.get_message(message.id).await);
+        let own = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "mine".to_string(), 0)
+            .await
+            .unwrap();
         assert_eq!(
-            ApiError::Banned,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+            ApiError::MissingChannelPermission {
+                permission: ChannelPermission::Manage
+            },
+            hub.edit_message(&OTHER_USER_ID, *CHANNEL_ID, own.id, "yours".to_string())
+                .await
+                .unwrap_err()
+        );
+        let moderated = hub
+            .edit_message(&USER_ID, *CHANNEL_ID, message.id, "[removed]".to_string())
+            .await
+            .unwrap();
+        assert_eq!(2, moderated.edits.len());
+        assert_eq!(*USER_ID, moderated.edits[1].editor);
+        assert_eq!(
+            ApiError::MessageNotFound,
+            hub.edit_message(&USER_ID, *CHANNEL_ID, new_id(), "missing".to_string())
+                .await
+                .unwrap_err()
         );
-        assert_eq!(ApiError::IsOwner, hub.ban_user(*USER_ID).unwrap_err());
-        hub.check_membership(&USER_ID).unwrap();
-        assert!(!hub.bans.contains(&USER_ID));
     }
 
     #[test]
-    fn kick() {
+    fn admins() {
         let mut hub = test_hub();
+        let admin = new_id();
+        hub.user_join(admin).unwrap();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.members
+            .get_mut(&admin)
+            .unwrap()
+            .set_permission(HubPermission::Administrate, Some(true));
+        assert_eq!(vec![admin], hub.admins());
+        assert!(!hub.admins().contains(&*OTHER_USER_ID));
+        assert!(!hub.admins().contains(&hub.owner));
+    }
+
+    #[tokio::test]
+    async fn message_flags() {
+        let hub = test_hub();
+        let flags = Message::FLAG_SPOILER | Message::FLAG_SILENT;
+        let message = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "test message".to_string(), flags)
+            .await
+            .unwrap();
+        let got = hub
+            .channels
+            .get(&*CHANNEL_ID)
+            .unwrap()
+            .get_message(message.id)
+            .await
+            .unwrap();
+        assert_eq!(flags, got.flags);
+        assert!(got.has_flags(Message::FLAG_SPOILER));
+        assert!(!got.has_flags(Message::FLAG_EPHEMERAL));
         assert_eq!(
-            ApiError::NotInHub,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+            ApiError::InvalidMessageFlags,
+            hub.send_message(*USER_ID, *CHANNEL_ID, "test message".to_string(), 1 << 31)
+                .await
+                .unwrap_err()
         );
+    }
+
+    #[test]
+    fn batch_permissions() {
+        use crate::permission::{ChannelPermissionSet, HubPermissionSet};
+        let mut hub = test_hub();
         hub.user_join(*OTHER_USER_ID).unwrap();
-        hub.kick_user(&OTHER_USER_ID).unwrap();
+        let batch = vec![
+            PermissionSet::Hub(HubPermissionSet {
+                permission: HubPermission::Mute,
+                setting: Some(true),
+            }),
+            PermissionSet::Hub(HubPermissionSet {
+                permission: HubPermission::Kick,
+                setting: Some(true),
+            }),
+            PermissionSet::Channel(ChannelPermissionSet {
+                permission: ChannelPermission::Manage,
+                setting: Some(true),
+                channel: *CHANNEL_ID,
+            }),
+        ];
+        let mut bad_batch = batch.clone();
+        bad_batch.push(PermissionSet::Channel(ChannelPermissionSet {
+            permission: ChannelPermission::Read,
+            setting: Some(true),
+            channel: ID::from_u128(0),
+        }));
         assert_eq!(
-            ApiError::NotInHub,
-            hub.check_membership(&OTHER_USER_ID).unwrap_err()
+            ApiError::ChannelNotFound,
+            hub.set_member_permissions(&USER_ID, &OTHER_USER_ID, &bad_batch)
+                .unwrap_err()
         );
+        let member = hub.get_member(&OTHER_USER_ID).unwrap();
+        assert!(!member.has_permission(HubPermission::Mute, &hub));
+        hub.set_member_permissions(&USER_ID, &OTHER_USER_ID, &batch)
+            .unwrap();
+        let member = hub.get_member(&OTHER_USER_ID).unwrap();
+        assert!(member.has_permission(HubPermission::Mute, &hub));
+        assert!(member.has_permission(HubPermission::Kick, &hub));
+        assert!(member.has_channel_permission(*CHANNEL_ID, ChannelPermission::Manage, &hub));
+        assert_eq!(
+            ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            },
+            hub.set_member_permissions(&OTHER_USER_ID, &USER_ID, &batch)
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn clear_overrides() {
+        let mut hub = test_hub();
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_permission(HubPermission::Kick, Some(true));
         hub.user_join(*OTHER_USER_ID).unwrap();
-        assert_eq!(ApiError::IsOwner, hub.kick_user(&USER_ID).unwrap_err());
-        hub.get_member(&USER_ID).unwrap();
+        let member = hub.get_member_mut(&OTHER_USER_ID).unwrap();
+        member.set_permission(HubPermission::Kick, Some(false));
+        member.set_permission(HubPermission::Ban, Some(true));
+        member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Manage, Some(true));
+        member.clear_overrides();
+        let member = hub.get_member(&OTHER_USER_ID).unwrap();
+        assert!(member.has_permission(HubPermission::Kick, &hub));
+        assert!(!member.has_permission(HubPermission::Ban, &hub));
+        assert!(!member.has_channel_permission(*CHANNEL_ID, ChannelPermission::Manage, &hub));
     }
 
     #[tokio::test]
-    async fn mute() {
+    async fn clone_as_template() {
         let mut hub = test_hub();
-        hub.groups
-            .get_mut(&GROUP_ID)
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.default_channel = Some(*CHANNEL_ID);
+        hub.send_message(*USER_ID, *CHANNEL_ID, "test message".to_string(), 0)
+            .await
+            .unwrap();
+        let new_owner = ID::from_u128(3);
+        let clone = hub.clone_as_template(new_owner, "clone".to_string());
+        assert_ne!(hub.id, clone.id);
+        assert_eq!(new_owner, clone.owner);
+        assert_eq!(
+            vec![new_owner],
+            clone.members.keys().copied().collect::<Vec<ID>>()
+        );
+        assert_eq!(hub.channels.len(), clone.channels.len());
+        let channel = clone.channels.values().next().unwrap();
+        assert_ne!(*CHANNEL_ID, channel.id);
+        assert_eq!(hub.channels[&*CHANNEL_ID].name, channel.name);
+        assert_eq!(Some(channel.id), clone.default_channel);
+        channel.create_dir().await.unwrap();
+        assert!(channel.get_last_messages(10).await.is_empty());
+        assert_eq!(hub.groups.len(), clone.groups.len());
+        let group = &clone.groups[&clone.default_group];
+        assert_eq!(hub.groups[&*GROUP_ID].name, group.name);
+        assert_eq!(vec![new_owner], group.members);
+        assert!(group.channel_permissions.contains_key(&channel.id));
+    }
+
+    #[test]
+    fn search_members() {
+        let mut hub = test_hub();
+        for (i, nick) in ["Alice", "alfred", "Bob", "ALBERT"].iter().enumerate() {
+            let user_id = ID::from_u128(10 + i as u128);
+            hub.user_join(user_id).unwrap();
+            hub.get_member_mut(&user_id).unwrap().nick = nick.to_string();
+        }
+        let nicks = |found: Vec<&HubMember>| {
+            found
+                .into_iter()
+                .map(|member| member.nick.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            vec!["ALBERT", "Alice", "alfred"],
+            nicks(hub.search_members("aL", 10))
+        );
+        assert_eq!(vec!["ALBERT", "Alice"], nicks(hub.search_members("al", 2)));
+        assert_eq!(vec!["Bob"], nicks(hub.search_members("b", 10)));
+        assert!(hub.search_members("carol", 10).is_empty());
+        assert!(hub.search_members("al", 0).is_empty());
+    }
+
+    #[test]
+    fn member_groups() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let mut group = PermissionGroup::new("extra".to_string(), ID::from_u128(3));
+        hub.get_member_mut(&USER_ID).unwrap().join_group(&mut group);
+        hub.groups.insert(group.id, group);
+        let mut groups = hub
+            .get_member_groups(&USER_ID)
             .unwrap()
-            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
-        hub.groups
-            .get_mut(&GROUP_ID)
+            .iter()
+            .map(|group| group.id)
+            .collect::<Vec<ID>>();
+        groups.sort();
+        let mut expected = hub.get_member(&USER_ID).unwrap().groups.clone();
+        expected.sort();
+        assert_eq!(expected, groups);
+        assert_eq!(2, groups.len());
+        let other = hub.get_member_groups(&OTHER_USER_ID).unwrap();
+        assert_eq!(1, other.len());
+        assert_eq!(*GROUP_ID, other[0].id);
+        assert_eq!(
+            Err(ApiError::MemberNotFound),
+            hub.get_member_groups(&ID::from_u128(4)).map(|_| ())
+        );
+    }
+
+    #[tokio::test]
+    async fn channel_permission_cleanup() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            Err(ApiError::ChannelNotFound),
+            hub.set_member_channel_permission(
+                &USER_ID,
+                &OTHER_USER_ID,
+                ID::from_u128(3),
+                ChannelPermission::Read,
+                Some(true),
+            )
+        );
+        assert!(hub
+            .get_member(&OTHER_USER_ID)
             .unwrap()
-            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+            .channel_permissions
+            .is_empty());
+        hub.set_member_channel_permission(
+            &USER_ID,
+            &OTHER_USER_ID,
+            *CHANNEL_ID,
+            ChannelPermission::Read,
+            Some(true),
+        )
+        .unwrap();
+        assert!(hub.groups[&*GROUP_ID]
+            .channel_permissions
+            .contains_key(&*CHANNEL_ID));
+        hub.delete_channel(&USER_ID, *CHANNEL_ID).await.unwrap();
+        assert!(hub
+            .members
+            .values()
+            .all(|member| !member.channel_permissions.contains_key(&*CHANNEL_ID)));
+        assert!(hub
+            .groups
+            .values()
+            .all(|group| !group.channel_permissions.contains_key(&*CHANNEL_ID)));
+    }
+
+    #[test]
+    fn prune_channel_permissions() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let stale = ID::from_u128(3);
+        for member in hub.members.values_mut() {
+            member.set_channel_permission(stale, ChannelPermission::Read, Some(true));
+            member.set_channel_permission(*CHANNEL_ID, ChannelPermission::Read, Some(true));
+        }
+        for group in hub.groups.values_mut() {
+            group.set_channel_permission(stale, ChannelPermission::Read, Some(true));
+        }
+        hub.prune_channel_permissions();
+        assert!(hub
+            .members
+            .values()
+            .all(|member| !member.channel_permissions.contains_key(&stale)
+                && member.channel_permissions.contains_key(&*CHANNEL_ID)));
+        assert!(hub
+            .groups
+            .values()
+            .all(|group| !group.channel_permissions.contains_key(&stale)));
+    }
+
+    #[test]
+    fn member_limit() {
+        let mut hub = test_hub();
+        hub.max_members = Some(2);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        let third = ID::from_u128(3);
+        assert_eq!(Err(ApiError::HubFull), hub.user_join(third).map(|_| ()));
+        assert!(!hub.is_member(&third));
+        hub.user_leave(&OTHER_USER_ID).unwrap();
+        hub.user_join(third).unwrap();
+        assert!(hub.is_member(&third));
+    }
+
+    #[tokio::test]
+    async fn rename_group() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            Ok("test group".to_string()),
+            hub.rename_group(&USER_ID, *GROUP_ID, "renamed".to_string())
+        );
+        assert_eq!(
+            Err(ApiError::InvalidName),
+            hub.rename_group(&USER_ID, *GROUP_ID, "a".repeat(crate::MAX_NAME_SIZE + 1))
+        );
+        assert_eq!(
+            Err(ApiError::GroupNotFound),
+            hub.rename_group(&USER_ID, ID::from_u128(3), "renamed".to_string())
+        );
+        assert!(hub
+            .rename_group(&OTHER_USER_ID, *GROUP_ID, "other".to_string())
+            .is_err());
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let hub = Hub::load(hub.id).await.unwrap();
+        assert_eq!("renamed", hub.groups[&*GROUP_ID].name);
+    }
+
+    #[tokio::test]
+    async fn threads() {
+        let hub = test_hub();
+        let parent = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "parent".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(ApiError::MessageNotFound),
+            hub.create_thread(&USER_ID, *CHANNEL_ID, new_id(), "thread".to_string())
+                .await
+        );
+        let thread = hub
+            .create_thread(&USER_ID, *CHANNEL_ID, parent.id, "thread".to_string())
+            .await
+            .unwrap();
+        assert_eq!(parent.id, thread.message_id);
+        let message = hub
+            .send_thread_message(*USER_ID, *CHANNEL_ID, thread.id, "reply".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(thread.id, message.channel_id);
+        assert_eq!(
+            vec![message.clone()],
+            thread.channel().get_last_messages(10).await
+        );
+        let channel = hub.get_channel(&USER_ID, *CHANNEL_ID).unwrap();
+        assert_eq!(vec![parent.clone()], channel.get_last_messages(10).await);
+        let threads = hub.get_threads(&USER_ID, *CHANNEL_ID).await.unwrap();
+        assert_eq!(1, threads.len());
+        assert_eq!(thread.id, threads[0].id);
+        let summary = hub
+            .get_thread_summary(&USER_ID, *CHANNEL_ID, parent.id)
+            .await
+            .unwrap();
+        assert_eq!(1, summary.thread_count);
+        assert_eq!(Some(message), summary.last_thread_message);
+    }
+
+    #[tokio::test]
+    async fn member_messages() {
+        let mut hub = test_hub();
+        let other_channel = hub
+            .new_channel(&USER_ID, "other".to_string(), String::new())
+            .await
+            .unwrap();
         hub.user_join(*OTHER_USER_ID).unwrap();
-        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string())
+        let mut sent = Vec::new();
+        for channel_id in [*CHANNEL_ID, other_channel, *CHANNEL_ID].iter() {
+            let message =
+                Message::new(*OTHER_USER_ID, "target".to_string(), hub.id, *channel_id, 0);
+            Channel::write_message(&message).await.unwrap();
+            sent.push(message);
+            hub.send_message(*USER_ID, *channel_id, "moderator".to_string(), 0)
+                .await
+                .unwrap();
+        }
+        sent.reverse();
+        let messages = hub
+            .get_member_messages(&USER_ID, &OTHER_USER_ID, 10)
             .await
             .unwrap();
-        hub.mute_user(*OTHER_USER_ID).unwrap();
         assert_eq!(
-            ApiError::Muted,
-            hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string())
+            sent.iter().map(|m| m.id).collect::<Vec<ID>>(),
+            messages.iter().map(|m| m.id).collect::<Vec<ID>>()
+        );
+        assert_eq!(
+            sent[..2].iter().map(|m| m.id).collect::<Vec<ID>>(),
+            hub.get_member_messages(&USER_ID, &OTHER_USER_ID, 2)
                 .await
-                .unwrap_err()
+                .unwrap()
+                .iter()
+                .map(|m| m.id)
+                .collect::<Vec<ID>>()
         );
-        hub.unmute_user(&OTHER_USER_ID);
-        hub.send_message(*OTHER_USER_ID, *CHANNEL_ID, "test message".to_string())
+        assert_eq!(
+            Err(ApiError::MissingHubPermission {
+                permission: HubPermission::Kick
+            }),
+            hub.get_member_messages(&OTHER_USER_ID, &USER_ID, 10)
+                .await
+                .map(|_| ())
+        );
+    }
+
+    #[tokio::test]
+    async fn tags() {
+        let mut hub = test_hub();
+        assert_eq!(
+            Ok(Vec::new()),
+            hub.set_tags(vec![
+                "games".to_string(),
+                "retro-80s".to_string(),
+                "games".to_string()
+            ])
+        );
+        assert_eq!(Ok(None), hub.set_category("Gaming".to_string()));
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let loaded = Hub::load(hub.id).await.unwrap();
+        assert_eq!(vec!["games", "retro-80s"], loaded.tags);
+        assert_eq!(Some("Gaming".to_string()), loaded.category);
+        for invalid in [
+            vec!["Games".to_string()],
+            vec![String::new()],
+            vec!["two words".to_string()],
+            vec!["a".repeat(crate::MAX_TAG_SIZE + 1)],
+            (0..=crate::MAX_HUB_TAGS).map(|i| i.to_string()).collect(),
+        ]
+        .iter()
+        {
+            assert_eq!(Err(ApiError::InvalidTags), hub.set_tags(invalid.clone()));
+        }
+        assert_eq!(vec!["games", "retro-80s"], hub.tags);
+        assert_eq!(
+            Ok(Some("Gaming".to_string())),
+            hub.set_category(String::new())
+        );
+        assert_eq!(None, hub.category);
+    }
+
+    #[test]
+    fn server_admin_administrative() {
+        crate::config::set_server_admins(vec![*SERVER_ADMIN_ID]);
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            Ok(()),
+            hub.check_administrative(&SERVER_ADMIN_ID, HubPermission::All)
+        );
+        assert_eq!(
+            Ok(()),
+            hub.set_word_filter(&SERVER_ADMIN_ID, WordFilter::default())
+        );
+        assert_eq!(
+            Err(ApiError::MissingHubPermission {
+                permission: HubPermission::All
+            }),
+            hub.check_administrative(&OTHER_USER_ID, HubPermission::All)
+        );
+        assert_eq!(
+            Err(ApiError::MemberNotFound),
+            hub.check_administrative(&ID::from_u128(4), HubPermission::All)
+        );
+        assert!(!hub.members.contains_key(&*SERVER_ADMIN_ID));
+        assert_eq!(
+            Ok(()),
+            hub.set_member_channel_permission(
+                &SERVER_ADMIN_ID,
+                &OTHER_USER_ID,
+                *CHANNEL_ID,
+                ChannelPermission::Manage,
+                Some(true)
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            hub.check_channel_administrative(
+                &SERVER_ADMIN_ID,
+                *CHANNEL_ID,
+                ChannelPermission::Manage
+            )
+        );
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        crate::config::set_server_admins(vec![*SERVER_ADMIN_ID]);
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert_eq!(
+            Err(ApiError::MissingHubPermission {
+                permission: HubPermission::All
+            }),
+            hub.transfer_ownership(&OTHER_USER_ID, *OTHER_USER_ID)
+        );
+        assert_eq!(
+            Err(ApiError::MemberNotFound),
+            hub.transfer_ownership(&SERVER_ADMIN_ID, ID::from_u128(4))
+        );
+        assert_eq!(*USER_ID, hub.owner);
+        assert_eq!(
+            Ok(*USER_ID),
+            hub.transfer_ownership(&SERVER_ADMIN_ID, *OTHER_USER_ID)
+        );
+        assert_eq!(*OTHER_USER_ID, hub.owner);
+        assert!(!hub.can(&USER_ID, HubPermission::All));
+        assert_eq!(
+            ApiError::IsOwner,
+            hub.kick_user(&OTHER_USER_ID).unwrap_err()
+        );
+        hub.kick_user(&USER_ID).unwrap();
+        assert_eq!(
+            Ok(*OTHER_USER_ID),
+            hub.transfer_ownership(&OTHER_USER_ID, *OTHER_USER_ID)
+        );
+    }
+
+    #[tokio::test]
+    async fn compact_channel() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.send_message(*USER_ID, *CHANNEL_ID, "message".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(ApiError::MissingHubPermission {
+                permission: HubPermission::Administrate
+            }),
+            hub.compact_channel(&OTHER_USER_ID, *CHANNEL_ID).await
+        );
+        assert_eq!(
+            Err(ApiError::ChannelNotFound),
+            hub.compact_channel(&USER_ID, new_id()).await
+        );
+        let report = hub.compact_channel(&USER_ID, *CHANNEL_ID).await.unwrap();
+        assert_eq!(1, report.files_after);
+        assert_eq!(1, report.messages);
+    }
+
+    #[tokio::test]
+    async fn thread_summary() {
+        let hub = test_hub();
+        let parent = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "parent".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            ThreadSummary::default(),
+            hub.get_thread_summary(&USER_ID, *CHANNEL_ID, parent.id)
+                .await
+                .unwrap()
+        );
+        let first = hub
+            .create_thread(&USER_ID, *CHANNEL_ID, parent.id, "first".to_string())
+            .await
+            .unwrap();
+        let second = hub
+            .create_thread(&USER_ID, *CHANNEL_ID, parent.id, "second".to_string())
             .await
             .unwrap();
-        assert_eq!(ApiError::IsOwner, hub.mute_user(*USER_ID).unwrap_err());
+        let mut last = None;
+        for thread in [first.id, first.id, second.id].iter() {
+            last = Some(
+                hub.send_thread_message(*USER_ID, *CHANNEL_ID, *thread, "reply".to_string(), 0)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let summary = hub
+            .get_thread_summary(&USER_ID, *CHANNEL_ID, parent.id)
+            .await
+            .unwrap();
+        assert_eq!(3, summary.thread_count);
+        assert_eq!(last, summary.last_thread_message);
+        assert_eq!(
+            2,
+            Thread::load(hub.id, *CHANNEL_ID, first.id)
+                .await
+                .unwrap()
+                .reply_count
+        );
+        let replies = (0..10).map(|_| {
+            hub.send_thread_message(*USER_ID, *CHANNEL_ID, second.id, "reply".to_string(), 0)
+        });
+        for reply in futures::future::join_all(replies).await {
+            reply.unwrap();
+        }
+        assert_eq!(
+            11,
+            Thread::load(hub.id, *CHANNEL_ID, second.id)
+                .await
+                .unwrap()
+                .reply_count
+        );
+        assert_eq!(
+            13,
+            hub.get_thread_summary(&USER_ID, *CHANNEL_ID, parent.id)
+                .await
+                .unwrap()
+                .thread_count
+        );
+    }
+
+    #[test]
+    fn channel_permission_fallback_matches() {
+        for permission in ChannelPermission::VARIANTS.iter().copied() {
+            let fallback = channel_permission_fallback(permission);
+            for hub_permission in HubPermission::VARIANTS.iter().copied() {
+                let mut hub = test_hub();
+                hub.user_join(*OTHER_USER_ID).unwrap();
+                let member = hub.get_member_mut(&OTHER_USER_ID).unwrap();
+                member.set_channel_permission(*CHANNEL_ID, permission, None);
+                member.set_permission(hub_permission, Some(true));
+                let member = hub.get_member(&OTHER_USER_ID).unwrap();
+                let expected = hub_permission == fallback || hub_permission == HubPermission::All;
+                assert_eq!(
+                    expected,
+                    member.has_channel_permission(*CHANNEL_ID, permission, &hub),
+                    "{} with {}",
+                    permission,
+                    hub_permission
+                );
+                let mut group = PermissionGroup::new("test".to_string(), ID::from_u128(3));
+                group.set_channel_permission(*CHANNEL_ID, permission, None);
+                group.set_permission(hub_permission, Some(true));
+                assert_eq!(
+                    expected,
+                    group.has_channel_permission(*CHANNEL_ID, permission),
+                    "{} with {}",
+                    permission,
+                    hub_permission
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn can() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        assert!(hub.can(&USER_ID, HubPermission::Ban));
+        assert!(hub.can_in_channel(&USER_ID, *CHANNEL_ID, ChannelPermission::Manage));
+        assert!(!hub.can_in_channel(&USER_ID, ID::from_u128(3), ChannelPermission::Read));
+        assert!(!hub.can(&OTHER_USER_ID, HubPermission::Kick));
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_permission(HubPermission::Kick, Some(true));
+        assert!(hub.can(&OTHER_USER_ID, HubPermission::Kick));
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_channel_permission(*CHANNEL_ID, ChannelPermission::Write, Some(true));
+        assert!(hub.can_in_channel(&OTHER_USER_ID, *CHANNEL_ID, ChannelPermission::Write));
+        hub.mute_user(*OTHER_USER_ID, None).unwrap();
+        assert!(!hub.can_in_channel(&OTHER_USER_ID, *CHANNEL_ID, ChannelPermission::Write));
+        assert!(hub.can(&OTHER_USER_ID, HubPermission::Kick));
+        hub.groups
+            .get_mut(&*GROUP_ID)
+            .unwrap()
+            .set_permission(HubPermission::WriteChannels, Some(true));
+        assert!(!hub.can(&OTHER_USER_ID, HubPermission::WriteChannels));
+        hub.unmute_user(&OTHER_USER_ID);
+        assert!(hub.can(&OTHER_USER_ID, HubPermission::WriteChannels));
+        hub.bans.insert(ID::from_u128(3), None);
+        assert!(!hub.can(&ID::from_u128(3), HubPermission::ReadChannels));
     }
 }
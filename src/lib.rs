@@ -3,7 +3,10 @@
 extern crate log;
 
 #[cfg(feature = "server")]
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 #[cfg(feature = "server")]
 use error::{Error, Result};
@@ -18,6 +21,11 @@ pub mod channel;
 /// Various objects for storing configuration.
 #[cfg(feature = "server")]
 pub mod config;
+/// Public directory of hubs that chose to be listed.
+#[cfg(feature = "server")]
+pub mod discovery;
+/// Direct message channels between users.
+pub mod dm;
 /// Errors
 pub mod error;
 /// GraphQL model definition.
@@ -28,6 +36,9 @@ pub mod graphql_model;
 pub mod httpapi;
 /// Hubs, permission management, channel management and member management.
 pub mod hub;
+/// Notifications for users that are not connected.
+#[cfg(feature = "server")]
+pub mod notification;
 /// Permissions are defined here.
 pub mod permission;
 /// Public API exports.
@@ -35,6 +46,8 @@ pub mod prelude;
 /// Server implementation.
 #[cfg(feature = "server")]
 pub mod server;
+/// Per-user data such as block lists.
+pub mod user;
 /// Definition of the WebSocket API.
 #[cfg(feature = "server")]
 pub mod websocket;
@@ -54,22 +67,141 @@ pub const MAX_DESCRIPTION_SIZE: usize = 8192;
 /// Maximum size of a message in bytes. Clients should be able to accept larger and smaller values.
 pub const MAX_MESSAGE_SIZE: usize = 8192;
 
+/// Maximum number of links recorded in a message's [`links`](channel::Message::links), any further links are left in the content only.
+pub const MAX_MESSAGE_LINKS: usize = 16;
+
+/// Maximum number of tags a hub can have.
+pub const MAX_HUB_TAGS: usize = 10;
+
+/// Maximum size of a hub tag in bytes.
+pub const MAX_TAG_SIZE: usize = 32;
+
+/// Number of characters in the codes of hub invites, see [`HubInvite`](hub::HubInvite).
+pub const INVITE_CODE_LENGTH: usize = 10;
+
+/// Maximum number of changes kept in a hub's change log, older changes are dropped when new ones are recorded.
+pub const MAX_HUB_CHANGES: usize = 256;
+
+/// Maximum number of name and description changes kept in the history of each channel.
+pub const MAX_CHANNEL_HISTORY: usize = 64;
+
+/// Maximum number of administrators listed by [`Hub::admins`](hub::Hub::admins), the ones that joined first are listed.
+pub const MAX_LISTED_ADMINS: usize = 100;
+
+/// Maximum number of members in a group direct message conversation.
+pub const MAX_GROUP_DM_MEMBERS: usize = 10;
+
+/// Scheme used to generate message IDs, see [`Message::new`](channel::Message::new).
+pub const MESSAGE_ID_SCHEME: prelude::MessageIdScheme = prelude::MessageIdScheme::RandomV4;
+
+/// Size in bytes above which websocket frames are compressed for clients that connected with compression enabled.
+pub const WS_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Default maximum size of the websocket frames sent by clients in bytes, bigger frames are answered with [`ApiError::TooBig`](error::ApiError::TooBig) without being parsed.
+/// Connections that send frames more than twice this size are closed without the frame being read.
+pub const WS_MAX_FRAME_SIZE: usize = 65_536;
+
+/// Default number of frames that can wait to be sent to each websocket connection, see [`server::SendQueue`].
+pub const WS_SEND_QUEUE_SIZE: usize = 256;
+
+/// Default for how deeply GraphQL queries can nest fields.
+pub const GRAPHQL_MAX_DEPTH: usize = 8;
+
+/// Default for how complex GraphQL queries can be, each field counts as 1 except for message searches and reads which count once per message requested.
+pub const GRAPHQL_MAX_COMPLEXITY: usize = 1000;
+
+/// How long server statistics are cached for in milliseconds, counting messages means reading every message file.
+pub const SERVER_STATS_LIFETIME: i64 = 300_000;
+
+/// How long the subscriptions of a disconnected websocket connection can be resumed for in milliseconds.
+pub const RESUME_TOKEN_LIFETIME: i64 = 60_000;
+
+/// How long a repeated hub creation request with the same idempotency key returns the hub created by the first request for in milliseconds.
+pub const HUB_CREATE_KEY_LIFETIME: i64 = 600_000;
+
+/// Maximum size of an idempotency key in bytes.
+pub const MAX_IDEMPOTENCY_KEY_SIZE: usize = 64;
+
+/// How long after sending a message its sender can delete it without [`ChannelPermission::Manage`](permission::ChannelPermission::Manage) in milliseconds.
+pub const MESSAGE_DELETE_GRACE_PERIOD: i64 = 120_000;
+
+/// Default for how often messages are checked for expiry in milliseconds, see [`Message::expires_at`](channel::Message::expires_at).
+pub const EXPIRY_SWEEP_INTERVAL: u64 = 10_000;
+
+/// Default for the number of hubs kept in memory, see [`server::HubCache`].
+pub const HUB_CACHE_SIZE: usize = 256;
+
+/// Default maximum number of messages written to a channel at once when write batching is enabled, see [`server::WriteBatcher`].
+pub const WRITE_BATCH_SIZE: usize = 64;
+
+/// Default for how often timed mutes and bans are checked for expiry in milliseconds, see [`Hub::expire_timed_moderation`](hub::Hub::expire_timed_moderation).
+pub const MODERATION_EXPIRY_INTERVAL: u64 = 60_000;
+
+/// Default for how often the idempotency keys of hub creations are checked for expiry in milliseconds, see [`HUB_CREATE_KEY_LIFETIME`].
+pub const CREATE_KEY_EXPIRY_INTERVAL: u64 = 60_000;
+
+/// Default for how often typing indicators are checked for expiry in milliseconds.
+pub const TYPING_EXPIRY_INTERVAL: u64 = 1_000;
+
+/// How long a user is shown as typing for in milliseconds if their client does not tell the server that they stopped.
+pub const TYPING_TIMEOUT: i64 = 10_000;
+
+/// How long a [`WebhookSink`](notification::WebhookSink) waits for the webhook to respond in milliseconds before giving up on a notification.
+pub const NOTIFICATION_WEBHOOK_TIMEOUT: u64 = 10_000;
+
 /// How long to wait before commiting new messages to the tantivy search engine in milliseconds, this takes a lot of time, which is why it should be done only periodically.
 pub const TANTIVY_COMMIT_THRESHOLD: u8 = 10;
 
+/// Default number of messages that can wait to be indexed by each message server before indexing new messages is deferred until the channel is next searched or indexed.
+pub const MAX_INDEX_QUEUE: usize = 1024;
+
+/// Set while the server is in maintenance mode, see [`set_maintenance_mode`].
+#[cfg(feature = "server")]
+static MAINTENANCE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Turns maintenance mode on or off, while it is on the HTTP API only allows reads and messages cannot be sent over websockets.
+#[cfg(feature = "server")]
+pub fn set_maintenance_mode(enabled: bool) {
+    MAINTENANCE_MODE.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns [`error::ApiError::Maintenance`] if the server is in maintenance mode.
+#[cfg(feature = "server")]
+pub fn check_maintenance_mode() -> error::ApiResult {
+    if MAINTENANCE_MODE.load(Ordering::SeqCst) {
+        Err(error::ApiError::Maintenance)
+    } else {
+        Ok(())
+    }
+}
+
 /// Starts WICRS Server in the current directory loading the configuration from `config.json`.
 #[cfg(feature = "server")]
 pub async fn start() -> Result {
     let config = config::load_config("config.json");
+    set_maintenance_mode(config.maintenance_mode);
+    config::set_server_admins(config.server_admins.iter().copied());
     if std::fs::create_dir_all("data").is_err() {
         Err(Error::from("Failed to create data directory.".to_string()))
     } else {
-        let server = Server::new()
-            .await?
-            .start()
-            .await
-            .map_err(|_| Error::ServerStartFailed)?;
-        httpapi::start(config, Arc::new(server)).await
+        let message_servers = if config.search {
+            config.message_servers.max(1)
+        } else {
+            0
+        };
+        let mut server = Server::with_message_servers(message_servers).await?;
+        server.set_index_queue_limit(config.index_queue_limit);
+        server.set_send_queue(config.ws_send_queue_size, config.ws_overflow_policy);
+        server.set_scheduled_tasks(config.scheduled_tasks.clone());
+        server.set_hub_cache_size(config.hub_cache_size).await;
+        server.set_write_batching(config.write_batch_interval, config.write_batch_size);
+        server.warm_indexes(&config.warmup_channels);
+        if let Some(url) = &config.notification_webhook {
+            server.add_notification_sink(Arc::new(notification::WebhookSink::new(url)?));
+        }
+        let hub_cache = server.hub_cache();
+        let server = server.start().await.map_err(|_| Error::ServerStartFailed)?;
+        httpapi::start(config, Arc::new(server), hub_cache).await
     }
 }
 
@@ -109,6 +241,7 @@ pub mod test {
         pub static ref CHANNEL_ID: Uuid = Uuid::from_u128(4567890123);
         pub static ref MESSAGE_ID: Uuid = Uuid::from_u128(5678901234);
         pub static ref OTHER_USER_ID: Uuid = Uuid::from_u128(6789012345);
+        pub static ref SERVER_ADMIN_ID: Uuid = Uuid::from_u128(7890123456);
     }
 
     pub fn utc(seconds: i64) -> DateTime<Utc> {
@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, Body, Client, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, ID};
+
+/// Events that users can be notified of while they are not connected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum NotificationEvent {
+    /// The user was mentioned in a message.
+    Mention {
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        sender: ID,
+    },
+    /// The user received a message in a direct message channel.
+    DirectMessage {
+        channel_id: ID,
+        message_id: ID,
+        sender: ID,
+    },
+}
+
+/// Destination for notifications of users that are offline, implemented by operators to forward events to push services.
+/// Sinks are notified in the background, a slow sink only delays its own notifications.
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    /// Notifies the given user of an event, failures should be handled by the sink.
+    async fn notify(&self, user: ID, event: NotificationEvent);
+}
+
+/// Sink that drops all notifications.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopSink;
+
+#[async_trait]
+impl NotificationSink for NoopSink {
+    async fn notify(&self, _user: ID, _event: NotificationEvent) {}
+}
+
+/// Body of the requests sent by a [`WebhookSink`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WebhookNotification {
+    pub user: ID,
+    pub event: NotificationEvent,
+}
+
+/// Sink that sends each notification as a JSON [`WebhookNotification`] in a POST request to a URL, only plain HTTP is supported.
+/// Requests that take longer than [`crate::NOTIFICATION_WEBHOOK_TIMEOUT`] are abandoned.
+#[derive(Clone, Debug)]
+pub struct WebhookSink {
+    url: Uri,
+    client: Client<HttpConnector>,
+}
+
+impl WebhookSink {
+    /// Creates a webhook sink that posts to the given URL.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the URL could not be parsed.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url
+                .parse()
+                .map_err(|_| format!("Invalid notification webhook URL: {}", url))?,
+            client: Client::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationSink for WebhookSink {
+    async fn notify(&self, user: ID, event: NotificationEvent) {
+        let body = match serde_json::to_vec(&WebhookNotification { user, event }) {
+            Ok(body) => body,
+            Err(err) => return warn!("Failed to serialize a notification: {}", err),
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header("content-type", "application/json")
+            .body(Body::from(body));
+        match request {
+            Ok(request) => match tokio::time::timeout(
+                std::time::Duration::from_millis(crate::NOTIFICATION_WEBHOOK_TIMEOUT),
+                self.client.request(request),
+            )
+            .await
+            {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!("Failed to send a notification webhook: {}", err),
+                Err(_) => warn!("Notification webhook timed out"),
+            },
+            Err(err) => warn!("Failed to build a notification webhook request: {}", err),
+        }
+    }
+}
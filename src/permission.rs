@@ -48,6 +48,13 @@ impl From<(ChannelPermission, PermissionSetting, ID)> for ChannelPermissionSet {
     }
 }
 
+/// A permission setting for either the whole hub or a single channel, used to change several permissions at once.
+#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum PermissionSet {
+    Hub(HubPermissionSet),
+    Channel(ChannelPermissionSet),
+}
+
 /// Hub-wide permission, can be all of these except for the `All` permission can be overridden by channel permissions.
 #[derive(PartialEq, Hash, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
 #[cfg_attr(feature = "graphql", derive(Enum))]
@@ -64,6 +71,22 @@ pub enum HubPermission {
     Unban,
 }
 
+impl HubPermission {
+    /// Every hub permission, in declaration order.
+    pub const VARIANTS: &'static [Self] = &[
+        HubPermission::All,
+        HubPermission::ReadChannels,
+        HubPermission::WriteChannels,
+        HubPermission::Administrate,
+        HubPermission::ManageChannels,
+        HubPermission::Mute,
+        HubPermission::Unmute,
+        HubPermission::Kick,
+        HubPermission::Ban,
+        HubPermission::Unban,
+    ];
+}
+
 impl Display for HubPermission {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -112,6 +135,22 @@ pub enum ChannelPermission {
     Read,
     Manage,
     All,
+    CreateThread,
+    EmbedLinks,
+    SendAttachments,
+}
+
+impl ChannelPermission {
+    /// Every channel permission, in declaration order.
+    pub const VARIANTS: &'static [Self] = &[
+        ChannelPermission::Write,
+        ChannelPermission::Read,
+        ChannelPermission::Manage,
+        ChannelPermission::All,
+        ChannelPermission::CreateThread,
+        ChannelPermission::EmbedLinks,
+        ChannelPermission::SendAttachments,
+    ];
 }
 
 impl Display for ChannelPermission {
@@ -121,6 +160,9 @@ impl Display for ChannelPermission {
             ChannelPermission::Read => "READ",
             ChannelPermission::Manage => "MANAGE",
             ChannelPermission::All => "ALL",
+            ChannelPermission::CreateThread => "CREATE_THREAD",
+            ChannelPermission::EmbedLinks => "EMBED_LINKS",
+            ChannelPermission::SendAttachments => "SEND_ATTACHMENTS",
         })
     }
 }
@@ -134,6 +176,9 @@ impl FromStr for ChannelPermission {
             "READ" => ChannelPermission::Read,
             "MANAGE" => ChannelPermission::Manage,
             "ALL" => ChannelPermission::All,
+            "CREATE_THREAD" => ChannelPermission::CreateThread,
+            "EMBED_LINKS" => ChannelPermission::EmbedLinks,
+            "SEND_ATTACHMENTS" => ChannelPermission::SendAttachments,
             _ => return Err(ApiError::InvalidText),
         })
     }
@@ -146,6 +191,9 @@ impl From<ChannelPermission> for HubPermission {
             ChannelPermission::Read => HubPermission::ReadChannels,
             ChannelPermission::Manage => HubPermission::ManageChannels,
             ChannelPermission::All => HubPermission::All,
+            ChannelPermission::CreateThread => HubPermission::WriteChannels,
+            ChannelPermission::EmbedLinks => HubPermission::WriteChannels,
+            ChannelPermission::SendAttachments => HubPermission::WriteChannels,
         }
     }
 }
@@ -1,10 +1,16 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
-pub use crate::channel::{Channel, Message};
+pub use crate::channel::{Channel, Message, MessageReference, Thread, ThreadSummary};
+pub use crate::dm::{DmChannel, GroupDm};
 pub use crate::error::{ApiError as Error, ApiResult as Result};
-pub use crate::hub::{Hub, HubMember, PermissionGroup};
-pub use crate::permission::{ChannelPermission, HubPermission, PermissionSetting};
+pub use crate::hub::{
+    ChannelInfoChange, Hub, HubChange, HubMember, PermissionGroup, WordFilter, WordFilterMode,
+};
+pub use crate::permission::{
+    ChannelPermission, ChannelPermissions, HubPermission, PermissionSet, PermissionSetting,
+};
 pub use crate::ID;
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -14,9 +20,127 @@ pub enum Response<T> {
     Error(Error),
 }
 
+/// How the server generates message IDs, tells clients whether or not sorting messages by ID is meaningful.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageIdScheme {
+    /// Random version 4 UUIDs, messages must be sorted by their `created` field.
+    RandomV4,
+    /// Time-ordered version 7 UUIDs, sorting by ID matches the order the messages were created in.
+    TimeOrderedV7,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HttpServerInfo {
     pub version: String,
+    #[serde(default = "default_message_id_scheme")]
+    pub message_id_scheme: MessageIdScheme,
+}
+
+/// Totals for the whole server, see [`crate::SERVER_STATS_LIFETIME`] for how long they are cached.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HttpServerStats {
+    pub hubs: usize,
+    pub channels: usize,
+    pub members: usize,
+    pub messages: usize,
+    pub connections: usize,
+    /// Number of messages whose indexing was deferred because the search indexing queue was full.
+    pub deferred_indexing: u64,
+    /// Number of corrupt records skipped while reading message files.
+    pub corrupt_records: u64,
+    /// Number of channels whose search index has been loaded on startup.
+    pub warmup_done: usize,
+    /// Number of channels whose search index is to be loaded on startup.
+    pub warmup_total: usize,
+    /// When the hub, channel, member and message totals were counted.
+    pub counted: DateTime<Utc>,
+}
+
+/// Sizes of the server actor's in-memory state, used to diagnose leaks such as stale subscriptions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct HttpServerState {
+    /// Number of open client connections.
+    pub connections: usize,
+    /// Number of connections that have subscription entries, including ones that are not open anymore.
+    pub subscribed_connections: usize,
+    /// Number of channels that have subscription entries.
+    pub subscribed_channels: usize,
+    /// Number of hubs that have subscription entries.
+    pub subscribed_hubs: usize,
+    /// Total number of channel subscriptions across all connections.
+    pub channel_subscriptions: usize,
+    /// Total number of hub subscriptions across all connections.
+    pub hub_subscriptions: usize,
+    /// Number of disconnected connections that can still be resumed.
+    pub resumable: usize,
+    /// Number of resume tokens that have been issued and not used yet.
+    pub resume_tokens: usize,
+    /// Number of users that are currently typing, counted once per channel.
+    pub typing: usize,
+    /// Number of channels that have messages waiting to expire.
+    pub expiring_channels: usize,
+    /// Number of messages waiting to be indexed by each message server.
+    pub index_queues: Vec<usize>,
+    /// Number of channels whose indexing was deferred because their message server's queue was full.
+    pub deferred_channels: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpDiscoverQuery {
+    #[serde(default)]
+    pub query: String,
+    pub tag: Option<String>,
+    pub category: Option<String>,
+    pub limit: usize,
+}
+
+/// Body of the admin endpoint that turns maintenance mode on or off, see [`crate::set_maintenance_mode`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpMaintenanceMode {
+    /// Whether the server should be in maintenance mode.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpHubListQuery {
+    pub limit: usize,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HttpHubSummary {
+    pub id: ID,
+    pub name: String,
+    pub owner: ID,
+    pub member_count: usize,
+    pub created: DateTime<Utc>,
+}
+
+/// Every permission the server knows of, along with the hub permission each channel permission falls back to when it is not set.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpPermissionSchema {
+    pub hub: Vec<HubPermission>,
+    pub channel: Vec<ChannelPermission>,
+    pub channel_to_hub: Vec<(ChannelPermission, HubPermission)>,
+}
+
+impl Default for HttpPermissionSchema {
+    fn default() -> Self {
+        Self {
+            hub: HubPermission::VARIANTS.to_vec(),
+            channel: ChannelPermission::VARIANTS.to_vec(),
+            channel_to_hub: ChannelPermission::VARIANTS
+                .iter()
+                .map(|permission| (*permission, channel_permission_fallback(*permission)))
+                .collect(),
+        }
+    }
+}
+
+/// Scheme assumed for servers that do not report one, older servers only used random IDs.
+fn default_message_id_scheme() -> MessageIdScheme {
+    MessageIdScheme::RandomV4
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -26,16 +150,86 @@ pub struct HttpMemberStatus {
     pub muted: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpCreateThread {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpGroupUpdate {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpMemberGroup {
+    pub id: ID,
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct HttpSetPermission {
     pub setting: PermissionSetting,
 }
 
+/// Hypothetical change to a hub member's permissions and the permissions to check for once it is made, see `POST /member/{hub_id}/{member_id}/permissions/simulate`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HttpSimulatePermissions {
+    /// Groups the member would be in instead of their current groups, the current groups are kept if not set.
+    #[serde(default)]
+    pub groups: Option<Vec<ID>>,
+    /// Permission settings that would be given to the member.
+    #[serde(default)]
+    pub overrides: Vec<PermissionSet>,
+    /// Hub permissions to check for.
+    #[serde(default)]
+    pub hub: Vec<HubPermission>,
+    /// Channel permissions to check for along with the ID of the channel to check in.
+    #[serde(default)]
+    pub channel: Vec<(ID, ChannelPermission)>,
+}
+
+/// Whether or not a hub member would have each of the permissions asked for in a [`HttpSimulatePermissions`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpSimulatedPermissions {
+    pub hub: Vec<(HubPermission, bool)>,
+    pub channel: Vec<(ID, ChannelPermission, bool)>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HttpHubUpdate {
     pub name: Option<String>,
     pub description: Option<String>,
     pub default_group: Option<ID>,
+    pub verification: Option<bool>,
+    pub max_members: Option<u32>,
+    pub discoverable: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    /// Empty to remove the category.
+    pub category: Option<String>,
+    /// ID of the message containing the rules members have to accept, nil to stop requiring it.
+    pub require_accept: Option<ID>,
+    /// Permissions the default group gets in new channels, see [`Hub::new_channel_permissions`].
+    pub new_channel_permissions: Option<ChannelPermissions>,
+}
+
+/// Permissions that are explicitly allowed or denied in a channel, permissions that are not set are left out.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct HttpChannelPermissionOverrides {
+    /// Settings of the permission groups that set any permissions in the channel, keyed by group ID.
+    pub groups: HashMap<ID, ChannelPermissions>,
+    /// Settings of the hub members that set any permissions in the channel, keyed by user ID.
+    pub members: HashMap<ID, ChannelPermissions>,
+}
+
+/// Channel to create as part of a batch, see `POST /channel/{hub_id}/batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpChannelSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Permissions to set in the new channel, setting any requires the [`HubPermission::Administrate`] permission.
+    #[serde(default)]
+    pub permissions: HttpChannelPermissionOverrides,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -44,9 +238,45 @@ pub struct HttpChannelUpdate {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpEditMessage {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpSetDraft {
+    pub content: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HttpSendMessage {
     pub message: String,
+    /// Flags to set on the message, see [`Message::FLAG_SPOILER`] and the other `FLAG_` constants.
+    #[serde(default)]
+    pub flags: u32,
+    /// Time after which the message should be deleted, must be in the future.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Location of the message to forward, the user must be able to read it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpForwardMessage {
+    pub hub_id: ID,
+    pub channel_id: ID,
+    pub message_id: ID,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpCloneHub {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpCreateGroupDm {
+    pub name: String,
+    /// Members other than the creator.
+    pub members: HashSet<ID>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,11 +284,65 @@ pub struct HttpSetNick {
     pub nick: String,
 }
 
+/// Optional body of the mute and ban endpoints, the mute or ban is permanent if `expires_at` is not given.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HttpModerationExpiry {
+    /// Time at which the mute or ban is lifted.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Optional body of the invite creation endpoint, the invite never expires and can be used any number of times if neither is given.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HttpCreateInvite {
+    /// Time after which the invite can no longer be used.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Number of times the invite can be used, 0 means there is no limit.
+    #[serde(default)]
+    pub max_uses: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpMemberSearchQuery {
+    pub prefix: String,
+    pub limit: usize,
+}
+
+/// People who run a hub, see [`Hub::admins`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HttpHubAdmins {
+    pub owner: ID,
+    /// Members with the [`HubPermission::Administrate`] permission, not including the owner.
+    pub admins: Vec<ID>,
+}
+
+/// What a message would look like once sent, see [`Hub::preview_message`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HttpMessagePreview {
+    /// Content of the message after it has gone through the hub's word filter.
+    pub content: String,
+    /// IDs of the members of the hub mentioned in the message.
+    pub mentions: HashSet<ID>,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpMemberSummary {
+    pub user_id: ID,
+    pub nick: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpLastMessagesQuery {
     pub max: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpMemberMessagesQuery {
+    pub limit: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpMessagesBeforeQuery {
     pub to: ID,
@@ -71,6 +355,23 @@ pub struct HttpMessagesAfterQuery {
     pub max: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpMessagePageQuery {
+    /// Position from the cursor of the previous page, 0 for the first page.
+    #[serde(default)]
+    pub position: u64,
+    /// `as_of` from the cursor of the previous page, omitted for the first page.
+    pub as_of: Option<u64>,
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpSearchQuery {
+    pub query: String,
+    pub limit: usize,
+    pub sender: Option<ID>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpMessagesBetweenQuery {
     pub from: DateTime<Utc>,
@@ -79,12 +380,45 @@ pub struct HttpMessagesBetweenQuery {
     pub new_to_old: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpWordFilterUpdate {
+    pub mode: WordFilterMode,
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpHubChangesQuery {
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpHubChanges {
+    /// True if the change log no longer goes back far enough, the client should refetch the whole hub.
+    pub full_refetch: bool,
+    /// Changes made since the requested time, oldest first.
+    pub changes: Vec<HubChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HttpWebsocketQuery {
+    /// If true, frames bigger than [`crate::WS_COMPRESSION_THRESHOLD`] are deflate compressed and sent as binary frames.
+    #[serde(default)]
+    pub compress: bool,
+}
+
+/// Gets the hub permission that decides whether a member has a channel permission when the permission is not set for that channel.
+/// For example a member with [`HubPermission::WriteChannels`] can write in any channel where [`ChannelPermission::Write`] is left unset.
+pub fn channel_permission_fallback(permission: ChannelPermission) -> HubPermission {
+    permission.into()
+}
+
 /// Wraps `is_valid_name` to return a `Result<()>`.
 ///
 /// # Errors
 ///
 /// This function returns an error for any of the following reasons:
 ///
+/// * The name is empty.
 /// * The name is too big (maximum in bytes defined by [`MAX_NAME_SIZE`]).
 pub fn check_name_validity(name: &str) -> Result {
     if is_valid_name(name) {
@@ -94,9 +428,59 @@ pub fn check_name_validity(name: &str) -> Result {
     }
 }
 
-/// Checks if a name is valid (not too long and only allowed characters).
+/// Checks that a list of hub tags is valid, see [`is_valid_tag`].
+///
+/// # Errors
+///
+/// This function returns an error for any of the following reasons:
+///
+/// * There are more than [`crate::MAX_HUB_TAGS`] tags.
+/// * One of the tags is not valid.
+pub fn check_tags_validity(tags: &[String]) -> Result {
+    if tags.len() <= crate::MAX_HUB_TAGS && tags.iter().all(|tag| is_valid_tag(tag)) {
+        Ok(())
+    } else {
+        Err(Error::InvalidTags)
+    }
+}
+
+/// Checks if a tag is valid, tags are not empty, no bigger than [`crate::MAX_TAG_SIZE`] and only contain lowercase ASCII letters, digits and dashes.
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= crate::MAX_TAG_SIZE
+        && tag
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// Checks if a name is valid (not empty, not too long and only allowed characters).
 pub fn is_valid_name(name: &str) -> bool {
-    name.as_bytes().len() <= crate::MAX_NAME_SIZE
+    !name.is_empty() && name.as_bytes().len() <= crate::MAX_NAME_SIZE
+}
+
+/// Trims a name and collapses the runs of whitespace in it into single spaces, returning the normalized name if it passes [`check_name_validity`].
+/// Hub and channel names are normalized before they are stored.
+///
+/// # Errors
+///
+/// This function returns an error if the normalized name is not valid, for example if the name was only whitespace.
+pub fn normalize_name(name: &str) -> Result<String> {
+    let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    check_name_validity(&name)?;
+    Ok(name)
+}
+
+/// Checks that a description is no bigger than `max_size` bytes, see [`crate::config::Config::max_description_size`].
+///
+/// # Errors
+///
+/// This function returns [`Error::TooBig`] if the description is bigger than `max_size`.
+pub fn check_description_size(description: &str, max_size: usize) -> Result {
+    if description.as_bytes().len() > max_size {
+        Err(Error::TooBig)
+    } else {
+        Ok(())
+    }
 }
 
 /// Generates a new random ID.
@@ -134,11 +518,19 @@ pub enum WsClientMessage {
         hub_id: ID,
         channel_id: ID,
         message: String,
+        #[serde(default)]
+        flags: u32,
+    },
+    /// Asks for the hubs and channels the connection is subscribed to.
+    GetSubscriptions,
+    /// Restores the subscriptions of a previous connection using the token sent in [`WsServerMessage::Connected`].
+    Resume {
+        token: ID,
     },
 }
 
 /// Types of updates that trigger [`ServerNotification::HubUpdated`]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum WsHubUpdateType {
     HubDeleted,
     HubUpdated,
@@ -148,15 +540,41 @@ pub enum WsHubUpdateType {
     UserMuted(ID),
     UserUnmuted(ID),
     UserUnbanned(ID),
+    UserApproved(ID),
+    /// The member accepted the hub's rules.
+    UserAcceptedRules(ID),
     UserKicked(ID),
+    /// The member was made the owner of the hub.
+    OwnershipTransferred(ID),
     UserHubPermissionChanged(ID),
     UserChannelPermissionChanged(ID, ID),
+    /// Several of the member's permissions were changed at once.
+    UserPermissionsChanged(ID),
+    /// All of the member's individual permission settings were removed.
+    UserPermissionsCleared(ID),
+    /// A permission group was renamed.
+    GroupRenamed(ID),
     MemberNicknameChanged(ID),
     ChannelCreated(ID),
     ChannelDeleted(ID),
     ChannelUpdated(ID),
 }
 
+impl From<Message> for WsServerMessage {
+    fn from(message: Message) -> Self {
+        Self::ChatMessage {
+            sender_id: message.sender,
+            hub_id: message.hub_id,
+            channel_id: message.channel_id,
+            message_id: message.id,
+            message: message.content,
+            flags: message.flags,
+            forwarded_from: message.forwarded_from,
+            links: message.links,
+        }
+    }
+}
+
 /// Messages that the server can send to websocket clients.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum WsServerMessage {
@@ -164,16 +582,26 @@ pub enum WsServerMessage {
     InvalidCommand,
     NotSigned,
     CommandFailed,
+    /// A new message, `hub_id` is nil for messages sent in direct message channels.
     ChatMessage {
         sender_id: ID,
         hub_id: ID,
         channel_id: ID,
         message_id: ID,
         message: String,
+        flags: u32,
+        /// Set if the message is a copy of another message.
+        #[serde(default)]
+        forwarded_from: Option<MessageReference>,
+        /// Links found in the message, see [`Message::links`].
+        #[serde(default)]
+        links: Vec<String>,
     },
     HubUpdated {
         hub_id: ID,
         update_type: WsHubUpdateType,
+        /// Sequence number of the change, see [`HubChange::sequence`].
+        sequence: u64,
     },
     Success,
     UserStartedTyping {
@@ -186,4 +614,35 @@ pub enum WsServerMessage {
         hub_id: ID,
         channel_id: ID,
     },
+    /// Response to [`WsClientMessage::GetSubscriptions`], `channels` contains `(hub_id, channel_id)` pairs.
+    Subscriptions {
+        channels: HashSet<(ID, ID)>,
+        hubs: HashSet<ID>,
+    },
+    /// A message was edited, `message` is its new content, see [`Message::edits`].
+    MessageEdited {
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+        message: String,
+        links: Vec<String>,
+        /// ID of the user that edited the message.
+        editor: ID,
+    },
+    /// A message was deleted by its sender or a moderator.
+    MessageDeleted {
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+    },
+    /// A message was deleted because it expired, see [`Message::expires_at`].
+    MessageExpired {
+        hub_id: ID,
+        channel_id: ID,
+        message_id: ID,
+    },
+    /// Sent once the connection is authenticated, `resume_token` can be used with [`WsClientMessage::Resume`] on a new connection if this one drops.
+    Connected {
+        resume_token: ID,
+    },
 }
@@ -1,45 +1,60 @@
 use crate::{
     channel::{self},
     check_permission,
-    error::{ApiError, Error, Result},
-    hub::Hub,
-    prelude::{WsHubUpdateType, WsServerMessage},
+    config::ScheduledTasks,
+    error::{ApiError, ApiResult, Error, Result},
+    hub::{Hub, HubChange},
+    notification::{NotificationEvent, NotificationSink},
+    prelude::{HttpServerState, HttpServerStats, WsHubUpdateType, WsServerMessage},
+    user::BlockList,
+    websocket::encode_message,
     ID,
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use futures::stream::SplitSink;
 use futures::SinkExt;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
     io::Read,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use tantivy::{
     collector::TopDocs,
     directory::MmapDirectory,
     doc,
-    query::QueryParser,
-    schema::{Field, Schema, FAST, STORED, TEXT},
-    Index, IndexReader, IndexWriter, LeasedItem, ReloadPolicy, Searcher,
+    query::{BooleanQuery, Occur, Query, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, FAST, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, LeasedItem, ReloadPolicy, Searcher, TantivyError, Term,
 };
 use tokio::io::AsyncWriteExt;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{oneshot, Mutex, Notify, RwLock};
 use warp::ws::Message as WebSocketMessage;
 use warp::ws::WebSocket;
 use xactor::*;
 
 use lazy_static::lazy_static;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
 
 pub type ServerAddress = Arc<Addr<Server>>;
 
 pub mod client_command {
-    use super::{message, Arc, Mutex, Result, SplitSink, WebSocket, WebSocketMessage, ID};
+    use super::{message, Arc, HashSet, Mutex, Result, SplitSink, WebSocket, WebSocketMessage, ID};
 
-    /// Disconnects the client by unsubscribing them from everything (does not drop connection).
+    /// Registers a new client connection, returns the ID assigned to the connection.
     #[message(result = "u128")]
     #[derive(Clone, Debug)]
     pub struct Connect {
+        /// ID of the user that the connection is authenticated as.
+        pub user_id: ID,
         pub websocket_writer: Arc<Mutex<SplitSink<WebSocket, WebSocketMessage>>>,
+        /// Whether or not large frames sent to the client should be compressed.
+        pub compress: bool,
     }
     /// Disconnects the client by unsubscribing them from everything (does not drop connection).
     #[message(result = "()")]
@@ -79,6 +94,28 @@ pub mod client_command {
         pub channel_id: ID,
         pub connection_id: u128,
     }
+    /// Gets the channels and hubs the given connection is subscribed to, the connection ID is never taken from the client so a connection can only get its own subscriptions.
+    #[message(result = "(HashSet<(ID, ID)>, HashSet<ID>)")]
+    #[derive(Debug, Clone)]
+    pub struct GetSubscriptions {
+        pub connection_id: u128,
+    }
+    /// Issues a token that can be used with [`Resume`] to restore the connection's subscriptions after it disconnects.
+    #[message(result = "ID")]
+    #[derive(Debug, Clone)]
+    pub struct IssueResumeToken {
+        pub user_id: ID,
+        pub connection_id: u128,
+    }
+    /// Restores the subscriptions of a disconnected connection given the token issued to it, permissions are checked again so hubs and channels the user lost access to are skipped.
+    /// Returns the restored channel and hub subscriptions.
+    #[message(result = "Result<(HashSet<(ID, ID)>, HashSet<ID>)>")]
+    #[derive(Debug, Clone)]
+    pub struct Resume {
+        pub user_id: ID,
+        pub token: ID,
+        pub connection_id: u128,
+    }
     /// Notifies other clients subscribed to the given channel that the given user has started typing.
     #[message(result = "Result")]
     #[derive(Debug, Clone)]
@@ -102,6 +139,8 @@ pub mod client_command {
 pub struct MessageSchemaFields {
     pub content: Field,
     pub id: Field,
+    pub sender: Field,
+    pub message_id: Field,
 }
 
 /// Message to tell the message server that there is a new message in a channel.
@@ -125,6 +164,25 @@ pub struct SearchMessageIndex {
     pub limit: usize,
     /// Query string.
     pub query: String,
+    /// If set only messages sent by this user are returned.
+    pub sender: Option<ID>,
+}
+
+/// Command for a [`MessageServer`] to remove deleted messages from the search index of a channel.
+#[message(result = "Result")]
+#[derive(Clone, Debug)]
+pub struct RemoveMessagesFromIndex {
+    pub hub_id: ID,
+    pub channel_id: ID,
+    pub ids: Vec<ID>,
+}
+
+/// Command for a [`MessageServer`] to load the search index of a channel ahead of the first search in it.
+#[message(result = "Result")]
+#[derive(Clone, Copy, Debug)]
+pub struct WarmIndex {
+    pub hub_id: ID,
+    pub channel_id: ID,
 }
 
 /// Message to notify the server of a change made externally, usually used so the server can notify clients.
@@ -132,19 +190,61 @@ pub struct SearchMessageIndex {
 #[derive(Debug, Clone)]
 pub enum ServerNotification {
     NewMessage(channel::Message),
-    HubUpdated(ID, WsHubUpdateType),
+    /// A message sent in a direct message channel along with the IDs of the participants.
+    NewDirectMessage(channel::Message, HashSet<ID>),
+    /// A message sent in a hub was edited, holds the edited message.
+    MessageEdited(channel::Message),
+    /// A message was deleted from a hub channel, holds the IDs of the hub, the channel and the message.
+    MessageDeleted(ID, ID, ID),
+    HubUpdated(ID, HubChange),
+    /// The block list of the given user changed.
+    BlocksUpdated(ID, BlockList),
+}
+
+/// Tells the [`Server`] to get an address to the [`MessageServer`] that handles the given channel, `None` if search is disabled.
+#[message(result = "Option<Addr<MessageServer>>")]
+#[derive(Clone, Copy)]
+pub struct GetMessageServer {
+    pub hub_id: ID,
+    pub channel_id: ID,
+}
+
+/// Background work that the [`Server`] runs periodically once it is started, see [`Server::set_scheduled_tasks`].
+#[message(result = "()")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScheduledTask {
+    /// Tells clients that users who started typing more than [`crate::TYPING_TIMEOUT`] ago stopped.
+    TypingExpiry,
+    /// Deletes the messages that have expired, see [`Server::sweep_expired`].
+    MessageExpiry,
+    /// Lifts the mutes and bans that have expired, see [`Server::expire_moderation`].
+    ModerationExpiry,
+    /// Drops the idempotency keys of hub creations that expired, see [`crate::httpapi::handlers::hub::expire_create_keys`].
+    CreateKeyExpiry,
 }
 
-/// Tells the [`Server`] to get an address to it's [`MessageServer`].
-#[message(result = "Addr<MessageServer>")]
+/// Gets how many times each [`ScheduledTask`] has run since the server started.
+#[message(result = "HashMap<ScheduledTask, u64>")]
 #[derive(Clone, Copy)]
-pub struct GetMessageServer;
+pub struct GetScheduledTaskRuns;
+
+/// Gets the number of open client connections.
+#[message(result = "usize")]
+#[derive(Clone, Copy)]
+pub struct GetConnectionCount;
+
+/// Gets the sizes of the server's in-memory state, see [`HttpServerState`].
+#[message(result = "HttpServerState")]
+#[derive(Clone, Copy)]
+pub struct GetServerState;
 
 lazy_static! {
     static ref MESSAGE_SCHEMA: Schema = {
         let mut schema_builder = Schema::builder();
         schema_builder.add_text_field("content", TEXT);
         schema_builder.add_bytes_field("id", STORED | FAST);
+        schema_builder.add_text_field("sender", STRING);
+        schema_builder.add_text_field("message_id", STRING);
         schema_builder.build()
     };
     static ref MESSAGE_SCHEMA_FIELDS: MessageSchemaFields = MessageSchemaFields {
@@ -154,6 +254,12 @@ lazy_static! {
         id: MESSAGE_SCHEMA
             .get_field("id")
             .expect("Failed to create a Tantivy schema correctly."),
+        sender: MESSAGE_SCHEMA
+            .get_field("sender")
+            .expect("Failed to create a Tantivy schema correctly."),
+        message_id: MESSAGE_SCHEMA
+            .get_field("message_id")
+            .expect("Failed to create a Tantivy schema correctly."),
     };
 }
 
@@ -162,47 +268,186 @@ pub fn add_message_to_writer(writer: &mut IndexWriter, message: channel::Message
     writer.add_document(doc!(
         MESSAGE_SCHEMA_FIELDS.id => bincode::serialize(&message.id)?,
         MESSAGE_SCHEMA_FIELDS.content => message.content,
+        MESSAGE_SCHEMA_FIELDS.sender => message.sender.to_string(),
+        MESSAGE_SCHEMA_FIELDS.message_id => message.id.to_string(),
     ));
     Ok(())
 }
 
-/// Logs the given message ID to a file, should be called after any Tantivy commits.
-async fn log_last_message(hub_id: ID, channel_id: ID, message_id: ID) -> Result {
-    let log_path_string = format!(
-        "{}/{:x}/{:x}/log",
+/// Gets the folder in which the search index and log of a channel are stored.
+fn index_folder(hub_id: ID, channel_id: ID) -> String {
+    format!(
+        "{}/{:x}/{:x}",
         crate::hub::HUB_DATA_FOLDER,
         hub_id.as_u128(),
         channel_id.as_u128()
-    );
-    tokio::fs::write(log_path_string, &message_id.as_u128().to_ne_bytes()).await?;
+    )
+}
+
+/// Logs the given message ID to a file, should be called after any Tantivy commits.
+async fn log_last_message(hub_id: ID, channel_id: ID, message_id: ID) -> Result {
+    let folder = index_folder(hub_id, channel_id);
+    tokio::fs::create_dir_all(&folder).await?;
+    tokio::fs::write(
+        format!("{}/log", folder),
+        &message_id.as_u128().to_ne_bytes(),
+    )
+    .await?;
     Ok(())
 }
 
 async fn log_if_nologs(hub_id: ID, channel_id: ID, message_id: ID) -> Result {
-    let mut file = tokio::fs::OpenOptions::new()
+    let folder = index_folder(hub_id, channel_id);
+    tokio::fs::create_dir_all(&folder).await?;
+    let mut file = match tokio::fs::OpenOptions::new()
         .write(true)
         .create_new(true)
-        .open(format!(
-            "{}/{:x}/{:x}/log",
-            crate::hub::HUB_DATA_FOLDER,
-            hub_id.as_u128(),
-            channel_id.as_u128()
-        ))
-        .await?;
+        .open(format!("{}/log", folder))
+        .await
+    {
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => return Ok(()),
+        result => result?,
+    };
     file.write(&message_id.as_u128().to_ne_bytes()).await?;
     Ok(())
 }
 
+/// Adds every message sent in a channel since the message logged by [`log_last_message`] to the channel's index and commits them, returns the ID of the last message added.
+/// Does nothing if there is no log for the channel.
+async fn catch_up(writer: &mut IndexWriter, hub_id: ID, channel_id: ID) -> Result<Option<ID>> {
+    let log_path_string = format!("{}/log", index_folder(hub_id, channel_id));
+    let log_path = std::path::Path::new(&log_path_string);
+    if !log_path.is_file() {
+        return Ok(None);
+    }
+    let mut buf: [u8; 16] = [0; 16];
+    tokio::fs::read(log_path)
+        .await?
+        .as_slice()
+        .read_exact(&mut buf)?;
+    let last_id = ID::from_u128(u128::from_le_bytes(buf));
+    let hub = Hub::load(hub_id).await?;
+    if let Some(channel) = hub.channels.get(&channel_id) {
+        let messages = channel.get_all_messages_from(last_id).await;
+        let last_id = messages.last().map(|last| last.id);
+        for message in messages {
+            add_message_to_writer(writer, message)?;
+        }
+        writer.commit()?;
+        if let Some(last_id) = last_id {
+            log_last_message(hub_id, channel_id, last_id).await?;
+        }
+        Ok(last_id)
+    } else {
+        Ok(None)
+    }
+}
+
+/// File where the time at which the next message expires in each channel is saved, see [`Server::sweep_expired`].
+const EXPIRING_FILE: &str = "data/expiring";
+
+/// Number of channels whose search index is being loaded on startup and number of those that have been loaded so far, see [`Server::warm_indexes`].
+static WARMUP_TOTAL: AtomicUsize = AtomicUsize::new(0);
+static WARMUP_DONE: AtomicUsize = AtomicUsize::new(0);
+
+/// Gets the number of channels whose search index has been loaded on startup and the number of channels that are to be loaded in total.
+pub fn warmup_progress() -> (usize, usize) {
+    (
+        WARMUP_DONE.load(Ordering::SeqCst),
+        WARMUP_TOTAL.load(Ordering::SeqCst),
+    )
+}
+
+/// Number of messages that were not sent to a [`MessageServer`] to be indexed straight away because its queue was full.
+static DEFERRED_INDEXING: AtomicU64 = AtomicU64::new(0);
+
+/// Gets the number of messages whose indexing has been deferred since the server started, see [`IndexQueue`].
+pub fn deferred_indexing() -> u64 {
+    DEFERRED_INDEXING.load(Ordering::SeqCst)
+}
+
+/// Tracks the [`NewMessageForIndex`] commands waiting to be handled by a [`MessageServer`].
+/// Once the queue is full new messages are not sent to the message server, their channel is marked as deferred instead and the
+/// messages are indexed from the channel's files the next time it is indexed or searched.
+#[derive(Default)]
+pub struct IndexQueue {
+    queued: AtomicUsize,
+    peak: AtomicUsize,
+    deferred: std::sync::Mutex<HashSet<(ID, ID)>>,
+}
+
+impl IndexQueue {
+    /// Takes a place in the queue for a message sent in the given channel, if the queue already holds `limit` messages the channel is marked as deferred and `false` is returned.
+    pub fn try_push(&self, hub_id: ID, channel_id: ID, limit: usize) -> bool {
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+        if queued >= limit {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            self.deferred
+                .lock()
+                .expect("index queue lock poisoned")
+                .insert((hub_id, channel_id));
+            DEFERRED_INDEXING.fetch_add(1, Ordering::SeqCst);
+            false
+        } else {
+            self.peak.fetch_max(queued + 1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    /// Frees a place in the queue, does nothing if it is empty.
+    pub fn pop(&self) {
+        let _ = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                queued.checked_sub(1)
+            });
+    }
+
+    /// Unmarks the given channel as deferred, returns whether or not it was.
+    pub fn take_deferred(&self, hub_id: ID, channel_id: ID) -> bool {
+        self.deferred
+            .lock()
+            .expect("index queue lock poisoned")
+            .remove(&(hub_id, channel_id))
+    }
+
+    /// Gets the number of messages currently in the queue.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Gets the number of channels whose indexing is currently deferred.
+    pub fn deferred_channels(&self) -> usize {
+        self.deferred
+            .lock()
+            .expect("index queue lock poisoned")
+            .len()
+    }
+
+    /// Gets the largest number of messages the queue has held.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
 pub type IndexMap = HashMap<(ID, ID), Index>;
 pub type IndexWriterMap = HashMap<(ID, ID), IndexWriter>;
 pub type IndexReaderMap = HashMap<(ID, ID), IndexReader>;
 pub type PendingMessageMap = HashMap<(ID, ID), (u8, ID)>;
 
+/// Gets the index of the [`MessageServer`] shard that handles the given channel when there are `shards` message servers.
+pub fn message_server_shard(hub_id: ID, channel_id: ID, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    (hub_id, channel_id).hash(&mut hasher);
+    (hasher.finish() % shards.max(1) as u64) as usize
+}
+
 pub struct MessageServer {
     indexes: IndexMap,
     index_writers: IndexWriterMap,
     index_readers: IndexReaderMap,
     pending_messages: PendingMessageMap,
+    queue: Arc<IndexQueue>,
 }
 
 impl MessageServer {
@@ -212,62 +457,68 @@ impl MessageServer {
             index_writers: HashMap::new(),
             index_readers: HashMap::new(),
             pending_messages: HashMap::new(),
+            queue: Arc::new(IndexQueue::default()),
+        }
+    }
+
+    /// Gets the queue of messages waiting to be indexed by this message server.
+    pub fn queue(&self) -> Arc<IndexQueue> {
+        Arc::clone(&self.queue)
+    }
+
+    /// Loads the search index of a channel if it has not been loaded yet, catching up on messages sent since it was last committed.
+    async fn warm_index(&mut self, hub_id: ID, channel_id: ID) -> Result {
+        self.get_reader(hub_id, channel_id).await?;
+        Ok(())
+    }
+
+    /// Runs [`catch_up`] for a channel if it was marked as deferred in the queue, returns whether or not it was.
+    async fn catch_up_deferred(&mut self, hub_id: ID, channel_id: ID) -> Result<bool> {
+        if !self.queue.take_deferred(hub_id, channel_id) {
+            return Ok(false);
+        }
+        let writer = self.get_writer(hub_id, channel_id).await?;
+        if let Some(last_id) = catch_up(writer, hub_id, channel_id).await? {
+            self.pending_messages
+                .insert((hub_id, channel_id), (0, last_id));
         }
+        Ok(true)
     }
 
     /// Sets up the Tantivy index for a given channel, also makes sure that the index is up to date by commiting any messages sent after the last message sent (logged by [`log_last_message`]).
+    /// Indexes created with an older schema are deleted and rebuilt from the channel's messages.
     async fn setup_index(&mut self, hub_id: ID, channel_id: ID) -> Result {
-        let dir_string = format!(
-            "{}/{:x}/{:x}/index",
-            crate::hub::HUB_DATA_FOLDER,
-            hub_id.as_u128(),
-            channel_id.as_u128()
-        );
+        let dir_string = format!("{}/index", index_folder(hub_id, channel_id));
         let dir_path = std::path::Path::new(&dir_string);
         if !dir_path.is_dir() {
             tokio::fs::create_dir_all(dir_path).await?;
         }
         let dir = MmapDirectory::open(dir_path)?;
-        let index = Index::open_or_create(dir, MESSAGE_SCHEMA.clone())?;
+        let index = match Index::open_or_create(dir, MESSAGE_SCHEMA.clone()) {
+            Err(TantivyError::SchemaError(_)) => {
+                tokio::fs::remove_dir_all(dir_path).await?;
+                tokio::fs::create_dir_all(dir_path).await?;
+                // Catching up from the first message reindexes the whole channel.
+                if let Some(first) = channel::Channel::new(String::new(), channel_id, hub_id)
+                    .get_messages_page(0, None, 1)
+                    .await
+                    .messages
+                    .first()
+                {
+                    log_last_message(hub_id, channel_id, first.id).await?;
+                }
+                Index::open_or_create(MmapDirectory::open(dir_path)?, MESSAGE_SCHEMA.clone())?
+            }
+            result => result?,
+        };
         let reader = index
             .reader_builder()
             .reload_policy(ReloadPolicy::OnCommit)
             .try_into()?;
         let mut writer = index.writer(50_000_000)?;
         let key = (hub_id, channel_id);
-        let log_path_string = format!(
-            "{}/{:x}/{:x}/log",
-            crate::hub::HUB_DATA_FOLDER,
-            hub_id.as_u128(),
-            channel_id.as_u128()
-        );
-        let log_path = std::path::Path::new(&log_path_string);
-        if log_path.is_file() {
-            let mut buf: [u8; 16] = [0; 16];
-            tokio::fs::read(log_path)
-                .await?
-                .as_slice()
-                .read_exact(&mut buf)?;
-            let last_id = ID::from_u128(u128::from_le_bytes(buf));
-            let filename = format!("{}{:x}.json", crate::hub::HUB_INFO_FOLDER, hub_id.as_u128());
-            let path = std::path::Path::new(&filename);
-            if !path.exists() {
-                return Err(ApiError::HubNotFound.into());
-            }
-            let json = tokio::fs::read_to_string(path).await?;
-            let hub = serde_json::from_str::<Hub>(&json)?;
-            if let Some(channel) = hub.channels.get(&channel_id) {
-                let messages = channel.get_all_messages_from(last_id).await;
-                let last_id = messages.last().map(|last| last.id);
-                for message in messages {
-                    add_message_to_writer(&mut writer, message)?;
-                }
-                writer.commit()?;
-                if let Some(last_id) = last_id {
-                    log_last_message(hub_id, channel_id, last_id).await?;
-                }
-                reader.reload()?;
-            }
+        if catch_up(&mut writer, hub_id, channel_id).await?.is_some() {
+            reader.reload()?;
         }
         self.indexes.insert(key, index);
         self.index_readers.insert(key, reader);
@@ -334,6 +585,7 @@ impl Handler<SearchMessageIndex> for MessageServer {
         _ctx: &mut Context<Self>,
         msg: SearchMessageIndex,
     ) -> Result<Vec<ID>> {
+        self.catch_up_deferred(msg.hub_id, msg.channel_id).await?;
         {
             let pending = {
                 self.pending_messages
@@ -353,7 +605,17 @@ impl Handler<SearchMessageIndex> for MessageServer {
         let searcher = self.get_searcher(msg.hub_id, msg.channel_id).await?;
         let query_parser =
             QueryParser::for_index(searcher.index(), vec![MESSAGE_SCHEMA_FIELDS.content]);
-        let query = query_parser.parse_query(&msg.query)?;
+        let mut query = query_parser.parse_query(&msg.query)?;
+        if let Some(sender) = msg.sender {
+            let sender_query: Box<dyn Query> = Box::new(TermQuery::new(
+                Term::from_field_text(MESSAGE_SCHEMA_FIELDS.sender, &sender.to_string()),
+                IndexRecordOption::Basic,
+            ));
+            query = Box::new(BooleanQuery::from(vec![
+                (Occur::Must, query),
+                (Occur::Must, sender_query),
+            ]));
+        }
         let top_docs = searcher.search(&query, &TopDocs::with_limit(msg.limit))?;
         let mut result = Vec::new();
         for (_score, doc_address) in top_docs {
@@ -361,7 +623,10 @@ impl Handler<SearchMessageIndex> for MessageServer {
             if let Some(value) = retrieved_doc.get_first(MESSAGE_SCHEMA_FIELDS.id) {
                 if let Some(bytes) = value.bytes_value() {
                     if let Ok(id) = bincode::deserialize::<ID>(bytes) {
-                        result.push(id);
+                        // Messages can be indexed twice when catching up.
+                        if !result.contains(&id) {
+                            result.push(id);
+                        }
                     }
                 }
             }
@@ -370,30 +635,62 @@ impl Handler<SearchMessageIndex> for MessageServer {
     }
 }
 
+#[async_trait]
+impl Handler<RemoveMessagesFromIndex> for MessageServer {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: RemoveMessagesFromIndex) -> Result {
+        let writer = self.get_writer(msg.hub_id, msg.channel_id).await?;
+        for id in msg.ids {
+            writer.delete_term(Term::from_field_text(
+                MESSAGE_SCHEMA_FIELDS.message_id,
+                &id.to_string(),
+            ));
+        }
+        writer.commit()?;
+        if let Some((pending, last_id)) =
+            self.pending_messages.get_mut(&(msg.hub_id, msg.channel_id))
+        {
+            if *pending != 0 {
+                log_last_message(msg.hub_id, msg.channel_id, *last_id).await?;
+                *pending = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Handler<WarmIndex> for MessageServer {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: WarmIndex) -> Result {
+        let result = self.warm_index(msg.hub_id, msg.channel_id).await;
+        WARMUP_DONE.fetch_add(1, Ordering::SeqCst);
+        result
+    }
+}
+
 #[async_trait]
 impl Handler<NewMessageForIndex> for MessageServer {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: NewMessageForIndex) -> Result {
-        let mut new_pending: u8;
+        self.queue.pop();
+        // The message has already been written to the channel, so catching up indexes it along with the deferred ones.
+        if self.catch_up_deferred(msg.hub_id, msg.channel_id).await? {
+            return Ok(());
+        }
         let message_id = msg.message.id;
-        if let Some((pending, _)) = self
+        let pending = self
             .pending_messages
             .get(&(msg.hub_id, msg.channel_id))
-            .cloned()
-        {
-            new_pending = pending + 1;
-            if pending >= crate::TANTIVY_COMMIT_THRESHOLD {
-                let writer = self.get_writer(msg.hub_id, msg.channel_id).await?;
-                add_message_to_writer(writer, msg.message)?;
-                writer.commit()?;
-                log_last_message(msg.hub_id, msg.channel_id, message_id).await?;
-                new_pending = 0;
-            } else {
-                log_if_nologs(msg.hub_id, msg.channel_id, message_id).await?;
-            }
+            .map_or(0, |(pending, _)| *pending)
+            + 1;
+        let writer = self.get_writer(msg.hub_id, msg.channel_id).await?;
+        add_message_to_writer(writer, msg.message)?;
+        let new_pending = if pending >= crate::TANTIVY_COMMIT_THRESHOLD {
+            writer.commit()?;
+            log_last_message(msg.hub_id, msg.channel_id, message_id).await?;
+            0
         } else {
-            new_pending = 1;
             log_if_nologs(msg.hub_id, msg.channel_id, message_id).await?;
-        }
+            pending
+        };
         let _ = self
             .pending_messages
             .insert((msg.hub_id, msg.channel_id), (new_pending, message_id));
@@ -404,8 +701,429 @@ impl Handler<NewMessageForIndex> for MessageServer {
 pub type SubscribedChannelMap = Arc<RwLock<HashMap<(ID, ID), Arc<RwLock<HashSet<u128>>>>>>;
 pub type SubscribedHubMap = Arc<RwLock<HashMap<ID, Arc<RwLock<HashSet<u128>>>>>>;
 pub type SubscribedMap = Arc<RwLock<HashMap<u128, Arc<RwLock<(HashSet<(ID, ID)>, HashSet<ID>)>>>>>;
-pub type ConnectedMap =
-    Arc<RwLock<HashMap<u128, Arc<Mutex<SplitSink<WebSocket, WebSocketMessage>>>>>>;
+pub type ConnectedMap = Arc<RwLock<HashMap<u128, ClientConnection>>>;
+
+/// Subscriptions of a disconnected connection kept so that they can be restored with [`client_command::Resume`].
+#[derive(Clone, Debug)]
+pub struct ResumeState {
+    /// ID of the user that the connection was authenticated as.
+    pub user_id: ID,
+    /// Channel subscriptions as `(hub_id, channel_id)` pairs.
+    pub channels: HashSet<(ID, ID)>,
+    /// Hub subscriptions.
+    pub hubs: HashSet<ID>,
+    /// Time after which the state can no longer be resumed.
+    pub expires: DateTime<Utc>,
+}
+
+/// Sending half of a websocket client connection along with the options the client connected with.
+#[derive(Clone, Debug)]
+pub struct ClientConnection {
+    /// ID of the user that the connection is authenticated as.
+    pub user_id: ID,
+    /// Sink used to send frames to the client.
+    pub writer: Arc<Mutex<SplitSink<WebSocket, WebSocketMessage>>>,
+    /// Frames waiting to be written to [`ClientConnection::writer`], see [`SendQueue`].
+    pub queue: Arc<SendQueue>,
+    /// Whether or not frames bigger than [`crate::WS_COMPRESSION_THRESHOLD`] should be compressed.
+    pub compress: bool,
+    /// Users whose messages should not be delivered to the connection.
+    pub blocks: BlockList,
+}
+
+/// What happens when a frame is sent to a connection whose [`SendQueue`] is full.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued frame to make room for the new one.
+    DropOldest,
+    /// Drop the new frame.
+    DropNewest,
+    /// Drop every queued frame and close the connection.
+    Disconnect,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        Self::DropOldest
+    }
+}
+
+/// Bounded queue of the frames being sent to a websocket connection, drained into the socket by a dedicated task so that a slow client does not hold up sending to other clients.
+#[derive(Debug)]
+pub struct SendQueue {
+    frames: std::sync::Mutex<VecDeque<WebSocketMessage>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    closed: AtomicBool,
+    notify: Notify,
+}
+
+impl SendQueue {
+    /// Creates an empty queue that holds up to `capacity` frames (at least one) and applies `policy` once it is full.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            frames: std::sync::Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            policy,
+            closed: AtomicBool::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Queues a frame, applying the queue's [`OverflowPolicy`] if it is full, returns `false` if the queue is closed.
+    pub fn push(&self, frame: WebSocketMessage) -> bool {
+        if self.is_closed() {
+            return false;
+        }
+        {
+            let mut frames = self.frames.lock().expect("send queue lock poisoned");
+            if frames.len() >= self.capacity {
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        frames.pop_front();
+                    }
+                    OverflowPolicy::DropNewest => return true,
+                    OverflowPolicy::Disconnect => {
+                        frames.clear();
+                        drop(frames);
+                        self.close();
+                        return false;
+                    }
+                }
+            }
+            frames.push_back(frame);
+        }
+        self.notify.notify_one();
+        true
+    }
+
+    /// Waits for the next frame, returns `None` once the queue is closed.
+    pub async fn pop(&self) -> Option<WebSocketMessage> {
+        loop {
+            if self.is_closed() {
+                return None;
+            }
+            if let Some(frame) = self
+                .frames
+                .lock()
+                .expect("send queue lock poisoned")
+                .pop_front()
+            {
+                return Some(frame);
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Closes the queue, frames that are still queued are dropped.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    /// Checks if the queue was closed, either by [`SendQueue::close`] or by overflowing with [`OverflowPolicy::Disconnect`].
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Gets the number of frames waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.frames.lock().expect("send queue lock poisoned").len()
+    }
+
+    /// Checks if there are no frames waiting to be sent.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Writes the frames of a [`SendQueue`] to a websocket until the queue is closed or writing fails, then closes the websocket.
+async fn drain_send_queue(
+    queue: Arc<SendQueue>,
+    writer: Arc<Mutex<SplitSink<WebSocket, WebSocketMessage>>>,
+) {
+    while let Some(frame) = queue.pop().await {
+        if writer.lock().await.send(frame).await.is_err() {
+            queue.close();
+            break;
+        }
+    }
+    let _ = writer.lock().await.close().await;
+}
+
+lazy_static! {
+    /// Batcher used by [`channel::Channel::write_message`], disabled until configured with [`Server::set_write_batching`].
+    static ref WRITE_BATCHER: WriteBatcher = WriteBatcher::new(0, crate::WRITE_BATCH_SIZE);
+}
+
+/// In-memory cache of the most recently used hubs so that they do not have to be read from disk every time they are loaded, see [`Hub::load_cached`].
+/// Hubs are cached when they are loaded or saved and removed from the cache when they are deleted.
+#[derive(Clone)]
+pub struct HubCache {
+    hubs: Arc<RwLock<LruCache<ID, Arc<RwLock<Hub>>>>>,
+    /// Locks held while a hub is written to disk and its cached copy is updated, so that the cached copy is always the one written last.
+    /// There is one per hub so that saving a hub does not wait for other hubs to be written.
+    saving: Arc<std::sync::Mutex<HashMap<ID, Arc<Mutex<()>>>>>,
+    /// Incremented whenever a hub is saved or deleted, a hub read from disk is only cached if this did not change while it was being read.
+    generation: Arc<AtomicU64>,
+}
+
+impl HubCache {
+    /// Creates an empty cache that holds at most `capacity` hubs, nothing is cached if it is 0.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            hubs: Arc::new(RwLock::new(LruCache::new(capacity))),
+            saving: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Changes the number of hubs that can be cached, dropping the least recently used ones if there are too many.
+    pub async fn resize(&self, capacity: usize) {
+        self.hubs.write().await.resize(capacity);
+    }
+
+    /// Checks if a hub is cached without marking it as recently used.
+    pub async fn contains(&self, id: &ID) -> bool {
+        self.hubs.read().await.contains(id)
+    }
+
+    /// Gets a copy of a cached hub, marking it as the most recently used.
+    pub async fn get(&self, id: &ID) -> Option<Hub> {
+        let cached = self.hubs.write().await.get(id).cloned()?;
+        let hub = cached.read().await.clone();
+        Some(hub)
+    }
+
+    /// Gets the IDs of the cached hubs for which `filter` returns true, without marking them as recently used.
+    pub async fn find<F>(&self, filter: F) -> Vec<ID>
+    where
+        F: Fn(&Hub) -> bool,
+    {
+        let cached = self
+            .hubs
+            .read()
+            .await
+            .iter()
+            .map(|(id, hub)| (*id, Arc::clone(hub)))
+            .collect::<Vec<_>>();
+        let mut found = Vec::new();
+        for (id, hub) in cached {
+            if filter(&*hub.read().await) {
+                found.push(id);
+            }
+        }
+        found
+    }
+
+    /// Gets the number of times hubs have been saved or deleted, to be given to [`HubCache::insert_loaded`] along with a hub read from disk after calling this.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Caches a hub that was read from disk if it is not cached yet, unless a hub was saved or deleted since `generation` was gotten since the hub read may be outdated.
+    pub async fn insert_loaded(&self, hub: &Hub, generation: u64) {
+        let mut hubs = self.hubs.write().await;
+        if self.generation() == generation && !hubs.contains(&hub.id) {
+            hubs.put(hub.id, Arc::new(RwLock::new(hub.clone())));
+        }
+    }
+
+    /// Writes a hub to disk and updates its cached copy, caching it if it was not already.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the hub could not be written for any of the reasons outlined by [`Hub::write`].
+    pub async fn save(&self, hub: &Hub) -> Result {
+        let lock = self.save_lock(hub.id);
+        let _saving = lock.lock().await;
+        hub.write().await?;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        let mut hubs = self.hubs.write().await;
+        if let Some(cached) = hubs.get(&hub.id) {
+            *cached.write().await = hub.clone();
+        } else {
+            hubs.put(hub.id, Arc::new(RwLock::new(hub.clone())));
+        }
+        Ok(())
+    }
+
+    /// Removes a hub from the cache, called when the hub is deleted.
+    pub async fn remove(&self, id: &ID) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.hubs.write().await.pop(id);
+        self.saving
+            .lock()
+            .expect("hub save lock map poisoned")
+            .remove(id);
+    }
+
+    /// Gets the lock held while the given hub is saved, see [`HubCache::save`].
+    /// This is not [`Hub::lock`] since hubs are saved while that is held for reading.
+    fn save_lock(&self, id: ID) -> Arc<Mutex<()>> {
+        Arc::clone(
+            self.saving
+                .lock()
+                .expect("hub save lock map poisoned")
+                .entry(id)
+                .or_default(),
+        )
+    }
+
+    /// Reloads the cached copy of a hub from disk if it is older than the change with the given sequence number, does nothing if the hub is not cached.
+    pub async fn refresh(&self, id: ID, sequence: u64) {
+        let cached = self.hubs.read().await.peek(&id).cloned();
+        if let Some(cached) = cached {
+            if cached.read().await.last_sequence >= sequence {
+                return;
+            }
+            let lock = self.save_lock(id);
+            let _saving = lock.lock().await;
+            if let Ok(hub) = Hub::load(id).await {
+                *cached.write().await = hub;
+            }
+        }
+    }
+}
+
+/// Messages waiting to be written to a channel by a [`WriteBatcher`], along with the senders waiting for them to be written.
+/// Each batch is only written once, messages that find a batch that has already been written start a new one.
+struct PendingWrites {
+    channel: channel::Channel,
+    messages: Vec<channel::Message>,
+    waiting: Vec<oneshot::Sender<ApiResult>>,
+    written: bool,
+}
+
+/// Coalesces messages sent to the same channel within a short time into a single append to the channel's message files, see [`channel::Channel::add_messages`].
+/// A batch is written once it has `size` messages or `interval` milliseconds after its first message was added, whichever comes first.
+/// Messages are written in the order they were added and [`WriteBatcher::write`] only returns once its message has been written.
+#[derive(Clone)]
+pub struct WriteBatcher {
+    pending: Arc<std::sync::Mutex<HashMap<ID, Arc<Mutex<PendingWrites>>>>>,
+    /// Milliseconds to wait for more messages before writing a batch, messages are written one at a time if it is 0.
+    interval: Arc<AtomicU64>,
+    size: Arc<AtomicUsize>,
+    /// Number of times messages were written to a channel, once per batch.
+    writes: Arc<AtomicU64>,
+}
+
+impl WriteBatcher {
+    /// Creates a batcher that writes batches of up to `size` messages, batching is disabled if `interval` is 0 or `size` is less than 2.
+    pub fn new(interval: u64, size: usize) -> Self {
+        Self {
+            pending: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            interval: Arc::new(AtomicU64::new(interval)),
+            size: Arc::new(AtomicUsize::new(size)),
+            writes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Gets the batcher used by [`channel::Channel::write_message`], shared by every [`Server`] since they all use the same message files.
+    pub fn shared() -> Self {
+        WRITE_BATCHER.clone()
+    }
+
+    /// Changes how long messages are collected for and how many can be written at once, applies to batches started after it is called.
+    pub fn configure(&self, interval: u64, size: usize) {
+        self.interval.store(interval, Ordering::SeqCst);
+        self.size.store(size, Ordering::SeqCst);
+    }
+
+    /// Gets the number of times messages were written to a channel, a batch counts as one write.
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    /// Writes a message to its channel, waiting for the batch it was added to to be written if batching is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Possible errors outlined by [`channel::Channel::add_messages`], every message in a batch fails if the batch could not be written.
+    pub async fn write(&self, message: &channel::Message) -> Result {
+        let channel = channel::Channel::new(String::new(), message.channel_id, message.hub_id);
+        let interval = self.interval.load(Ordering::SeqCst);
+        let size = self.size.load(Ordering::SeqCst);
+        if interval == 0 || size < 2 {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            return channel.add_message(message).await;
+        }
+        let (sender, receiver) = oneshot::channel();
+        loop {
+            let pending = Arc::clone(
+                self.pending
+                    .lock()
+                    .expect("pending write map poisoned")
+                    .entry(channel.id)
+                    .or_insert_with(|| {
+                        Arc::new(Mutex::new(PendingWrites {
+                            channel: channel.clone(),
+                            messages: Vec::new(),
+                            waiting: Vec::new(),
+                            written: false,
+                        }))
+                    }),
+            );
+            let mut batch = pending.lock().await;
+            if batch.written {
+                continue;
+            }
+            batch.messages.push(message.clone());
+            batch.waiting.push(sender);
+            if batch.messages.len() >= size {
+                self.flush(&pending, &mut batch).await;
+            } else if batch.messages.len() == 1 {
+                let batcher = self.clone();
+                let pending = Arc::clone(&pending);
+                tokio::spawn(async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(interval)).await;
+                    let mut batch = pending.lock().await;
+                    if !batch.written {
+                        batcher.flush(&pending, &mut batch).await;
+                    }
+                });
+            }
+            break;
+        }
+        receiver
+            .await
+            .unwrap_or(Err(ApiError::InternalError))
+            .map_err(Error::from)
+    }
+
+    /// Writes the messages of a batch and tells the senders waiting for them whether or not they were written.
+    /// The batch is then removed from the pending batches so that the next message sent to the channel starts a new one.
+    async fn flush(&self, pending: &Arc<Mutex<PendingWrites>>, batch: &mut PendingWrites) {
+        let result = batch
+            .channel
+            .add_messages(&batch.messages)
+            .await
+            .map_err(|error| {
+                let api_error = ApiError::from(&error);
+                if api_error == ApiError::InternalError {
+                    error!(
+                        "Failed to write {} messages: {}",
+                        batch.messages.len(),
+                        error
+                    );
+                }
+                api_error
+            });
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        batch.messages.clear();
+        for sender in batch.waiting.drain(..) {
+            let _ = sender.send(result.clone());
+        }
+        batch.written = true;
+        let mut batches = self.pending.lock().expect("pending write map poisoned");
+        if batches
+            .get(&batch.channel.id)
+            .map_or(false, |current| Arc::ptr_eq(current, pending))
+        {
+            batches.remove(&batch.channel.id);
+        }
+    }
+}
 
 /// Server that handles socket clients and manages notifying them of new messages/changes as well as sending messages to be indexed by Tantivy.
 pub struct Server {
@@ -413,67 +1131,628 @@ pub struct Server {
     subscribed_hubs: SubscribedHubMap,
     subscribed: SubscribedMap,
     connected: ConnectedMap,
-    message_server: Addr<MessageServer>,
+    message_servers: Vec<Addr<MessageServer>>,
+    index_queues: Vec<Arc<IndexQueue>>,
+    index_queue_limit: usize,
+    send_queue_size: usize,
+    overflow_policy: OverflowPolicy,
+    notification_sinks: Vec<Arc<dyn NotificationSink>>,
+    resume_tokens: HashMap<u128, (ID, ID)>,
+    resumable: HashMap<ID, ResumeState>,
+    expiring: HashMap<(ID, ID), DateTime<Utc>>,
+    typing: HashMap<(ID, ID, ID), DateTime<Utc>>,
+    scheduled_tasks: ScheduledTasks,
+    task_runs: HashMap<ScheduledTask, u64>,
+    hub_cache: HubCache,
+    write_batcher: WriteBatcher,
 }
 
 impl Server {
-    /// Creates a new server with default options, also creates a [`MessageServer`] with the given `commit_threshold` (how many messages should be added to the search index before commiting to the index).
+    /// Creates a new server with default options, also creates a single [`MessageServer`].
     pub async fn new() -> Result<Self> {
+        Self::with_message_servers(1).await
+    }
+
+    /// Creates a new server with default options and `shards` [`MessageServer`]s, channels are split between them by [`message_server_shard`] so that indexing and searches in different channels can run in parallel.
+    /// No message servers are started if `shards` is 0, messages are then not indexed and searches fail with [`ApiError::SearchDisabled`].
+    pub async fn with_message_servers(shards: usize) -> Result<Self> {
+        let mut message_servers = Vec::new();
+        let mut index_queues = Vec::new();
+        for _ in 0..shards {
+            let message_server = MessageServer::new();
+            index_queues.push(message_server.queue());
+            message_servers.push(
+                message_server
+                    .start()
+                    .await
+                    .map_err(|_| Error::ServerStartFailed)?,
+            );
+        }
         Ok(Self {
             subscribed_channels: Arc::new(RwLock::new(HashMap::new())),
             subscribed_hubs: Arc::new(RwLock::new(HashMap::new())),
             subscribed: Arc::new(RwLock::new(HashMap::new())),
             connected: Arc::new(RwLock::new(HashMap::new())),
-            message_server: MessageServer::new()
-                .start()
-                .await
-                .map_err(|_| Error::ServerStartFailed)?,
+            message_servers,
+            index_queues,
+            index_queue_limit: crate::MAX_INDEX_QUEUE,
+            send_queue_size: crate::WS_SEND_QUEUE_SIZE,
+            overflow_policy: OverflowPolicy::default(),
+            notification_sinks: Vec::new(),
+            resume_tokens: HashMap::new(),
+            resumable: HashMap::new(),
+            expiring: match tokio::fs::read(EXPIRING_FILE).await {
+                Ok(bytes) => bincode::deserialize(&bytes).unwrap_or_default(),
+                Err(_) => HashMap::new(),
+            },
+            typing: HashMap::new(),
+            scheduled_tasks: ScheduledTasks::default(),
+            task_runs: HashMap::new(),
+            hub_cache: HubCache::new(crate::HUB_CACHE_SIZE),
+            write_batcher: WriteBatcher::shared(),
         })
     }
 
-    /// Sends a [`ServreMessage`] to all clients subscribed to notifications for the given hub.
-    async fn send_hub(&self, message: WsServerMessage, hub_id: &ID) -> Result {
-        if let Some(subscribed_arc) = self.subscribed_hubs.read().await.get(hub_id) {
-            let message = WebSocketMessage::text(serde_json::to_string(&message)?);
-            for connection_id in subscribed_arc.read().await.iter() {
-                if let Some(connection) = self.connected.read().await.get(connection_id) {
-                    let _ = connection.lock().await.send(message.clone()).await;
+    /// Counts the hubs, channels, members and messages on the server, does not count connections, deferred indexing or warmup progress.
+    pub async fn count_totals() -> HttpServerStats {
+        let mut stats = HttpServerStats {
+            hubs: 0,
+            channels: 0,
+            members: 0,
+            messages: 0,
+            connections: 0,
+            deferred_indexing: 0,
+            corrupt_records: 0,
+            warmup_done: 0,
+            warmup_total: 0,
+            counted: Utc::now(),
+        };
+        for id in Hub::list_ids().await {
+            if let Ok(hub) = Hub::load(id).await {
+                stats.hubs += 1;
+                stats.channels += hub.channels.len();
+                stats.members += hub.members.len();
+                for channel in hub.channels.values() {
+                    stats.messages += channel.count_messages().await;
                 }
             }
         }
-        Ok(())
+        stats
     }
 
-    /// Sends a [`ServreMessage`] to all clients subscribed to notifications for the given channel.
-    async fn send_channel(&self, message: WsServerMessage, hub_id: ID, channel_id: ID) -> Result {
-        if let Some(subscribed_arc) = self
-            .subscribed_channels
-            .read()
-            .await
-            .get(&(hub_id, channel_id))
+    /// Gets the [`MessageServer`] that handles the given channel, `None` if search is disabled.
+    fn message_server(&self, hub_id: ID, channel_id: ID) -> Option<&Addr<MessageServer>> {
+        self.message_servers.get(message_server_shard(
+            hub_id,
+            channel_id,
+            self.message_servers.len(),
+        ))
+    }
+
+    /// Sends a message to be indexed by the [`MessageServer`] that handles its channel without waiting for it to be indexed, unless too many messages are already waiting.
+    fn index_message(&self, message: &channel::Message) {
+        if self.message_servers.is_empty() {
+            return;
+        }
+        let shard = message_server_shard(
+            message.hub_id,
+            message.channel_id,
+            self.message_servers.len(),
+        );
+        let queue = &self.index_queues[shard];
+        if queue.try_push(message.hub_id, message.channel_id, self.index_queue_limit)
+            && self.message_servers[shard]
+                .send(NewMessageForIndex {
+                    hub_id: message.hub_id,
+                    channel_id: message.channel_id,
+                    message: message.clone(),
+                })
+                .is_err()
         {
-            let message = WebSocketMessage::text(serde_json::to_string(&message)?);
-            for connection_id in subscribed_arc.read().await.iter() {
-                if let Some(connection) = self.connected.read().await.get(connection_id) {
-                    let _ = connection.lock().await.send(message.clone()).await;
+            queue.pop();
+        }
+    }
+
+    /// Loads the search indexes of the given channels in the background so that the first search in them is fast, progress can be checked with [`warmup_progress`].
+    /// Does nothing if search is disabled.
+    pub fn warm_indexes(&self, channels: &[(ID, ID)]) {
+        for (hub_id, channel_id) in channels {
+            if let Some(message_server) = self.message_server(*hub_id, *channel_id) {
+                WARMUP_TOTAL.fetch_add(1, Ordering::SeqCst);
+                if message_server
+                    .send(WarmIndex {
+                        hub_id: *hub_id,
+                        channel_id: *channel_id,
+                    })
+                    .is_err()
+                {
+                    WARMUP_DONE.fetch_add(1, Ordering::SeqCst);
                 }
             }
         }
-        Ok(())
     }
-}
 
-impl Actor for Server {}
+    /// Sets how many messages can wait to be indexed by each [`MessageServer`] before indexing is deferred, see [`IndexQueue`].
+    pub fn set_index_queue_limit(&mut self, limit: usize) {
+        self.index_queue_limit = limit;
+    }
+
+    /// Sets how many frames can wait to be sent to each websocket connection and what happens when a connection's queue is full, only affects new connections, see [`SendQueue`].
+    pub fn set_send_queue(&mut self, size: usize, policy: OverflowPolicy) {
+        self.send_queue_size = size;
+        self.overflow_policy = policy;
+    }
+
+    /// Remembers when a message expires so that it is deleted by [`Server::sweep_expired`], does nothing for messages that do not expire.
+    async fn track_expiry(&mut self, message: &channel::Message) -> Result {
+        if let Some(expires_at) = message.expires_at {
+            let next = self
+                .expiring
+                .entry((message.hub_id, message.channel_id))
+                .or_insert(expires_at);
+            *next = (*next).min(expires_at);
+            tokio::fs::write(EXPIRING_FILE, bincode::serialize(&self.expiring)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the messages that expired before `now` from their channels and search indexes, notifying subscribed clients with [`WsServerMessage::MessageExpired`].
+    /// Only channels that have a message tracked by [`Server::track_expiry`] that has expired are read.
+    async fn sweep_expired(&mut self, now: DateTime<Utc>) -> Result {
+        let due = self
+            .expiring
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(key, _)| *key)
+            .collect::<Vec<(ID, ID)>>();
+        if due.is_empty() {
+            return Ok(());
+        }
+        for (hub_id, channel_id) in due {
+            let mut next: Option<DateTime<Utc>> = None;
+            let removed = channel::Channel::new(String::new(), channel_id, hub_id)
+                .remove_messages(|message| match message.expires_at {
+                    Some(expires_at) if expires_at <= now => true,
+                    Some(expires_at) => {
+                        next = Some(next.map_or(expires_at, |next| next.min(expires_at)));
+                        false
+                    }
+                    None => false,
+                })
+                .await;
+            match next {
+                Some(next) => self.expiring.insert((hub_id, channel_id), next),
+                None => self.expiring.remove(&(hub_id, channel_id)),
+            };
+            // The channel is gone along with its messages if its folder could not be read.
+            let removed = if let Ok(removed) = removed {
+                removed
+            } else {
+                continue;
+            };
+            if removed.is_empty() {
+                continue;
+            }
+            if let Some(message_server) = self.message_server(hub_id, channel_id) {
+                let _ = message_server.send(RemoveMessagesFromIndex {
+                    hub_id,
+                    channel_id,
+                    ids: removed.iter().map(|message| message.id).collect(),
+                });
+            }
+            for message in removed {
+                let _ = self
+                    .send_channel(
+                        WsServerMessage::MessageExpired {
+                            hub_id,
+                            channel_id,
+                            message_id: message.id,
+                        },
+                        hub_id,
+                        channel_id,
+                        None,
+                    )
+                    .await;
+            }
+        }
+        tokio::fs::write(EXPIRING_FILE, bincode::serialize(&self.expiring)?).await?;
+        Ok(())
+    }
+
+    /// Sets the number of hubs kept in memory by the server's [`HubCache`].
+    pub async fn set_hub_cache_size(&self, size: usize) {
+        self.hub_cache.resize(size).await;
+    }
+
+    /// Gets the server's [`HubCache`], hubs should be loaded and saved through it while the server is running so that it stays up to date.
+    pub fn hub_cache(&self) -> HubCache {
+        self.hub_cache.clone()
+    }
+
+    /// Configures the server's [`WriteBatcher`], messages sent to the same channel within `interval` milliseconds are written together, up to `size` at a time.
+    pub fn set_write_batching(&self, interval: u64, size: usize) {
+        self.write_batcher.configure(interval, size);
+    }
+
+    /// Sets how often each [`ScheduledTask`] is run, only takes effect if called before the server is started.
+    pub fn set_scheduled_tasks(&mut self, tasks: ScheduledTasks) {
+        self.scheduled_tasks = tasks;
+    }
+
+    /// Runs a [`ScheduledTask`] once.
+    async fn run_task(&mut self, task: ScheduledTask) -> Result {
+        *self.task_runs.entry(task).or_default() += 1;
+        match task {
+            ScheduledTask::TypingExpiry => self.expire_typing(Utc::now()).await,
+            ScheduledTask::MessageExpiry => self.sweep_expired(Utc::now()).await,
+            ScheduledTask::ModerationExpiry => self.expire_moderation().await,
+            ScheduledTask::CreateKeyExpiry => {
+                crate::httpapi::handlers::hub::expire_create_keys(Utc::now());
+                Ok(())
+            }
+        }
+    }
+
+    /// Lifts the mutes and bans that have expired in the hubs in the server's [`HubCache`], saving the hubs that changed and telling their subscribers.
+    /// Hubs that are not cached are not read, their expired mutes and bans already count as lifted and are removed once the hub is cached again.
+    async fn expire_moderation(&mut self) -> Result {
+        let now = Utc::now();
+        let due = self
+            .hub_cache
+            .find(|hub| {
+                hub.bans
+                    .values()
+                    .chain(hub.mutes.values())
+                    .any(|expires| expires.map_or(false, |expires| expires <= now))
+            })
+            .await;
+        for hub_id in due {
+            let mut hub = if let Ok(hub) = Hub::load_cached(hub_id, &self.hub_cache).await {
+                hub
+            } else {
+                continue;
+            };
+            let banned = hub.bans.clone();
+            let muted = hub.mutes.clone();
+            let expired = hub.expire_timed_moderation();
+            if expired.is_empty() {
+                continue;
+            }
+            let mut changes = Vec::new();
+            for user_id in expired {
+                if banned.contains_key(&user_id) && !hub.bans.contains_key(&user_id) {
+                    changes.push(hub.record_change(WsHubUpdateType::UserUnbanned(user_id)));
+                }
+                if muted.contains_key(&user_id) && !hub.mutes.contains_key(&user_id) {
+                    changes.push(hub.record_change(WsHubUpdateType::UserUnmuted(user_id)));
+                }
+            }
+            match hub.save(&self.hub_cache).await {
+                // The hub was deleted since it was loaded.
+                Err(Error::ApiError(ApiError::HubNotFound)) => continue,
+                result => result?,
+            }
+            for change in changes {
+                let _ = self
+                    .send_hub(
+                        WsServerMessage::HubUpdated {
+                            hub_id,
+                            update_type: change.update_type,
+                            sequence: change.sequence,
+                        },
+                        &hub_id,
+                    )
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tells clients subscribed to the channel that users who started typing more than [`crate::TYPING_TIMEOUT`] before `now` stopped.
+    async fn expire_typing(&mut self, now: DateTime<Utc>) -> Result {
+        let timeout = Duration::milliseconds(crate::TYPING_TIMEOUT);
+        let expired = self
+            .typing
+            .iter()
+            .filter(|(_, started)| now.signed_duration_since(**started) > timeout)
+            .map(|(key, _)| *key)
+            .collect::<Vec<(ID, ID, ID)>>();
+        for (hub_id, channel_id, user_id) in expired {
+            self.typing.remove(&(hub_id, channel_id, user_id));
+            let _ = self
+                .send_channel(
+                    WsServerMessage::UserStoppedTyping {
+                        user_id,
+                        hub_id,
+                        channel_id,
+                    },
+                    hub_id,
+                    channel_id,
+                    Some(&user_id),
+                )
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Adds a sink that will be notified of events for users that are not connected.
+    pub fn add_notification_sink(&mut self, sink: Arc<dyn NotificationSink>) {
+        self.notification_sinks.push(sink);
+    }
+
+    /// Subscribes a connection to notifications on a hub if the user is a member of it.
+    async fn subscribe_hub(&self, user_id: ID, hub_id: ID, connection_id: u128) -> Result {
+        Hub::load_cached(hub_id, &self.hub_cache)
+            .await
+            .and_then(|hub| Ok(hub.get_member(&user_id)?.clone()))?;
+        self.subscribed
+            .write()
+            .await
+            .entry(connection_id)
+            .or_default()
+            .write()
+            .await
+            .1
+            .insert(hub_id);
+        self.subscribed_hubs
+            .write()
+            .await
+            .entry(hub_id)
+            .or_default()
+            .write()
+            .await
+            .insert(connection_id);
+        Ok(())
+    }
+
+    /// Subscribes a connection to new messages in a channel if the user has permission to read it.
+    async fn subscribe_channel(
+        &self,
+        user_id: ID,
+        hub_id: ID,
+        channel_id: ID,
+        connection_id: u128,
+    ) -> Result {
+        Hub::load_cached(hub_id, &self.hub_cache)
+            .await
+            .and_then(|hub| {
+                if let Ok(member) = hub.get_member(&user_id) {
+                    let member = member.clone();
+                    Ok((hub, member))
+                } else {
+                    Err(ApiError::MemberNotFound.into())
+                }
+            })
+            .and_then(|(hub, user)| {
+                check_permission!(
+                    user,
+                    channel_id,
+                    crate::permission::ChannelPermission::Read,
+                    hub
+                );
+                Ok(())
+            })?;
+        let key = (hub_id, channel_id);
+        self.subscribed
+            .write()
+            .await
+            .entry(connection_id)
+            .or_default()
+            .write()
+            .await
+            .0
+            .insert(key);
+        self.subscribed_channels
+            .write()
+            .await
+            .entry(key)
+            .or_default()
+            .write()
+            .await
+            .insert(connection_id);
+        Ok(())
+    }
+
+    /// Checks if the given user has at least one open websocket connection.
+    async fn is_online(&self, user_id: &ID) -> bool {
+        self.connected
+            .read()
+            .await
+            .values()
+            .any(|connection| &connection.user_id == user_id)
+    }
+
+    /// Notifies the sinks of mentions of offline hub members in the given message.
+    async fn notify_mentions(&self, message: &channel::Message) {
+        if self.notification_sinks.is_empty() || !message.should_notify() {
+            return;
+        }
+        let mentions = message.mentions();
+        if mentions.is_empty() {
+            return;
+        }
+        let hub = if let Ok(hub) = Hub::load_cached(message.hub_id, &self.hub_cache).await {
+            hub
+        } else {
+            return;
+        };
+        let mut notifications = Vec::new();
+        for user in mentions {
+            if user == message.sender || !hub.is_member(&user) || self.is_online(&user).await {
+                continue;
+            }
+            notifications.push((
+                user,
+                NotificationEvent::Mention {
+                    hub_id: message.hub_id,
+                    channel_id: message.channel_id,
+                    message_id: message.id,
+                    sender: message.sender,
+                },
+            ));
+        }
+        self.dispatch_notifications(notifications);
+    }
+
+    /// Gives notifications to every sink in the background, so that slow sinks do not hold up the delivery of messages.
+    fn dispatch_notifications(&self, notifications: Vec<(ID, NotificationEvent)>) {
+        if notifications.is_empty() {
+            return;
+        }
+        for sink in self.notification_sinks.iter() {
+            let sink = Arc::clone(sink);
+            let notifications = notifications.clone();
+            tokio::spawn(async move {
+                for (user, event) in notifications {
+                    sink.notify(user, event).await;
+                }
+            });
+        }
+    }
+
+    /// Sends a [`WsServerMessage`] to all of the given connections, the message is only encoded once for each compression setting.
+    /// If a sender is given the message is not sent to connections of users that have blocked the sender.
+    async fn send_connections(
+        &self,
+        message: &WsServerMessage,
+        connection_ids: &HashSet<u128>,
+        sender: Option<&ID>,
+    ) -> Result {
+        let mut plain = None;
+        let mut compressed = None;
+        for connection_id in connection_ids.iter() {
+            if let Some(connection) = self.connected.read().await.get(connection_id) {
+                if sender.map_or(false, |sender| connection.blocks.is_blocked(sender)) {
+                    continue;
+                }
+                let frame = if connection.compress {
+                    &mut compressed
+                } else {
+                    &mut plain
+                };
+                if frame.is_none() {
+                    *frame = Some(encode_message(message, connection.compress)?);
+                }
+                if let Some(frame) = frame {
+                    if !connection.queue.push(frame.clone()) {
+                        debug!(
+                            "Dropped a frame for closed connection {} of user {}",
+                            connection_id, connection.user_id
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a [`WsServerMessage`] to all of the connections of the given users.
+    async fn send_users(
+        &self,
+        message: WsServerMessage,
+        user_ids: &HashSet<ID>,
+        sender: Option<&ID>,
+    ) -> Result {
+        let connection_ids = self
+            .connected
+            .read()
+            .await
+            .iter()
+            .filter(|(_, connection)| user_ids.contains(&connection.user_id))
+            .map(|(id, _)| *id)
+            .collect();
+        self.send_connections(&message, &connection_ids, sender)
+            .await
+    }
+
+    /// Notifies the sinks of a direct message for each offline participant other than the sender.
+    async fn notify_direct_message(&self, message: &channel::Message, user_ids: &HashSet<ID>) {
+        if self.notification_sinks.is_empty() || !message.should_notify() {
+            return;
+        }
+        let mut notifications = Vec::new();
+        for user in user_ids {
+            if user == &message.sender || self.is_online(user).await {
+                continue;
+            }
+            notifications.push((
+                *user,
+                NotificationEvent::DirectMessage {
+                    channel_id: message.channel_id,
+                    message_id: message.id,
+                    sender: message.sender,
+                },
+            ));
+        }
+        self.dispatch_notifications(notifications);
+    }
+
+    /// Sends a [`WsServerMessage`] to all clients subscribed to notifications for the given hub.
+    async fn send_hub(&self, message: WsServerMessage, hub_id: &ID) -> Result {
+        if let Some(subscribed_arc) = self.subscribed_hubs.read().await.get(hub_id) {
+            self.send_connections(&message, &*subscribed_arc.read().await, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a [`WsServerMessage`] to all clients subscribed to notifications for the given channel, except for those of users that blocked the sender.
+    async fn send_channel(
+        &self,
+        message: WsServerMessage,
+        hub_id: ID,
+        channel_id: ID,
+        sender: Option<&ID>,
+    ) -> Result {
+        if let Some(subscribed_arc) = self
+            .subscribed_channels
+            .read()
+            .await
+            .get(&(hub_id, channel_id))
+        {
+            self.send_connections(&message, &*subscribed_arc.read().await, sender)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Actor for Server {
+    async fn started(&mut self, ctx: &mut xactor::Context<Self>) -> xactor::Result<()> {
+        for (task, interval) in self.scheduled_tasks.intervals() {
+            if interval > 0 {
+                ctx.send_interval(task, std::time::Duration::from_millis(interval));
+            }
+        }
+        Ok(())
+    }
+}
 
 #[async_trait]
 impl Handler<client_command::Connect> for Server {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: client_command::Connect) -> u128 {
+        let blocks = BlockList::load(&msg.user_id).await.unwrap_or_default();
         let mut connection_set = self.connected.write().await;
         let mut id = rand::random::<u128>();
         while connection_set.contains_key(&id) {
             id = rand::random::<u128>();
         }
-        connection_set.insert(id, msg.websocket_writer);
+        let queue = Arc::new(SendQueue::new(self.send_queue_size, self.overflow_policy));
+        tokio::spawn(drain_send_queue(
+            Arc::clone(&queue),
+            Arc::clone(&msg.websocket_writer),
+        ));
+        connection_set.insert(
+            id,
+            ClientConnection {
+                user_id: msg.user_id,
+                writer: msg.websocket_writer,
+                queue,
+                compress: msg.compress,
+                blocks,
+            },
+        );
         id
     }
 }
@@ -481,8 +1760,22 @@ impl Handler<client_command::Connect> for Server {
 #[async_trait]
 impl Handler<client_command::Disconnect> for Server {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: client_command::Disconnect) {
+        let now = Utc::now();
+        self.resumable.retain(|_, state| state.expires > now);
+        let resume_token = self.resume_tokens.remove(&msg.connection_id);
         if let Some(subscribed) = self.subscribed.write().await.remove(&msg.connection_id) {
             let subscribed = subscribed.write().await;
+            if let Some((token, user_id)) = resume_token {
+                self.resumable.insert(
+                    token,
+                    ResumeState {
+                        user_id,
+                        channels: subscribed.0.clone(),
+                        hubs: subscribed.1.clone(),
+                        expires: now + Duration::milliseconds(crate::RESUME_TOKEN_LIFETIME),
+                    },
+                );
+            }
             let subscribed_channels = self.subscribed_channels.write().await;
             for channel in subscribed.0.iter() {
                 if let Some(subs) = subscribed_channels.get(channel) {
@@ -497,7 +1790,9 @@ impl Handler<client_command::Disconnect> for Server {
                 }
             }
             drop(subscribed_hubs);
-            self.connected.write().await.remove(&msg.connection_id);
+        }
+        if let Some(connection) = self.connected.write().await.remove(&msg.connection_id) {
+            connection.queue.close();
         }
     }
 }
@@ -509,27 +1804,8 @@ impl Handler<client_command::SubscribeHub> for Server {
         _ctx: &mut Context<Self>,
         msg: client_command::SubscribeHub,
     ) -> Result {
-        Hub::load(msg.hub_id)
-            .await
-            .and_then(|hub| Ok(hub.get_member(&msg.user_id)?.clone()))?;
-        self.subscribed
-            .write()
-            .await
-            .entry(msg.connection_id)
-            .or_default()
-            .write()
-            .await
-            .1
-            .insert(msg.hub_id);
-        self.subscribed_hubs
-            .write()
+        self.subscribe_hub(msg.user_id, msg.hub_id, msg.connection_id)
             .await
-            .entry(msg.hub_id)
-            .or_default()
-            .write()
-            .await
-            .insert(msg.connection_id);
-        Ok(())
     }
 }
 
@@ -552,44 +1828,8 @@ impl Handler<client_command::SubscribeChannel> for Server {
         _ctx: &mut Context<Self>,
         msg: client_command::SubscribeChannel,
     ) -> Result {
-        Hub::load(msg.hub_id)
+        self.subscribe_channel(msg.user_id, msg.hub_id, msg.channel_id, msg.connection_id)
             .await
-            .and_then(|hub| {
-                if let Ok(member) = hub.get_member(&msg.user_id) {
-                    let member = member.clone();
-                    Ok((hub, member))
-                } else {
-                    Err(ApiError::MemberNotFound.into())
-                }
-            })
-            .and_then(|(hub, user)| {
-                check_permission!(
-                    user,
-                    msg.channel_id,
-                    crate::permission::ChannelPermission::Read,
-                    hub
-                );
-                Ok(())
-            })?;
-        let key = (msg.hub_id, msg.channel_id);
-        self.subscribed
-            .write()
-            .await
-            .entry(msg.connection_id)
-            .or_default()
-            .write()
-            .await
-            .0
-            .insert(key);
-        self.subscribed_channels
-            .write()
-            .await
-            .entry(key)
-            .or_default()
-            .write()
-            .await
-            .insert(msg.connection_id);
-        Ok(())
     }
 }
 
@@ -606,6 +1846,73 @@ impl Handler<client_command::UnsubscribeChannel> for Server {
     }
 }
 
+#[async_trait]
+impl Handler<client_command::GetSubscriptions> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: client_command::GetSubscriptions,
+    ) -> (HashSet<(ID, ID)>, HashSet<ID>) {
+        if let Some(subs) = self.subscribed.read().await.get(&msg.connection_id) {
+            subs.read().await.clone()
+        } else {
+            (HashSet::new(), HashSet::new())
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<client_command::IssueResumeToken> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: client_command::IssueResumeToken,
+    ) -> ID {
+        let token = crate::new_id();
+        self.resume_tokens
+            .insert(msg.connection_id, (token, msg.user_id));
+        token
+    }
+}
+
+#[async_trait]
+impl Handler<client_command::Resume> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: client_command::Resume,
+    ) -> Result<(HashSet<(ID, ID)>, HashSet<ID>)> {
+        let state = match self.resumable.get(&msg.token) {
+            Some(state) if state.user_id == msg.user_id && state.expires > Utc::now() => {
+                state.clone()
+            }
+            _ => return Err(ApiError::InvalidResumeToken.into()),
+        };
+        self.resumable.remove(&msg.token);
+        let mut hubs = HashSet::new();
+        for hub_id in state.hubs {
+            if self
+                .subscribe_hub(msg.user_id, hub_id, msg.connection_id)
+                .await
+                .is_ok()
+            {
+                hubs.insert(hub_id);
+            }
+        }
+        let mut channels = HashSet::new();
+        for (hub_id, channel_id) in state.channels {
+            if self
+                .subscribe_channel(msg.user_id, hub_id, channel_id, msg.connection_id)
+                .await
+                .is_ok()
+            {
+                channels.insert((hub_id, channel_id));
+            }
+        }
+        Ok((channels, hubs))
+    }
+}
+
 #[async_trait]
 impl Handler<client_command::StartTyping> for Server {
     async fn handle(
@@ -613,7 +1920,7 @@ impl Handler<client_command::StartTyping> for Server {
         _ctx: &mut Context<Self>,
         msg: client_command::StartTyping,
     ) -> Result {
-        Hub::load(msg.hub_id)
+        Hub::load_cached(msg.hub_id, &self.hub_cache)
             .await
             .and_then(|hub| {
                 if let Ok(member) = hub.get_member(&msg.user_id) {
@@ -632,6 +1939,8 @@ impl Handler<client_command::StartTyping> for Server {
                 );
                 Ok(())
             })?;
+        self.typing
+            .insert((msg.hub_id, msg.channel_id, msg.user_id), Utc::now());
         let _ = self
             .send_channel(
                 WsServerMessage::UserStartedTyping {
@@ -641,6 +1950,7 @@ impl Handler<client_command::StartTyping> for Server {
                 },
                 msg.hub_id,
                 msg.channel_id,
+                Some(&msg.user_id),
             )
             .await;
         Ok(())
@@ -654,7 +1964,7 @@ impl Handler<client_command::StopTyping> for Server {
         _ctx: &mut Context<Self>,
         msg: client_command::StopTyping,
     ) -> Result {
-        Hub::load(msg.hub_id)
+        Hub::load_cached(msg.hub_id, &self.hub_cache)
             .await
             .and_then(|hub| {
                 if let Ok(member) = hub.get_member(&msg.user_id) {
@@ -673,6 +1983,8 @@ impl Handler<client_command::StopTyping> for Server {
                 );
                 Ok(())
             })?;
+        self.typing
+            .remove(&(msg.hub_id, msg.channel_id, msg.user_id));
         let _ = self
             .send_channel(
                 WsServerMessage::UserStoppedTyping {
@@ -682,6 +1994,7 @@ impl Handler<client_command::StopTyping> for Server {
                 },
                 msg.hub_id,
                 msg.channel_id,
+                Some(&msg.user_id),
             )
             .await;
         Ok(())
@@ -693,34 +2006,93 @@ impl Handler<ServerNotification> for Server {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: ServerNotification) {
         match msg {
             ServerNotification::NewMessage(message) => {
+                self.index_message(&message);
+                let _ = self.track_expiry(&message).await;
+                self.notify_mentions(&message).await;
+                let (hub_id, channel_id, sender) =
+                    (message.hub_id, message.channel_id, message.sender);
                 let _ = self
-                    .message_server
-                    .call(NewMessageForIndex {
+                    .send_channel(
+                        WsServerMessage::from(message),
+                        hub_id,
+                        channel_id,
+                        Some(&sender),
+                    )
+                    .await;
+            }
+            ServerNotification::NewDirectMessage(message, user_ids) => {
+                self.notify_direct_message(&message, &user_ids).await;
+                let sender = message.sender;
+                let _ = self
+                    .send_users(WsServerMessage::from(message), &user_ids, Some(&sender))
+                    .await;
+            }
+            ServerNotification::MessageEdited(message) => {
+                if let Some(message_server) =
+                    self.message_server(message.hub_id, message.channel_id)
+                {
+                    let _ = message_server.send(RemoveMessagesFromIndex {
                         hub_id: message.hub_id,
                         channel_id: message.channel_id,
-                        message: message.clone(),
-                    })
-                    .await;
+                        ids: vec![message.id],
+                    });
+                }
+                self.index_message(&message);
+                let (hub_id, channel_id, sender) =
+                    (message.hub_id, message.channel_id, message.sender);
+                let editor = message.edits.last().map_or(sender, |edit| edit.editor);
                 let _ = self
                     .send_channel(
-                        WsServerMessage::ChatMessage {
-                            sender_id: message.sender,
-                            hub_id: message.hub_id,
-                            channel_id: message.channel_id,
-                            message: message.content,
+                        WsServerMessage::MessageEdited {
+                            hub_id,
+                            channel_id,
                             message_id: message.id,
+                            message: message.content,
+                            links: message.links,
+                            editor,
+                        },
+                        hub_id,
+                        channel_id,
+                        Some(&sender),
+                    )
+                    .await;
+            }
+            ServerNotification::MessageDeleted(hub_id, channel_id, message_id) => {
+                if let Some(message_server) = self.message_server(hub_id, channel_id) {
+                    let _ = message_server.send(RemoveMessagesFromIndex {
+                        hub_id,
+                        channel_id,
+                        ids: vec![message_id],
+                    });
+                }
+                let _ = self
+                    .send_channel(
+                        WsServerMessage::MessageDeleted {
+                            hub_id,
+                            channel_id,
+                            message_id,
                         },
-                        message.hub_id,
-                        message.channel_id,
+                        hub_id,
+                        channel_id,
+                        None,
                     )
                     .await;
             }
-            ServerNotification::HubUpdated(hub_id, update_type) => {
+            ServerNotification::BlocksUpdated(user_id, blocks) => {
+                for connection in self.connected.write().await.values_mut() {
+                    if connection.user_id == user_id {
+                        connection.blocks = blocks.clone();
+                    }
+                }
+            }
+            ServerNotification::HubUpdated(hub_id, change) => {
+                self.hub_cache.refresh(hub_id, change.sequence).await;
                 let _ = self
                     .send_hub(
                         WsServerMessage::HubUpdated {
                             hub_id,
-                            update_type,
+                            update_type: change.update_type,
+                            sequence: change.sequence,
                         },
                         &hub_id,
                     )
@@ -735,8 +2107,655 @@ impl Handler<GetMessageServer> for Server {
     async fn handle(
         &mut self,
         _ctx: &mut Context<Self>,
-        _msg: GetMessageServer,
-    ) -> Addr<MessageServer> {
-        self.message_server.clone()
+        msg: GetMessageServer,
+    ) -> Option<Addr<MessageServer>> {
+        self.message_server(msg.hub_id, msg.channel_id).cloned()
+    }
+}
+
+#[async_trait]
+impl Handler<ScheduledTask> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, task: ScheduledTask) {
+        let _ = self.run_task(task).await;
+    }
+}
+
+#[async_trait]
+impl Handler<GetScheduledTaskRuns> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: GetScheduledTaskRuns,
+    ) -> HashMap<ScheduledTask, u64> {
+        self.task_runs.clone()
+    }
+}
+
+#[async_trait]
+impl Handler<GetConnectionCount> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetConnectionCount) -> usize {
+        self.connected.read().await.len()
+    }
+}
+
+#[async_trait]
+impl Handler<GetServerState> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetServerState) -> HttpServerState {
+        let mut state = HttpServerState {
+            connections: self.connected.read().await.len(),
+            subscribed_channels: self.subscribed_channels.read().await.len(),
+            subscribed_hubs: self.subscribed_hubs.read().await.len(),
+            resumable: self.resumable.len(),
+            resume_tokens: self.resume_tokens.len(),
+            typing: self.typing.len(),
+            expiring_channels: self.expiring.len(),
+            index_queues: self
+                .index_queues
+                .iter()
+                .map(|queue| queue.queued())
+                .collect(),
+            deferred_channels: self
+                .index_queues
+                .iter()
+                .map(|queue| queue.deferred_channels())
+                .sum(),
+            ..Default::default()
+        };
+        let subscribed = self.subscribed.read().await;
+        state.subscribed_connections = subscribed.len();
+        for subscriptions in subscribed.values() {
+            let subscriptions = subscriptions.read().await;
+            state.channel_subscriptions += subscriptions.0.len();
+            state.hub_subscriptions += subscriptions.1.len();
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hub::test::test_hub;
+    use crate::test::*;
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<(ID, NotificationEvent)>>);
+
+    #[async_trait]
+    impl NotificationSink for RecordingSink {
+        async fn notify(&self, user: ID, event: NotificationEvent) {
+            self.0.lock().await.push((user, event));
+        }
+    }
+
+    /// Sink that never finishes notifying.
+    struct StuckSink;
+
+    #[async_trait]
+    impl NotificationSink for StuckSink {
+        async fn notify(&self, _user: ID, _event: NotificationEvent) {
+            futures::future::pending::<()>().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn mention_offline_user() {
+        let mut hub = test_hub();
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let mut server = Server::new().await.unwrap();
+        server.add_notification_sink(Arc::new(StuckSink));
+        server.add_notification_sink(sink.clone());
+        let message = hub
+            .send_message(
+                *USER_ID,
+                *CHANNEL_ID,
+                format!("hello @{}!", *OTHER_USER_ID),
+                0,
+            )
+            .await
+            .unwrap();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            server.notify_mentions(&message),
+        )
+        .await
+        .expect("a stuck sink held up the server");
+        // Sinks are notified in the background.
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while sink.0.lock().await.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the sink was not notified");
+        assert_eq!(
+            vec![(
+                *OTHER_USER_ID,
+                NotificationEvent::Mention {
+                    hub_id: hub.id,
+                    channel_id: *CHANNEL_ID,
+                    message_id: message.id,
+                    sender: *USER_ID,
+                }
+            )],
+            *sink.0.lock().await
+        );
+    }
+
+    #[tokio::test]
+    async fn search_by_sender() {
+        let hub_id = crate::new_id();
+        let message_server = MessageServer::new().start().await.unwrap();
+        let mut sent = Vec::new();
+        for sender in [*USER_ID, *OTHER_USER_ID, *USER_ID].iter() {
+            let message =
+                channel::Message::new(*sender, "hello there".to_string(), hub_id, *CHANNEL_ID, 0);
+            sent.push(message.clone());
+            message_server
+                .call(NewMessageForIndex {
+                    hub_id,
+                    channel_id: *CHANNEL_ID,
+                    message,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let search = |sender| SearchMessageIndex {
+            hub_id,
+            channel_id: *CHANNEL_ID,
+            limit: 10,
+            query: "hello".to_string(),
+            sender,
+        };
+        let all = message_server.call(search(None)).await.unwrap().unwrap();
+        assert_eq!(3, all.len());
+        let mut found = message_server
+            .call(search(Some(*USER_ID)))
+            .await
+            .unwrap()
+            .unwrap();
+        found.sort();
+        let mut expected = sent
+            .iter()
+            .filter(|message| message.sender == *USER_ID)
+            .map(|message| message.id)
+            .collect::<Vec<ID>>();
+        expected.sort();
+        assert_eq!(expected, found);
+    }
+
+    #[tokio::test]
+    async fn message_server_shards() {
+        let hub_id = crate::new_id();
+        let first = crate::new_id();
+        let second = std::iter::repeat_with(crate::new_id)
+            .find(|id| {
+                message_server_shard(hub_id, *id, 4) != message_server_shard(hub_id, first, 4)
+            })
+            .unwrap();
+        let server = Server::with_message_servers(4)
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let get = |channel_id| server.call(GetMessageServer { hub_id, channel_id });
+        let first_server = get(first).await.unwrap().unwrap();
+        let second_server = get(second).await.unwrap().unwrap();
+        assert_ne!(first_server.actor_id(), second_server.actor_id());
+        assert_eq!(
+            first_server.actor_id(),
+            get(first).await.unwrap().unwrap().actor_id()
+        );
+        let message = |channel_id| NewMessageForIndex {
+            hub_id,
+            channel_id,
+            message: channel::Message::new(
+                *USER_ID,
+                "hello there".to_string(),
+                hub_id,
+                channel_id,
+                0,
+            ),
+        };
+        let (a, b) = futures::join!(
+            first_server.call(message(first)),
+            second_server.call(message(second))
+        );
+        a.unwrap().unwrap();
+        b.unwrap().unwrap();
+        let search = |channel_id| SearchMessageIndex {
+            hub_id,
+            channel_id,
+            limit: 10,
+            query: "hello".to_string(),
+            sender: None,
+        };
+        let (a, b) = futures::join!(
+            first_server.call(search(first)),
+            second_server.call(search(second))
+        );
+        assert_eq!(1, a.unwrap().unwrap().len());
+        assert_eq!(1, b.unwrap().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn warm_index() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let message = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "warm".to_string(), 0)
+            .await
+            .unwrap();
+        log_if_nologs(hub.id, *CHANNEL_ID, message.id)
+            .await
+            .unwrap();
+        let mut message_server = MessageServer::new();
+        message_server
+            .warm_index(hub.id, *CHANNEL_ID)
+            .await
+            .unwrap();
+        let key = (hub.id, *CHANNEL_ID);
+        assert!(message_server.index_readers.contains_key(&key));
+        assert!(message_server.index_writers.contains_key(&key));
+        let searcher = message_server
+            .get_searcher(hub.id, *CHANNEL_ID)
+            .await
+            .unwrap();
+        assert_eq!(1, searcher.num_docs());
+    }
+
+    #[tokio::test]
+    async fn expired_messages() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let mut server = Server::new().await.unwrap();
+        assert_eq!(
+            ApiError::InvalidTime,
+            hub.send_expiring_message(
+                *USER_ID,
+                *CHANNEL_ID,
+                "expired".to_string(),
+                0,
+                Some(Utc::now())
+            )
+            .await
+            .unwrap_err()
+        );
+        let expires_at = Utc::now() + Duration::milliseconds(50);
+        let expiring = hub
+            .send_expiring_message(
+                *USER_ID,
+                *CHANNEL_ID,
+                "expiring".to_string(),
+                0,
+                Some(expires_at),
+            )
+            .await
+            .unwrap();
+        let kept = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "kept".to_string(), 0)
+            .await
+            .unwrap();
+        server.track_expiry(&expiring).await.unwrap();
+        let channel = hub.channels.get(&*CHANNEL_ID).unwrap();
+        server
+            .sweep_expired(expires_at - Duration::milliseconds(1))
+            .await
+            .unwrap();
+        assert!(channel.get_message(expiring.id).await.is_some());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        server.sweep_expired(Utc::now()).await.unwrap();
+        assert!(channel.get_message(expiring.id).await.is_none());
+        assert!(channel.get_message(kept.id).await.is_some());
+        assert!(!server.expiring.contains_key(&(hub.id, *CHANNEL_ID)));
+    }
+
+    #[tokio::test]
+    async fn scheduled_tasks() {
+        let mut server = Server::new().await.unwrap();
+        let now = Utc::now();
+        let stale = (crate::new_id(), *CHANNEL_ID, *USER_ID);
+        let recent = (crate::new_id(), *CHANNEL_ID, *USER_ID);
+        server.typing.insert(
+            stale,
+            now - Duration::milliseconds(crate::TYPING_TIMEOUT + 1),
+        );
+        server.typing.insert(recent, now);
+        server.expire_typing(now).await.unwrap();
+        assert!(!server.typing.contains_key(&stale));
+        assert!(server.typing.contains_key(&recent));
+        server.set_scheduled_tasks(ScheduledTasks {
+            typing_expiry: 10,
+            message_expiry: 0,
+            moderation_expiry: 0,
+            create_key_expiry: 0,
+        });
+        let server = server.start().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let runs = server.call(GetScheduledTaskRuns).await.unwrap();
+        assert!(runs[&ScheduledTask::TypingExpiry] >= 2);
+        assert!(!runs.contains_key(&ScheduledTask::MessageExpiry));
+    }
+
+    #[tokio::test]
+    async fn index_burst() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let mut server = Server::new().await.unwrap();
+        server.set_index_queue_limit(2);
+        let queue = Arc::clone(&server.index_queues[0]);
+        let server = server.start().await.unwrap();
+        let mut sent = Vec::new();
+        for _ in 0..50 {
+            let message = hub
+                .send_message(*USER_ID, *CHANNEL_ID, "burst".to_string(), 0)
+                .await
+                .unwrap();
+            sent.push(message.id);
+            server
+                .send(ServerNotification::NewMessage(message))
+                .unwrap();
+        }
+        let message_server = server
+            .call(GetMessageServer {
+                hub_id: hub.id,
+                channel_id: *CHANNEL_ID,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let mut found = message_server
+            .call(SearchMessageIndex {
+                hub_id: hub.id,
+                channel_id: *CHANNEL_ID,
+                limit: 1000,
+                query: "burst".to_string(),
+                sender: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(queue.peak() <= 2);
+        sent.sort();
+        found.sort();
+        assert_eq!(sent, found);
+    }
+
+    #[tokio::test]
+    async fn hub_cache() {
+        let cache = HubCache::new(1);
+        let mut hub = test_hub();
+        cache.save(&hub).await.unwrap();
+        hub.name = "renamed".to_string();
+        hub.write().await.unwrap();
+        assert_eq!(
+            "test hub",
+            Hub::load_cached(hub.id, &cache).await.unwrap().name
+        );
+        let change = hub.record_change(WsHubUpdateType::UserMuted(*OTHER_USER_ID));
+        hub.write().await.unwrap();
+        cache.refresh(hub.id, change.sequence).await;
+        assert_eq!(
+            "renamed",
+            Hub::load_cached(hub.id, &cache).await.unwrap().name
+        );
+        let other = test_hub();
+        other.write().await.unwrap();
+        Hub::load_cached(other.id, &cache).await.unwrap();
+        assert!(!cache.contains(&hub.id).await);
+        assert!(cache.contains(&other.id).await);
+        cache.remove(&other.id).await;
+        assert!(!cache.contains(&other.id).await);
+    }
+
+    #[tokio::test]
+    async fn write_batching() {
+        use crate::channel::Message;
+        let batcher = WriteBatcher::new(50, 16);
+        let channel = test_channel(crate::new_id());
+        let messages = (0..40)
+            .map(|i| Message::new(*USER_ID, i.to_string(), channel.hub_id, channel.id, 0))
+            .collect::<Vec<Message>>();
+        let writes = messages.iter().map(|message| batcher.write(message));
+        for result in futures::future::join_all(writes).await {
+            result.expect("failed to write a message");
+        }
+        assert!(batcher.writes() >= 3);
+        assert!(batcher.writes() < 40);
+        assert!(batcher.pending.lock().unwrap().is_empty());
+        let written = channel.get_last_messages(40).await;
+        assert_eq!(
+            messages.iter().map(|m| m.id).collect::<HashSet<ID>>(),
+            written.iter().map(|m| m.id).collect::<HashSet<ID>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn moderation_expiry() {
+        let mut hub = test_hub();
+        let banned = ID::from_u128(3);
+        hub.user_join(*OTHER_USER_ID).unwrap();
+        hub.mutes
+            .insert(*OTHER_USER_ID, Some(Utc::now() - Duration::seconds(1)));
+        hub.bans
+            .insert(banned, Some(Utc::now() + Duration::hours(1)));
+        let mut server = Server::new().await.unwrap();
+        hub.save(&server.hub_cache()).await.unwrap();
+        server.expire_moderation().await.unwrap();
+        let hub = Hub::load(hub.id).await.unwrap();
+        assert!(hub.mutes.is_empty());
+        assert!(hub.is_user_banned(&banned));
+        assert_eq!(
+            Some(&WsHubUpdateType::UserUnmuted(*OTHER_USER_ID)),
+            hub.changes.back().map(|change| &change.update_type)
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_message_unindexed() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let server = Server::new().await.unwrap().start().await.unwrap();
+        let message = hub
+            .send_message(*USER_ID, *CHANNEL_ID, "regrettable".to_string(), 0)
+            .await
+            .unwrap();
+        server
+            .send(ServerNotification::NewMessage(message.clone()))
+            .unwrap();
+        let message_server = server
+            .call(GetMessageServer {
+                hub_id: hub.id,
+                channel_id: *CHANNEL_ID,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let search = || SearchMessageIndex {
+            hub_id: hub.id,
+            channel_id: *CHANNEL_ID,
+            limit: 10,
+            query: "regrettable".to_string(),
+            sender: None,
+        };
+        assert_eq!(
+            vec![message.id],
+            message_server.call(search()).await.unwrap().unwrap()
+        );
+        hub.delete_message(&USER_ID, *CHANNEL_ID, message.id, 0)
+            .await
+            .unwrap();
+        server
+            .send(ServerNotification::MessageDeleted(
+                hub.id,
+                *CHANNEL_ID,
+                message.id,
+            ))
+            .unwrap();
+        server.call(GetConnectionCount).await.unwrap();
+        assert!(message_server
+            .call(search())
+            .await
+            .unwrap()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_subscriptions() {
+        let mut hub = test_hub();
+        let channel_id = hub
+            .new_channel(&USER_ID, "other".to_string(), String::new())
+            .await
+            .unwrap();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let server = Server::new().await.unwrap().start().await.unwrap();
+        for channel_id in [*CHANNEL_ID, channel_id].iter() {
+            server
+                .call(client_command::SubscribeChannel {
+                    user_id: *USER_ID,
+                    hub_id: hub.id,
+                    channel_id: *channel_id,
+                    connection_id: 1,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        server
+            .call(client_command::SubscribeHub {
+                user_id: *USER_ID,
+                hub_id: hub.id,
+                connection_id: 2,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let (channels, hubs) = server
+            .call(client_command::GetSubscriptions { connection_id: 1 })
+            .await
+            .unwrap();
+        let expected: HashSet<(ID, ID)> = [(hub.id, *CHANNEL_ID), (hub.id, channel_id)]
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(expected, channels);
+        assert!(hubs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resume_subscriptions() {
+        let hub = test_hub();
+        hub.save(&HubCache::new(0)).await.unwrap();
+        let server = Server::new().await.unwrap().start().await.unwrap();
+        let token = server
+            .call(client_command::IssueResumeToken {
+                user_id: *USER_ID,
+                connection_id: 1,
+            })
+            .await
+            .unwrap();
+        server
+            .call(client_command::SubscribeChannel {
+                user_id: *USER_ID,
+                hub_id: hub.id,
+                channel_id: *CHANNEL_ID,
+                connection_id: 1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        server
+            .call(client_command::SubscribeHub {
+                user_id: *USER_ID,
+                hub_id: hub.id,
+                connection_id: 1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        server
+            .call(client_command::Disconnect { connection_id: 1 })
+            .await
+            .unwrap();
+        assert!(server
+            .call(client_command::Resume {
+                user_id: *OTHER_USER_ID,
+                token,
+                connection_id: 2,
+            })
+            .await
+            .unwrap()
+            .is_err());
+        let (channels, hubs) = server
+            .call(client_command::Resume {
+                user_id: *USER_ID,
+                token,
+                connection_id: 4,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (channels.clone(), hubs.clone()),
+            server
+                .call(client_command::GetSubscriptions { connection_id: 4 })
+                .await
+                .unwrap()
+        );
+        assert!(channels.contains(&(hub.id, *CHANNEL_ID)));
+        assert!(hubs.contains(&hub.id));
+        assert!(server
+            .call(client_command::Resume {
+                user_id: *USER_ID,
+                token,
+                connection_id: 5,
+            })
+            .await
+            .unwrap()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn send_queue_overflow() {
+        let frames = |queue: &SendQueue| {
+            let mut frames = Vec::new();
+            while !queue.is_empty() {
+                frames.push(
+                    queue
+                        .frames
+                        .lock()
+                        .unwrap()
+                        .pop_front()
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                );
+            }
+            frames
+        };
+        let queue = SendQueue::new(2, OverflowPolicy::DropOldest);
+        for frame in &["1", "2", "3"] {
+            assert!(queue.push(WebSocketMessage::text(*frame)));
+        }
+        assert_eq!(vec!["2", "3"], frames(&queue));
+        let queue = SendQueue::new(2, OverflowPolicy::DropNewest);
+        for frame in &["1", "2", "3"] {
+            assert!(queue.push(WebSocketMessage::text(*frame)));
+        }
+        assert_eq!(vec!["1", "2"], frames(&queue));
+        let queue = SendQueue::new(2, OverflowPolicy::Disconnect);
+        assert!(queue.push(WebSocketMessage::text("1")));
+        assert!(queue.push(WebSocketMessage::text("2")));
+        assert!(!queue.is_closed());
+        assert!(!queue.push(WebSocketMessage::text("3")));
+        assert!(queue.is_closed());
+        assert!(queue.is_empty());
+        assert_eq!(None, queue.pop().await);
     }
 }
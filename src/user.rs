@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ID;
+
+#[cfg(feature = "server")]
+use tokio::io::AsyncWriteExt;
+
+#[cfg(feature = "server")]
+use crate::{channel::Message, error::Result};
+
+/// Relative path of the folder in which per-user data is stored.
+#[cfg(not(test))]
+pub const USER_DATA_FOLDER: &str = "data/users/";
+#[cfg(test)]
+pub const USER_DATA_FOLDER: &str = "test_data/users/";
+
+/// Users whose messages a user does not want to see, only affects what the owner of the list is shown.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct BlockList {
+    /// IDs of the blocked users.
+    pub blocked: HashSet<ID>,
+}
+
+#[cfg(feature = "server")]
+impl BlockList {
+    /// Get the path of the file the block list of the given user is stored in.
+    pub fn get_path(user_id: &ID) -> String {
+        format!("{}{}/blocks", USER_DATA_FOLDER, user_id.to_string())
+    }
+
+    /// Loads the block list of the given user, users that have never blocked anyone have an empty list.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block list file exists but could not be read or deserialized.
+    pub async fn load(user_id: &ID) -> Result<Self> {
+        let path = Self::get_path(user_id);
+        if !std::path::Path::new(&path).is_file() {
+            return Ok(Self::default());
+        }
+        Ok(bincode::deserialize(&tokio::fs::read(path).await?)?)
+    }
+
+    /// Saves the block list of the given user.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the user's data folder could not be created or the file could not be written.
+    pub async fn save(&self, user_id: &ID) -> Result {
+        tokio::fs::create_dir_all(format!("{}{}", USER_DATA_FOLDER, user_id.to_string())).await?;
+        let mut file = tokio::fs::File::create(Self::get_path(user_id)).await?;
+        file.write_all(&bincode::serialize(self)?).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Checks if the given user is blocked.
+    pub fn is_blocked(&self, user_id: &ID) -> bool {
+        self.blocked.contains(user_id)
+    }
+
+    /// Removes the messages sent by blocked users.
+    pub fn filter(&self, mut messages: Vec<Message>) -> Vec<Message> {
+        if !self.blocked.is_empty() {
+            messages.retain(|message| !self.is_blocked(&message.sender));
+        }
+        messages
+    }
+}
+
+/// Message a user started writing in a channel but has not sent yet, stored so that it follows them across clients.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Draft {
+    /// Content of the unsent message.
+    pub content: String,
+    /// Time at which the draft was last saved.
+    pub updated: DateTime<Utc>,
+}
+
+#[cfg(feature = "server")]
+impl Draft {
+    /// Creates a draft last saved now, returns [`ApiError::TooBig`](crate::error::ApiError::TooBig) if the content is bigger than [`crate::MAX_MESSAGE_SIZE`].
+    pub fn new(content: String) -> crate::error::ApiResult<Self> {
+        if content.as_bytes().len() > crate::MAX_MESSAGE_SIZE {
+            return Err(crate::error::ApiError::TooBig);
+        }
+        Ok(Self {
+            content,
+            updated: Utc::now(),
+        })
+    }
+
+    /// Get the path of the file the given user's draft in the given channel is stored in.
+    pub fn get_path(user_id: &ID, hub_id: &ID, channel_id: &ID) -> String {
+        format!(
+            "{}{}/drafts/{}/{}",
+            USER_DATA_FOLDER,
+            user_id.to_string(),
+            hub_id.to_string(),
+            channel_id.to_string()
+        )
+    }
+
+    /// Loads the given user's draft in a channel, `None` if they do not have one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the draft file exists but could not be read or deserialized.
+    pub async fn load(user_id: &ID, hub_id: &ID, channel_id: &ID) -> Result<Option<Self>> {
+        let path = Self::get_path(user_id, hub_id, channel_id);
+        if !std::path::Path::new(&path).is_file() {
+            return Ok(None);
+        }
+        Ok(Some(bincode::deserialize(&tokio::fs::read(path).await?)?))
+    }
+
+    /// Saves the draft as the given user's draft in a channel, replacing their previous draft there.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the drafts folder could not be created or the file could not be written.
+    pub async fn save(&self, user_id: &ID, hub_id: &ID, channel_id: &ID) -> Result {
+        tokio::fs::create_dir_all(format!(
+            "{}{}/drafts/{}",
+            USER_DATA_FOLDER,
+            user_id.to_string(),
+            hub_id.to_string()
+        ))
+        .await?;
+        let mut file = tokio::fs::File::create(Self::get_path(user_id, hub_id, channel_id)).await?;
+        file.write_all(&bincode::serialize(self)?).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Deletes the given user's draft in a channel, does nothing if they do not have one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the draft file exists but could not be removed.
+    pub async fn delete(user_id: &ID, hub_id: &ID, channel_id: &ID) -> Result {
+        match tokio::fs::remove_file(Self::get_path(user_id, hub_id, channel_id)).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::channel::{test::test_channel, Channel};
+    use crate::test::*;
+
+    #[tokio::test]
+    async fn blocked_messages_hidden() {
+        let channel = test_channel(crate::new_id());
+        for sender in [*USER_ID, *OTHER_USER_ID].iter() {
+            Channel::write_message(&Message::new(
+                *sender,
+                "test message".to_string(),
+                channel.hub_id,
+                channel.id,
+                0,
+            ))
+            .await
+            .unwrap();
+        }
+        let blocker = ID::from_u128(3);
+        let mut blocks = BlockList::default();
+        blocks.blocked.insert(*OTHER_USER_ID);
+        blocks.save(&blocker).await.unwrap();
+        let blocks = BlockList::load(&blocker).await.unwrap();
+        let messages = channel.get_last_messages(2).await;
+        let filtered = blocks.filter(messages.clone());
+        assert_eq!(1, filtered.len());
+        assert_eq!(*USER_ID, filtered[0].sender);
+        let others = BlockList::load(&ID::from_u128(4)).await.unwrap();
+        assert_eq!(messages, others.filter(messages.clone()));
+    }
+}
@@ -1,12 +1,16 @@
-use std::sync::Arc;
+use std::{io::Write, sync::Arc};
 
 use crate::{
-    channel::Message,
     error::{ApiError, Error, Result},
-    server::{Server, ServerNotification},
+    hub::Hub,
+    server::{HubCache, Server, ServerNotification},
 };
 use crate::{server::client_command, ID};
-use futures_util::{SinkExt, StreamExt};
+use flate2::{write::DeflateEncoder, Compression};
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
 use tokio::sync::Mutex;
 use warp::ws::WebSocket;
 use xactor::Addr;
@@ -17,9 +21,34 @@ use crate::prelude::{WsClientMessage, WsServerMessage};
 
 pub mod prelude {}
 
+/// Serializes a [`WsServerMessage`] into a websocket frame. If `compress` is true and the serialized message is bigger than [`crate::WS_COMPRESSION_THRESHOLD`] it is deflate compressed and sent as a binary frame, otherwise it is sent as a text frame.
+pub fn encode_message(message: &WsServerMessage, compress: bool) -> Result<WebSocketMessage> {
+    let json = serde_json::to_string(message)?;
+    if compress && json.len() > crate::WS_COMPRESSION_THRESHOLD {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.as_bytes())?;
+        Ok(WebSocketMessage::binary(encoder.finish()?))
+    } else {
+        Ok(WebSocketMessage::text(json))
+    }
+}
+
+/// Options a websocket connection was opened with.
+#[derive(Clone)]
+pub struct ConnectionOptions {
+    /// Whether or not big frames sent to the client are compressed, see [`encode_message`].
+    pub compress: bool,
+    /// Size in bytes of the biggest text frame sent by the client that is handled, bigger frames are answered with [`ApiError::TooBig`].
+    pub max_frame_size: usize,
+    /// Cache of the [`Server`] the connection belongs to, used to load the hubs messages are sent to.
+    pub hub_cache: HubCache,
+}
+
+/// Authenticates a websocket connection and handles its commands until it is closed.
 pub async fn handle_connection(
     websocket: WebSocket,
     init_user_id: ID,
+    options: ConnectionOptions,
     addr: Arc<Addr<Server>>,
 ) -> Result {
     let (outgoing, mut incoming) = websocket.split();
@@ -32,153 +61,296 @@ pub async fn handle_connection(
                     {
                         let result = addr
                             .call(client_command::Connect {
+                                user_id: init_user_id,
                                 websocket_writer: out_arc.clone(),
+                                compress: options.compress,
                             })
                             .await
                             .map_err(|_| Error::ApiError(ApiError::InternalError))?;
                         connection_id = result;
                     }
-                    while let Some(msg) = incoming.next().await {
-                        let msg = msg?;
-                        if let Ok(text) = msg.to_str() {
-                            let raw_response = if let Ok(command) = serde_json::from_str(text) {
-                                match command {
-                                    WsClientMessage::SubscribeChannel { hub_id, channel_id } => {
-                                        if let Ok(result) = addr
-                                            .call(client_command::SubscribeChannel {
-                                                user_id,
-                                                hub_id,
-                                                channel_id,
-                                                connection_id,
-                                            })
-                                            .await
-                                        {
-                                            result.map_or_else(
-                                                |err| WsServerMessage::Error((&err).into()),
-                                                |_| WsServerMessage::Success,
-                                            )
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::UnsubscribeChannel { hub_id, channel_id } => {
-                                        if addr
-                                            .call(client_command::UnsubscribeChannel {
-                                                hub_id,
-                                                channel_id,
-                                                connection_id,
-                                            })
-                                            .await
-                                            .is_ok()
-                                        {
-                                            WsServerMessage::Success
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::StartTyping { hub_id, channel_id } => {
-                                        if let Ok(result) = addr
-                                            .call(client_command::StartTyping {
-                                                user_id,
-                                                hub_id,
-                                                channel_id,
-                                            })
-                                            .await
-                                        {
-                                            result.map_or_else(
-                                                |err| WsServerMessage::Error((&err).into()),
-                                                |_| WsServerMessage::Success,
-                                            )
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::StopTyping { hub_id, channel_id } => {
-                                        if let Ok(result) = addr
-                                            .call(client_command::StopTyping {
-                                                user_id,
-                                                hub_id,
-                                                channel_id,
-                                            })
-                                            .await
-                                        {
-                                            result.map_or_else(
-                                                |err| WsServerMessage::Error((&err).into()),
-                                                |_| WsServerMessage::Success,
-                                            )
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::SubscribeHub { hub_id } => {
-                                        if let Ok(result) = addr
-                                            .call(client_command::SubscribeHub {
-                                                user_id,
-                                                hub_id,
-                                                connection_id,
-                                            })
-                                            .await
-                                        {
-                                            result.map_or_else(
-                                                |err| WsServerMessage::Error((&err).into()),
-                                                |_| WsServerMessage::Success,
-                                            )
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::UnsubscribeHub { hub_id } => {
-                                        if addr
-                                            .call(client_command::UnsubscribeHub {
-                                                hub_id,
-                                                connection_id,
-                                            })
-                                            .await
-                                            .is_ok()
-                                        {
-                                            WsServerMessage::Success
-                                        } else {
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
-                                    WsClientMessage::SendMessage {
-                                        message,
-                                        hub_id,
-                                        channel_id,
-                                    } => {
-                                        let message =
-                                            Message::new(user_id, message, hub_id, channel_id);
-                                        if let Err(err) =
-                                            crate::channel::Channel::write_message(&message).await
-                                        {
-                                            WsServerMessage::Error((&err).into())
-                                        } else if addr
-                                            .call(ServerNotification::NewMessage(message))
-                                            .await
-                                            .is_ok()
-                                        {
-                                            WsServerMessage::Success
-                                        } else {
-                                            println!("fail here");
-                                            WsServerMessage::Error(ApiError::InternalError)
-                                        }
-                                    }
+                    let resume_token = addr
+                        .call(client_command::IssueResumeToken {
+                            user_id,
+                            connection_id,
+                        })
+                        .await
+                        .map_err(|_| Error::ApiError(ApiError::InternalError))?;
+                    let result = handle_commands(
+                        &mut incoming,
+                        out_arc,
+                        user_id,
+                        connection_id,
+                        resume_token,
+                        options,
+                        &addr,
+                    )
+                    .await;
+                    let _ = addr
+                        .call(client_command::Disconnect { connection_id })
+                        .await;
+                    return result;
+                }
+            }
+        }
+    }
+    Err(ApiError::WsNotAuthenticated.into())
+}
+
+/// Handles the commands sent by an authenticated client until the connection is closed.
+async fn handle_commands(
+    incoming: &mut SplitStream<WebSocket>,
+    out_arc: Arc<Mutex<SplitSink<WebSocket, WebSocketMessage>>>,
+    user_id: ID,
+    connection_id: u128,
+    resume_token: ID,
+    options: ConnectionOptions,
+    addr: &Addr<Server>,
+) -> Result {
+    {
+        let mut lock = out_arc.lock().await;
+        lock.send(encode_message(
+            &WsServerMessage::Connected { resume_token },
+            options.compress,
+        )?)
+        .await?;
+        lock.flush().await?;
+    }
+    while let Some(msg) = incoming.next().await {
+        let msg = msg?;
+        if let Ok(text) = msg.to_str() {
+            let raw_response = if text.len() > options.max_frame_size {
+                WsServerMessage::Error(ApiError::TooBig)
+            } else if let Ok(command) = serde_json::from_str(text) {
+                match command {
+                    WsClientMessage::SubscribeChannel { hub_id, channel_id } => {
+                        if let Ok(result) = addr
+                            .call(client_command::SubscribeChannel {
+                                user_id,
+                                hub_id,
+                                channel_id,
+                                connection_id,
+                            })
+                            .await
+                        {
+                            result.map_or_else(
+                                |err| WsServerMessage::Error((&err).into()),
+                                |_| WsServerMessage::Success,
+                            )
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::UnsubscribeChannel { hub_id, channel_id } => {
+                        if addr
+                            .call(client_command::UnsubscribeChannel {
+                                hub_id,
+                                channel_id,
+                                connection_id,
+                            })
+                            .await
+                            .is_ok()
+                        {
+                            WsServerMessage::Success
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::StartTyping { hub_id, channel_id } => {
+                        if let Ok(result) = addr
+                            .call(client_command::StartTyping {
+                                user_id,
+                                hub_id,
+                                channel_id,
+                            })
+                            .await
+                        {
+                            result.map_or_else(
+                                |err| WsServerMessage::Error((&err).into()),
+                                |_| WsServerMessage::Success,
+                            )
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::StopTyping { hub_id, channel_id } => {
+                        if let Ok(result) = addr
+                            .call(client_command::StopTyping {
+                                user_id,
+                                hub_id,
+                                channel_id,
+                            })
+                            .await
+                        {
+                            result.map_or_else(
+                                |err| WsServerMessage::Error((&err).into()),
+                                |_| WsServerMessage::Success,
+                            )
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::SubscribeHub { hub_id } => {
+                        if let Ok(result) = addr
+                            .call(client_command::SubscribeHub {
+                                user_id,
+                                hub_id,
+                                connection_id,
+                            })
+                            .await
+                        {
+                            result.map_or_else(
+                                |err| WsServerMessage::Error((&err).into()),
+                                |_| WsServerMessage::Success,
+                            )
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::UnsubscribeHub { hub_id } => {
+                        if addr
+                            .call(client_command::UnsubscribeHub {
+                                hub_id,
+                                connection_id,
+                            })
+                            .await
+                            .is_ok()
+                        {
+                            WsServerMessage::Success
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
+                        }
+                    }
+                    WsClientMessage::SendMessage { message, .. }
+                        if message.as_bytes().len() > crate::MAX_MESSAGE_SIZE =>
+                    {
+                        WsServerMessage::Error(ApiError::TooBig)
+                    }
+                    WsClientMessage::SendMessage {
+                        message,
+                        hub_id,
+                        channel_id,
+                        flags,
+                    } => match crate::check_maintenance_mode() {
+                        Err(err) => WsServerMessage::Error(err),
+                        Ok(()) => {
+                            let lock = Hub::lock(hub_id);
+                            let guard = lock.read().await;
+                            let sent = match Hub::load_cached(hub_id, &options.hub_cache).await {
+                                Ok(hub) => {
+                                    hub.send_message(user_id, channel_id, message, flags).await
                                 }
-                            } else {
-                                WsServerMessage::InvalidCommand
+                                Err(err) => Err((&err).into()),
                             };
-                            let mut lock = out_arc.lock().await;
-                            lock.send(WebSocketMessage::text(serde_json::to_string(
-                                &raw_response,
-                            )?))
-                            .await?;
-                            lock.flush().await?;
+                            drop(guard);
+                            match sent {
+                                Ok(message) => {
+                                    if let Err(err) =
+                                        addr.call(ServerNotification::NewMessage(message)).await
+                                    {
+                                        error!(
+                                            "Failed to notify the server of a new message: {}",
+                                            err
+                                        );
+                                        WsServerMessage::Error(ApiError::InternalError)
+                                    } else {
+                                        WsServerMessage::Success
+                                    }
+                                }
+                                Err(err) => WsServerMessage::Error(err),
+                            }
+                        }
+                    },
+                    WsClientMessage::Resume { token } => match addr
+                        .call(client_command::Resume {
+                            user_id,
+                            token,
+                            connection_id,
+                        })
+                        .await
+                    {
+                        Ok(Ok((channels, hubs))) => {
+                            WsServerMessage::Subscriptions { channels, hubs }
+                        }
+                        Ok(Err(err)) => WsServerMessage::Error((&err).into()),
+                        Err(_) => WsServerMessage::Error(ApiError::InternalError),
+                    },
+                    WsClientMessage::GetSubscriptions => {
+                        if let Ok((channels, hubs)) = addr
+                            .call(client_command::GetSubscriptions { connection_id })
+                            .await
+                        {
+                            WsServerMessage::Subscriptions { channels, hubs }
+                        } else {
+                            WsServerMessage::Error(ApiError::InternalError)
                         }
                     }
-                    return Ok(());
                 }
+            } else {
+                WsServerMessage::InvalidCommand
+            };
+            let mut lock = out_arc.lock().await;
+            lock.send(encode_message(&raw_response, options.compress)?)
+                .await?;
+            lock.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{channel::Message, test::*};
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn compress_large_frames() {
+        let message = WsServerMessage::ChatMessage {
+            sender_id: *USER_ID,
+            hub_id: ID::from_u128(0),
+            channel_id: *CHANNEL_ID,
+            message_id: *MESSAGE_ID,
+            message: "test message ".repeat(crate::WS_COMPRESSION_THRESHOLD),
+            flags: 0,
+            forwarded_from: None,
+            links: Vec::new(),
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        let frame = encode_message(&message, true).unwrap();
+        assert!(frame.is_binary());
+        assert!(frame.as_bytes().len() < json.len());
+        let mut decompressed = String::new();
+        DeflateDecoder::new(frame.as_bytes())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(json, decompressed);
+        let frame = encode_message(&message, false).unwrap();
+        assert!(frame.is_text());
+        assert_eq!(json, frame.to_str().unwrap());
+    }
+
+    #[test]
+    fn small_frames_not_compressed() {
+        let frame = encode_message(&WsServerMessage::Success, true).unwrap();
+        assert!(frame.is_text());
+    }
+
+    #[test]
+    fn silent_message_delivered() {
+        let mut message = crate::channel::test::test_message(ID::from_u128(0));
+        message.flags = Message::FLAG_SILENT;
+        assert!(!message.should_notify());
+        let frame = encode_message(&WsServerMessage::from(message.clone()), false).unwrap();
+        match serde_json::from_str(frame.to_str().unwrap()).unwrap() {
+            WsServerMessage::ChatMessage {
+                message_id, flags, ..
+            } => {
+                assert_eq!(message.id, message_id);
+                assert_eq!(Message::FLAG_SILENT, flags);
             }
+            _ => panic!("expected a chat message"),
         }
     }
-    Err(ApiError::WsNotAuthenticated.into())
 }